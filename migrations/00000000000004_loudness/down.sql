@@ -0,0 +1,9 @@
+-- Revert loudness metrics
+ALTER TABLE analysis_results DROP COLUMN longest_clip_run;
+ALTER TABLE analysis_results DROP COLUMN clip_runs;
+ALTER TABLE analysis_results DROP COLUMN clipped_samples;
+ALTER TABLE analysis_results DROP COLUMN dynamic_range;
+ALTER TABLE analysis_results DROP COLUMN sample_peak;
+ALTER TABLE analysis_results DROP COLUMN true_peak;
+ALTER TABLE analysis_results DROP COLUMN loudness_range;
+ALTER TABLE analysis_results DROP COLUMN integrated_loudness;
@@ -0,0 +1,12 @@
+-- Loudness, dynamic range and clipping metrics per analysis
+-- Integrated loudness (LUFS), loudness range (LU), true/sample peak (dBTP/dBFS),
+-- DR score (dB) and clipped-run counts
+
+ALTER TABLE analysis_results ADD COLUMN integrated_loudness REAL;
+ALTER TABLE analysis_results ADD COLUMN loudness_range REAL;
+ALTER TABLE analysis_results ADD COLUMN true_peak REAL;
+ALTER TABLE analysis_results ADD COLUMN sample_peak REAL;
+ALTER TABLE analysis_results ADD COLUMN dynamic_range REAL;
+ALTER TABLE analysis_results ADD COLUMN clipped_samples INTEGER;
+ALTER TABLE analysis_results ADD COLUMN clip_runs INTEGER;
+ALTER TABLE analysis_results ADD COLUMN longest_clip_run INTEGER;
@@ -0,0 +1,7 @@
+-- Revert stream integrity
+ALTER TABLE analysis_results DROP COLUMN clicks;
+ALTER TABLE analysis_results DROP COLUMN dropouts;
+ALTER TABLE analysis_results DROP COLUMN missing_secs;
+ALTER TABLE analysis_results DROP COLUMN stream_errors;
+ALTER TABLE analysis_results DROP COLUMN decode_errors;
+ALTER TABLE analysis_results DROP COLUMN integrity;
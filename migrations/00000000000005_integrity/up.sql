@@ -0,0 +1,10 @@
+-- Stream integrity per analysis
+-- Integrity verdict (OK/GLITCHES/DAMAGED), rejected packets, audio missing
+-- from the end (s), and dropout/click counts
+
+ALTER TABLE analysis_results ADD COLUMN integrity TEXT;
+ALTER TABLE analysis_results ADD COLUMN decode_errors INTEGER;
+ALTER TABLE analysis_results ADD COLUMN stream_errors INTEGER;
+ALTER TABLE analysis_results ADD COLUMN missing_secs REAL;
+ALTER TABLE analysis_results ADD COLUMN dropouts INTEGER;
+ALTER TABLE analysis_results ADD COLUMN clicks INTEGER;
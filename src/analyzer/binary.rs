@@ -15,20 +15,55 @@
 //!
 //! 1. **Lowpass Mismatch**: The LAME encoder records what lowpass filter it used.
 //!    If a "320kbps" file has lowpass=16kHz, it was transcoded from 128kbps.
+//!    The expected value comes from the LAME version, mode and preset in the
+//!    tag when they are known, and from the bitrate otherwise.
 //!
 //! 2. **Multiple Encoder Signatures**: If a file has both "LAME" and "Lavf"
 //!    (FFmpeg) signatures, it was likely re-encoded at some point.
 //!
-//! 3. **Frame Size Irregularities**: CBR files should have uniform frame sizes.
-//!    High variance in a "CBR 320kbps" file suggests something is wrong.
+//! 3. **VBRI Header**: Fraunhofer's encoders write a VBRI header instead. Its
+//!    frame and byte counts are checked against the stream, and the encoder's
+//!    usual bandwidth against the measured cutoff.
+//!
+//! 4. **LAME Tag**: The extended tag records the encoder's input sample rate
+//!    and preset. A 48 kHz source inside a 44.1 kHz stream was resampled.
+//!
+//! 5. **Stream Verification**: Every frame is walked and checked against the
+//!    Xing frame/byte counts and the LAME music CRC. Disagreement means the
+//!    file was cut, spliced or re-muxed after encoding.
+//!
+//! 6. **Tags**: ID3v1/ID3v2/APE/Lyrics3 tags are read. TSSE should agree with
+//!    the LAME tag, TLEN with the stream's duration, and tags should only sit
+//!    at the ends of the file.
+//!
+//! 7. **Stream Structure**: The whole stream is walked frame by frame. Junk
+//!    between frames, a change of sample rate or MPEG version, or a second
+//!    Xing/Info frame mid-stream marks a splice or two files joined together.
+//!    Protected frames whose CRC fails were damaged after encoding.
+//!
+//! 8. **Side Info**: Each frame's Layer III side info shows how the encoder
+//!    worked (bit reservoir, block switching, stereo modes) and the real coded
+//!    bandwidth. Habits LAME never has mean the LAME text is not the encoder.
+//!
+//! 9. **Channel Modes**: Frames are counted per channel mode, with mid/side
+//!    and intensity stereo use. They should match the stereo mode in the
+//!    LAME tag, and switching back and forth points to another encoder.
+//!
+//! 10. **Frame Size Irregularities**: CBR files should have uniform frame sizes.
+//!     High variance in a "CBR 320kbps" file suggests something is wrong.
 //!
 //! Binary analysis is fast (just reads headers) but only works on MP3 files
 //! encoded with LAME. Other formats (AAC, Opus, FLAC) need spectral analysis.
 
-use crate::mp3::{frame, lame};
+use super::hdcd::HdcdDetails;
+use super::lossywav::LossyWavDetails;
+use crate::mp3::{frame, lame, lowpass, sideinfo, stream, tags, vbri, verify};
 use serde::Serialize;
 use std::io::{Read, Seek};
 
+/// Audio frames in the bitrate timeline and frame size statistics
+const TIMELINE_FRAMES: usize = 500;
+
 /// Per-frame bitrate data for VBR timeline visualization
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct BitrateTimeline {
@@ -44,17 +79,48 @@ pub struct BitrateTimeline {
     pub max_bitrate: u32,
     /// Average bitrate
     pub avg_bitrate: u32,
+    /// Free format stream: bitrates measured from the distance between frames
+    pub free_format: bool,
 }
 
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct BinaryDetails {
     pub lowpass: Option<u32>,
     pub expected_lowpass: Option<u32>,
+    /// What the expected lowpass is based on ("LAME3.99 V2 @ 44100 Hz", "192 kbps @ 44100 Hz")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_lowpass_basis: Option<String>,
     pub encoder_version: Option<String>,
     pub encoder_count: usize,
     pub frame_size_cv: f64,
     pub is_vbr: bool,
     pub total_frames: Option<u32>,
+    /// Exact playing time: every frame's own duration, less the header frame
+    /// and the encoder delay and padding. Only set when the walk covers the audio
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<f64>,
+    /// Extended LAME tag: delay/padding, ReplayGain, flags, source rate, preset, CRCs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lame_tag: Option<lame::LameTag>,
+    /// Fraunhofer VBRI header: delay, quality, frame/byte counts, TOC
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vbri: Option<vbri::VbriHeader>,
+    /// Frame walk compared with the Xing frame/byte counts and LAME CRCs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_verification: Option<verify::StreamVerification>,
+    /// Full frame walk: real frame count and duration, sync losses, format changes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_structure: Option<stream::StreamStructure>,
+    /// Layer III side info statistics: bit reservoir, block types, global gain,
+    /// stereo modes, coded bandwidth and the encoder they point to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub side_info: Option<sideinfo::SideInfoStats>,
+    /// Frames per channel mode, mid/side and intensity stereo use, mode switches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel_modes: Option<frame::ChannelModeStats>,
+    /// ID3v1/ID3v2/APE/Lyrics3 tags and the fields they record
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<tags::TagSet>,
     /// Number of times LAME signature appears (>1 = re-encoded)
     pub lame_occurrences: usize,
     /// Number of times FFmpeg/Lavf signature appears
@@ -66,6 +132,12 @@ pub struct BinaryDetails {
     /// Per-frame bitrate timeline data for visualization
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bitrate_timeline: Option<BitrateTimeline>,
+    /// lossyWAV checks (zeroed low bits per block, FLAC tag markers)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lossywav: Option<LossyWavDetails>,
+    /// HDCD control codes in the LSBs (16-bit lossless files)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hdcd: Option<HdcdDetails>,
 }
 
 pub struct BinaryResult {
@@ -92,26 +164,70 @@ impl Default for BinaryResult {
 pub fn analyze<R: Read + Seek>(data: &[u8], reader: &mut R, bitrate: u32) -> BinaryResult {
     let mut result = BinaryResult::default();
 
+    // One walk over every frame feeds the header checks, the stream structure,
+    // side info, channel modes and the bitrate timeline
+    let mut collector = sideinfo::SideInfoCollector::default();
+    let mut channel_modes = frame::ChannelModeStats::default();
+    let mut frame_stats = frame::FrameStats::default();
+    let structure = stream::walk_with(data, |bytes, header| {
+        collector.add(bytes, header);
+        channel_modes.add(header);
+        if frame_stats.frame_count < TIMELINE_FRAMES {
+            frame_stats.add(header);
+        }
+    });
+    frame_stats.summarize();
+
     // Extract LAME header
     if let Some(lame_header) = lame::LameHeader::extract(data) {
+        // The version, VBR method and preset say which lowpass LAME would pick
+        let expected_lowpass = lowpass::for_header(&lame_header, bitrate);
         result.encoder = if lame_header.encoder.is_empty() {
             "LAME".to_string()
         } else {
             lame_header.encoder.clone()
         };
 
+        // Edits after encoding leave the header describing a different stream.
+        // Reported, not scored: trimming and tag editing are not transcoding
+        if let Some(verification) = verify::verify(data, &lame_header, &structure) {
+            // The encoder's delay and padding are silence, not part of the music
+            let trim = lame_header.tag.as_ref().map_or(0, |tag| tag.encoder_delay as u32 + tag.encoder_padding as u32);
+            result.details.duration_secs = lame_header
+                .sample_rate
+                .map(|rate| (verification.duration_secs - trim as f64 / rate as f64).max(0.0));
+            result.flags.extend(verification.flags());
+            result.details.stream_verification = Some(verification);
+        }
+
         result.lowpass = lame_header.lowpass;
         result.details.lowpass = lame_header.lowpass;
         result.details.encoder_version = Some(lame_header.encoder);
         result.details.is_vbr = lame_header.is_vbr_header;
         result.details.total_frames = lame_header.total_frames;
 
+        // The tag records the rate LAME was fed. A different stream rate
+        // means the encoder (or whoever fed it) resampled the source.
+        // The lowest range is also what a zero-filled tag reads as, so it is not trusted
+        if let (Some(ref tag), Some(rate)) = (&lame_header.tag, lame_header.sample_rate) {
+            let source = tag.source_sample_rate;
+            if source != lame::SourceSampleRate::Low && !source.matches(rate) {
+                result.flags.push(format!(
+                    "lame_source_resampled({}→{}k)",
+                    source.as_str(),
+                    rate as f64 / 1000.0
+                ));
+            }
+        }
+        result.details.lame_tag = lame_header.tag;
+
         // KEY CHECK: Lowpass mismatch
         if let Some(actual_lowpass) = lame_header.lowpass {
             let (is_suspicious, expected, reason) =
-                lame::check_lowpass_mismatch(bitrate, actual_lowpass);
+                lame::check_lowpass(&expected_lowpass, actual_lowpass);
 
             result.details.expected_lowpass = Some(expected);
+            result.details.expected_lowpass_basis = Some(expected_lowpass.basis);
 
             if is_suspicious {
                 result.score += 35;
@@ -123,6 +239,16 @@ pub fn analyze<R: Read + Seek>(data: &[u8], reader: &mut R, bitrate: u32) -> Bin
                 }
             }
         }
+    } else if let Some(vbri_header) = vbri::VbriHeader::extract(data) {
+        // Fraunhofer's VBR header. No lowpass is recorded, so the expected
+        // bandwidth is checked against the measured cutoff after spectral analysis
+        result.encoder = "Fraunhofer".to_string();
+        result.details.encoder_version = Some(format!("Fraunhofer VBRI v{}", vbri_header.version));
+        result.details.is_vbr = true;
+        result.details.total_frames = Some(vbri_header.total_frames);
+        let average = vbri_header.average_bitrate().unwrap_or(bitrate);
+        result.details.expected_lowpass = Some(vbri::expected_bandwidth(average));
+        result.details.vbri = Some(vbri_header);
     } else {
         // Check for other encoders
         reader.seek(std::io::SeekFrom::Start(0)).ok();
@@ -192,8 +318,7 @@ pub fn analyze<R: Read + Seek>(data: &[u8], reader: &mut R, bitrate: u32) -> Bin
     }
 
     // Frame size analysis and bitrate timeline
-    reader.seek(std::io::SeekFrom::Start(0)).ok();
-    if let Ok(frame_stats) = frame::scan_frames(reader, 500) {
+    if frame_stats.frame_count > 0 {
         let cv = frame_stats.frame_size_cv();
         result.details.frame_size_cv = cv;
 
@@ -203,32 +328,91 @@ pub fn analyze<R: Read + Seek>(data: &[u8], reader: &mut R, bitrate: u32) -> Bin
             result.flags.push("irregular_frames".to_string());
         }
 
-        // Build bitrate timeline for visualization
-        if !frame_stats.bitrates.is_empty() {
-            // Calculate time for each frame
-            // MPEG1 Layer3: 1152 samples/frame at 44100 Hz = ~26.1ms per frame
-            const SAMPLES_PER_FRAME: f64 = 1152.0;
-            const SAMPLE_RATE: f64 = 44100.0;
-            let frame_duration = SAMPLES_PER_FRAME / SAMPLE_RATE;
-
-            let times: Vec<f64> = (0..frame_stats.bitrates.len())
-                .map(|i| i as f64 * frame_duration)
-                .collect();
-
-            result.details.bitrate_timeline = Some(BitrateTimeline {
-                times,
-                bitrates: frame_stats.bitrates.clone(),
-                is_vbr: frame_stats.is_vbr,
-                min_bitrate: frame_stats.min_bitrate,
-                max_bitrate: frame_stats.max_bitrate,
-                avg_bitrate: frame_stats.avg_bitrate,
+        // Build bitrate timeline for visualization. Each frame is timed by its
+        // own header: MPEG-1 Layer3 is 1152 samples (~26.1ms at 44.1kHz), but
+        // MPEG-2/2.5 is 576 samples at 24kHz and below
+        result.details.bitrate_timeline = Some(BitrateTimeline {
+            times: frame_stats.times,
+            bitrates: frame_stats.bitrates,
+            is_vbr: frame_stats.is_vbr,
+            min_bitrate: frame_stats.min_bitrate,
+            max_bitrate: frame_stats.max_bitrate,
+            avg_bitrate: frame_stats.avg_bitrate,
+            free_format: frame_stats.free_format,
+        });
+    }
+
+    // Full stream walk: splices and joins leave junk, format changes and
+    // extra Xing/Info frames behind. Reported, not scored
+    if structure.frames > 0 {
+        // A truncation the header check already reported is only named once
+        for flag in structure.flags() {
+            if !result.flags.contains(&flag) {
+                result.flags.push(flag);
+            }
+        }
+        if let Some(ref vbri_header) = result.details.vbri {
+            result.flags.extend(vbri_header.check_length(&structure));
+        }
+        if result.details.duration_secs.is_none() && structure.covers_audio() {
+            result.details.duration_secs = Some(match result.details.vbri {
+                // The VBRI frame holds no audio, and its delay is silence
+                Some(ref vbri_header) if vbri_header.sample_rate > 0 => (structure.duration_secs
+                    - (vbri_header.samples_per_frame + vbri_header.delay as u32) as f64 / vbri_header.sample_rate as f64)
+                    .max(0.0),
+                _ => structure.duration_secs,
             });
         }
+        result.details.stream_structure = Some(structure);
+    }
+
+    // Side info records how the audio was actually coded. A file whose text
+    // says LAME but whose frames use features LAME never does was encoded by
+    // something else and retagged. Reported, not scored
+    if let Some(stats) = collector.finish() {
+        let contradictions = stats.lame_contradictions();
+        if result.encoder.starts_with("LAME") && !contradictions.is_empty() {
+            result.flags.push(format!("side_info_not_lame({})", contradictions.join(",")));
+        }
+        result.details.side_info = Some(stats);
+    }
+
+    // Channel modes: the frames should use the stereo mode the LAME tag
+    // records, and one encoder keeps to one mode. Reported, not scored
+    if channel_modes.frames > 0 {
+        if let Some(ref tag) = result.details.lame_tag {
+            result.flags.extend(channel_modes.check_lame_mode(tag.stereo_mode));
+        }
+        result.flags.extend(channel_modes.flags());
+        result.details.channel_modes = Some(channel_modes);
+    }
+
+    // Tags: TSSE and TLEN checked against the stream, and where the tags sit.
+    // Reported, not scored: a retagged file is not a transcode
+    let tag_set = tags::read_tags(data);
+    if !tag_set.is_empty() {
+        let duration = measured_duration(&result.details);
+        result.flags.extend(tag_set.cross_check(
+            result.details.encoder_version.as_deref(),
+            result.details.lame_tag.as_ref(),
+            duration,
+        ));
+        result.details.tags = Some(tag_set);
     }
 
     result
 }
 
+/// Stream duration from the frame walks, when the walk covered the audio
+fn measured_duration(details: &BinaryDetails) -> Option<f64> {
+    details
+        .stream_structure
+        .as_ref()
+        .filter(|structure| structure.covers_audio())
+        .map(|structure| structure.duration_secs)
+        .or_else(|| details.stream_verification.as_ref().map(|verification| verification.duration_secs))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -315,6 +499,81 @@ mod tests {
         data
     }
 
+    #[test]
+    fn test_vbri_identifies_fraunhofer() {
+        // No LAME header: the VBRI header names the encoder and gives the totals
+        // VBRI 32 bytes after the first header: version, then byte and frame totals
+        let mut data = vec![0u8; 417];
+        data[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+        data[36..40].copy_from_slice(b"VBRI");
+        data[40..42].copy_from_slice(&1u16.to_be_bytes());
+        data[46..50].copy_from_slice(&(41u32 * 417).to_be_bytes());
+        data[50..54].copy_from_slice(&40u32.to_be_bytes());
+        for _ in 0..40 {
+            data.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+            data.extend_from_slice(&[0x11; 413]);
+        }
+        let mut cursor = Cursor::new(&data);
+        let result = analyze(&data, &mut cursor, 128);
+
+        assert_eq!(result.encoder, "Fraunhofer");
+        assert_eq!(result.details.total_frames, Some(40));
+        assert_eq!(result.details.expected_lowpass, Some(16000));
+        assert!(result.details.vbri.is_some());
+        assert!(!result.flags.iter().any(|f| f.starts_with("vbri_")), "Got {:?}", result.flags);
+    }
+
+    #[test]
+    fn test_lame_source_rate_reveals_resampling() {
+        // LAME records the rate of its input. A 44.1kHz stream encoded from
+        // a 48kHz source was resampled on the way in
+        let mut data = create_test_mp3_data("LAME3.100", 20500, false);
+        let lame_pos = data.windows(4).position(|w| w == b"LAME").unwrap();
+        data[lame_pos + 24] = 2 << 6;
+        let mut cursor = Cursor::new(data.clone());
+        let result = analyze(&data, &mut cursor, 320);
+        assert!(
+            result.flags.contains(&"lame_source_resampled(48k→44.1k)".to_string()),
+            "Got {:?}",
+            result.flags
+        );
+        assert!(result.details.lame_tag.is_some());
+
+        // Matching source rate: nothing to report
+        data[lame_pos + 24] = 1 << 6;
+        let mut cursor = Cursor::new(data.clone());
+        let result = analyze(&data, &mut cursor, 320);
+        assert!(!result.flags.iter().any(|f| f.starts_with("lame_source_resampled")));
+    }
+
+    #[test]
+    fn test_channel_modes_checked_against_lame_tag() {
+        // LAME tag says joint stereo, frames are plain stereo: allowed
+        let mut data = create_test_mp3_data("LAME3.100", 17000, false);
+        let lame_pos = data.windows(4).position(|w| w == b"LAME").unwrap();
+        data[lame_pos + 24] = (1 << 6) | (3 << 2);
+        data.resize(417, 0);
+        for _ in 0..20 {
+            data.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+            data.extend_from_slice(&[0x11; 413]);
+        }
+        let mut cursor = Cursor::new(&data);
+        let result = analyze(&data, &mut cursor, 128);
+        let modes = result.details.channel_modes.as_ref().expect("Should count channel modes");
+        assert_eq!((modes.frames, modes.stereo, modes.mode_switches), (20, 20, 0));
+        assert!(!result.flags.iter().any(|f| f.starts_with("lame_stereo_mode")), "Got {:?}", result.flags);
+
+        // LAME tag says mono: the stereo frames came from somewhere else
+        data[lame_pos + 24] = 1 << 6;
+        let mut cursor = Cursor::new(&data);
+        let result = analyze(&data, &mut cursor, 128);
+        assert!(
+            result.flags.contains(&"lame_stereo_mode_mismatch(tag=mono,frames=stereo)".to_string()),
+            "Got {:?}",
+            result.flags
+        );
+    }
+
     // ==========================================================================
     // LOWPASS MISMATCH DETECTION TESTS
     // ==========================================================================
@@ -358,6 +617,23 @@ mod tests {
         assert_eq!(result.lowpass, Some(16000));
     }
 
+    #[test]
+    fn test_lowpass_judged_by_lame_preset() {
+        // A loud -V5 file can average 200kbps, but -V5 means a ~17kHz lowpass
+        let mut data = create_test_mp3_data("LAME3.100", 16500, true);
+        let mut cursor = Cursor::new(data.clone());
+        let result = analyze(&data, &mut cursor, 200);
+        assert!(result.flags.iter().any(|f| f.contains("lowpass_mismatch")));
+
+        let lame_pos = data.windows(4).position(|w| w == b"LAME").unwrap();
+        data[lame_pos + 26..lame_pos + 28].copy_from_slice(&450u16.to_be_bytes());
+        let mut cursor = Cursor::new(data.clone());
+        let result = analyze(&data, &mut cursor, 200);
+        assert!(!result.flags.iter().any(|f| f.contains("lowpass_mismatch")), "Got {:?}", result.flags);
+        assert_eq!(result.details.expected_lowpass, Some(17000));
+        assert_eq!(result.details.expected_lowpass_basis.as_deref(), Some("LAME3.100 V5 @ 44100 Hz"));
+    }
+
     #[test]
     fn test_legitimate_320_not_flagged() {
         // SCENARIO: Legitimate 320kbps encoding from lossless source
@@ -762,6 +1038,7 @@ mod tests {
             min_bitrate: 320,
             max_bitrate: 320,
             avg_bitrate: 320,
+            free_format: false,
         };
 
         assert!(!bt.is_vbr, "Should be CBR");
@@ -779,6 +1056,7 @@ mod tests {
             min_bitrate: 128,
             max_bitrate: 320,
             avg_bitrate: 224,
+            free_format: false,
         };
 
         assert!(bt.is_vbr, "Should be VBR");
@@ -787,16 +1065,64 @@ mod tests {
 
     #[test]
     fn test_bitrate_timeline_time_calculation() {
-        // Time should increase by ~26ms per frame (1152 samples at 44.1kHz)
-        const SAMPLES_PER_FRAME: f64 = 1152.0;
-        const SAMPLE_RATE: f64 = 44100.0;
-        let frame_duration = SAMPLES_PER_FRAME / SAMPLE_RATE;
+        // Each frame is timed by its own header: MPEG2 Layer3 at 22.05kHz is
+        // 576 samples (~26.1ms), and at 16kHz 576 samples is 36ms
+        let mut data = Vec::new();
+        for header in [[0xFF, 0xF3, 0x80, 0x00], [0xFF, 0xF3, 0x88, 0x00]] {
+            let frame = frame::FrameHeader::parse(header).unwrap();
+            for _ in 0..5 {
+                data.extend_from_slice(&header);
+                data.extend(vec![0x11; frame.frame_size as usize - 4]);
+            }
+        }
+        let mut cursor = Cursor::new(&data);
+        let result = analyze(&data, &mut cursor, 64);
+
+        let bt = result.details.bitrate_timeline.expect("Should build timeline");
+        assert_eq!(bt.times.len(), 10);
+        assert!((bt.times[1] - 576.0 / 22050.0).abs() < 1e-9);
+        assert!((bt.times[6] - (5.0 * 576.0 / 22050.0 + 0.036)).abs() < 1e-9);
+        let duration = result.details.duration_secs.expect("Should measure duration");
+        assert!((duration - (5.0 * 576.0 / 22050.0 + 5.0 * 0.036)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_free_format_timeline() {
+        // 480kbps free format: no bitrate in the headers, 1567-byte frames
+        let mut data = Vec::new();
+        for _ in 0..8 {
+            data.extend_from_slice(&[0xFF, 0xFB, 0x00, 0x00]);
+            data.extend_from_slice(&[0x11; 1563]);
+        }
+        let mut cursor = Cursor::new(&data);
+        let result = analyze(&data, &mut cursor, 480);
+
+        let bt = result.details.bitrate_timeline.expect("Should build timeline");
+        assert!(bt.free_format);
+        assert_eq!((bt.bitrates.len(), bt.avg_bitrate), (8, 480));
+        let structure = result.details.stream_structure.expect("Should walk the stream");
+        assert_eq!((structure.frames, structure.junk_bytes), (8, 0));
+    }
 
-        assert!((frame_duration - 0.026).abs() < 0.001, "Frame duration ~26ms");
+    #[test]
+    fn test_duration_excludes_lame_delay_and_padding() {
+        // 20 audio frames of 1152 samples, less 576 samples of delay and 1152 of padding
+        let mut data = create_test_mp3_data("LAME3.100", 17000, false);
+        let lame_pos = data.windows(4).position(|w| w == b"LAME").unwrap();
+        data[lame_pos + 21..lame_pos + 24].copy_from_slice(&[0x24, 0x04, 0x80]);
+        data.resize(417, 0);
+        for _ in 0..20 {
+            data.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+            data.extend_from_slice(&[0x11; 413]);
+        }
+        let mut cursor = Cursor::new(&data);
+        let result = analyze(&data, &mut cursor, 128);
 
-        let times: Vec<f64> = (0..5).map(|i| i as f64 * frame_duration).collect();
-        assert!((times[1] - 0.026).abs() < 0.001);
-        assert!((times[4] - 0.104).abs() < 0.001);
+        let tag = result.details.lame_tag.as_ref().unwrap();
+        assert_eq!((tag.encoder_delay, tag.encoder_padding), (576, 1152));
+        let expected = (20.0 * 1152.0 - 576.0 - 1152.0) / 44100.0;
+        let duration = result.details.duration_secs.expect("Should measure duration");
+        assert!((duration - expected).abs() < 1e-9, "Got {}", duration);
     }
 
     #[test]
@@ -810,6 +1136,7 @@ mod tests {
                 min_bitrate: 320,
                 max_bitrate: 320,
                 avg_bitrate: 320,
+                free_format: false,
             }),
             ..Default::default()
         };
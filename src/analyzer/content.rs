@@ -0,0 +1,445 @@
+//! Content characterisation for band-limited productions
+//!
+//! Electronic music made entirely in a DAW often has nothing above 16-18 kHz:
+//! the synths are filtered, and there's no microphone or room to add air. The
+//! upper-band checks read that as a lossy cutoff. Three properties of the
+//! content tell the two apart:
+//!
+//! ```text
+//! Edge shape:                         HF dynamics:
+//!
+//!  ────────╮                           low band   ╱╲  ╱╲    ╱╲
+//!           ╲   synth filter           synth top  ╱╲  ╱╲    ╱╲   (follows)
+//!            ╲  (12-48 dB/oct)         codec top  ╱╲   _    ╱    (drops out)
+//!  ────────┐  ╲
+//!          │     codec lowpass
+//!          └──── (hundreds of dB/oct)
+//! ```
+//!
+//! - **Edge slope**: the steepest fall above 8 kHz, in dB per octave. Synth and
+//!   analog filters roll off at 12-48 dB/oct; an encoder's lowpass falls
+//!   tens of dB inside a fraction of an octave.
+//! - **HF tracking**: correlation of the band just below the edge with the
+//!   lower band, frame by frame. Filtered instruments keep their dynamics up
+//!   there; an encoder short of bits drops that band in and out.
+//! - **Harmonicity**: share of the 100 Hz-5 kHz energy in tonal peaks.
+//!   Synthesised parts are strongly harmonic.
+//!
+//! When the file is band-limited through a gentle slope and at least one of
+//! the other two holds, the spectral scoring switches to an adaptive mode that
+//! treats the missing top end as a production choice.
+
+use rustfft::{num_complex::Complex, FftPlanner};
+use serde::Serialize;
+
+/// Lowest frequency of the band levels used for the edge (Hz)
+const BANDS_LOW_HZ: f64 = 2000.0;
+/// Band levels per octave
+const BANDS_PER_OCTAVE: f64 = 24.0;
+/// Span the slope is measured over (bands; 1/6 octave)
+const SLOPE_SPAN: usize = 4;
+/// Edges are looked for above this (Hz)
+const EDGE_LOW_HZ: f64 = 8000.0;
+/// The fall must start this far above the spectrum's floor (dB)
+const EDGE_ABOVE_FLOOR_DB: f64 = 15.0;
+/// Steepest fall a synth or analog filter produces (dB/oct)
+const MAX_FILTER_SLOPE: f64 = 72.0;
+/// Reference passband for the top-end drop (Hz)
+const REFERENCE_LOW_HZ: f64 = 2000.0;
+const REFERENCE_HIGH_HZ: f64 = 8000.0;
+/// Top end measured from here up (Hz)
+const TOP_LOW_HZ: f64 = 16000.0;
+/// Top end at least this far below the passband = band-limited (dB)
+const MIN_BAND_LIMIT_DB: f64 = 15.0;
+/// Frame size at 44.1 kHz for the envelope and harmonicity pass
+const FRAME_SIZE: usize = 2048;
+/// Low band the HF envelope is compared with (Hz)
+const LOW_BAND_HZ: (f64, f64) = (200.0, 4000.0);
+/// HF band: this fraction of the edge frequency up to the edge
+const HF_BAND_RATIO: f64 = 0.6;
+/// Frames quieter than this in the low band are skipped (dB re full scale)
+const SILENT_FRAME_DB: f64 = -70.0;
+/// Harmonicity range (Hz)
+const TONAL_RANGE_HZ: (f64, f64) = (100.0, 5000.0);
+/// Peaks this far above the local median are tonal (dB)
+const TONAL_PEAK_DB: f64 = 10.0;
+/// Bins either side in the local median
+const TONAL_MEDIAN_BINS: usize = 16;
+/// HF envelope following the low band at least this well = filtered instruments
+const MIN_HF_TRACKING: f64 = 0.6;
+/// Tonal share at least this high = synthesised harmonic parts
+const MIN_HARMONICITY: f64 = 0.5;
+/// Fewest frames for the envelope correlation
+const MIN_FRAMES: usize = 20;
+
+/// How the spectral scoring treated the content
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentMode {
+    /// Scored as usual
+    #[default]
+    Standard,
+    /// Band-limited through a filter slope: upper-band checks scaled down
+    BandLimitedProduction,
+}
+
+impl ContentMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentMode::Standard => "standard",
+            ContentMode::BandLimitedProduction => "band_limited_production",
+        }
+    }
+}
+
+/// What the content looks like, and how it was scored
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ContentProfile {
+    /// Share of the 100 Hz-5 kHz energy in tonal peaks (0.0-1.0)
+    pub harmonicity: f64,
+    /// Correlation of the HF envelope with the low band (-1.0-1.0; None if too few frames)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hf_tracking: Option<f64>,
+    /// Steepest fall above 8 kHz (dB/oct; None if nothing falls)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edge_slope: Option<f64>,
+    /// Where that fall is (Hz)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edge_frequency: Option<f64>,
+    /// Level of the top end (16 kHz up) below the 2-8 kHz passband (dB)
+    pub top_drop: f64,
+    /// Scoring mode applied
+    pub mode: ContentMode,
+}
+
+impl ContentProfile {
+    /// Whether the adaptive mode was applied
+    pub fn is_adaptive(&self) -> bool {
+        self.mode != ContentMode::Standard
+    }
+
+    /// Flag text, e.g. "adaptive_content_mode(band_limited_production,slope=24dB/oct)"
+    pub fn flag(&self) -> String {
+        match self.edge_slope {
+            Some(slope) => format!(
+                "adaptive_content_mode({},slope={:.0}dB/oct)",
+                self.mode.as_str(),
+                slope
+            ),
+            None => format!("adaptive_content_mode({})", self.mode.as_str()),
+        }
+    }
+}
+
+/// Characterise the content from the averaged spectrum (dB per bin) and the samples
+pub fn profile(
+    spectrum_db: &[f64],
+    bin_hz: f64,
+    samples: &[f64],
+    sample_rate: u32,
+) -> ContentProfile {
+    let nyquist = sample_rate as f64 / 2.0;
+    let bands = band_levels(spectrum_db, bin_hz, nyquist);
+    let level_between = |low: f64, high: f64| {
+        let levels: Vec<f64> = bands
+            .iter()
+            .filter(|(f, _)| *f >= low && *f < high)
+            .map(|(_, l)| *l)
+            .collect();
+        power_mean_db(&levels)
+    };
+    let top_drop = match (
+        level_between(REFERENCE_LOW_HZ, REFERENCE_HIGH_HZ),
+        level_between(TOP_LOW_HZ, nyquist),
+    ) {
+        (Some(reference), Some(top)) => reference - top,
+        _ => 0.0,
+    };
+    let edge = steepest_fall(&bands);
+
+    let hf_high = edge.map_or(TOP_LOW_HZ, |(freq, _)| freq).min(nyquist);
+    let (harmonicity, hf_tracking) =
+        measure_frames(samples, sample_rate, (hf_high * HF_BAND_RATIO, hf_high));
+
+    let gentle_edge = edge.is_none_or(|(_, slope)| slope < MAX_FILTER_SLOPE);
+    let follows = hf_tracking.is_some_and(|t| t >= MIN_HF_TRACKING);
+    let mode = if top_drop >= MIN_BAND_LIMIT_DB
+        && gentle_edge
+        && (follows || harmonicity >= MIN_HARMONICITY)
+    {
+        ContentMode::BandLimitedProduction
+    } else {
+        ContentMode::Standard
+    };
+
+    ContentProfile {
+        harmonicity,
+        hf_tracking,
+        edge_slope: edge.map(|(_, slope)| slope),
+        edge_frequency: edge.map(|(freq, _)| freq),
+        top_drop,
+        mode,
+    }
+}
+
+/// Levels (dB) of 1/24-octave bands from 2 kHz to just below Nyquist, by centre frequency
+fn band_levels(spectrum_db: &[f64], bin_hz: f64, nyquist: f64) -> Vec<(f64, f64)> {
+    let ratio = 2f64.powf(1.0 / BANDS_PER_OCTAVE);
+    let mut bands = Vec::new();
+    let mut low = BANDS_LOW_HZ;
+    while low * ratio < nyquist * 0.98 {
+        let high = low * ratio;
+        let bins = (low / bin_hz).ceil() as usize
+            ..((high / bin_hz).ceil() as usize).min(spectrum_db.len());
+        if let Some(level) = power_mean_db(&spectrum_db[bins]) {
+            bands.push(((low * high).sqrt(), level));
+        }
+        low = high;
+    }
+    bands
+}
+
+/// Frequency and slope (dB/oct) of the steepest 1/6-octave fall above 8 kHz
+fn steepest_fall(bands: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let floor = bands.iter().map(|(_, l)| *l).fold(f64::INFINITY, f64::min);
+    let octaves = SLOPE_SPAN as f64 / BANDS_PER_OCTAVE;
+    bands
+        .windows(SLOPE_SPAN + 1)
+        .filter(|w| w[0].0 >= EDGE_LOW_HZ && w[0].1 >= floor + EDGE_ABOVE_FLOOR_DB)
+        .map(|w| {
+            (
+                (w[0].0 * w[SLOPE_SPAN].0).sqrt(),
+                (w[0].1 - w[SLOPE_SPAN].1) / octaves,
+            )
+        })
+        .filter(|&(_, slope)| slope > 0.0)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Harmonicity and HF tracking from non-overlapping frames
+fn measure_frames(samples: &[f64], sample_rate: u32, hf_band: (f64, f64)) -> (f64, Option<f64>) {
+    let size = (FRAME_SIZE as f64 * sample_rate as f64 / 44100.0).round() as usize;
+    let size = size.next_power_of_two().max(FRAME_SIZE / 2);
+    let bin_hz = sample_rate as f64 / size as f64;
+    let bins = |(low, high): (f64, f64)| {
+        (low / bin_hz).ceil() as usize..((high / bin_hz) as usize).min(size / 2)
+    };
+    let low_bins = bins(LOW_BAND_HZ);
+    let hf_bins = bins(hf_band);
+    let tonal_bins = bins(TONAL_RANGE_HZ);
+
+    let window: Vec<f64> = (0..size)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / size as f64).cos())
+        .collect();
+    let fft = FftPlanner::new().plan_fft_forward(size);
+    // A full-scale sine peaks at (N/4)² in a Hann-windowed bin
+    let full_scale = (size as f64 / 4.0).powi(2);
+    let silent = full_scale * 10f64.powf(SILENT_FRAME_DB / 10.0);
+    let tonal_ratio = 10f64.powf(TONAL_PEAK_DB / 10.0);
+
+    let mut buf = vec![Complex::new(0.0, 0.0); size];
+    let mut neighbourhood = Vec::with_capacity(2 * TONAL_MEDIAN_BINS + 1);
+    let (mut tonal, mut total) = (0.0, 0.0);
+    let mut envelopes = Vec::new();
+    for frame in samples.chunks_exact(size) {
+        for (b, (&s, &w)) in buf.iter_mut().zip(frame.iter().zip(&window)) {
+            *b = Complex::new(s * w, 0.0);
+        }
+        fft.process(&mut buf);
+        let power: Vec<f64> = buf[..size / 2].iter().map(|c| c.norm_sqr()).collect();
+
+        let low: f64 = power[low_bins.clone()].iter().sum();
+        if low < silent {
+            continue;
+        }
+        let hf: f64 = power[hf_bins.clone()].iter().sum();
+        envelopes.push((to_db(low), to_db(hf)));
+
+        for k in tonal_bins.clone() {
+            total += power[k];
+            let is_peak = power[k] > power[k - 1] && power[k] >= power[k + 1];
+            if !is_peak {
+                continue;
+            }
+            neighbourhood.clear();
+            neighbourhood.extend_from_slice(
+                &power[k.saturating_sub(TONAL_MEDIAN_BINS)
+                    ..(k + TONAL_MEDIAN_BINS + 1).min(size / 2)],
+            );
+            let mid = neighbourhood.len() / 2;
+            let median = *neighbourhood
+                .select_nth_unstable_by(mid, |a, b| a.total_cmp(b))
+                .1;
+            if power[k] > median * tonal_ratio {
+                // The peak and the window's main lobe either side
+                tonal += power[k - 1] + power[k] + power[k + 1];
+            }
+        }
+    }
+
+    let harmonicity = if total > 0.0 {
+        (tonal / total).min(1.0)
+    } else {
+        0.0
+    };
+    let tracking = if envelopes.len() >= MIN_FRAMES && !hf_bins.is_empty() {
+        correlation(&envelopes)
+    } else {
+        None
+    };
+    (harmonicity, tracking)
+}
+
+/// Pearson correlation of paired values (None if either is constant)
+fn correlation(pairs: &[(f64, f64)]) -> Option<f64> {
+    let n = pairs.len() as f64;
+    let (mean_a, mean_b) = pairs
+        .iter()
+        .fold((0.0, 0.0), |(a, b), (x, y)| (a + x / n, b + y / n));
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (a, b) in pairs {
+        cov += (a - mean_a) * (b - mean_b);
+        var_a += (a - mean_a).powi(2);
+        var_b += (b - mean_b).powi(2);
+    }
+    if var_a <= f64::EPSILON || var_b <= f64::EPSILON {
+        return None;
+    }
+    Some(cov / (var_a * var_b).sqrt())
+}
+
+/// Mean of dB values taken in the power domain (None if empty)
+fn power_mean_db(levels: &[f64]) -> Option<f64> {
+    if levels.is_empty() {
+        return None;
+    }
+    let mean = levels.iter().map(|l| 10f64.powf(l / 10.0)).sum::<f64>() / levels.len() as f64;
+    Some(to_db(mean))
+}
+
+fn to_db(power: f64) -> f64 {
+    10.0 * power.max(1e-20).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_noise::TestNoise;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: Filter slopes vs codec lowpasses
+    // ==========================================================================
+    //
+    // A synth's lowpass is an analog-style filter: each pole adds 6 dB/oct of
+    // rolloff, so a 4-pole "ladder" falls 24 dB per octave above its cutoff.
+    // Even an aggressive 8-pole filter only reaches 48 dB/oct.
+    //
+    // An encoder's lowpass is part of its filterbank. Everything above the
+    // chosen band is simply not coded, so the spectrum falls 40-80 dB within
+    // a few hundred Hz - several hundred dB per octave.
+    //
+    // Content below the edge differs too: a filtered synth keeps playing up
+    // to the edge with the same envelope as its lower harmonics, while an
+    // encoder short of bits drops its top bands in and out.
+    // ==========================================================================
+
+    const RATE: u32 = 44100;
+    const BIN_HZ: f64 = 44100.0 / 8192.0;
+
+    /// Averaged spectrum: -6 dB/oct from 1 kHz, shaped by `lowpass` (dB at a frequency)
+    fn spectrum(lowpass: impl Fn(f64) -> f64) -> Vec<f64> {
+        (0..4096)
+            .map(|k| {
+                let f = (k as f64 * BIN_HZ).max(1.0);
+                let tilt = -6.0 * (f / 1000.0).log2().max(0.0);
+                (-20.0 + tilt + lowpass(f)).max(-110.0)
+            })
+            .collect()
+    }
+
+    fn ladder_db(f: f64, fc: f64, poles: i32) -> f64 {
+        -10.0 * (1.0 + (f / fc).powi(2 * poles)).log10()
+    }
+
+    /// Pulsing sawtooth, harmonics up to 14 kHz, all following one envelope
+    fn pulsing_saw(secs: f64) -> Vec<f64> {
+        (0..(RATE as f64 * secs) as usize)
+            .map(|n| {
+                let t = n as f64 / RATE as f64;
+                let envelope = 0.05 + (-(t % 0.5) / 0.1).exp();
+                let saw: f64 = (1..=63)
+                    .map(|h| (2.0 * std::f64::consts::PI * 220.0 * h as f64 * t).sin() / h as f64)
+                    .sum();
+                0.2 * envelope * saw
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_steepest_fall() {
+        let synth = band_levels(&spectrum(|f| ladder_db(f, 12000.0, 4)), BIN_HZ, 22050.0);
+        let (_, slope) = steepest_fall(&synth).unwrap();
+        assert!(slope > 12.0 && slope < MAX_FILTER_SLOPE, "Got {}", slope);
+
+        let codec = band_levels(
+            &spectrum(|f| if f < 16000.0 { 0.0 } else { -80.0 }),
+            BIN_HZ,
+            22050.0,
+        );
+        let (freq, slope) = steepest_fall(&codec).unwrap();
+        assert!(slope > 200.0, "Got {}", slope);
+        assert!((freq - 16000.0).abs() < 1000.0, "Got {}", freq);
+    }
+
+    #[test]
+    fn test_measure_frames() {
+        let saw = pulsing_saw(3.0);
+        let (harmonicity, tracking) = measure_frames(&saw, RATE, (8000.0, 13000.0));
+        assert!(harmonicity > 0.8, "Got {}", harmonicity);
+        assert!(tracking.unwrap() > 0.9, "Got {:?}", tracking);
+
+        // Noise has no tonal peaks
+        let noise = TestNoise::new(1).samples(RATE as usize * 3, 0.5);
+        let (harmonicity, _) = measure_frames(&noise, RATE, (8000.0, 13000.0));
+        assert!(harmonicity < 0.2, "Got {}", harmonicity);
+    }
+
+    #[test]
+    fn test_profile_modes() {
+        let saw = pulsing_saw(3.0);
+
+        let synth = profile(&spectrum(|f| ladder_db(f, 9000.0, 4)), BIN_HZ, &saw, RATE);
+        assert_eq!(
+            synth.mode,
+            ContentMode::BandLimitedProduction,
+            "Got {:?}",
+            synth
+        );
+        assert!(synth
+            .flag()
+            .starts_with("adaptive_content_mode(band_limited_production,slope="));
+
+        // Same content behind a codec lowpass
+        let codec = profile(
+            &spectrum(|f| if f < 16000.0 { 0.0 } else { -80.0 }),
+            BIN_HZ,
+            &saw,
+            RATE,
+        );
+        assert_eq!(codec.mode, ContentMode::Standard, "Got {:?}", codec);
+
+        // Full bandwidth: nothing to adapt to
+        let full = profile(&spectrum(|_| 0.0), BIN_HZ, &saw, RATE);
+        assert_eq!(full.mode, ContentMode::Standard, "Got {:?}", full);
+    }
+
+    #[test]
+    fn test_correlation() {
+        let rising: Vec<(f64, f64)> = (0..10).map(|i| (i as f64, 2.0 * i as f64 + 1.0)).collect();
+        assert!((correlation(&rising).unwrap() - 1.0).abs() < 1e-9);
+        let falling: Vec<(f64, f64)> = (0..10).map(|i| (i as f64, -(i as f64))).collect();
+        assert!((correlation(&falling).unwrap() + 1.0).abs() < 1e-9);
+        let flat: Vec<(f64, f64)> = (0..10).map(|i| (i as f64, 3.0)).collect();
+        assert!(correlation(&flat).is_none());
+    }
+}
@@ -0,0 +1,523 @@
+//! Model-based cutoff frequency estimation
+//!
+//! The per-window `detect_cutoff_frequency` in the spectral module reports the
+//! first bin that falls 20 dB below a reference band. That is cheap, but it is
+//! sensitive to the threshold and to noise in any single bin. This module fits
+//! a logistic step to the averaged high-frequency spectrum instead:
+//!
+//! ```text
+//!   level(f) = floor + (top - floor) / (1 + exp((f - fc) / w))
+//!
+//!   dB
+//!   top ─────────────╮
+//!                     ╲   ← width of the transition is set by w
+//!                      ╲
+//!   floor               ╰──────────
+//!                    fc
+//! ```
+//!
+//! For a fixed cutoff `fc` and scale `w` the model is linear in `top` and
+//! `floor`, so those are solved exactly by least squares. `fc` and `w` are grid
+//! searched, then `fc` is refined to sub-bin precision by parabolic
+//! interpolation of the error profile. The same profile gives an approximate
+//! 95% confidence interval for the cutoff.
+//!
+//! The fitted transition width classifies the edge: lossy encoders leave a
+//! brick wall a few bins wide, while tape and vinyl roll off over kilohertz.
+
+use crate::mp3::lame;
+use serde::Serialize;
+
+/// Edges shallower than this are not treated as a cutoff at all (dB)
+const MIN_EDGE_DEPTH_DB: f64 = 10.0;
+/// 10-90% transition at most this wide = brick wall (Hz)
+const BRICK_WALL_WIDTH_HZ: f64 = 500.0;
+/// 10-90% transition at most this wide = steep (Hz)
+const STEEP_WIDTH_HZ: f64 = 2000.0;
+/// Minimum fraction of variance explained for the fit to drive scoring
+const MIN_RELIABLE_R_SQUARED: f64 = 0.8;
+/// Widest confidence interval for the fit to drive scoring (Hz)
+const MAX_RELIABLE_INTERVAL_HZ: f64 = 1000.0;
+/// Furthest a fitted cutoff may be from a bitrate's typical lowpass to match it (Hz)
+const SOURCE_BITRATE_TOLERANCE_HZ: f64 = 750.0;
+/// Bitrates considered when mapping a cutoff back to a source bitrate (kbps)
+const SOURCE_BITRATES: [u32; 8] = [96, 112, 128, 160, 192, 224, 256, 320];
+/// Most edges `find_edges` will report
+const MAX_EDGES: usize = 4;
+/// Edges closer than this are the same edge (Hz)
+const MIN_EDGE_SEPARATION_HZ: f64 = 300.0;
+/// Span either side of a bin used to measure the level drop across it (Hz)
+const EDGE_SEARCH_HZ: f64 = 400.0;
+
+/// Number of transition scales tried per candidate cutoff
+const WIDTH_STEPS: usize = 16;
+/// Target number of points in the decimated coarse search
+const COARSE_POINTS: usize = 256;
+/// Minimum number of spectrum points needed to attempt a fit
+const MIN_FIT_POINTS: usize = 16;
+/// 10-90% width of a logistic in units of its scale: 2 * ln(9)
+const LOGISTIC_10_90: f64 = 4.394_449_154_672_439;
+/// Chi-squared (1 dof) value for a 95% interval
+const CHI2_95: f64 = 3.841;
+
+/// Shape of the high-frequency edge
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EdgeShape {
+    /// No meaningful edge in the analysed range
+    #[default]
+    None,
+    /// Wide rolloff - natural/analog source
+    Gradual,
+    /// Narrower than natural rolloff but not a wall
+    Steep,
+    /// Sharp lowpass - typical of lossy encoders
+    BrickWall,
+}
+
+/// Logistic fit of the high-frequency rolloff
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CutoffFit {
+    /// Interpolated cutoff - the midpoint of the fitted edge (Hz)
+    pub frequency: f64,
+    /// Lower bound of the ~95% confidence interval (Hz)
+    pub confidence_low: f64,
+    /// Upper bound of the ~95% confidence interval (Hz)
+    pub confidence_high: f64,
+    /// 10-90% transition width of the fitted edge (Hz)
+    pub transition_width: f64,
+    /// Level difference between passband and stopband (dB)
+    pub depth: f64,
+    /// Fraction of spectral variance explained by the model (0.0-1.0)
+    pub r_squared: f64,
+    /// Classification of the edge
+    pub shape: EdgeShape,
+    /// MP3 bitrate whose typical lowpass matches the cutoff (None if not a lossy edge)
+    pub estimated_source_bitrate: Option<u32>,
+}
+
+impl CutoffFit {
+    /// Whether the fit is trustworthy enough to base scoring on
+    pub fn is_reliable(&self) -> bool {
+        self.shape != EdgeShape::None
+            && self.r_squared >= MIN_RELIABLE_R_SQUARED
+            && self.confidence_high - self.confidence_low <= MAX_RELIABLE_INTERVAL_HZ
+    }
+}
+
+/// Least-squares fit of the logistic model for one (fc, w) pair
+#[derive(Clone, Copy)]
+struct Candidate {
+    sse: f64,
+    top: f64,
+    floor: f64,
+    width: f64,
+}
+
+/// Spectrum points in a fixed x grid (x in the caller's units)
+struct Points<'a> {
+    x: &'a [f64],
+    y: &'a [f64],
+    sst: f64,
+}
+
+impl Points<'_> {
+    /// Solve for top/floor at fixed (fc, w) and return the residual error
+    ///
+    /// With s = 1 / (1 + exp((x - fc) / w)) the model is
+    /// y = floor * (1 - s) + top * s, which is linear in (floor, top).
+    fn solve(&self, fc: f64, w: f64) -> Candidate {
+        let no_edge = Candidate {
+            sse: self.sst,
+            top: 0.0,
+            floor: 0.0,
+            width: w,
+        };
+
+        let (mut s_uu, mut s_us, mut s_ss, mut s_uy, mut s_sy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (&x, &y) in self.x.iter().zip(self.y) {
+            let s = logistic(x, fc, w);
+            let u = 1.0 - s;
+            s_uu += u * u;
+            s_us += u * s;
+            s_ss += s * s;
+            s_uy += u * y;
+            s_sy += s * y;
+        }
+
+        let det = s_uu * s_ss - s_us * s_us;
+        if det.abs() < 1e-9 {
+            return no_edge;
+        }
+        let floor = (s_uy * s_ss - s_sy * s_us) / det;
+        let top = (s_sy * s_uu - s_uy * s_us) / det;
+
+        // Only a falling edge counts as a cutoff
+        if top <= floor {
+            return no_edge;
+        }
+
+        let sse = self
+            .x
+            .iter()
+            .zip(self.y)
+            .map(|(&x, &y)| {
+                let s = logistic(x, fc, w);
+                (y - (floor * (1.0 - s) + top * s)).powi(2)
+            })
+            .sum();
+
+        Candidate { sse, top, floor, width: w }
+    }
+
+    /// Best fit at fixed fc over the width grid (the profile error at fc)
+    fn profile(&self, fc: f64, widths: &[f64]) -> Candidate {
+        widths
+            .iter()
+            .map(|&w| self.solve(fc, w))
+            .fold(None, |best: Option<Candidate>, c| match best {
+                Some(b) if b.sse <= c.sse => Some(b),
+                _ => Some(c),
+            })
+            .expect("width grid is never empty")
+    }
+}
+
+fn logistic(x: f64, fc: f64, w: f64) -> f64 {
+    1.0 / (1.0 + ((x - fc) / w).exp())
+}
+
+/// Geometric grid of transition scales from `min` to `max`
+fn width_grid(min: f64, max: f64) -> Vec<f64> {
+    let max = max.max(min);
+    let ratio = (max / min).powf(1.0 / (WIDTH_STEPS - 1) as f64);
+    (0..WIDTH_STEPS).map(|i| min * ratio.powi(i as i32)).collect()
+}
+
+/// Fit a logistic edge to an averaged magnitude spectrum
+///
+/// `spectrum_db[i]` is the level of bin `i` (frequency `i * bin_hz`). Only bins
+/// at or above `low_hz` are fitted. Returns None if there are too few points.
+pub fn fit_cutoff(spectrum_db: &[f64], bin_hz: f64, low_hz: f64) -> Option<CutoffFit> {
+    if bin_hz <= 0.0 {
+        return None;
+    }
+    let first = (low_hz / bin_hz).ceil().max(0.0) as usize;
+    if spectrum_db.len() < first + MIN_FIT_POINTS {
+        return None;
+    }
+
+    // Work in bin units so the grids are independent of sample rate / FFT size
+    let x: Vec<f64> = (first..spectrum_db.len()).map(|i| i as f64).collect();
+    let y = &spectrum_db[first..];
+    let n = y.len();
+    let mean = y.iter().sum::<f64>() / n as f64;
+    let sst: f64 = y.iter().map(|v| (v - mean).powi(2)).sum();
+    if sst <= 0.0 {
+        return None;
+    }
+    let points = Points { x: &x, y, sst };
+    let (x_min, x_max) = (x[0], x[n - 1]);
+
+    // Coarse search on a decimated spectrum
+    let step = (n / COARSE_POINTS).max(1);
+    let coarse_x: Vec<f64> = x.chunks(step).map(|c| c.iter().sum::<f64>() / c.len() as f64).collect();
+    let coarse_y: Vec<f64> = y.chunks(step).map(|c| c.iter().sum::<f64>() / c.len() as f64).collect();
+    let coarse_sst = {
+        let m = coarse_y.iter().sum::<f64>() / coarse_y.len() as f64;
+        coarse_y.iter().map(|v| (v - m).powi(2)).sum()
+    };
+    let coarse = Points {
+        x: &coarse_x,
+        y: &coarse_y,
+        sst: coarse_sst,
+    };
+    let widths = width_grid(0.25, n as f64 / 4.0);
+    let coarse_best = coarse_x
+        .iter()
+        .map(|&fc| (fc, coarse.profile(fc, &widths).sse))
+        .fold((x_min, f64::INFINITY), |best, c| if c.1 < best.1 { c } else { best })
+        .0;
+
+    // Fine search at full resolution, one bin at a time around the coarse minimum
+    let span = 2.0 * step as f64;
+    let lo = (coarse_best - span).max(x_min).round();
+    let hi = (coarse_best + span).min(x_max).round();
+    let (best_fc, best) = (lo as i64..=hi as i64)
+        .map(|k| (k as f64, points.profile(k as f64, &widths)))
+        .fold(None, |best: Option<(f64, Candidate)>, c| match best {
+            Some(b) if b.1.sse <= c.1.sse => Some(b),
+            _ => Some(c),
+        })?;
+
+    // Sub-bin refinement: vertex of the parabola through the neighbouring profile errors
+    let left = points.profile(best_fc - 1.0, &widths).sse;
+    let right = points.profile(best_fc + 1.0, &widths).sse;
+    let curvature = left - 2.0 * best.sse + right;
+    let offset = if curvature > 0.0 {
+        (0.5 * (left - right) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+    let fc = best_fc + offset;
+
+    // ~95% interval: where the profile error rises by chi2 * residual variance
+    let dof = n.saturating_sub(4).max(1) as f64;
+    let threshold = best.sse + CHI2_95 * best.sse / dof;
+    let bound = |direction: f64| -> f64 {
+        let limit = if direction < 0.0 { x_min } else { x_max };
+        let mut prev = (best_fc, best.sse);
+        let mut distance = 1.0;
+        loop {
+            let at = best_fc + direction * distance;
+            if (direction < 0.0 && at < limit) || (direction > 0.0 && at > limit) {
+                return limit;
+            }
+            let sse = points.profile(at, &widths).sse;
+            if sse > threshold {
+                // Linear interpolation of the crossing
+                let t = (threshold - prev.1) / (sse - prev.1);
+                return prev.0 + t * (at - prev.0);
+            }
+            prev = (at, sse);
+            // Bin steps near the minimum, coarser further out
+            distance += if distance < span { 1.0 } else { step as f64 };
+        }
+    };
+    let confidence_low = bound(-1.0).min(fc);
+    let confidence_high = bound(1.0).max(fc);
+
+    let depth = best.top - best.floor;
+    let transition_width = LOGISTIC_10_90 * best.width * bin_hz;
+    // An edge at the very end of the range is the analysis boundary, not a cutoff
+    let inside = fc > x_min + 1.0 && fc < x_max - 1.0;
+    let shape = if depth < MIN_EDGE_DEPTH_DB || !inside {
+        EdgeShape::None
+    } else if transition_width <= BRICK_WALL_WIDTH_HZ {
+        EdgeShape::BrickWall
+    } else if transition_width <= STEEP_WIDTH_HZ {
+        EdgeShape::Steep
+    } else {
+        EdgeShape::Gradual
+    };
+
+    let mut fit = CutoffFit {
+        frequency: fc * bin_hz,
+        confidence_low: confidence_low * bin_hz,
+        confidence_high: confidence_high * bin_hz,
+        transition_width,
+        depth,
+        r_squared: (1.0 - best.sse / sst).clamp(0.0, 1.0),
+        shape,
+        estimated_source_bitrate: None,
+    };
+    if matches!(shape, EdgeShape::BrickWall | EdgeShape::Steep) && fit.is_reliable() {
+        fit.estimated_source_bitrate = source_bitrate_for_cutoff(fit.frequency);
+    }
+    Some(fit)
+}
+
+/// Find every lossy-looking edge in the spectrum, lowest frequency first
+///
+/// Each re-encode through a lossy codec adds its own lowpass. When a later
+/// encoder has a higher lowpass than an earlier one, its quantisation noise
+/// fills the band between the two, leaving a staircase: a cliff at the first
+/// encoder's cutoff, a noise shelf, then a second cliff. A single logistic
+/// can't describe a staircase, so candidate cliffs are located from the level
+/// drop across each bin, then each one is fitted on its own neighbourhood.
+pub fn find_edges(spectrum_db: &[f64], bin_hz: f64, low_hz: f64) -> Vec<CutoffFit> {
+    if bin_hz <= 0.0 {
+        return Vec::new();
+    }
+    let first = (low_hz / bin_hz).ceil().max(0.0) as usize;
+    let half = ((EDGE_SEARCH_HZ / bin_hz).round() as usize).max(MIN_FIT_POINTS);
+    if spectrum_db.len() < first + 2 * half {
+        return Vec::new();
+    }
+
+    // Mean level just below minus just above each bin (prefix sums keep this linear)
+    let prefix: Vec<f64> = std::iter::once(0.0)
+        .chain(spectrum_db.iter().scan(0.0, |acc, v| {
+            *acc += v;
+            Some(*acc)
+        }))
+        .collect();
+    let mean = |lo: usize, hi: usize| (prefix[hi] - prefix[lo]) / (hi - lo) as f64;
+    let drops: Vec<(usize, f64)> = (first + half..spectrum_db.len() - half)
+        .map(|k| (k, mean(k - half, k) - mean(k, k + half)))
+        .collect();
+
+    // Peaks of the drop profile, deepest first, skipping anything too close to
+    // an earlier pick. Only peaks count: the shoulders of one wide, deep edge
+    // also drop by more than the minimum and would split it into pieces.
+    let separation = (MIN_EDGE_SEPARATION_HZ / bin_hz).ceil() as usize;
+    let mut candidates: Vec<(usize, f64)> = drops
+        .iter()
+        .enumerate()
+        .filter(|&(i, &(_, drop))| {
+            let neighbourhood = &drops[i.saturating_sub(separation)..(i + separation + 1).min(drops.len())];
+            drop >= MIN_EDGE_DEPTH_DB && neighbourhood.iter().all(|&(_, d)| d <= drop)
+        })
+        .map(|(_, &c)| c)
+        .collect();
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    let mut picked: Vec<usize> = Vec::new();
+    for (k, _) in candidates {
+        if picked.len() >= MAX_EDGES {
+            break;
+        }
+        if picked.iter().all(|&p| p.abs_diff(k) >= separation) {
+            picked.push(k);
+        }
+    }
+    picked.sort_unstable();
+
+    // Fit each candidate between the midpoints to its neighbours
+    let mut edges: Vec<CutoffFit> = Vec::new();
+    for (i, &k) in picked.iter().enumerate() {
+        let lo = match i {
+            0 => k.saturating_sub(2 * half).max(first),
+            _ => (picked[i - 1] + k) / 2,
+        };
+        let hi = match picked.get(i + 1) {
+            Some(&next) => (k + next) / 2,
+            None => (k + 2 * half).min(spectrum_db.len()),
+        };
+        let Some(fit) = fit_cutoff(&spectrum_db[..hi], bin_hz, lo as f64 * bin_hz) else {
+            continue;
+        };
+        let lossy = matches!(fit.shape, EdgeShape::BrickWall | EdgeShape::Steep);
+        let distinct = edges
+            .iter()
+            .all(|e| (e.frequency - fit.frequency).abs() >= MIN_EDGE_SEPARATION_HZ);
+        if lossy && fit.r_squared >= MIN_RELIABLE_R_SQUARED && distinct {
+            edges.push(fit);
+        }
+    }
+    edges
+}
+
+/// MP3 bitrate whose typical lowpass is closest to `cutoff_hz`, if any is close
+fn source_bitrate_for_cutoff(cutoff_hz: f64) -> Option<u32> {
+    SOURCE_BITRATES
+        .iter()
+        .map(|&br| (br, (lame::expected_lowpass_for_bitrate(br) as f64 - cutoff_hz).abs()))
+        .filter(|&(_, distance)| distance <= SOURCE_BITRATE_TOLERANCE_HZ)
+        .fold(None, |best: Option<(u32, f64)>, c| match best {
+            Some(b) if b.1 <= c.1 => Some(b),
+            _ => Some(c),
+        })
+        .map(|(br, _)| br)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: Fitting the Edge Instead of Thresholding It
+    // ==========================================================================
+    //
+    // A threshold detector asks "where is the first bin 20 dB down?". One
+    // noisy bin can move that answer by kilohertz. Fitting a model uses every
+    // bin in the range, so the estimate averages the noise away:
+    //
+    //   - frequency: logistic midpoint, interpolated between bins
+    //   - confidence interval: how far fc can move before the fit gets
+    //     significantly worse
+    //   - transition width: brick wall (lossy) vs gradual (analog)
+    //
+    // 44.1 kHz with an 8192-point FFT gives ~5.38 Hz per bin.
+    // ==========================================================================
+
+    const BIN_HZ: f64 = 44100.0 / 8192.0;
+
+    /// Synthetic averaged spectrum: flat passband, logistic edge, flat floor, small ripple
+    fn edge_spectrum(cutoff_hz: f64, width_hz: f64, depth: f64) -> Vec<f64> {
+        let w = width_hz / LOGISTIC_10_90;
+        (0..4096)
+            .map(|i| {
+                let f = i as f64 * BIN_HZ;
+                let ripple = ((i * 7919) % 13) as f64 / 13.0 - 0.5;
+                -20.0 - depth + depth / (1.0 + ((f - cutoff_hz) / w).exp()) + ripple
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fit_brick_wall_sub_bin() {
+        // 16 kHz cutoff that falls between bins (16000 / 5.38 = 2972.1)
+        let fit = fit_cutoff(&edge_spectrum(16000.0, 60.0, 60.0), BIN_HZ, 8000.0).unwrap();
+
+        assert_eq!(fit.shape, EdgeShape::BrickWall);
+        assert!((fit.frequency - 16000.0).abs() < BIN_HZ, "Got {}", fit.frequency);
+        assert!(fit.confidence_low <= fit.frequency && fit.frequency <= fit.confidence_high);
+        assert!(fit.confidence_high - fit.confidence_low < 100.0, "Brick wall should be tightly bounded");
+        assert!((fit.depth - 60.0).abs() < 2.0);
+        assert!(fit.r_squared > 0.99);
+        assert!(fit.is_reliable());
+        assert_eq!(fit.estimated_source_bitrate, Some(128));
+    }
+
+    #[test]
+    fn test_fit_gradual_rolloff() {
+        let fit = fit_cutoff(&edge_spectrum(15000.0, 5000.0, 30.0), BIN_HZ, 8000.0).unwrap();
+
+        assert_eq!(fit.shape, EdgeShape::Gradual);
+        assert!((fit.frequency - 15000.0).abs() < 200.0, "Got {}", fit.frequency);
+        assert!(fit.transition_width > STEEP_WIDTH_HZ);
+        assert_eq!(fit.estimated_source_bitrate, None, "Natural rolloff implies no source bitrate");
+    }
+
+    #[test]
+    fn test_fit_steep_edge() {
+        let fit = fit_cutoff(&edge_spectrum(19000.0, 1200.0, 40.0), BIN_HZ, 8000.0).unwrap();
+        assert_eq!(fit.shape, EdgeShape::Steep);
+    }
+
+    #[test]
+    fn test_fit_no_edge() {
+        // Flat spectrum with ripple only: nothing to fit
+        let flat: Vec<f64> = (0..4096).map(|i| -20.0 + ((i * 7919) % 13) as f64 / 13.0).collect();
+        let fit = fit_cutoff(&flat, BIN_HZ, 8000.0).unwrap();
+        assert_eq!(fit.shape, EdgeShape::None);
+        assert!(!fit.is_reliable());
+    }
+
+    #[test]
+    fn test_fit_too_few_points() {
+        assert!(fit_cutoff(&[0.0; 10], BIN_HZ, 0.0).is_none());
+        assert!(fit_cutoff(&[0.0; 4096], 0.0, 8000.0).is_none());
+    }
+
+    #[test]
+    fn test_find_edges_staircase() {
+        // 128k then 320k: cliff at 16 kHz onto a noise shelf, second cliff at 20 kHz
+        let first = edge_spectrum(16000.0, 60.0, 40.0);
+        let second = edge_spectrum(20000.0, 60.0, 40.0);
+        let stacked: Vec<f64> = first.iter().zip(&second).map(|(a, b)| a + b + 20.0).collect();
+
+        let edges = find_edges(&stacked, BIN_HZ, 8000.0);
+        assert_eq!(edges.len(), 2, "Both generations should be found: {:?}", edges);
+        assert!((edges[0].frequency - 16000.0).abs() < 50.0);
+        assert!((edges[1].frequency - 20000.0).abs() < 50.0);
+        assert_eq!(edges[0].estimated_source_bitrate, Some(128));
+    }
+
+    #[test]
+    fn test_find_edges_single_and_none() {
+        let single = find_edges(&edge_spectrum(16000.0, 60.0, 60.0), BIN_HZ, 8000.0);
+        assert_eq!(single.len(), 1);
+
+        // Natural rolloff isn't a lossy edge at all
+        assert!(find_edges(&edge_spectrum(15000.0, 5000.0, 30.0), BIN_HZ, 8000.0).is_empty());
+    }
+
+    #[test]
+    fn test_source_bitrate_for_cutoff() {
+        assert_eq!(source_bitrate_for_cutoff(16000.0), Some(128));
+        assert_eq!(source_bitrate_for_cutoff(18600.0), Some(192));
+        assert_eq!(source_bitrate_for_cutoff(20450.0), Some(320));
+        assert_eq!(source_bitrate_for_cutoff(22000.0), None, "Beyond any MP3 lowpass");
+    }
+}
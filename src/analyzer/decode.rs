@@ -0,0 +1,270 @@
+//! One decode of the whole file, shared by every sample-level check
+//!
+//! Spectral analysis, loudness, integrity, lossyWAV and HDCD all work on PCM.
+//! The file is decoded once into `DecodedAudio` and each check reads the part
+//! it needs:
+//!
+//! ```text
+//! Check        Reads
+//! spectral     first 15 s, per channel
+//! lossyWAV     first 30 s, as integers
+//! HDCD         first 30 s, as integers (16-bit only)
+//! loudness     everything
+//! integrity    everything, plus the packets that failed
+//! ```
+//!
+//! Samples are kept as f32, which holds integer PCM up to 24 bits exactly, so
+//! the integer checks get back the bits the file stored.
+
+use symphonia::core::audio::{Channels, SampleBuffer};
+use symphonia::core::codecs::{CodecType, DecoderOptions};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+const SAMPLE_RATE: u32 = 44100;
+/// Consecutive demuxer errors before giving up on the stream
+const MAX_CONSECUTIVE_STREAM_ERRORS: u32 = 16;
+/// Deepest integer samples f32 holds exactly
+const MAX_EXACT_BITS: u32 = 24;
+
+/// A packet that produced no audio
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PacketError {
+    /// The decoder rejected the packet (corrupt frame, CRC mismatch)
+    Decode {
+        /// Packet timestamp (seconds)
+        time: f64,
+        /// Frames the packet would have held
+        frames: u64,
+    },
+    /// The demuxer couldn't read a packet at all
+    Stream,
+}
+
+/// A failed packet and where it fell in the decoded audio
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LostPacket {
+    /// Frames decoded before it
+    pub frame: u64,
+    pub error: PacketError,
+}
+
+/// Every decoded sample of the default track
+#[derive(Debug, Clone)]
+pub struct DecodedAudio {
+    /// Interleaved samples
+    pub samples: Vec<f32>,
+    pub channels: usize,
+    /// Position of each channel (empty if the container doesn't say)
+    pub layout: Vec<Channels>,
+    pub sample_rate: u32,
+    /// Bit depth of integer codecs (None for lossy codecs, which decode to floats)
+    pub bits_per_sample: Option<u32>,
+    /// Width each sample is stored in, if wider than the bit depth
+    /// (e.g. 20-bit audio in 24-bit WAVE_FORMAT_EXTENSIBLE samples)
+    pub coded_bits_per_sample: Option<u32>,
+    pub codec: CodecType,
+    /// Frames the container declares, if it says
+    pub declared_frames: Option<u64>,
+    /// Frames per packet, if fixed (MP3: samples per frame)
+    pub frames_per_packet: Option<u64>,
+    /// Packets that failed, in stream order
+    pub lost_packets: Vec<LostPacket>,
+}
+
+impl DecodedAudio {
+    /// Decode the default track. Returns None if the file can't be opened or
+    /// holds no audio.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let mut format = probe(data)?;
+        let track = format.default_track()?;
+        let track_id = track.id;
+        let params = &track.codec_params;
+        let sample_rate = params.sample_rate.unwrap_or(SAMPLE_RATE);
+        let time_base = params.time_base;
+        let mut audio = DecodedAudio {
+            samples: Vec::new(),
+            channels: 0,
+            layout: Vec::new(),
+            sample_rate,
+            bits_per_sample: params.bits_per_sample,
+            coded_bits_per_sample: params.bits_per_coded_sample,
+            codec: params.codec,
+            declared_frames: params.n_frames,
+            frames_per_packet: params.max_frames_per_packet,
+            lost_packets: Vec::new(),
+        };
+
+        let mut decoder = symphonia::default::get_codecs()
+            .make(params, &DecoderOptions::default())
+            .ok()?;
+
+        let mut sample_buf: Option<SampleBuffer<f32>> = None;
+        let mut consecutive_stream_errors = 0;
+
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                // End of stream
+                Err(Error::IoError(_)) | Err(Error::ResetRequired) => break,
+                Err(_) => {
+                    audio.lose(PacketError::Stream);
+                    consecutive_stream_errors += 1;
+                    if consecutive_stream_errors >= MAX_CONSECUTIVE_STREAM_ERRORS {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            consecutive_stream_errors = 0;
+
+            if packet.track_id() != track_id {
+                continue;
+            }
+
+            let decoded = match decoder.decode(&packet) {
+                Ok(d) => d,
+                Err(_) => {
+                    let time = match time_base {
+                        Some(tb) => {
+                            let t = tb.calc_time(packet.ts());
+                            t.seconds as f64 + t.frac
+                        }
+                        None => packet.ts() as f64 / sample_rate as f64,
+                    };
+                    audio.lose(PacketError::Decode {
+                        time,
+                        frames: packet.dur(),
+                    });
+                    continue;
+                }
+            };
+
+            if sample_buf.is_none() {
+                let spec = *decoded.spec();
+                let duration = decoded.capacity() as u64;
+                sample_buf = Some(SampleBuffer::new(duration, spec));
+                audio.channels = spec.channels.count().max(1);
+                audio.layout = spec.channels.iter().collect();
+            }
+
+            if let Some(buf) = sample_buf.as_mut() {
+                buf.copy_interleaved_ref(decoded);
+                audio.samples.extend_from_slice(buf.samples());
+            }
+        }
+
+        (audio.channels > 0).then_some(audio)
+    }
+
+    fn lose(&mut self, error: PacketError) {
+        let frame = self.frames() as u64;
+        self.lost_packets.push(LostPacket { frame, error });
+    }
+
+    /// Playing time of the decoded audio (seconds)
+    pub fn duration_secs(&self) -> f64 {
+        self.frames() as f64 / self.sample_rate.max(1) as f64
+    }
+
+    /// Playing time the container declares, read without decoding
+    pub fn declared_duration(data: &[u8]) -> Option<f64> {
+        let format = probe(data)?;
+        let params = &format.default_track()?.codec_params;
+        Some(params.n_frames? as f64 / params.sample_rate? as f64)
+    }
+
+    /// Frames decoded (samples per channel)
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1)
+    }
+
+    /// Interleaved samples of the first `secs` seconds
+    pub fn head(&self, secs: usize) -> &[f32] {
+        let frames = self.frames().min(self.sample_rate as usize * secs);
+        &self.samples[..frames * self.channels]
+    }
+
+    /// Whether the samples are integers the file stored, rather than a lossy
+    /// decoder's output
+    pub fn is_integer_pcm(&self) -> bool {
+        self.bits_per_sample.is_some()
+    }
+
+    /// Integer samples of the first `secs` seconds, left-justified in i32 as
+    /// integer decoders hand them out. None for lossy codecs, and for depths
+    /// f32 can't hold exactly.
+    pub fn integer_head(&self, secs: usize) -> Option<Vec<i32>> {
+        if self.bits_per_sample? > MAX_EXACT_BITS {
+            return None;
+        }
+        let scale = -(i32::MIN as f64);
+        Some(
+            self.head(secs)
+                .iter()
+                .map(|&s| (s as f64 * scale).clamp(i32::MIN as f64, i32::MAX as f64) as i32)
+                .collect(),
+        )
+    }
+}
+
+/// Open the container, letting symphonia detect the format
+fn probe(data: &[u8]) -> Option<Box<dyn FormatReader>> {
+    let cursor = std::io::Cursor::new(data.to_vec());
+    let mss = MediaSourceStream::new(Box::new(cursor), Default::default());
+    symphonia::default::get_probe()
+        .format(&Hint::new(), mss, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()
+        .map(|probed| probed.format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_wav::pcm16;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: ONE DECODE, MANY READERS
+    // ==========================================================================
+    //
+    // Decoding is the slow part of looking at samples: a FLAC or MP3 has to be
+    // unpacked frame by frame before anything can be measured. Every check
+    // that needs PCM reads it from the same decode, each taking as much of
+    // it as it needs.
+    //
+    // The integer checks (lossyWAV's zeroed bits, HDCD's LSB channel) need the
+    // exact stored bits. Decoders scale integers into [-1, 1) by a power of
+    // two, which f32 undoes exactly for anything up to 24 bits.
+    // ==========================================================================
+
+    #[test]
+    fn test_decode_wav() {
+        let samples: Vec<i16> = (0..8000).map(|n| (n * 7 - 28000) as i16).collect();
+        let audio = DecodedAudio::decode(&pcm16(2, &samples, 8000)).unwrap();
+        assert_eq!((audio.channels, audio.frames(), audio.sample_rate), (2, 4000, 8000));
+        assert_eq!(audio.duration_secs(), 0.5);
+        assert_eq!(DecodedAudio::declared_duration(&pcm16(2, &samples, 8000)), Some(0.5));
+        assert!(audio.is_integer_pcm());
+        assert!(audio.lost_packets.is_empty());
+        assert_eq!(audio.head(0).len(), 0);
+        assert_eq!(audio.head(60).len(), 8000);
+    }
+
+    #[test]
+    fn test_integer_samples_exact() {
+        // Every 16-bit value comes back bit for bit, left-justified
+        let samples: Vec<i16> = (i16::MIN..=i16::MAX).collect();
+        let audio = DecodedAudio::decode(&pcm16(2, &samples, 44100)).unwrap();
+        let integers = audio.integer_head(60).unwrap();
+        assert_eq!(integers.len(), samples.len());
+        assert!(integers.iter().zip(&samples).all(|(&i, &s)| i == (s as i32) << 16));
+    }
+
+    #[test]
+    fn test_not_audio() {
+        assert!(DecodedAudio::decode(&[0x42; 4096]).is_none());
+    }
+}
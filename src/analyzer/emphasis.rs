@@ -0,0 +1,431 @@
+//! CD pre-emphasis detection
+//!
+//! Some early CDs (mostly 1983-1990) were mastered with pre-emphasis: a
+//! 50/15 µs treble shelf boosting everything above ~3 kHz by up to 10 dB, to
+//! be undone by a matching de-emphasis filter in the player. The disc's
+//! table of contents sets a `PRE` flag per track so players know to do it.
+//!
+//! Rips often lose that flag. The audio then plays ~10 dB too bright, and the
+//! boosted upper bands hide the drop a lossy cutoff would otherwise show.
+//!
+//! ```text
+//! Gain (dB)
+//!  10 ┤                      ╭───────────   15 µs pole (10.6 kHz)
+//!     │                  ╭───╯
+//!   5 ┤              ╭───╯
+//!     │          ╭───╯  50 µs zero (3.2 kHz)
+//!   0 ┼──────────╯
+//!     └──────┬───────┬───────┬──────── f
+//!          1k      4k     16k
+//! ```
+//!
+//! Emphasis is taken from a cue sheet when one says so (a `.cue` next to a
+//! lossless file, or the CUESHEET block inside a FLAC). Otherwise the long-term
+//! spectrum is fitted with a straight tilt plus a scaled copy of the shelf;
+//! the scale is the emphasis strength (1.0 = exactly the CD curve).
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Emphasis zero time constant (seconds)
+const TAU_ZERO: f64 = 50e-6;
+/// Emphasis pole time constant (seconds)
+const TAU_POLE: f64 = 15e-6;
+/// Fit range: below this the shelf is flat and music varies too much
+const FIT_LOW_HZ: f64 = 500.0;
+/// Fit range upper limit (well above the pole, below typical lossy cutoffs)
+const FIT_HIGH_HZ: f64 = 15000.0;
+/// Shortest fit range that still sees the shelf rise
+const MIN_FIT_HIGH_HZ: f64 = 8000.0;
+/// Fit points per octave
+const BANDS_PER_OCTAVE: f64 = 3.0;
+/// Strength range that counts as emphasised audio (ripple in real spectra,
+/// e.g. the comb of a mono mix of offset channels, fits up to ~0.6 on its own)
+const MIN_STRENGTH: f64 = 0.7;
+const MAX_STRENGTH: f64 = 1.6;
+
+/// Where the emphasis finding came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmphasisSource {
+    /// Shelf-shaped treble boost in the spectrum
+    Spectral,
+    /// `FLAGS PRE` in a cue sheet next to the file
+    CueSheet,
+    /// Pre-emphasis bit in the FLAC's embedded CUESHEET block
+    FlacCuesheet,
+}
+
+impl EmphasisSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmphasisSource::Spectral => "spectral",
+            EmphasisSource::CueSheet => "cue",
+            EmphasisSource::FlacCuesheet => "flac_cuesheet",
+        }
+    }
+}
+
+/// Pre-emphasis found in a file
+#[derive(Debug, Clone, Serialize)]
+pub struct EmphasisEstimate {
+    pub source: EmphasisSource,
+    /// Fitted shelf scale (1.0 = the 50/15 µs curve), if the spectrum allowed a fit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strength: Option<f64>,
+}
+
+impl EmphasisEstimate {
+    /// Combine a declared source with the spectral fit
+    ///
+    /// A cue sheet is trusted as is; the spectrum alone needs a strength in
+    /// the plausible range.
+    pub fn detect(declared: Option<EmphasisSource>, strength: Option<f64>) -> Option<Self> {
+        match declared {
+            Some(source) => Some(Self { source, strength }),
+            None => strength
+                .filter(|s| (MIN_STRENGTH..=MAX_STRENGTH).contains(s))
+                .map(|s| Self {
+                    source: EmphasisSource::Spectral,
+                    strength: Some(s),
+                }),
+        }
+    }
+
+    /// Flag with its evidence, e.g. `pre_emphasis(spectral,strength=0.94)`
+    pub fn flag(&self) -> String {
+        match self.strength {
+            Some(strength) => format!(
+                "pre_emphasis({},strength={:.2})",
+                self.source.as_str(),
+                strength
+            ),
+            None => format!("pre_emphasis({})", self.source.as_str()),
+        }
+    }
+}
+
+/// Gain of the 50/15 µs emphasis curve at `freq` (dB, 0 to ~10.5)
+pub fn gain_db(freq: f64) -> f64 {
+    let w = 2.0 * std::f64::consts::PI * freq;
+    10.0 * (1.0 + (w * TAU_ZERO).powi(2)).log10() - 10.0 * (1.0 + (w * TAU_POLE).powi(2)).log10()
+}
+
+/// Fit `level = a + b·log2(f) + strength·gain_db(f)` to the average spectrum
+///
+/// `spectrum_db` is the long-term average level per FFT bin. `high_hz` caps
+/// the fit below any cutoff. Returns None if the range is too short to see
+/// the shelf.
+pub fn fit_strength(spectrum_db: &[f64], bin_hz: f64, high_hz: f64) -> Option<f64> {
+    let high_hz = high_hz.min(FIT_HIGH_HZ);
+    if high_hz < MIN_FIT_HIGH_HZ || bin_hz <= 0.0 {
+        return None;
+    }
+
+    // Third-octave band averages, so the many high bins don't outvote the low ones
+    let half_band = 2f64.powf(0.5 / BANDS_PER_OCTAVE);
+    let mut points = Vec::new();
+    let mut centre = FIT_LOW_HZ;
+    while centre * half_band <= high_hz {
+        let low = ((centre / half_band) / bin_hz).ceil() as usize;
+        let high = (((centre * half_band) / bin_hz).floor() as usize).min(spectrum_db.len());
+        if high > low {
+            let level = spectrum_db[low..high].iter().sum::<f64>() / (high - low) as f64;
+            points.push((centre.log2(), gain_db(centre), level));
+        }
+        centre *= half_band * half_band;
+    }
+    if points.len() < 6 {
+        return None;
+    }
+
+    // Least squares on the normal equations for (a, b, strength)
+    let mut ata = [[0.0; 3]; 3];
+    let mut aty = [0.0; 3];
+    for &(x, e, y) in &points {
+        let row = [1.0, x, e];
+        for i in 0..3 {
+            for j in 0..3 {
+                ata[i][j] += row[i] * row[j];
+            }
+            aty[i] += row[i] * y;
+        }
+    }
+    let det = det3(&ata);
+    if det.abs() < 1e-9 {
+        return None;
+    }
+    // Cramer's rule for the third unknown
+    let mut with_y = ata;
+    for (row, &y) in with_y.iter_mut().zip(&aty) {
+        row[2] = y;
+    }
+    Some(det3(&with_y) / det)
+}
+
+fn det3(m: &[[f64; 3]; 3]) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+// ============================================================================
+// CUE SHEETS
+// ============================================================================
+
+/// Cue sheets read per directory, so a folder of tracks reads its cues once
+#[derive(Debug, Default)]
+pub struct CueSheetCache {
+    dirs: Mutex<HashMap<PathBuf, Arc<Vec<CueSheet>>>>,
+}
+
+impl CueSheetCache {
+    /// Whether a cue sheet next to `path` flags any of its tracks `PRE`
+    ///
+    /// Checks `<stem>.cue`, then every other `.cue` in the directory that
+    /// names the file.
+    pub fn declares_emphasis(&self, path: &Path) -> bool {
+        let (Some(stem), Some(file_name)) = (path.file_stem(), path.file_name()) else {
+            return false;
+        };
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let sheets = self.sheets(dir);
+
+        // Same stem: it's this file's cue even if FILE names the original .wav
+        if let Some(own) = sheets.iter().find(|sheet| sheet.stem.as_os_str() == stem) {
+            return own.any_pre;
+        }
+        let file_stem = cue_stem(&file_name.to_string_lossy());
+        sheets.iter().any(|sheet| sheet.pre_files.contains(&file_stem))
+    }
+
+    fn sheets(&self, dir: &Path) -> Arc<Vec<CueSheet>> {
+        let mut dirs = self.dirs.lock().unwrap_or_else(|e| e.into_inner());
+        dirs.entry(dir.to_path_buf())
+            .or_insert_with(|| Arc::new(read_cue_sheets(dir)))
+            .clone()
+    }
+}
+
+/// The PRE flags of one `.cue`
+#[derive(Debug)]
+struct CueSheet {
+    /// The cue's own file stem
+    stem: PathBuf,
+    /// Any track flagged PRE
+    any_pre: bool,
+    /// Stems (lowercase) of the FILE entries holding a PRE track
+    pre_files: Vec<String>,
+}
+
+impl CueSheet {
+    /// Tracks belong to the last `FILE` line above them
+    fn parse(stem: PathBuf, text: &str) -> Self {
+        let mut sheet = CueSheet {
+            stem,
+            any_pre: false,
+            pre_files: Vec::new(),
+        };
+        let mut file: Option<String> = None;
+        for line in text.lines() {
+            let line = line.trim();
+            let keyword = line
+                .split_whitespace()
+                .next()
+                .unwrap_or("")
+                .to_ascii_uppercase();
+            match keyword.as_str() {
+                "FILE" => {
+                    // FILE "name with spaces.wav" WAVE
+                    let name = match (line.find('"'), line.rfind('"')) {
+                        (Some(start), Some(end)) if end > start => &line[start + 1..end],
+                        _ => line.split_whitespace().nth(1).unwrap_or(""),
+                    };
+                    file = Some(cue_stem(name));
+                }
+                "FLAGS"
+                    if line
+                        .split_whitespace()
+                        .skip(1)
+                        .any(|f| f.eq_ignore_ascii_case("PRE")) =>
+                {
+                    sheet.any_pre = true;
+                    if let Some(file) = &file {
+                        if !sheet.pre_files.contains(file) {
+                            sheet.pre_files.push(file.clone());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        sheet
+    }
+}
+
+/// Every `.cue` in `dir`
+fn read_cue_sheets(dir: &Path) -> Vec<CueSheet> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e.eq_ignore_ascii_case("cue")))
+        .filter_map(|p| {
+            let text = std::fs::read_to_string(&p).ok()?;
+            let stem = PathBuf::from(p.file_stem()?);
+            Some(CueSheet::parse(stem, &text))
+        })
+        .collect()
+}
+
+/// File names are matched by stem, so a cue written for `album.wav` still
+/// covers `album.flac`
+fn cue_stem(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    name.rsplit_once('.')
+        .map_or(name, |(stem, _)| stem)
+        .to_ascii_lowercase()
+}
+
+/// Whether a cue sheet flags `PRE` on a track of `file_name` (any file if None)
+pub fn cue_text_declares_emphasis(text: &str, file_name: Option<&str>) -> bool {
+    let sheet = CueSheet::parse(PathBuf::new(), text);
+    match file_name {
+        Some(name) => sheet.pre_files.contains(&cue_stem(name)),
+        None => sheet.any_pre,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: Why pre-emphasis existed
+    // ==========================================================================
+    //
+    // Early 14/16-bit converters had audible high-frequency noise. Boosting
+    // the treble before recording and cutting it by the same amount on
+    // playback cuts that noise too - the same trick as Dolby on tape or the
+    // RIAA curve on vinyl.
+    //
+    // The curve is one zero and one pole:
+    //
+    //   H(f) = (1 + j·2πf·50µs) / (1 + j·2πf·15µs)
+    //
+    // flat at 0 dB in the bass, +10.46 dB (= 20·log10(50/15)) in the treble.
+    // ==========================================================================
+
+    #[test]
+    fn test_gain_curve() {
+        assert!(gain_db(100.0) < 0.1);
+        assert!((gain_db(1.0e6) - 20.0 * (50.0f64 / 15.0).log10()).abs() < 0.01);
+        // Halfway up the shelf between the zero and pole frequencies
+        let mid = gain_db(5800.0);
+        assert!(mid > 4.0 && mid < 7.0, "{}", mid);
+    }
+
+    /// Average spectrum with a tilt (dB per octave) and an emphasis shelf
+    fn test_spectrum(bin_hz: f64, bins: usize, tilt: f64, strength: f64) -> Vec<f64> {
+        (0..bins)
+            .map(|i| {
+                let f = (i as f64 * bin_hz).max(1.0);
+                -20.0 + tilt * (f / 1000.0).log2() + strength * gain_db(f)
+                    // Music isn't smooth: some ripple on top
+                    + (f / 700.0).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fit_strength() {
+        let bin_hz = 44100.0 / 8192.0;
+        for &(tilt, strength) in &[
+            (0.0, 1.0),
+            (-4.0, 1.0),
+            (-4.0, 0.0),
+            (-6.0, 0.0),
+            (-2.0, 0.5),
+        ] {
+            let spectrum = test_spectrum(bin_hz, 4097, tilt, strength);
+            let fitted = fit_strength(&spectrum, bin_hz, 20000.0).unwrap();
+            assert!(
+                (fitted - strength).abs() < 0.25,
+                "tilt {} strength {}: {}",
+                tilt,
+                strength,
+                fitted
+            );
+        }
+        // A cutoff at 6 kHz leaves too little of the shelf to fit
+        let spectrum = test_spectrum(bin_hz, 4097, 0.0, 1.0);
+        assert!(fit_strength(&spectrum, bin_hz, 6000.0).is_none());
+    }
+
+    #[test]
+    fn test_detect() {
+        assert!(EmphasisEstimate::detect(None, Some(0.2)).is_none());
+        assert!(EmphasisEstimate::detect(None, Some(3.0)).is_none());
+        let spectral = EmphasisEstimate::detect(None, Some(0.94)).unwrap();
+        assert_eq!(spectral.flag(), "pre_emphasis(spectral,strength=0.94)");
+
+        // A cue sheet is believed even when the spectrum disagrees
+        let cue = EmphasisEstimate::detect(Some(EmphasisSource::CueSheet), None).unwrap();
+        assert_eq!(cue.flag(), "pre_emphasis(cue)");
+    }
+
+    const TEST_CUE: &str = r#"REM GENRE Rock
+PERFORMER "Some Band"
+FILE "Some Band - Album.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "One"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    FLAGS DCP PRE
+    INDEX 01 04:12:50
+FILE "Bonus.wav" WAVE
+  TRACK 03 AUDIO
+    INDEX 01 00:00:00
+"#;
+
+    #[test]
+    fn test_cue_pre_flag() {
+        assert!(cue_text_declares_emphasis(TEST_CUE, None));
+        // Matched by stem, so the .flac rip of the .wav image counts
+        assert!(cue_text_declares_emphasis(
+            TEST_CUE,
+            Some("Some Band - Album.flac")
+        ));
+        assert!(!cue_text_declares_emphasis(TEST_CUE, Some("Bonus.flac")));
+        assert!(!cue_text_declares_emphasis(TEST_CUE, Some("Other.flac")));
+        assert!(!cue_text_declares_emphasis(
+            "FILE \"a.wav\" WAVE\n  TRACK 01 AUDIO\n    FLAGS DCP\n",
+            None
+        ));
+    }
+
+    #[test]
+    fn test_cue_file_next_to_audio() {
+        let dir = std::env::temp_dir().join(format!("losselot_cue_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let audio = dir.join("Some Band - Album.flac");
+        std::fs::write(dir.join("image.cue"), TEST_CUE).unwrap();
+        let cues = CueSheetCache::default();
+        assert!(cues.declares_emphasis(&audio));
+        assert!(!cues.declares_emphasis(&dir.join("Bonus.flac")));
+
+        // The directory was read once; a cue added later isn't seen
+        std::fs::write(dir.join("Bonus.cue"), TEST_CUE).unwrap();
+        assert!(!cues.declares_emphasis(&dir.join("Bonus.flac")));
+        assert!(CueSheetCache::default().declares_emphasis(&dir.join("Bonus.flac")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,292 @@
+//! Estimate how many lossy generations a file went through
+//!
+//! Text signatures (see `EncoderSignatures::encoding_chain_description`) only
+//! describe chains whose encoders left their names behind. This module looks
+//! for traces each generation leaves in the audio itself:
+//!
+//! 1. **Stacked cutoffs**: every lossy encode applies its own lowpass. A
+//!    "320 from 192 from 128" chain shows a staircase - a cliff at ~16 kHz,
+//!    a shelf of later quantisation noise, a cliff at ~18.5 kHz, and so on.
+//!
+//! 2. **Block grid**: MP3 codes audio in 576-sample granules. Quantisation
+//!    changes from one granule to the next, so the high-frequency envelope
+//!    crossfades on a 576-sample grid. A grid shows the audio went through at
+//!    least one MP3 encode even when no cutoff is visible.
+//!
+//! ```text
+//! Fit error of one-fade-per-granule, by trial offset:
+//!
+//!   ────╮        ╭─────
+//!        ╲      ╱
+//!         ╰────╯
+//!   0     offset     576
+//! ```
+//!
+//! Each crossfade spans a whole granule, so the envelope only repeats at the
+//! granule rate itself. Grids from two encodes at different offsets add up to
+//! one grid at an offset in between, with one dip in the curve above. The grid
+//! therefore shows that there was at least one MP3 encode, not how many.
+//!
+//! The count is a lower bound. A downward chain (320 from 128) only shows the
+//! lowest cutoff, since the later encoder had nothing left to cut.
+//!
+//! # Not measured
+//!
+//! - **A second block grid**: for the reason above. Separating two grids would
+//!   mean finding each encoder's quantisation in the MDCT domain.
+//! - **Accumulated pre-echo**: each encode smears noise ahead of transients,
+//!   but how much depends on the transients in the music and the encoder's
+//!   block switching. There is no reference level to count generations from.
+
+use super::cutoff::{self, CutoffFit};
+use serde::Serialize;
+
+/// MP3 granule length in samples
+const GRANULE: usize = 576;
+/// Envelope block length in samples (divides GRANULE)
+const GRID_BLOCK: usize = 16;
+/// Phase bins per granule
+const GRID_BINS: usize = GRANULE / GRID_BLOCK;
+/// Fewest granules worth folding
+const MIN_GRID_GRANULES: usize = 64;
+/// Fit at the grid offset must beat the median offset by this fraction
+/// (white noise stays within about ±0.5%)
+const MIN_GRID_CONTRAST: f64 = 0.015;
+/// Edges closer than this are the same edge (Hz)
+const SAME_EDGE_HZ: f64 = 300.0;
+
+/// Estimated lossy encoding chain
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GenerationEstimate {
+    /// Lossy encodes found: one per cutoff, or one for a block grid alone
+    /// (0 = no lossy traces found). Earlier encodes can hide, so it's a minimum
+    pub generations: u32,
+    /// Edge left by each generation, lowest cutoff (earliest encode) first
+    pub cutoffs: Vec<CutoffFit>,
+    /// Position within the 576-sample MP3 granule of the block grid (None if no grid)
+    pub grid_offset: Option<usize>,
+    /// How much better the grid offset fits than a typical offset (0.0 = no grid)
+    pub grid_strength: f64,
+}
+
+impl GenerationEstimate {
+    /// Short chain description, e.g. "16.0k→18.5k→20.0k"
+    pub fn chain_description(&self) -> String {
+        self.cutoffs
+            .iter()
+            .map(|c| format!("{:.1}k", c.frequency / 1000.0))
+            .collect::<Vec<_>>()
+            .join("→")
+    }
+
+    /// Stop counting an edge that turned out not to come from a lossy encode
+    pub fn discount_edge(&mut self, frequency: f64) {
+        self.cutoffs.retain(|c| (c.frequency - frequency).abs() >= SAME_EDGE_HZ);
+        self.generations = self.cutoffs.len().max(self.grid_offset.is_some() as usize) as u32;
+    }
+}
+
+/// Estimate the lossy generations from an averaged spectrum (dB per bin,
+/// `bin_hz` apart) and the mono samples it was measured from
+pub fn estimate(spectrum_db: &[f64], bin_hz: f64, low_hz: f64, samples: &[f64]) -> GenerationEstimate {
+    let cutoffs = cutoff::find_edges(spectrum_db, bin_hz, low_hz);
+    let (grid_offset, grid_strength) = block_grid(samples);
+
+    GenerationEstimate {
+        generations: cutoffs.len().max(grid_offset.is_some() as usize) as u32,
+        cutoffs,
+        grid_offset,
+        grid_strength,
+    }
+}
+
+/// Find a granule-aligned block grid in the high-frequency envelope
+///
+/// Within one granule the envelope crossfades smoothly from the previous
+/// granule's level to the next (the MDCT window overlap). Cut the envelope into
+/// granule-length frames at a trial offset and fit each frame with that fade:
+/// at the grid's true offset every frame is one clean fade, anywhere else the
+/// frames straddle a granule boundary and fit worse.
+///
+/// Returns the grid offset (samples) and its contrast.
+fn block_grid(samples: &[f64]) -> (Option<usize>, f64) {
+    let blocks = samples.len() / GRID_BLOCK;
+    if blocks < (MIN_GRID_GRANULES + 1) * GRID_BINS {
+        return (None, 0.0);
+    }
+
+    // Log energy of the first difference (a crude high-pass) per block
+    let envelope: Vec<f64> = (0..blocks)
+        .map(|b| {
+            let start = (b * GRID_BLOCK).max(1);
+            let end = (b + 1) * GRID_BLOCK;
+            let energy: f64 = (start..end).map(|n| (samples[n] - samples[n - 1]).powi(2)).sum();
+            (energy / GRID_BLOCK as f64 + 1e-12).ln()
+        })
+        .collect();
+
+    // Fade template across one granule, centred for least squares
+    let fade: Vec<f64> = (0..GRID_BINS)
+        .map(|k| ((k as f64 + 0.5) / GRID_BINS as f64 * std::f64::consts::FRAC_PI_2).sin().powi(2))
+        .collect();
+    let fade_mean = fade.iter().sum::<f64>() / GRID_BINS as f64;
+    let centred: Vec<f64> = fade.iter().map(|t| t - fade_mean).collect();
+    let fade_ss: f64 = centred.iter().map(|t| t * t).sum();
+
+    // Residual of the per-frame fit y = a + b * fade at each trial offset
+    let frames = blocks / GRID_BINS - 1;
+    let residuals: Vec<f64> = (0..GRID_BINS)
+        .map(|offset| {
+            (0..frames)
+                .map(|j| {
+                    let y = &envelope[offset + j * GRID_BINS..offset + (j + 1) * GRID_BINS];
+                    let y_mean = y.iter().sum::<f64>() / GRID_BINS as f64;
+                    let sxy: f64 = y.iter().zip(&centred).map(|(v, t)| (v - y_mean) * t).sum();
+                    let syy: f64 = y.iter().map(|v| (v - y_mean).powi(2)).sum();
+                    syy - sxy * sxy / fade_ss
+                })
+                .sum()
+        })
+        .collect();
+
+    // Contrast: fractional improvement over the typical offset
+    let mut sorted = residuals.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[GRID_BINS / 2];
+    if median <= 0.0 {
+        return (None, 0.0);
+    }
+    let (best, strength) = residuals
+        .iter()
+        .map(|r| 1.0 - r / median)
+        .enumerate()
+        .fold((0, f64::NEG_INFINITY), |best, c| if c.1 > best.1 { c } else { best });
+
+    if strength >= MIN_GRID_CONTRAST {
+        (Some(best * GRID_BLOCK), strength)
+    } else {
+        (None, strength.max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_noise::TestNoise;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: Counting Generations
+    // ==========================================================================
+    //
+    // Every lossy encode throws information away in its own way. Bought-in
+    // catalogue tracks are often "320 from 192 from 128": each step looks
+    // like a plausible 320k file on its own, but the damage accumulates.
+    //
+    // Two independent traces are counted:
+    //   - cutoffs: one spectral cliff per encoder whose lowpass still had
+    //     content to remove
+    //   - grids: the 576-sample block grid MP3 leaves in the envelope. It
+    //     shows there was at least one MP3 encode; grids from two encodes
+    //     at different offsets merge into one, so they aren't counted
+    // ==========================================================================
+
+    const BIN_HZ: f64 = 44100.0 / 8192.0;
+
+    fn noise(len: usize, seed: u64) -> Vec<f64> {
+        TestNoise::new(seed).samples(len, 0.5)
+    }
+
+    /// Level that switches every granule, crossfaded like MDCT overlap
+    fn granule_envelope(len: usize, offset: usize, seed: u64) -> Vec<f64> {
+        let levels = noise(len / GRANULE + 3, seed);
+        let level = |i: usize| 1.0 + 2.0 * levels[i].abs();
+        (0..len)
+            .map(|n| {
+                let pos = n + GRANULE - offset;
+                let (g, phase) = (pos / GRANULE, (pos % GRANULE) as f64 / GRANULE as f64);
+                let fade = (phase * std::f64::consts::FRAC_PI_2).sin().powi(2);
+                level(g) * (1.0 - fade) + level(g + 1) * fade
+            })
+            .collect()
+    }
+
+    /// Noise with one encode's granule envelope
+    fn granule_modulated(len: usize, offset: usize) -> Vec<f64> {
+        let envelope = granule_envelope(len, offset, 11);
+        noise(len, 7).iter().zip(&envelope).map(|(x, e)| x * e).collect()
+    }
+
+    fn staircase(edges: &[f64]) -> Vec<f64> {
+        (0..4096)
+            .map(|i| {
+                let f = i as f64 * BIN_HZ;
+                let ripple = ((i * 7919) % 13) as f64 / 13.0 - 0.5;
+                edges.iter().map(|&e| -40.0 / (1.0 + ((e - f) / 10.0).exp())).sum::<f64>() + ripple
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stacked_cutoffs_counted() {
+        let est = estimate(&staircase(&[16000.0, 18500.0, 20000.0]), BIN_HZ, 8000.0, &[]);
+
+        assert_eq!(est.generations, 3);
+        assert_eq!(est.chain_description(), "16.0k→18.5k→20.0k");
+        let bitrates: Vec<_> = est.cutoffs.iter().map(|c| c.estimated_source_bitrate).collect();
+        assert_eq!(bitrates, vec![Some(128), Some(192), Some(256)]);
+    }
+
+    #[test]
+    fn test_no_lossy_traces() {
+        let flat: Vec<f64> = (0..4096).map(|i| ((i * 7919) % 13) as f64 / 13.0).collect();
+        let est = estimate(&flat, BIN_HZ, 8000.0, &noise(44100 * 5, 3));
+
+        assert_eq!(est.generations, 0);
+        assert_eq!(est.grid_offset, None, "White noise has no block grid");
+        assert_eq!(est.chain_description(), "");
+    }
+
+    #[test]
+    fn test_block_grid_found() {
+        for offset in [100, 400] {
+            let (found, strength) = block_grid(&granule_modulated(44100 * 5, offset));
+            let found = found.expect("Grid should be detected");
+            assert!(found.abs_diff(offset) <= 2 * GRID_BLOCK, "Offset {} found at {}", offset, found);
+            assert!(strength >= MIN_GRID_CONTRAST);
+        }
+    }
+
+    #[test]
+    fn test_grid_alone_counts_one_generation() {
+        let flat: Vec<f64> = (0..4096).map(|i| ((i * 7919) % 13) as f64 / 13.0).collect();
+        let est = estimate(&flat, BIN_HZ, 8000.0, &granule_modulated(44100 * 5, 200));
+        assert!(est.cutoffs.is_empty());
+        assert_eq!(est.generations, 1);
+    }
+
+    #[test]
+    fn test_two_grids_count_once() {
+        // A second encode's grid at another offset merges with the first
+        let len = 44100 * 5;
+        let second = granule_envelope(len, 300, 13);
+        let both: Vec<f64> = granule_modulated(len, 100).iter().zip(&second).map(|(x, e)| x * e).collect();
+        let flat: Vec<f64> = (0..4096).map(|i| ((i * 7919) % 13) as f64 / 13.0).collect();
+        let est = estimate(&flat, BIN_HZ, 8000.0, &both);
+        assert!(est.grid_offset.is_some());
+        assert_eq!(est.generations, 1);
+    }
+
+    #[test]
+    fn test_discount_edge() {
+        let mut est = estimate(&staircase(&[16000.0, 21000.0]), BIN_HZ, 8000.0, &[]);
+        assert_eq!(est.generations, 2);
+        est.discount_edge(21050.0);
+        assert_eq!(est.generations, 1);
+        assert_eq!(est.chain_description(), "16.0k");
+    }
+
+    #[test]
+    fn test_block_grid_too_short() {
+        assert_eq!(block_grid(&noise(GRANULE * 10, 1)), (None, 0.0));
+    }
+}
@@ -0,0 +1,318 @@
+//! HDCD (High Definition Compatible Digital) detection
+//!
+//! HDCD discs are ordinary 16-bit CDs with a control channel hidden in the
+//! least significant bit. An HDCD decoder reads it to undo two tricks the
+//! encoder played on the audio:
+//!
+//! 1. **Peak extension**: the loudest peaks were soft-limited by up to ~9 dB,
+//!    to be expanded again on playback.
+//!
+//! 2. **Gain adjustment**: quiet passages were raised in 0.5 dB steps (up to
+//!    -7.5 dB to undo), trading noise floor for resolution.
+//!
+//! Played without a decoder the peaks stay compressed, which skews dynamics
+//! and loudness measurements. The restoration workflow wants to know before
+//! processing a rip.
+//!
+//! # The control channel
+//!
+//! The LSB of each channel is a bit stream, scrambled by XOR with itself
+//! delayed by 5 and 23 bits. Descrambled, a packet is a 32-bit sync word
+//! followed by the control byte:
+//!
+//! ```text
+//! A: 0x7E0FA005 [..pt gggg]              8-bit code (bits 3, 6, 7 zero)
+//! B: 0x7E0FA006 [..pt gggg] [~code]      8-bit code + inverted check byte
+//!
+//!    p = peak extension on, t = transient filter, gggg = gain adjust (0.5 dB steps)
+//! ```
+//!
+//! A random LSB matches a sync word once per 2^32 bits, so a handful of valid
+//! packets is conclusive.
+
+use super::decode::DecodedAudio;
+use serde::Serialize;
+
+/// Seconds of audio scanned for packets
+const MAX_SCAN_SECS: usize = 30;
+/// Valid packets needed to call a file HDCD
+const MIN_PACKETS: u64 = 4;
+/// Descrambled sync words; the low bits say how many code bits follow (8 or 16)
+const SYNC_A: u32 = 0x7E0F_A005;
+const SYNC_B: u32 = 0x7E0F_A006;
+/// Bits of the descrambled stream after the sync, once a packet's code is in
+const PACKET_A_MASK: u32 = 0x0FA0_0500;
+const PACKET_B_MASK: u32 = 0xA006_0000;
+const CONTROL_PEAK_EXTEND: u8 = 0x10;
+const CONTROL_TRANSIENT_FILTER: u8 = 0x20;
+const CONTROL_GAIN: u8 = 0x0F;
+
+/// HDCD control packets found in the LSBs
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HdcdDetails {
+    /// Valid control packets (all channels)
+    pub packets: u64,
+    /// Packets with peak extension on
+    pub peak_extend_packets: u64,
+    /// Packets with the transient filter on
+    pub transient_filter_packets: u64,
+    /// Packets with a non-zero gain adjustment
+    pub gain_adjust_packets: u64,
+    /// Strongest gain adjustment seen (dB, 0 to -7.5)
+    pub max_gain_adjust: f64,
+    /// Sync words whose code failed its check
+    pub bad_packets: u64,
+}
+
+impl HdcdDetails {
+    pub fn detected(&self) -> bool {
+        self.packets >= MIN_PACKETS
+    }
+
+    /// Flag naming the features in use, e.g. `hdcd(peak_extend,gain_adjust=-6.0dB)`
+    ///
+    /// `no_effect` means the disc carries packets but decoding wouldn't change
+    /// the audio.
+    pub fn flag(&self) -> String {
+        let mut features = Vec::new();
+        if self.peak_extend_packets > 0 {
+            features.push("peak_extend".to_string());
+        }
+        if self.gain_adjust_packets > 0 {
+            features.push(format!("gain_adjust={:.1}dB", self.max_gain_adjust));
+        }
+        if features.is_empty() {
+            features.push("no_effect".to_string());
+        }
+        format!("hdcd({})", features.join(","))
+    }
+
+    fn add_control(&mut self, control: u8) {
+        self.packets += 1;
+        if control & CONTROL_PEAK_EXTEND != 0 {
+            self.peak_extend_packets += 1;
+        }
+        if control & CONTROL_TRANSIENT_FILTER != 0 {
+            self.transient_filter_packets += 1;
+        }
+        let gain = control & CONTROL_GAIN;
+        if gain > 0 {
+            self.gain_adjust_packets += 1;
+            self.max_gain_adjust = self.max_gain_adjust.min(-(gain as f64) / 2.0);
+        }
+    }
+}
+
+/// Scan 16-bit PCM for HDCD packets
+///
+/// Returns None for anything but 16-bit integer audio, where HDCD can't exist.
+pub fn analyze(audio: &DecodedAudio) -> Option<HdcdDetails> {
+    if audio.bits_per_sample != Some(16) {
+        return None;
+    }
+    let samples = audio.integer_head(MAX_SCAN_SECS)?;
+    let mut scanner = HdcdScanner::new(audio.channels);
+    scanner.add_interleaved(&samples);
+    Some(scanner.details)
+}
+
+/// Packet reader for one channel's LSB stream
+#[derive(Default)]
+struct ChannelState {
+    /// Raw (scrambled) LSBs, newest in bit 0
+    window: u64,
+    /// Bits still to read before the pending code is complete
+    readahead: u32,
+    /// A sync word was seen and its code is being read
+    in_packet: bool,
+}
+
+impl ChannelState {
+    /// Shift in one LSB, recording the packet if this bit completes one
+    fn push(&mut self, bit: u32, details: &mut HdcdDetails) {
+        self.window = (self.window << 1) | bit as u64;
+        if self.readahead > 1 {
+            self.readahead -= 1;
+            return;
+        }
+        self.readahead = 0;
+
+        // Undo the scrambler: each bit is XORed with the ones 5 and 23 before it
+        let bits = (self.window ^ (self.window >> 5) ^ (self.window >> 23)) as u32;
+
+        if self.in_packet {
+            self.in_packet = false;
+            if bits & PACKET_A_MASK == PACKET_A_MASK {
+                // A: 3-bit gain, so bits 3, 6 and 7 must be clear; the gain
+                // counts double to land on the same 0.5 dB scale as B
+                if bits & 0xC8 == 0 {
+                    details.add_control(((bits & 0xFF) + (bits & 0x07)) as u8);
+                } else {
+                    details.bad_packets += 1;
+                }
+            } else if bits & PACKET_B_MASK == PACKET_B_MASK {
+                // B: code byte followed by its complement
+                if (bits ^ (!bits >> 8 & 0xFF)) & 0xFFFF_00FF == PACKET_B_MASK {
+                    details.add_control((bits >> 8 & 0xFF) as u8);
+                } else {
+                    details.bad_packets += 1;
+                }
+            }
+        }
+
+        if bits == SYNC_A || bits == SYNC_B {
+            self.readahead = (bits & 3) * 8;
+            self.in_packet = true;
+        }
+    }
+}
+
+/// Runs every channel's LSB stream through its own packet reader
+struct HdcdScanner {
+    channels: Vec<ChannelState>,
+    frames: usize,
+    details: HdcdDetails,
+}
+
+impl HdcdScanner {
+    fn new(channels: usize) -> Self {
+        Self {
+            channels: (0..channels.max(1))
+                .map(|_| ChannelState::default())
+                .collect(),
+            frames: 0,
+            details: HdcdDetails::default(),
+        }
+    }
+
+    /// Add interleaved 16-bit samples, left-justified in i32 as decoders produce them
+    fn add_interleaved(&mut self, samples: &[i32]) {
+        let count = self.channels.len();
+        for frame in samples.chunks_exact(count) {
+            for (state, &sample) in self.channels.iter_mut().zip(frame) {
+                state.push((sample >> 16) as u32 & 1, &mut self.details);
+            }
+            self.frames += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_noise::TestNoise;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: Hiding a control channel in the LSB
+    // ==========================================================================
+    //
+    // The lowest bit of a 16-bit sample is ~-96 dBFS: below audibility, and
+    // noise anyway. HDCD replaces it with its own data, a few packets per
+    // second, so a normal player hears nothing unusual.
+    //
+    // The data is scrambled so it stays noise-like:
+    //
+    //   sent[t] = data[t] ^ sent[t-5] ^ sent[t-23]
+    //
+    // A decoder XORs the same taps back out. The tests build streams the
+    // same way, so they don't need real HDCD audio.
+    // ==========================================================================
+
+    /// Scramble descrambled bits into the LSB stream an encoder would write
+    fn scramble(data: &[u32]) -> Vec<u32> {
+        let mut sent: Vec<u32> = Vec::with_capacity(data.len());
+        for (t, &bit) in data.iter().enumerate() {
+            let tap = |d: usize| if t >= d { sent[t - d] } else { 0 };
+            let value = bit ^ tap(5) ^ tap(23);
+            sent.push(value);
+        }
+        sent
+    }
+
+    fn push_bits(stream: &mut Vec<u32>, value: u32, bits: u32) {
+        stream.extend((0..bits).rev().map(|i| (value >> i) & 1));
+    }
+
+    /// Descrambled stream: filler zeros with one packet every `spacing` bits
+    fn packet_stream(packets: usize, spacing: usize, packet: impl Fn(&mut Vec<u32>)) -> Vec<u32> {
+        let mut stream = Vec::new();
+        for _ in 0..packets {
+            stream.extend(std::iter::repeat_n(0, spacing));
+            packet(&mut stream);
+        }
+        stream.extend(std::iter::repeat_n(0, spacing));
+        stream
+    }
+
+    /// Stereo samples with the same LSB stream in both channels over random audio
+    fn test_samples(lsbs: &[u32]) -> Vec<i32> {
+        let mut noise = TestNoise::new(7);
+        lsbs.iter()
+            .flat_map(|&bit| {
+                let mut sample = || {
+                    let value = ((noise.next_u64() >> 48) as i32 >> 2) & !1;
+                    (value | bit as i32) << 16
+                };
+                [sample(), sample()]
+            })
+            .collect()
+    }
+
+    fn scan(samples: &[i32]) -> HdcdDetails {
+        let mut scanner = HdcdScanner::new(2);
+        scanner.add_interleaved(samples);
+        scanner.details
+    }
+
+    #[test]
+    fn test_packet_b_peak_extend_and_gain() {
+        // Peak extension on, gain adjust 12 steps = -6.0 dB
+        let control = CONTROL_PEAK_EXTEND | 12;
+        let stream = packet_stream(10, 400, |s| {
+            push_bits(s, SYNC_B, 32);
+            push_bits(s, control as u32, 8);
+            push_bits(s, !control as u32 & 0xFF, 8);
+        });
+        let details = scan(&test_samples(&scramble(&stream)));
+
+        // Both channels carry the packets
+        assert_eq!(details.packets, 20);
+        assert_eq!(details.peak_extend_packets, 20);
+        assert_eq!(details.max_gain_adjust, -6.0);
+        assert!(details.detected());
+        assert_eq!(details.flag(), "hdcd(peak_extend,gain_adjust=-6.0dB)");
+    }
+
+    #[test]
+    fn test_packet_a_no_effect() {
+        let stream = packet_stream(6, 300, |s| {
+            push_bits(s, SYNC_A, 32);
+            push_bits(s, 0, 8);
+        });
+        let details = scan(&test_samples(&scramble(&stream)));
+        assert_eq!(details.packets, 12);
+        assert_eq!(details.flag(), "hdcd(no_effect)");
+    }
+
+    #[test]
+    fn test_packet_b_bad_check() {
+        let stream = packet_stream(6, 300, |s| {
+            push_bits(s, SYNC_B, 32);
+            push_bits(s, 0x15, 8);
+            push_bits(s, 0x15, 8); // should be the complement
+        });
+        let details = scan(&test_samples(&scramble(&stream)));
+        assert_eq!(details.packets, 0);
+        assert_eq!(details.bad_packets, 12);
+        assert!(!details.detected());
+    }
+
+    #[test]
+    fn test_random_lsb_not_hdcd() {
+        let mut noise = TestNoise::new(3);
+        let lsbs: Vec<u32> = (0..200_000).map(|_| (noise.next_u64() >> 40) as u32 & 1).collect();
+        let details = scan(&test_samples(&lsbs));
+        assert_eq!(details.packets, 0);
+        assert!(!details.detected());
+    }
+}
@@ -0,0 +1,594 @@
+//! Stream integrity: decode errors, truncation, dropouts and clicks
+//!
+//! The other decoders in losselot skip packets they can't decode, which is
+//! right for measuring a spectrum but hides damage. This module decodes the
+//! whole file and keeps track of everything that went wrong:
+//!
+//! 1. **Decode errors**: packets the decoder rejected (corrupt frames, CRC
+//!    mismatches) and packets the demuxer couldn't read at all.
+//!
+//! 2. **Truncation**: fewer frames decoded than the container declares, or
+//!    an MP3 whose last frame the frame walk found cut off. A CBR file with
+//!    no Xing/Info frame declares nothing, so only the walk can tell.
+//!
+//! 3. **Dropouts**: runs of exact digital zero in every channel, with audio
+//!    on both sides. Leading and trailing silence don't count.
+//!
+//! 4. **Clicks**: isolated spikes in the second difference of the waveform,
+//!    far above the block's own level of high-frequency activity.
+//!
+//! ```text
+//! dropout:              click:
+//!    ╱╲      ╱╲              │
+//!   ╱  ╲____╱  ╲         ╱╲  │ ╱╲
+//!  ╱   exact 0   ╲      ╱  ╲ │╱  ╲
+//! ```
+//!
+//! 5. **Sync losses**: junk between MP3 frames, from the frame walk. The
+//!    decoder skips it without an error, but the audio either side of it
+//!    doesn't join up.
+//!
+//! Decode errors and truncation mean the file is damaged; dropouts, clicks
+//! and sync losses are glitches that may or may not be in the source. None
+//! of them changes the transcode score.
+
+use super::decode::{DecodedAudio, PacketError};
+use crate::mp3::lame::LameHeader;
+use crate::mp3::stream::StreamStructure;
+use serde::Serialize;
+use symphonia::core::codecs::CODEC_TYPE_MP3;
+
+/// Shortest run of all-channel zeros counted as a dropout (seconds)
+const MIN_DROPOUT_SECS: f64 = 0.001;
+/// Longer runs of silence are intentional gaps, not dropouts (seconds)
+const MAX_DROPOUT_SECS: f64 = 2.0;
+/// Window either side of a dropout that must hold audio (seconds)
+const DROPOUT_CONTEXT_SECS: f64 = 0.01;
+/// Peak level the audio around a dropout must reach (-40 dBFS)
+const DROPOUT_CONTEXT_LEVEL: f32 = 0.01;
+/// Click detection block length in frames
+const CLICK_BLOCK: usize = 4096;
+/// A click's second difference exceeds the block RMS by this factor
+const CLICK_RATIO: f32 = 20.0;
+/// ...and this absolute level
+const CLICK_MIN_LEVEL: f32 = 0.05;
+/// Outlier frames closer than this belong to the same click
+const CLICK_MERGE_FRAMES: u64 = 4;
+/// Blocks with more outliers than this are sharp by nature (square waves, transients)
+const MAX_CLICKS_PER_BLOCK: usize = 2;
+/// Missing audio tolerated before the stream counts as truncated (seconds);
+/// covers encoder delay and padding that some readers count and others don't
+const TRUNCATION_TOLERANCE_SECS: f64 = 0.1;
+/// Events kept in the report (counts include all of them)
+const MAX_EVENTS: usize = 50;
+
+/// Integrity verdict, separate from the transcode verdict
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum IntegrityVerdict {
+    /// Decoded cleanly, no glitches found
+    #[default]
+    Ok,
+    /// Decoded cleanly, but with dropouts or clicks in the audio
+    Glitches,
+    /// Decode errors or truncation: part of the audio is missing
+    Damaged,
+}
+
+impl std::fmt::Display for IntegrityVerdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntegrityVerdict::Ok => write!(f, "OK"),
+            IntegrityVerdict::Glitches => write!(f, "GLITCHES"),
+            IntegrityVerdict::Damaged => write!(f, "DAMAGED"),
+        }
+    }
+}
+
+/// Kind of integrity event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityEventKind {
+    DecodeError,
+    StreamError,
+    Dropout,
+    Click,
+}
+
+/// One problem found in the stream
+#[derive(Debug, Clone, Serialize)]
+pub struct IntegrityEvent {
+    pub kind: IntegrityEventKind,
+    /// Position in seconds
+    pub time: f64,
+    /// Length in seconds (dropouts only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+}
+
+/// Everything found while decoding the whole file
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IntegrityDetails {
+    /// Packets the decoder rejected
+    pub decode_errors: u32,
+    /// Packets the demuxer couldn't read
+    pub stream_errors: u32,
+    /// Frames the container declares, if it says
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub declared_frames: Option<u64>,
+    /// Frames actually decoded
+    pub decoded_frames: u64,
+    /// Audio missing from the end of the stream (seconds, 0 if complete)
+    pub missing_secs: f64,
+    /// The MP3 frame walk found the last frame cut off
+    pub stream_truncated: bool,
+    /// Places the MP3 frame walk lost sync mid-stream
+    pub sync_losses: u32,
+    pub dropouts: u32,
+    /// Total length of all dropouts (seconds)
+    pub dropout_secs: f64,
+    pub clicks: u32,
+    /// First events in stream order (decode errors by packet timestamp,
+    /// dropouts and clicks by position in the decoded audio)
+    pub events: Vec<IntegrityEvent>,
+}
+
+impl IntegrityDetails {
+    pub fn truncated(&self) -> bool {
+        self.missing_secs > 0.0 || self.stream_truncated
+    }
+
+    pub fn verdict(&self) -> IntegrityVerdict {
+        if self.decode_errors > 0 || self.stream_errors > 0 || self.truncated() {
+            IntegrityVerdict::Damaged
+        } else if self.dropouts > 0 || self.clicks > 0 || self.sync_losses > 0 {
+            IntegrityVerdict::Glitches
+        } else {
+            IntegrityVerdict::Ok
+        }
+    }
+
+    /// Flags for everything found, e.g. `decode_errors(3)`, `dropouts(2,total=0.15s)`.
+    /// The frame walk's findings are already flagged with the stream structure
+    pub fn flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        if self.decode_errors > 0 {
+            flags.push(format!("decode_errors({})", self.decode_errors));
+        }
+        if self.stream_errors > 0 {
+            flags.push(format!("stream_errors({})", self.stream_errors));
+        }
+        if self.missing_secs > 0.0 {
+            flags.push(format!("truncated(missing={:.1}s)", self.missing_secs));
+        }
+        if self.dropouts > 0 {
+            flags.push(format!("dropouts({},total={:.2}s)", self.dropouts, self.dropout_secs));
+        }
+        if self.clicks > 0 {
+            flags.push(format!("clicks({})", self.clicks));
+        }
+        flags
+    }
+
+    /// Take truncation and sync losses from the MP3 frame walk
+    fn add_stream(&mut self, structure: &StreamStructure) {
+        self.stream_truncated = structure.truncated;
+        self.sync_losses = structure.sync_losses.len() as u32;
+    }
+
+    fn push_event(&mut self, kind: IntegrityEventKind, time: f64, duration: Option<f64>) {
+        if self.events.len() < MAX_EVENTS {
+            self.events.push(IntegrityEvent { kind, time, duration });
+        }
+    }
+}
+
+/// Check the integrity of the whole decoded file (`data` is the file itself,
+/// `stream` the MP3 frame walk if there was one)
+pub fn analyze(audio: &DecodedAudio, data: &[u8], stream: Option<&StreamStructure>) -> IntegrityDetails {
+    // Symphonia estimates an MP3's length from the file size, which shrinks
+    // along with a truncated file; the Xing/Info frame count doesn't
+    let declared_frames = if audio.codec == CODEC_TYPE_MP3 {
+        LameHeader::extract(data)
+            .and_then(|h| h.total_frames)
+            .zip(audio.frames_per_packet)
+            .map(|(frames, len)| frames as u64 * len)
+            .or(audio.declared_frames)
+    } else {
+        audio.declared_frames
+    };
+
+    let mut scanner = IntegrityScanner::new(audio.sample_rate);
+    scanner.set_channels(audio.channels);
+
+    // Feed the audio a click block at a time, with each lost packet where it fell
+    // so the events stay in stream order
+    let feed = |scanner: &mut IntegrityScanner, samples: &[f32]| {
+        for chunk in samples.chunks(CLICK_BLOCK * audio.channels) {
+            scanner.add_interleaved(chunk);
+        }
+    };
+    let mut done = 0;
+    for lost in &audio.lost_packets {
+        let upto = (lost.frame as usize).min(audio.frames()) * audio.channels;
+        feed(&mut scanner, &audio.samples[done..upto]);
+        done = upto;
+        match lost.error {
+            PacketError::Decode { time, frames } => scanner.decode_error(time, frames),
+            PacketError::Stream => scanner.stream_error(),
+        }
+    }
+    feed(&mut scanner, &audio.samples[done..]);
+
+    let mut details = scanner.finish(declared_frames);
+    // Only a walk that is the file's audio describes it; frame lookalikes in
+    // other formats don't count
+    if audio.codec == CODEC_TYPE_MP3 {
+        if let Some(structure) = stream.filter(|structure| structure.covers_audio()) {
+            details.add_stream(structure);
+        }
+    }
+    details
+}
+
+/// A zero run waiting for the audio after it
+struct PendingDropout {
+    start: u64,
+    len: u64,
+    peak_after: f32,
+    frames_after: u64,
+}
+
+/// Streaming integrity checks over interleaved samples
+struct IntegrityScanner {
+    sample_rate: u32,
+    channels: usize,
+    details: IntegrityDetails,
+    /// Frames lost to decode errors, so they don't also count as truncation
+    lost_frames: u64,
+    frames: u64,
+
+    // Dropouts
+    context_frames: u64,
+    /// Peak of the current and previous context windows
+    window_peak: f32,
+    prev_window_peak: f32,
+    window_pos: u64,
+    zero_start: Option<u64>,
+    peak_before_zeros: f32,
+    pending: Option<PendingDropout>,
+
+    // Clicks: interleaved block, starting with the two frames before it
+    click_block: Vec<f32>,
+}
+
+impl IntegrityScanner {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            channels: 1,
+            details: IntegrityDetails::default(),
+            lost_frames: 0,
+            frames: 0,
+            context_frames: (DROPOUT_CONTEXT_SECS * sample_rate as f64).ceil() as u64,
+            window_peak: 0.0,
+            prev_window_peak: 0.0,
+            window_pos: 0,
+            zero_start: None,
+            peak_before_zeros: 0.0,
+            pending: None,
+            click_block: Vec::new(),
+        }
+    }
+
+    fn set_channels(&mut self, channels: usize) {
+        self.channels = channels.max(1);
+    }
+
+    fn secs(&self, frames: u64) -> f64 {
+        frames as f64 / self.sample_rate as f64
+    }
+
+    fn decode_error(&mut self, time: f64, frames: u64) {
+        self.details.decode_errors += 1;
+        self.lost_frames += frames;
+        self.details.push_event(IntegrityEventKind::DecodeError, time, None);
+    }
+
+    fn stream_error(&mut self) {
+        self.details.stream_errors += 1;
+        let time = self.secs(self.frames);
+        self.details.push_event(IntegrityEventKind::StreamError, time, None);
+    }
+
+    fn add_interleaved(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            self.add_frame(frame);
+        }
+        self.click_block.extend_from_slice(samples);
+        if self.click_block.len() >= (CLICK_BLOCK + 2) * self.channels {
+            self.scan_clicks();
+        }
+    }
+
+    fn add_frame(&mut self, frame: &[f32]) {
+        let peak = frame.iter().fold(0.0f32, |p, s| p.max(s.abs()));
+
+        if peak == 0.0 {
+            if self.zero_start.is_none() {
+                // Audio after the pending dropout ends where the next zero run starts
+                self.resolve_pending();
+                self.zero_start = Some(self.frames);
+                self.peak_before_zeros = self.window_peak.max(self.prev_window_peak);
+            }
+        } else {
+            if let Some(start) = self.zero_start.take() {
+                if self.peak_before_zeros >= DROPOUT_CONTEXT_LEVEL {
+                    self.pending = Some(PendingDropout {
+                        start,
+                        len: self.frames - start,
+                        peak_after: 0.0,
+                        frames_after: 0,
+                    });
+                }
+            }
+            if let Some(p) = self.pending.as_mut() {
+                p.peak_after = p.peak_after.max(peak);
+                p.frames_after += 1;
+                if p.frames_after >= self.context_frames {
+                    self.resolve_pending();
+                }
+            }
+        }
+
+        self.window_peak = self.window_peak.max(peak);
+        self.window_pos += 1;
+        if self.window_pos >= self.context_frames {
+            self.prev_window_peak = self.window_peak;
+            self.window_peak = 0.0;
+            self.window_pos = 0;
+        }
+        self.frames += 1;
+    }
+
+    fn resolve_pending(&mut self) {
+        let Some(p) = self.pending.take() else {
+            return;
+        };
+        let duration = self.secs(p.len);
+        if p.peak_after >= DROPOUT_CONTEXT_LEVEL && (MIN_DROPOUT_SECS..=MAX_DROPOUT_SECS).contains(&duration) {
+            self.details.dropouts += 1;
+            self.details.dropout_secs += duration;
+            let time = self.secs(p.start);
+            self.details.push_event(IntegrityEventKind::Dropout, time, Some(duration));
+        }
+    }
+
+    /// Find clicks in the buffered block, keeping its last two frames for the next one
+    fn scan_clicks(&mut self) {
+        let ch = self.channels;
+        let block = &self.click_block;
+        let frames = block.len() / ch;
+        if frames < 3 {
+            return;
+        }
+        // Frame index of block[0]
+        let first = self.frames - frames as u64;
+        let d2 = |n: usize, c: usize| block[n * ch + c] - 2.0 * block[(n - 1) * ch + c] + block[(n - 2) * ch + c];
+        let is_zero = |n: usize| block[n * ch..(n + 1) * ch].iter().all(|&s| s == 0.0);
+
+        let thresholds: Vec<f32> = (0..ch)
+            .map(|c| {
+                let power = (2..frames).map(|n| d2(n, c).powi(2)).sum::<f32>() / (frames - 2) as f32;
+                (power.sqrt() * CLICK_RATIO).max(CLICK_MIN_LEVEL)
+            })
+            .collect();
+
+        // Steps into or out of digital silence are dropout edges, not clicks
+        let mut clicks: Vec<u64> = Vec::new();
+        for n in 2..frames {
+            if !(0..ch).any(|c| d2(n, c).abs() > thresholds[c]) || (n - 2..=n).any(is_zero) {
+                continue;
+            }
+            let frame = first + n as u64;
+            match clicks.last() {
+                Some(&last) if frame - last <= CLICK_MERGE_FRAMES => {}
+                _ => clicks.push(frame),
+            }
+        }
+
+        if clicks.len() <= MAX_CLICKS_PER_BLOCK {
+            for frame in clicks {
+                self.details.clicks += 1;
+                let time = self.secs(frame);
+                self.details.push_event(IntegrityEventKind::Click, time, None);
+            }
+        }
+
+        let keep = self.click_block.len() - 2 * ch;
+        self.click_block.drain(..keep);
+    }
+
+    fn finish(mut self, declared_frames: Option<u64>) -> IntegrityDetails {
+        // A short final block still gets checked; a few frames can't give a fair RMS
+        if self.click_block.len() / self.channels >= CLICK_BLOCK / 4 {
+            self.scan_clicks();
+        }
+        // A zero run still open here is trailing silence
+        if self.zero_start.is_none() {
+            self.resolve_pending();
+        }
+
+        let accounted = self.frames + self.lost_frames;
+        let tolerance = (TRUNCATION_TOLERANCE_SECS * self.sample_rate as f64) as u64;
+        let mut details = self.details;
+        details.declared_frames = declared_frames;
+        details.decoded_frames = self.frames;
+        if let Some(declared) = declared_frames {
+            if declared > accounted + tolerance {
+                details.missing_secs = (declared - accounted) as f64 / self.sample_rate as f64;
+            }
+        }
+        details.events.sort_by(|a, b| a.time.total_cmp(&b.time));
+        details
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: What damaged audio looks like
+    // ==========================================================================
+    //
+    // Lossy-vs-lossless says nothing about whether a file plays through
+    // cleanly. The usual kinds of damage:
+    //
+    //   - Corrupt frames: a bad sector or a flipped bit in transfer. MP3
+    //     decoders mute or skip the frame; FLAC catches it with its frame CRC.
+    //   - Truncation: an interrupted download or copy. The header still
+    //     promises the full length.
+    //   - Dropouts: a ripper or interface losing sync writes blocks of exact
+    //     digital zero in the middle of the music. Real music never sits at
+    //     exactly 0 in every channel while it's playing.
+    //   - Clicks: a single bad sample or a splice leaves a spike that no
+    //     instrument makes. The second difference (x[n] - 2x[n-1] + x[n-2])
+    //     is near zero for smooth audio and huge at a discontinuity.
+    // ==========================================================================
+
+    const RATE: u32 = 44100;
+
+    fn scan(channels: usize, samples: &[f32]) -> IntegrityDetails {
+        let mut scanner = IntegrityScanner::new(RATE);
+        scanner.set_channels(channels);
+        // Feed in packet-sized pieces, as the decoder would
+        for packet in samples.chunks(1152 * channels) {
+            scanner.add_interleaved(packet);
+        }
+        scanner.finish(Some((samples.len() / channels) as u64))
+    }
+
+    fn sine(n: usize) -> f32 {
+        (0.3 * (2.0 * std::f64::consts::PI * 440.0 * n as f64 / RATE as f64).sin()) as f32
+    }
+
+    #[test]
+    fn test_clean_audio() {
+        let samples: Vec<f32> = (0..RATE as usize * 2).flat_map(|n| [sine(n), sine(n)]).collect();
+        let details = scan(2, &samples);
+        assert_eq!(details.verdict(), IntegrityVerdict::Ok);
+        assert!(details.events.is_empty());
+        assert!(details.flags().is_empty());
+    }
+
+    #[test]
+    fn test_dropout_mid_track() {
+        // 10 ms of zeros one second in
+        let start = RATE as usize;
+        let len = RATE as usize / 100;
+        let samples: Vec<f32> = (0..RATE as usize * 2)
+            .map(|n| if (start..start + len).contains(&n) { 0.0 } else { sine(n) })
+            .collect();
+        let details = scan(1, &samples);
+        assert_eq!(details.dropouts, 1);
+        assert!((details.dropout_secs - 0.01).abs() < 0.001);
+        assert_eq!(details.clicks, 0, "Dropout edges aren't clicks");
+        assert_eq!(details.verdict(), IntegrityVerdict::Glitches);
+        assert_eq!(details.events[0].kind, IntegrityEventKind::Dropout);
+        assert!((details.events[0].time - 1.0).abs() < 0.001);
+        assert_eq!(details.flags(), ["dropouts(1,total=0.01s)"]);
+    }
+
+    #[test]
+    fn test_leading_and_trailing_silence_ignored() {
+        let second = RATE as usize;
+        let samples: Vec<f32> = (0..second * 3)
+            .map(|n| if (second..second * 2).contains(&n) { sine(n) } else { 0.0 })
+            .collect();
+        let details = scan(1, &samples);
+        assert_eq!(details.dropouts, 0);
+        assert_eq!(details.verdict(), IntegrityVerdict::Ok);
+    }
+
+    #[test]
+    fn test_silence_in_one_channel_is_not_dropout() {
+        let samples: Vec<f32> = (0..RATE as usize * 2)
+            .flat_map(|n| [sine(n), if n > RATE as usize { 0.0 } else { sine(n) }])
+            .collect();
+        assert_eq!(scan(2, &samples).dropouts, 0);
+    }
+
+    #[test]
+    fn test_click() {
+        let mut samples: Vec<f32> = (0..RATE as usize * 2).map(sine).collect();
+        samples[30000] += 0.5;
+        let details = scan(1, &samples);
+        assert_eq!(details.clicks, 1);
+        assert_eq!(details.verdict(), IntegrityVerdict::Glitches);
+        assert!((details.events[0].time - 30000.0 / RATE as f64).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_square_wave_is_not_clicks() {
+        let samples: Vec<f32> = (0..RATE as usize * 2)
+            .map(|n| if (n / 100) % 2 == 0 { 0.5 } else { -0.5 })
+            .collect();
+        assert_eq!(scan(1, &samples).clicks, 0);
+    }
+
+    #[test]
+    fn test_truncation_and_decode_errors() {
+        let samples: Vec<f32> = (0..RATE as usize).map(sine).collect();
+        let mut scanner = IntegrityScanner::new(RATE);
+        scanner.add_interleaved(&samples);
+        scanner.decode_error(1.0, 1152);
+        let details = scanner.finish(Some(RATE as u64 * 3));
+        assert_eq!(details.decode_errors, 1);
+        assert!(details.truncated());
+        // 3 s declared, 1 s decoded, one packet lost to the decode error
+        assert!((details.missing_secs - (2.0 - 1152.0 / RATE as f64)).abs() < 1e-6);
+        assert_eq!(details.verdict(), IntegrityVerdict::Damaged);
+        assert_eq!(details.flags(), ["decode_errors(1)", "truncated(missing=2.0s)"]);
+    }
+
+    /// MPEG-1 Layer III 128 kbps at 44.1 kHz: 417-byte frames
+    fn mp3_frames(count: usize) -> Vec<u8> {
+        (0..count)
+            .flat_map(|_| [0xFF, 0xFB, 0x90, 0x00].into_iter().chain([0x11; 413]))
+            .collect()
+    }
+
+    fn with_stream(data: &[u8]) -> IntegrityDetails {
+        let samples: Vec<f32> = (0..RATE as usize).map(sine).collect();
+        let mut details = scan(1, &samples);
+        details.add_stream(&crate::mp3::stream::walk(data));
+        details
+    }
+
+    #[test]
+    fn test_cut_cbr_stream_is_damaged() {
+        // No Xing/Info frame declares a length, but the last frame is cut off
+        let mut data = mp3_frames(12);
+        data.truncate(5000);
+        let details = with_stream(&data);
+        assert!(details.stream_truncated);
+        assert!(details.truncated());
+        assert_eq!(details.verdict(), IntegrityVerdict::Damaged);
+        // Named once, by the stream structure
+        assert!(details.flags().is_empty());
+    }
+
+    #[test]
+    fn test_junk_mid_stream_is_a_glitch() {
+        let mut data = mp3_frames(20);
+        for _ in 0..5 {
+            data.extend_from_slice(&[0x55; 209]);
+            data.extend_from_slice(&mp3_frames(10));
+        }
+        let details = with_stream(&data);
+        assert_eq!(details.sync_losses, 5);
+        assert!(!details.truncated());
+        assert_eq!(details.verdict(), IntegrityVerdict::Glitches);
+    }
+}
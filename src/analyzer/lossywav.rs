@@ -0,0 +1,445 @@
+//! lossyWAV pre-processing detection
+//!
+//! lossyWAV makes FLAC files smaller by throwing away bits the listener
+//! supposedly won't miss. It works on fixed codec blocks (512 samples at
+//! 44.1/48 kHz) and, for each block, rounds every sample so its lowest N
+//! bits are zero. FLAC notices the "wasted bits" and stores the block in
+//! fewer bits per sample. The output is still a valid, bit-exact FLAC of the
+//! *processed* audio - so a renamed `.lossy.flac` passes for real lossless.
+//!
+//! The processing removes noise-floor bits, not high frequencies, so the
+//! spectral cliff checks rarely see it. Two things give it away instead:
+//!
+//! 1. **Zeroed low bits per block**: in real recordings the low bits are
+//!    noise, and the chance of 512 stereo samples all ending in a 0 bit is
+//!    ~2^-1024. lossyWAV output has that in most blocks, with a different
+//!    number of zeroed bits from block to block.
+//!
+//! 2. **Markers**: the lossyWAV version string often survives into the FLAC
+//!    vendor string or a Vorbis comment.
+//!
+//! ```text
+//! 16-bit samples, one codec block:          lossyWAV block (5 bits removed):
+//!
+//!   0110 1001 1101 0011                       0110 1001 1100 0000
+//!   1111 0010 0110 1110                       1111 0010 0110 0000
+//!   0001 1100 1011 0101                       0001 1100 1010 0000
+//!   OR of block → trailing zeros = 0          OR of block → trailing zeros = 5
+//! ```
+//!
+//! Padding is not lossyWAV. A container that stores samples wider than their
+//! bit depth says so (20 valid bits in 24-bit samples), and only the valid
+//! bits are checked. A header that doesn't say leaves the same number of
+//! zeroed bits in every block (16-bit audio written as 24-bit), where
+//! lossyWAV's count moves with the music.
+
+use super::decode::DecodedAudio;
+use crate::flac;
+use serde::Serialize;
+
+/// Score added when lossyWAV processing is found
+pub const LOSSYWAV_SCORE: u32 = 65;
+
+/// Seconds of audio checked for zeroed bits
+const MAX_SCAN_SECS: usize = 30;
+/// Fewer non-silent blocks than this can't be judged
+const MIN_BLOCKS: usize = 16;
+/// Fraction of blocks with zeroed bits above which the file is processed
+/// (real audio has essentially none; lossyWAV leaves only the odd block intact)
+const MIN_ZEROED_FRACTION: f64 = 0.3;
+/// Longest marker kept in the details
+const MAX_MARKER_LEN: usize = 80;
+
+/// Evidence of lossyWAV processing
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LossyWavDetails {
+    /// lossyWAV marker from the FLAC vendor string or a comment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marker: Option<String>,
+    /// Codec block size the samples were checked at
+    pub block_size: usize,
+    /// Non-silent blocks checked
+    pub blocks: usize,
+    /// Blocks with low bits of the valid samples zeroed
+    pub zeroed_blocks: usize,
+    /// Bits the container stores beyond the valid bits (e.g. 20-bit audio in
+    /// 24-bit samples); never checked
+    pub padding_bits: u32,
+    /// Bits zeroed in every block alike: padding the header doesn't declare
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constant_zeroed_bits: Option<u32>,
+    /// Average bits zeroed in the zeroed blocks
+    pub avg_zeroed_bits: f64,
+}
+
+impl LossyWavDetails {
+    /// Fraction of non-silent blocks with zeroed low bits
+    pub fn zeroed_fraction(&self) -> f64 {
+        if self.blocks == 0 {
+            0.0
+        } else {
+            self.zeroed_blocks as f64 / self.blocks as f64
+        }
+    }
+
+    /// Whether the samples alone show block-wise zeroed bits
+    pub fn zeroed_bits_detected(&self) -> bool {
+        self.blocks >= MIN_BLOCKS
+            && self.zeroed_fraction() >= MIN_ZEROED_FRACTION
+            && self.constant_zeroed_bits.is_none()
+    }
+
+    pub fn detected(&self) -> bool {
+        self.marker.is_some() || self.zeroed_bits_detected()
+    }
+
+    /// Flag with its evidence, e.g. `lossywav_processed(zeroed=94%,avg_bits=4.8)`
+    pub fn flag(&self) -> String {
+        let mut evidence = Vec::new();
+        if self.zeroed_bits_detected() {
+            evidence.push(format!("zeroed={:.0}%", self.zeroed_fraction() * 100.0));
+            evidence.push(format!("avg_bits={:.1}", self.avg_zeroed_bits));
+        }
+        if self.marker.is_some() {
+            evidence.push("tag".to_string());
+        }
+        format!("lossywav_processed({})", evidence.join(","))
+    }
+}
+
+/// Check a file for lossyWAV processing
+///
+/// Markers are always checked; the samples only when they were decoded.
+/// Returns None for lossy codecs (no integer samples to check) when there's
+/// no marker either.
+pub fn analyze(data: &[u8], audio: Option<&DecodedAudio>) -> Option<LossyWavDetails> {
+    let marker = flac_marker(data);
+    let scanned = audio.and_then(scan_blocks);
+
+    match (scanned, marker) {
+        (Some(mut details), marker) => {
+            details.marker = marker;
+            Some(details)
+        }
+        (None, Some(marker)) => Some(LossyWavDetails {
+            marker: Some(marker),
+            ..Default::default()
+        }),
+        (None, None) => None,
+    }
+}
+
+/// lossyWAV codec block size: 512 samples, scaled up with the sample rate
+fn codec_block_size(sample_rate: u32) -> usize {
+    if sample_rate > 96000 {
+        2048
+    } else if sample_rate > 48000 {
+        1024
+    } else {
+        512
+    }
+}
+
+// ============================================================================
+// SAMPLE SCAN
+// ============================================================================
+
+/// Measure zeroed bits per block over the first MAX_SCAN_SECS of integer samples
+///
+/// Only integer codecs declare a bit depth; lossy codecs decode to floats
+/// whose low bits mean nothing.
+fn scan_blocks(audio: &DecodedAudio) -> Option<LossyWavDetails> {
+    let bits_per_sample = audio.bits_per_sample?;
+    let coded_bits = audio.coded_bits_per_sample.unwrap_or(bits_per_sample);
+    let samples = audio.integer_head(MAX_SCAN_SECS)?;
+    let mut scan = BlockScan::new(
+        audio.channels,
+        codec_block_size(audio.sample_rate),
+        bits_per_sample,
+        coded_bits,
+    );
+    scan.add_interleaved(&samples);
+    Some(scan.finish())
+}
+
+/// Running OR of each codec block's samples
+///
+/// Decoders hand out integer samples left-justified in an i32, so a 16-bit
+/// file always has 16 trailing zeros; `shift` removes those, along with any
+/// padding below the valid bits.
+struct BlockScan {
+    channels: usize,
+    block_size: usize,
+    shift: u32,
+    padding_bits: u32,
+    frames: usize,
+    block_frames: usize,
+    block_bits: u32,
+    /// Zeroed low bits of each non-silent block
+    zeroed: Vec<u32>,
+}
+
+impl BlockScan {
+    fn new(channels: usize, block_size: usize, bits_per_sample: u32, coded_bits: u32) -> Self {
+        Self {
+            channels: channels.max(1),
+            block_size,
+            shift: 32u32.saturating_sub(bits_per_sample),
+            padding_bits: coded_bits.saturating_sub(bits_per_sample),
+            frames: 0,
+            block_frames: 0,
+            block_bits: 0,
+            zeroed: Vec::new(),
+        }
+    }
+
+    fn add_interleaved(&mut self, samples: &[i32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for &sample in frame {
+                self.block_bits |= sample as u32;
+            }
+            self.frames += 1;
+            self.block_frames += 1;
+            if self.block_frames == self.block_size {
+                // Digital silence has every bit zero and says nothing
+                if self.block_bits != 0 {
+                    self.zeroed
+                        .push(self.block_bits.trailing_zeros().saturating_sub(self.shift));
+                }
+                self.block_frames = 0;
+                self.block_bits = 0;
+            }
+        }
+    }
+
+    /// Summarise the blocks; a trailing partial block is ignored
+    fn finish(self) -> LossyWavDetails {
+        let zeroed: Vec<u32> = self.zeroed.iter().copied().filter(|&bits| bits > 0).collect();
+        let avg_zeroed_bits = if zeroed.is_empty() {
+            0.0
+        } else {
+            zeroed.iter().sum::<u32>() as f64 / zeroed.len() as f64
+        };
+        let constant_zeroed_bits = match self.zeroed.first() {
+            Some(&first) if first > 0 && self.zeroed.iter().all(|&bits| bits == first) => Some(first),
+            _ => None,
+        };
+
+        LossyWavDetails {
+            marker: None,
+            block_size: self.block_size,
+            blocks: self.zeroed.len(),
+            zeroed_blocks: zeroed.len(),
+            padding_bits: self.padding_bits,
+            constant_zeroed_bits,
+            avg_zeroed_bits,
+        }
+    }
+}
+
+/// lossyWAV marker from the FLAC vendor string or Vorbis comments
+fn flac_marker(data: &[u8]) -> Option<String> {
+    flac::metadata_blocks(data)
+        .iter()
+        .filter(|b| b.block_type == flac::VORBIS_COMMENT)
+        .flat_map(|b| flac::vorbis_comment_strings(b.data))
+        .find(|s| s.to_ascii_lowercase().contains("lossywav"))
+        .map(|s| s.chars().take(MAX_MARKER_LEN).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_noise::TestNoise;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: Why zeroed low bits give lossyWAV away
+    // ==========================================================================
+    //
+    // The least significant bits of a real recording are noise: mic hiss,
+    // dither, converter noise. Each is 0 or 1 with even odds, independently
+    // per sample. For a whole 512-sample stereo block to end in 0 takes 1024
+    // coin flips coming up the same way - it doesn't happen.
+    //
+    // lossyWAV decides per block how much of that noise nobody would hear,
+    // and rounds it away. So nearly every block ends in a run of zero bits,
+    // and the run length changes with the music (quiet passages lose fewer
+    // bits than loud ones).
+    //
+    // Things that are NOT lossyWAV:
+    // - Digital silence: every bit is zero, so silent blocks are skipped
+    // - Padding: 16-bit audio in a 24-bit file has 8 zero bits in EVERY block.
+    //   If the header declares 16 valid bits those are never looked at; if
+    //   it doesn't, the count is the same in every block, which lossyWAV's
+    //   never is. A floor that every lossyWAV block shares is still counted.
+    // ==========================================================================
+
+    /// Deterministic noise samples at the given bit depth, left-justified in i32
+    fn test_samples(frames: usize, channels: usize, bits: u32, seed: u64) -> Vec<i32> {
+        let mut noise = TestNoise::new(seed);
+        (0..frames * channels)
+            .map(|_| ((noise.next_u64() >> 32) as i32 >> 2) >> (32 - bits) << (32 - bits))
+            .collect()
+    }
+
+    /// Zero `bits(block)` low bits of every sample in each codec block
+    fn zero_blocks(
+        samples: &mut [i32],
+        channels: usize,
+        block: usize,
+        shift: u32,
+        bits: impl Fn(usize) -> u32,
+    ) {
+        for (index, chunk) in samples.chunks_mut(block * channels).enumerate() {
+            let mask = !((1i32 << (shift + bits(index))) - 1);
+            for sample in chunk {
+                *sample &= mask;
+            }
+        }
+    }
+
+    fn scan(samples: &[i32], channels: usize, bits: u32) -> LossyWavDetails {
+        scan_coded(samples, channels, bits, bits)
+    }
+
+    fn scan_coded(samples: &[i32], channels: usize, bits: u32, coded_bits: u32) -> LossyWavDetails {
+        let mut scan = BlockScan::new(channels, 512, bits, coded_bits);
+        scan.add_interleaved(samples);
+        scan.finish()
+    }
+
+    #[test]
+    fn test_genuine_audio_not_flagged() {
+        let details = scan(&test_samples(512 * 64, 2, 16, 1), 2, 16);
+        assert_eq!(details.blocks, 64);
+        assert_eq!(details.zeroed_blocks, 0);
+        assert_eq!(details.padding_bits, 0);
+        assert!(!details.detected());
+    }
+
+    #[test]
+    fn test_blockwise_zeroed_bits_detected() {
+        let mut samples = test_samples(512 * 64, 2, 16, 2);
+        // Varying bits per block, a few blocks left intact
+        zero_blocks(&mut samples, 2, 512, 16, |i| {
+            if i % 8 == 0 {
+                0
+            } else {
+                3 + (i % 4) as u32
+            }
+        });
+
+        let details = scan(&samples, 2, 16);
+        assert_eq!(details.zeroed_blocks, 56);
+        assert!(
+            (details.avg_zeroed_bits - 33.0 / 7.0).abs() < 0.1,
+            "{:?}",
+            details
+        );
+        assert!(details.detected());
+        assert_eq!(
+            details.flag(),
+            "lossywav_processed(zeroed=88%,avg_bits=4.7)"
+        );
+    }
+
+    #[test]
+    fn test_lossywav_floor_counted() {
+        // Every block loses at least 2 bits: the floor is lossyWAV's too
+        let mut samples = test_samples(512 * 64, 2, 16, 5);
+        zero_blocks(&mut samples, 2, 512, 16, |i| 2 + (i % 4) as u32);
+        let details = scan(&samples, 2, 16);
+        assert_eq!((details.zeroed_blocks, details.padding_bits), (64, 0));
+        assert!((details.avg_zeroed_bits - 3.5).abs() < 0.1, "{:?}", details);
+        assert!(details.detected());
+    }
+
+    #[test]
+    fn test_padding_is_not_lossywav() {
+        // 16-bit audio in a 24-bit container: 8 zero bits in every block
+        let samples = test_samples(512 * 64, 2, 16, 3);
+
+        // Declared: only the 16 valid bits are checked
+        let declared = scan_coded(&samples, 2, 16, 24);
+        assert_eq!(declared.padding_bits, 8);
+        assert_eq!(declared.zeroed_blocks, 0);
+        assert!(!declared.detected());
+
+        // Undeclared: the same 8 bits in every block
+        let undeclared = scan(&samples, 2, 24);
+        assert_eq!(undeclared.padding_bits, 0);
+        assert_eq!(undeclared.constant_zeroed_bits, Some(8));
+        assert!(!undeclared.detected());
+    }
+
+    #[test]
+    fn test_silent_blocks_skipped() {
+        let mut samples = vec![0i32; 512 * 2 * 32];
+        samples.extend(test_samples(512 * 32, 2, 16, 4));
+        let details = scan(&samples, 2, 16);
+        assert_eq!(details.blocks, 32);
+        assert!(!details.detected());
+    }
+
+    #[test]
+    fn test_codec_block_size_scales_with_rate() {
+        assert_eq!(codec_block_size(44100), 512);
+        assert_eq!(codec_block_size(48000), 512);
+        assert_eq!(codec_block_size(96000), 1024);
+        assert_eq!(codec_block_size(192000), 2048);
+    }
+
+    /// FLAC header with a single Vorbis comment block (no audio)
+    fn test_flac_tags(vendor: &str, comments: &[&str]) -> Vec<u8> {
+        let mut block = Vec::new();
+        block.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        block.extend_from_slice(vendor.as_bytes());
+        block.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            block.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            block.extend_from_slice(comment.as_bytes());
+        }
+
+        let mut flac = b"fLaC".to_vec();
+        // Empty padding block first, so the parser has to skip one
+        flac.extend_from_slice(&[1, 0, 0, 4, 0, 0, 0, 0]);
+        flac.push(0x80 | flac::VORBIS_COMMENT);
+        flac.extend_from_slice(&(block.len() as u32).to_be_bytes()[1..]);
+        flac.extend_from_slice(&block);
+        flac
+    }
+
+    #[test]
+    fn test_flac_comment_marker() {
+        let flac = test_flac_tags(
+            "reference libFLAC 1.4.3 20230623",
+            &[
+                "TITLE=Song",
+                "LOSSYWAV=lossyWAV 1.4.2 @ 2023-07-01, --standard",
+            ],
+        );
+        assert_eq!(
+            flac_marker(&flac).as_deref(),
+            Some("LOSSYWAV=lossyWAV 1.4.2 @ 2023-07-01, --standard")
+        );
+
+        let details = analyze(&flac, DecodedAudio::decode(&flac).as_ref()).unwrap();
+        assert!(details.detected());
+        assert_eq!(details.flag(), "lossywav_processed(tag)");
+    }
+
+    #[test]
+    fn test_flac_vendor_marker_behind_id3() {
+        let mut data = b"ID3\x04\x00\x00\x00\x00\x00\x02".to_vec();
+        data.extend_from_slice(&[0, 0]);
+        data.extend(test_flac_tags("lossyWAV 1.4.2, libFLAC 1.3.2", &[]));
+        assert!(flac_marker(&data).unwrap().starts_with("lossyWAV"));
+    }
+
+    #[test]
+    fn test_plain_flac_tags_no_marker() {
+        let flac = test_flac_tags("reference libFLAC 1.4.3 20230623", &["ARTIST=Someone"]);
+        assert!(flac_marker(&flac).is_none());
+        assert!(flac_marker(b"RIFF....WAVE").is_none());
+    }
+}
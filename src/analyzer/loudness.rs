@@ -0,0 +1,573 @@
+//! Loudness, dynamic range and clipping measurement
+//!
+//! Transcode detection only needs a slice of the file, but loudness has to
+//! see all of it. This module decodes every sample once and streams it through
+//! a handful of meters:
+//!
+//! 1. **Integrated loudness** (EBU R128 / ITU-R BS.1770): K-weighted power in
+//!    400 ms blocks, gated at -70 LUFS and again 10 LU below the result.
+//!
+//! 2. **Loudness range** (EBU Tech 3342): spread between the 10th and 95th
+//!    percentile of 3 s short-term loudness, gated 20 LU below the mean.
+//!
+//! 3. **True peak**: the signal is 4× oversampled (2× above 96 kHz) so peaks
+//!    falling between samples are caught, as a DAC would reconstruct them.
+//!
+//! 4. **DR score** (DR14 / "TT DR meter" style): peak-to-loudness ratio of the
+//!    loudest 20% of 3 s blocks, averaged over channels.
+//!
+//! 5. **Clipping**: runs of consecutive samples stuck at full scale.
+//!
+//! ```text
+//! K-weighting (relative to 1 kHz):
+//!
+//!  +4dB            ┌──────────   high shelf (head acoustics)
+//!   0dB    ┌───────┘
+//!          │
+//!         ╱  high-pass (~38 Hz)
+//!        ╱
+//!        20Hz   1kHz   10kHz
+//! ```
+
+use super::decode::DecodedAudio;
+use serde::Serialize;
+use symphonia::core::audio::Channels;
+
+/// Gating segment length (blocks are built from 100 ms segments)
+const SEGMENTS_PER_SEC: usize = 10;
+/// Momentary block: 400 ms
+const MOMENTARY_SEGMENTS: usize = 4;
+/// Short-term block: 3 s
+const SHORT_TERM_SEGMENTS: usize = 30;
+/// Blocks below this are silence (LUFS)
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Integrated loudness relative gate (LU)
+const INTEGRATED_RELATIVE_GATE: f64 = -10.0;
+/// Loudness range relative gate (LU)
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+/// Loudness range percentiles
+const RANGE_LOW_PERCENTILE: f64 = 0.10;
+const RANGE_HIGH_PERCENTILE: f64 = 0.95;
+/// Interpolation filter taps per oversampling phase
+const TRUE_PEAK_TAPS: usize = 12;
+/// DR block length in seconds
+const DR_BLOCK_SECS: usize = 3;
+/// Fraction of loudest DR blocks that count towards the score
+const DR_LOUDEST_FRACTION: f64 = 0.2;
+/// Samples at or above this magnitude are at full scale (~-0.01 dBFS)
+const CLIP_LEVEL: f64 = 0.999;
+/// Shortest full-scale run counted as clipping (single peaks touching 0 dBFS are normal)
+const MIN_CLIP_RUN: u64 = 3;
+/// Floor for levels of digital silence (dB)
+const SILENCE_DB: f64 = -120.0;
+
+/// Loudness and dynamics of the whole file
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LoudnessDetails {
+    /// Integrated loudness in LUFS (-70 if everything is below the absolute gate)
+    pub integrated_loudness: f64,
+    /// Loudness range in LU
+    pub loudness_range: f64,
+    /// Highest oversampled peak in dBTP
+    pub true_peak: f64,
+    /// Highest sample magnitude in dBFS
+    pub sample_peak: f64,
+    /// DR score in dB (DR14 style; higher = more dynamic, below ~8 is heavily limited)
+    pub dynamic_range: f64,
+    /// Samples inside clipped runs, all channels
+    pub clipped_samples: u64,
+    /// Number of clipped runs, all channels
+    pub clip_runs: u64,
+    /// Longest clipped run in samples
+    pub longest_clip_run: u64,
+}
+
+/// Measure loudness over the whole file. Returns None if it holds no audio.
+pub fn analyze(audio: &DecodedAudio) -> Option<LoudnessDetails> {
+    if audio.frames() == 0 {
+        return None;
+    }
+    let weights = (0..audio.channels)
+        .map(|i| channel_weight(audio.layout.get(i).copied().unwrap_or(Channels::empty())))
+        .collect();
+    let mut meter = LoudnessMeter::new(audio.sample_rate, weights);
+    meter.add_interleaved(&audio.samples);
+    Some(meter.finish())
+}
+
+/// BS.1770 channel weight: surrounds count +1.5 dB, LFE is ignored
+fn channel_weight(channel: Channels) -> f64 {
+    if channel.intersects(Channels::LFE1 | Channels::LFE2) {
+        0.0
+    } else if channel.intersects(
+        Channels::REAR_LEFT | Channels::REAR_RIGHT | Channels::SIDE_LEFT | Channels::SIDE_RIGHT,
+    ) {
+        1.41
+    } else {
+        1.0
+    }
+}
+
+fn to_db(value: f64) -> f64 {
+    if value <= 0.0 {
+        SILENCE_DB
+    } else {
+        (20.0 * value.log10()).max(SILENCE_DB)
+    }
+}
+
+/// Loudness of a mean weighted power (LUFS)
+fn power_to_lufs(power: f64) -> f64 {
+    if power <= 0.0 {
+        SILENCE_DB
+    } else {
+        -0.691 + 10.0 * power.log10()
+    }
+}
+
+/// Second-order IIR section (transposed direct form II)
+#[derive(Debug, Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    /// K-weighting stage 1: high shelf modelling the head (+4 dB above ~1.5 kHz)
+    fn k_shelf(sample_rate: u32) -> Self {
+        let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (std::f64::consts::PI * f0 / sample_rate as f64).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        Self {
+            b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    /// K-weighting stage 2: RLB high-pass (~38 Hz)
+    fn k_highpass(sample_rate: u32) -> Self {
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (std::f64::consts::PI * f0 / sample_rate as f64).tan();
+        let a0 = 1.0 + k / q + k * k;
+        Self {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// Polyphase interpolator for true-peak measurement
+#[derive(Debug, Clone)]
+struct Oversampler {
+    /// phases[p][j]: tap j of the filter producing the p-th point between samples
+    phases: Vec<[f64; TRUE_PEAK_TAPS]>,
+    /// Largest possible output for unit input (for skipping quiet stretches)
+    gain_bound: f64,
+}
+
+impl Oversampler {
+    /// Hann-windowed sinc, 4× below 96 kHz, 2× below 192 kHz, none above
+    fn new(sample_rate: u32) -> Self {
+        let factor = match sample_rate {
+            0..=95_999 => 4,
+            96_000..=191_999 => 2,
+            _ => 1,
+        };
+        let len = factor * TRUE_PEAK_TAPS;
+        let centre = (len - 1) as f64 / 2.0;
+        let prototype: Vec<f64> = (0..len)
+            .map(|k| {
+                let t = (k as f64 - centre) / factor as f64;
+                let sinc = if t == 0.0 { 1.0 } else { (std::f64::consts::PI * t).sin() / (std::f64::consts::PI * t) };
+                let hann = 0.5 - 0.5 * (2.0 * std::f64::consts::PI * (k as f64 + 0.5) / len as f64).cos();
+                sinc * hann
+            })
+            .collect();
+
+        let phases: Vec<[f64; TRUE_PEAK_TAPS]> = (0..factor)
+            .map(|p| std::array::from_fn(|j| prototype[j * factor + p]))
+            .collect();
+        let gain_bound = phases
+            .iter()
+            .map(|taps| taps.iter().map(|t| t.abs()).sum::<f64>())
+            .fold(1.0, f64::max);
+
+        Self { phases, gain_bound }
+    }
+
+    /// Largest interpolated magnitude around the newest sample (history[0])
+    fn peak(&self, history: &[f64; TRUE_PEAK_TAPS]) -> f64 {
+        self.phases
+            .iter()
+            .map(|taps| taps.iter().zip(history).map(|(t, x)| t * x).sum::<f64>().abs())
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Per-channel meter state
+#[derive(Debug, Clone)]
+struct ChannelMeter {
+    weight: f64,
+    shelf: Biquad,
+    highpass: Biquad,
+    /// Most recent samples, newest first
+    history: [f64; TRUE_PEAK_TAPS],
+    sample_peak: f64,
+    true_peak: f64,
+    clip_run: u64,
+    block_energy: f64,
+    block_peak: f64,
+    block_frames: usize,
+    /// DR "RMS" (√2 × RMS, so a full-scale sine reads 0 dB) of each block
+    block_rms: Vec<f64>,
+    block_peaks: Vec<f64>,
+}
+
+impl ChannelMeter {
+    fn new(sample_rate: u32, weight: f64) -> Self {
+        Self {
+            weight,
+            shelf: Biquad::k_shelf(sample_rate),
+            highpass: Biquad::k_highpass(sample_rate),
+            history: [0.0; TRUE_PEAK_TAPS],
+            sample_peak: 0.0,
+            true_peak: 0.0,
+            clip_run: 0,
+            block_energy: 0.0,
+            block_peak: 0.0,
+            block_frames: 0,
+            block_rms: Vec::new(),
+            block_peaks: Vec::new(),
+        }
+    }
+
+    fn close_block(&mut self) {
+        if self.block_frames > 0 {
+            self.block_rms.push((2.0 * self.block_energy / self.block_frames as f64).sqrt());
+            self.block_peaks.push(self.block_peak);
+        }
+        self.block_energy = 0.0;
+        self.block_peak = 0.0;
+        self.block_frames = 0;
+    }
+
+    /// DR of this channel: peak (second highest block peak) over the RMS of the loudest 20% of blocks
+    fn dynamic_range(&self) -> Option<f64> {
+        let mut peaks = self.block_peaks.clone();
+        peaks.sort_by(|a, b| b.total_cmp(a));
+        let peak = peaks.get(1).or_else(|| peaks.first()).copied()?;
+
+        let mut rms = self.block_rms.clone();
+        rms.sort_by(|a, b| b.total_cmp(a));
+        let loudest = ((rms.len() as f64 * DR_LOUDEST_FRACTION) as usize).max(1);
+        let top_rms = (rms[..loudest].iter().map(|r| r * r).sum::<f64>() / loudest as f64).sqrt();
+
+        if top_rms <= 0.0 || peak <= 0.0 {
+            return Some(0.0);
+        }
+        Some(20.0 * (peak / top_rms).log10())
+    }
+}
+
+/// Streaming loudness meter over interleaved frames
+struct LoudnessMeter {
+    channels: Vec<ChannelMeter>,
+    oversampler: Oversampler,
+    frames: u64,
+    segment_frames: usize,
+    segment_fill: usize,
+    segment_energy: f64,
+    /// Mean weighted K-filtered power of each complete 100 ms segment
+    segments: Vec<f64>,
+    dr_block_frames: usize,
+    clipped_samples: u64,
+    clip_runs: u64,
+    longest_clip_run: u64,
+}
+
+impl LoudnessMeter {
+    fn new(sample_rate: u32, weights: Vec<f64>) -> Self {
+        Self {
+            channels: weights.into_iter().map(|w| ChannelMeter::new(sample_rate, w)).collect(),
+            oversampler: Oversampler::new(sample_rate),
+            frames: 0,
+            segment_frames: (sample_rate as usize / SEGMENTS_PER_SEC).max(1),
+            segment_fill: 0,
+            segment_energy: 0.0,
+            segments: Vec::new(),
+            dr_block_frames: (sample_rate as usize * DR_BLOCK_SECS).max(1),
+            clipped_samples: 0,
+            clip_runs: 0,
+            longest_clip_run: 0,
+        }
+    }
+
+    fn add_interleaved(&mut self, samples: &[f32]) {
+        let channel_count = self.channels.len();
+        for frame in samples.chunks_exact(channel_count) {
+            self.add_frame(frame);
+        }
+    }
+
+    fn add_frame(&mut self, frame: &[f32]) {
+        let mut energy = 0.0;
+
+        for (ch, &sample) in self.channels.iter_mut().zip(frame) {
+            let x = sample as f64;
+            let magnitude = x.abs();
+
+            // Loudness
+            let y = ch.highpass.process(ch.shelf.process(x));
+            energy += ch.weight * y * y;
+
+            // Peaks; only interpolate where the result could beat the current true peak
+            ch.sample_peak = ch.sample_peak.max(magnitude);
+            ch.history.copy_within(..TRUE_PEAK_TAPS - 1, 1);
+            ch.history[0] = x;
+            let nearby = ch.history.iter().fold(0.0f64, |m, v| m.max(v.abs()));
+            if nearby * self.oversampler.gain_bound > ch.true_peak {
+                ch.true_peak = ch.true_peak.max(self.oversampler.peak(&ch.history));
+            }
+
+            // Clipping
+            if magnitude >= CLIP_LEVEL {
+                ch.clip_run += 1;
+            } else {
+                Self::end_clip_run(ch, &mut self.clipped_samples, &mut self.clip_runs, &mut self.longest_clip_run);
+            }
+
+            // DR blocks
+            ch.block_energy += x * x;
+            ch.block_peak = ch.block_peak.max(magnitude);
+            ch.block_frames += 1;
+            if ch.block_frames == self.dr_block_frames {
+                ch.close_block();
+            }
+        }
+
+        self.frames += 1;
+        self.segment_energy += energy;
+        self.segment_fill += 1;
+        if self.segment_fill == self.segment_frames {
+            self.segments.push(self.segment_energy / self.segment_frames as f64);
+            self.segment_energy = 0.0;
+            self.segment_fill = 0;
+        }
+    }
+
+    fn end_clip_run(ch: &mut ChannelMeter, clipped: &mut u64, runs: &mut u64, longest: &mut u64) {
+        if ch.clip_run >= MIN_CLIP_RUN {
+            *clipped += ch.clip_run;
+            *runs += 1;
+            *longest = (*longest).max(ch.clip_run);
+        }
+        ch.clip_run = 0;
+    }
+
+    fn finish(mut self) -> LoudnessDetails {
+        for ch in &mut self.channels {
+            Self::end_clip_run(ch, &mut self.clipped_samples, &mut self.clip_runs, &mut self.longest_clip_run);
+            // A trailing partial block only counts when there is nothing else
+            if ch.block_rms.is_empty() {
+                ch.close_block();
+            }
+        }
+
+        let dr: Vec<f64> = self.channels.iter().filter_map(ChannelMeter::dynamic_range).collect();
+        let sample_peak = self.channels.iter().map(|c| c.sample_peak).fold(0.0, f64::max);
+        let true_peak = self.channels.iter().map(|c| c.true_peak).fold(sample_peak, f64::max);
+
+        LoudnessDetails {
+            integrated_loudness: integrated_loudness(&self.segments),
+            loudness_range: loudness_range(&self.segments),
+            true_peak: to_db(true_peak),
+            sample_peak: to_db(sample_peak),
+            dynamic_range: if dr.is_empty() { 0.0 } else { dr.iter().sum::<f64>() / dr.len() as f64 },
+            clipped_samples: self.clipped_samples,
+            clip_runs: self.clip_runs,
+            longest_clip_run: self.longest_clip_run,
+        }
+    }
+}
+
+/// Mean power of each block of `len` segments, stepping one segment at a time
+fn block_powers(segments: &[f64], len: usize) -> Vec<f64> {
+    segments
+        .windows(len)
+        .map(|w| w.iter().sum::<f64>() / len as f64)
+        .collect()
+}
+
+/// Blocks passing the absolute gate and a gate `relative` LU below their mean
+fn gated(blocks: &[f64], relative: f64) -> Vec<f64> {
+    let audible: Vec<f64> = blocks.iter().copied().filter(|&p| power_to_lufs(p) > ABSOLUTE_GATE).collect();
+    if audible.is_empty() {
+        return audible;
+    }
+    let gate = power_to_lufs(audible.iter().sum::<f64>() / audible.len() as f64) + relative;
+    audible.into_iter().filter(|&p| power_to_lufs(p) > gate).collect()
+}
+
+/// BS.1770 gated integrated loudness (LUFS)
+fn integrated_loudness(segments: &[f64]) -> f64 {
+    let blocks = gated(&block_powers(segments, MOMENTARY_SEGMENTS), INTEGRATED_RELATIVE_GATE);
+    if blocks.is_empty() {
+        return ABSOLUTE_GATE;
+    }
+    power_to_lufs(blocks.iter().sum::<f64>() / blocks.len() as f64)
+}
+
+/// EBU Tech 3342 loudness range (LU)
+fn loudness_range(segments: &[f64]) -> f64 {
+    let mut loudness: Vec<f64> = gated(&block_powers(segments, SHORT_TERM_SEGMENTS), RANGE_RELATIVE_GATE)
+        .into_iter()
+        .map(power_to_lufs)
+        .collect();
+    if loudness.len() < 2 {
+        return 0.0;
+    }
+    loudness.sort_by(|a, b| a.total_cmp(b));
+    let percentile = |p: f64| loudness[((loudness.len() - 1) as f64 * p).round() as usize];
+    percentile(RANGE_HIGH_PERCENTILE) - percentile(RANGE_LOW_PERCENTILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_noise::TestNoise;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: Loudness vs Level
+    // ==========================================================================
+    //
+    // Peak level says how close the waveform gets to full scale; loudness
+    // says how loud it sounds. The "loudness war" pushed loudness up while
+    // peaks stayed pinned at 0 dBFS, which only works by squashing dynamics
+    // (low DR, small loudness range) or by clipping outright.
+    //
+    // Reference points:
+    //   - Streaming services normalise to around -14 LUFS
+    //   - EBU R128 broadcast target is -23 LUFS, true peak below -1 dBTP
+    //   - DR14 scores: 14+ very dynamic, 8-13 typical, below 8 heavily limited
+    //
+    // Calibration: a 1 kHz sine in both channels of a stereo file measures
+    // the same in LUFS as its peak in dBFS. K-weighting adds ~+0.69 dB at
+    // 1 kHz, which the -0.691 constant takes back out.
+    // ==========================================================================
+
+    const RATE: u32 = 48000;
+
+    fn measure(channels: usize, len: usize, signal: impl Fn(usize, usize) -> f32) -> LoudnessDetails {
+        let mut meter = LoudnessMeter::new(RATE, vec![1.0; channels]);
+        let interleaved: Vec<f32> = (0..len)
+            .flat_map(|n| (0..channels).map(move |c| (n, c)))
+            .map(|(n, c)| signal(n, c))
+            .collect();
+        meter.add_interleaved(&interleaved);
+        meter.finish()
+    }
+
+    fn sine(freq: f64, amplitude: f64, n: usize) -> f32 {
+        (amplitude * (2.0 * std::f64::consts::PI * freq * n as f64 / RATE as f64).sin()) as f32
+    }
+
+    fn noise(seed: u64) -> impl FnMut() -> f64 {
+        let mut noise = TestNoise::new(seed);
+        move || noise.signed()
+    }
+
+    #[test]
+    fn test_stereo_sine_calibration() {
+        let details = measure(2, RATE as usize * 10, |n, _| sine(1000.0, 0.1, n));
+        assert!((details.integrated_loudness + 20.0).abs() < 0.1, "Got {}", details.integrated_loudness);
+        assert!(details.loudness_range < 0.1, "Steady tone has no range");
+        assert!((details.sample_peak + 20.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_mono_full_scale_sine() {
+        // BS.1770 reference: 997 Hz at 0 dBFS in one channel reads -3.01 LUFS
+        let details = measure(1, RATE as usize * 5, |n, _| sine(997.0, 1.0, n));
+        assert!((details.integrated_loudness + 3.01).abs() < 0.1, "Got {}", details.integrated_loudness);
+    }
+
+    #[test]
+    fn test_silence_is_gated() {
+        let details = measure(2, RATE as usize * 2, |_, _| 0.0);
+        assert_eq!(details.integrated_loudness, ABSOLUTE_GATE);
+        assert_eq!(details.loudness_range, 0.0);
+        assert_eq!(details.sample_peak, SILENCE_DB);
+    }
+
+    #[test]
+    fn test_loudness_range_of_two_levels() {
+        // 20 s at -30 dBFS then 20 s at -10 dBFS: range is ~20 LU
+        let half = RATE as usize * 20;
+        let details = measure(2, half * 2, |n, _| sine(1000.0, if n < half { 0.0316 } else { 0.316 }, n));
+        assert!((details.loudness_range - 20.0).abs() < 1.0, "Got {}", details.loudness_range);
+        // Quiet half is within 20 LU of the mean but more than 10 LU below it
+        assert!((details.integrated_loudness + 10.0).abs() < 0.5, "Got {}", details.integrated_loudness);
+    }
+
+    #[test]
+    fn test_true_peak_between_samples() {
+        // fs/4 sine at 45° phase: every sample lands at ±0.707 of the true peak
+        let details = measure(1, RATE as usize, |n, _| {
+            (0.5 * (std::f64::consts::FRAC_PI_2 * n as f64 + std::f64::consts::FRAC_PI_4).sin()) as f32
+        });
+        assert!((details.sample_peak + 9.03).abs() < 0.05, "Got {}", details.sample_peak);
+        assert!((details.true_peak + 6.02).abs() < 0.3, "Got {}", details.true_peak);
+    }
+
+    #[test]
+    fn test_dynamic_range_sine_vs_noise_bursts() {
+        // DR treats a steady sine as having no dynamics
+        let steady = measure(2, RATE as usize * 12, |n, _| sine(440.0, 0.5, n));
+        assert!(steady.dynamic_range.abs() < 0.2, "Got {}", steady.dynamic_range);
+
+        // Sparse clicks over quiet noise: high peaks, low loudness
+        let mut rng = noise(5);
+        let samples: Vec<f32> = (0..RATE as usize * 12)
+            .map(|n| if n % 4800 == 0 { 0.9 } else { (rng() * 0.01) as f32 })
+            .collect();
+        let dynamic = measure(1, samples.len(), |n, _| samples[n]);
+        assert!(dynamic.dynamic_range > 20.0, "Got {}", dynamic.dynamic_range);
+    }
+
+    #[test]
+    fn test_clipped_runs() {
+        // Square-topped sine: clipped at 0.999 for a stretch of every cycle
+        let details = measure(2, RATE as usize, |n, _| (sine(100.0, 1.5, n) as f64).clamp(-1.0, 1.0) as f32);
+        // 100 Hz for 1 s: 200 flat tops per channel
+        assert_eq!(details.clip_runs, 400);
+        assert!(details.longest_clip_run > 100);
+        assert!(details.clipped_samples >= details.clip_runs * details.longest_clip_run / 2);
+    }
+
+    #[test]
+    fn test_isolated_full_scale_samples_not_clipping() {
+        let details = measure(1, RATE as usize, |n, _| if n % 1000 == 0 { 1.0 } else { 0.0 });
+        assert_eq!(details.clip_runs, 0);
+        assert_eq!(details.clipped_samples, 0);
+        assert_eq!(details.sample_peak, 0.0);
+    }
+
+    #[test]
+    fn test_channel_weights() {
+        assert_eq!(channel_weight(Channels::FRONT_LEFT), 1.0);
+        assert_eq!(channel_weight(Channels::LFE1), 0.0);
+        assert_eq!(channel_weight(Channels::SIDE_RIGHT), 1.41);
+    }
+}
@@ -0,0 +1,457 @@
+//! Psychoacoustic quantisation-noise analysis
+//!
+//! A lossy encoder spends its bits where they are audible. It runs a masking
+//! model over each block, and any component below the masking threshold is
+//! quantised coarsely or dropped to zero. Bandwidth can stay intact (V0,
+//! 320k, AAC 256), but the time-frequency plane keeps the encoder's decisions:
+//! components that sat near the threshold flicker in and out from one block
+//! to the next.
+//!
+//! ```text
+//! one bin over time, near the threshold:
+//!
+//!  threshold ─ ─ ─ ─ ─ ─ ─ ─ ─ ─ ─ ─ ─ ─ ─
+//!  lossless    ~~~~~~~~~~~~~~~~~~~~~~~~~     (noise stays put)
+//!  lossy       ~~~~~~    ~~~~~~~~   ~~~~     (dropped blocks leave holes)
+//!                    └──┘        └─┘
+//! ```
+//!
+//! The model here is deliberately simple: half-Bark bands, the Schroeder
+//! spreading function and a fixed offset below the spread energy. Each cell
+//! whose neighbours two frames either side sit near the threshold is a
+//! candidate; it is a hole if it falls far below both the threshold and those
+//! neighbours. Natural audio, tonal or noisy, almost never does that: a
+//! steady floor stays a floor, and a decaying partial doesn't come back.
+//!
+//! The hole rate is measured per band group, and `masking_conformity` rises
+//! from 0 at the natural rate to 1 at ten times that, in the worst group.
+
+use rustfft::{num_complex::Complex, FftPlanner};
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// FFT size at 44.1 kHz (~23 ms, between an MP3 granule pair and an AAC frame)
+const BASE_FFT_SIZE: usize = 1024;
+const BASE_SAMPLE_RATE: f64 = 44100.0;
+/// Masking threshold below the spread band energy (dB)
+const MASK_OFFSET_DB: f64 = 12.0;
+/// Frames either side that must hold content near the threshold
+const NEIGHBOUR_FRAMES: usize = 2;
+/// Neighbours count as near the threshold within this range (dB)
+const NEAR_BELOW_DB: f64 = -15.0;
+const NEAR_ABOVE_DB: f64 = 10.0;
+/// A hole sits this far below the threshold (dB)...
+const HOLE_BELOW_MASK_DB: f64 = -20.0;
+/// ...and this far below both neighbours (dB)
+const HOLE_BELOW_NEIGHBOURS_DB: f64 = -30.0;
+/// Thresholds below this (dB re a full-scale sine's bin) are digital silence
+const SILENT_MASK_DB: f64 = -90.0;
+/// Band groups (Hz); the top one stops short of any lossy cutoff
+const BAND_GROUPS: [(f64, f64); 4] = [
+    (1500.0, 6000.0),
+    (6000.0, 11000.0),
+    (11000.0, 16000.0),
+    (16000.0, 20000.0),
+];
+/// Depth below the threshold tracked for the noise-to-mask median (dB)
+const BELOW_MASK_RANGE_DB: usize = 120;
+/// Fewest candidates for a group's hole rate to count
+const MIN_CANDIDATES: usize = 1000;
+/// Hole rate of natural audio: noise and steady partials stay below this
+const NATURAL_HOLE_RATE: f64 = 0.0005;
+/// Conformity reaches 1 at this multiple of the natural rate
+const CONFORMITY_SPAN: f64 = 10.0;
+
+/// Noise-to-mask measurements for one band group
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MaskingBand {
+    pub low_hz: f64,
+    pub high_hz: f64,
+    /// Median level of the components below the threshold, relative to it (dB)
+    pub noise_to_mask_db: f64,
+    /// Cells with content near the threshold two frames either side
+    pub candidates: usize,
+    /// Fraction of candidates that are holes
+    pub hole_rate: f64,
+}
+
+/// How closely the file's noise follows a masking model
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MaskingEstimate {
+    /// 0.0 = natural noise, 1.0 = noise shaped by a lossy encoder's masking model
+    pub masking_conformity: f64,
+    /// Band groups with enough candidates to measure
+    pub bands: Vec<MaskingBand>,
+}
+
+impl MaskingEstimate {
+    /// Flag text, e.g. "mask_shaped_noise(conformity=0.93)"
+    pub fn flag(&self, name: &str) -> String {
+        format!("{}(conformity={:.2})", name, self.masking_conformity)
+    }
+}
+
+/// Run the masking model over mono samples. `max_hz` caps the band groups
+/// (pass a lossy cutoff so the empty band above it isn't read as holes).
+/// Returns None if no band group had enough candidates.
+pub fn estimate(samples: &[f64], sample_rate: u32, max_hz: f64) -> Option<MaskingEstimate> {
+    let fft_size = fft_size(sample_rate);
+    let hop = fft_size / 2;
+    if samples.len() < fft_size * 8 {
+        return None;
+    }
+
+    let model = MaskingModel::new(sample_rate, fft_size);
+    let window: Vec<f64> = (0..fft_size)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / fft_size as f64).cos())
+        .collect();
+    let fft = FftPlanner::new().plan_fft_forward(fft_size);
+    // A full-scale sine peaks at (N/4)² in a Hann-windowed bin
+    let full_scale = (fft_size as f64 / 4.0).powi(2);
+    let silent = full_scale * db_to_power(SILENT_MASK_DB);
+
+    let bin_hz = sample_rate as f64 / fft_size as f64;
+    let nyquist = sample_rate as f64 / 2.0;
+    let mut groups: Vec<BandGroup> = BAND_GROUPS
+        .iter()
+        .map(|&(low_hz, high_hz)| (low_hz, high_hz.min(max_hz).min(nyquist)))
+        .filter(|&(low_hz, high_hz)| high_hz - low_hz >= 1000.0)
+        .map(|(low_hz, high_hz)| BandGroup {
+            low_hz,
+            high_hz,
+            bins: (low_hz / bin_hz).ceil() as usize..(high_hz / bin_hz) as usize,
+            below_mask: vec![0; BELOW_MASK_RANGE_DB],
+            ..Default::default()
+        })
+        .collect();
+
+    // Only the frames either side of the one being judged are kept
+    let span = 2 * NEIGHBOUR_FRAMES + 1;
+    let mut recent: VecDeque<(Vec<f64>, Vec<f64>)> = VecDeque::with_capacity(span);
+    let mut buf = vec![Complex::new(0.0, 0.0); fft_size];
+    let frames = (samples.len() - fft_size) / hop + 1;
+    for f in 0..frames {
+        let frame = &samples[f * hop..f * hop + fft_size];
+        for (b, (&s, &w)) in buf.iter_mut().zip(frame.iter().zip(&window)) {
+            *b = Complex::new(s * w, 0.0);
+        }
+        fft.process(&mut buf);
+        let power: Vec<f64> = buf[..fft_size / 2].iter().map(|c| c.norm_sqr()).collect();
+        let thresholds = model.thresholds(&power);
+        if recent.len() == span {
+            recent.pop_front();
+        }
+        recent.push_back((power, thresholds));
+        if recent.len() == span {
+            for group in &mut groups {
+                group.judge(
+                    &recent[0].0,
+                    &recent[NEIGHBOUR_FRAMES],
+                    &recent[span - 1].0,
+                    silent,
+                );
+            }
+        }
+    }
+
+    let bands: Vec<MaskingBand> = groups
+        .into_iter()
+        .filter(|g| g.candidates >= MIN_CANDIDATES)
+        .map(BandGroup::finish)
+        .collect();
+
+    let worst = bands
+        .iter()
+        .map(|b| b.hole_rate)
+        .fold(None, |w: Option<f64>, r| Some(w.map_or(r, |w| w.max(r))))?;
+    Some(MaskingEstimate {
+        masking_conformity: conformity(worst),
+        bands,
+    })
+}
+
+/// Running counts for one band group
+#[derive(Default)]
+struct BandGroup {
+    low_hz: f64,
+    high_hz: f64,
+    bins: std::ops::Range<usize>,
+    candidates: usize,
+    holes: usize,
+    /// Cells below the threshold, by whole dB under it
+    below_mask: Vec<usize>,
+}
+
+impl BandGroup {
+    /// Judge the middle frame's cells against the frames either side
+    fn judge(
+        &mut self,
+        before: &[f64],
+        (power, thresholds): &(Vec<f64>, Vec<f64>),
+        after: &[f64],
+        silent: f64,
+    ) {
+        let near_low = db_to_power(NEAR_BELOW_DB);
+        let near_high = db_to_power(NEAR_ABOVE_DB);
+        let hole_mask = db_to_power(HOLE_BELOW_MASK_DB);
+        let hole_neighbours = db_to_power(HOLE_BELOW_NEIGHBOURS_DB);
+        for k in self.bins.clone() {
+            let threshold = thresholds[k];
+            if threshold < silent {
+                continue;
+            }
+            if power[k] < threshold {
+                let depth = (-power_to_db(power[k] / threshold)) as usize;
+                self.below_mask[depth.min(BELOW_MASK_RANGE_DB - 1)] += 1;
+            }
+            let neighbours = before[k].min(after[k]);
+            if neighbours < threshold * near_low || neighbours > threshold * near_high {
+                continue;
+            }
+            self.candidates += 1;
+            if power[k] < threshold * hole_mask && power[k] < neighbours * hole_neighbours {
+                self.holes += 1;
+            }
+        }
+    }
+
+    fn finish(self) -> MaskingBand {
+        let total: usize = self.below_mask.iter().sum();
+        let mut seen = 0;
+        let median = self.below_mask.iter().position(|&n| {
+            seen += n;
+            seen * 2 > total
+        });
+        MaskingBand {
+            low_hz: self.low_hz,
+            high_hz: self.high_hz,
+            noise_to_mask_db: median.map_or(0.0, |depth| -(depth as f64 + 0.5)),
+            candidates: self.candidates,
+            hole_rate: self.holes as f64 / self.candidates as f64,
+        }
+    }
+}
+
+/// Conformity from a hole rate: 0 at the natural rate, 1 at CONFORMITY_SPAN times it (log scale)
+fn conformity(hole_rate: f64) -> f64 {
+    if hole_rate <= NATURAL_HOLE_RATE {
+        return 0.0;
+    }
+    ((hole_rate / NATURAL_HOLE_RATE).ln() / CONFORMITY_SPAN.ln()).min(1.0)
+}
+
+/// FFT size giving about the same frame length at any sample rate
+fn fft_size(sample_rate: u32) -> usize {
+    let scaled = BASE_FFT_SIZE as f64 * sample_rate as f64 / BASE_SAMPLE_RATE;
+    (scaled.round() as usize)
+        .next_power_of_two()
+        .max(BASE_FFT_SIZE / 2)
+}
+
+fn db_to_power(db: f64) -> f64 {
+    10f64.powf(db / 10.0)
+}
+
+fn power_to_db(power: f64) -> f64 {
+    10.0 * power.max(1e-20).log10()
+}
+
+/// Critical-band rate (Bark) of a frequency (Zwicker & Terhardt)
+fn bark(freq: f64) -> f64 {
+    13.0 * (0.00076 * freq).atan() + 3.5 * (freq / 7500.0).powi(2).atan()
+}
+
+/// Schroeder spreading function: masking `dz` Bark away from the masker (dB)
+fn spreading_db(dz: f64) -> f64 {
+    15.81 + 7.5 * (dz + 0.474) - 17.5 * (1.0 + (dz + 0.474).powi(2)).sqrt()
+}
+
+/// Half-Bark bands and their spreading matrix for one FFT size
+struct MaskingModel {
+    /// Band of each bin
+    band_of: Vec<usize>,
+    /// Bins per band
+    widths: Vec<usize>,
+    /// spread[maskee][masker] as a power ratio, offset included
+    spread: Vec<Vec<f64>>,
+}
+
+impl MaskingModel {
+    fn new(sample_rate: u32, fft_size: usize) -> Self {
+        let bin_hz = sample_rate as f64 / fft_size as f64;
+        let band_of: Vec<usize> = (0..fft_size / 2)
+            .map(|k| (bark(k as f64 * bin_hz) * 2.0) as usize)
+            .collect();
+        let bands = band_of.last().map_or(0, |&b| b + 1);
+        let mut widths = vec![0; bands];
+        for &b in &band_of {
+            widths[b] += 1;
+        }
+        let centre = |b: usize| (b as f64 + 0.5) / 2.0;
+        let offset = db_to_power(-MASK_OFFSET_DB);
+        let spread = (0..bands)
+            .map(|maskee| {
+                (0..bands)
+                    .map(|masker| {
+                        db_to_power(spreading_db(centre(maskee) - centre(masker))) * offset
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            band_of,
+            widths,
+            spread,
+        }
+    }
+
+    /// Masking threshold per bin for one power spectrum
+    fn thresholds(&self, power: &[f64]) -> Vec<f64> {
+        let mut energy = vec![0.0; self.widths.len()];
+        for (&p, &b) in power.iter().zip(&self.band_of) {
+            energy[b] += p;
+        }
+        let per_bin: Vec<f64> = self
+            .spread
+            .iter()
+            .zip(&self.widths)
+            .map(|(row, &width)| {
+                let masked: f64 = row.iter().zip(&energy).map(|(s, e)| s * e).sum();
+                masked / width.max(1) as f64
+            })
+            .collect();
+        self.band_of.iter().map(|&b| per_bin[b]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_noise::TestNoise;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: Masking and why encoders leave holes
+    // ==========================================================================
+    //
+    // A loud sound hides quieter ones near it in frequency: the masker raises
+    // the hearing threshold over a skirt that falls ~25 dB per Bark below it
+    // and ~10 dB per Bark above it. Encoders compute that threshold for every
+    // block and quantise each band so the noise stays under it. Components
+    // that were under it anyway round to zero.
+    //
+    // Near the threshold that decision is a coin toss from one block to the
+    // next, so content there blinks on and off. A spectrogram of a 320k MP3
+    // shows it as speckle in the upper bands. The recording itself has no
+    // reason to do that: noise at a steady level stays there.
+    // ==========================================================================
+
+    const RATE: u32 = 44100;
+
+    fn noise(seed: u64) -> impl FnMut() -> f64 {
+        let mut noise = TestNoise::new(seed);
+        move || noise.signed()
+    }
+
+    /// Tones at 3, 7 and 12 kHz over a hiss floor, 4 s
+    fn test_signal() -> Vec<f64> {
+        let mut rng = noise(3);
+        (0..RATE as usize * 4)
+            .map(|n| {
+                let t = n as f64 / RATE as f64;
+                let tones: f64 = [3000.0, 7000.0, 12000.0]
+                    .iter()
+                    .map(|f| 0.1 * (2.0 * std::f64::consts::PI * f * t).sin())
+                    .sum();
+                tones + 0.003 * rng()
+            })
+            .collect()
+    }
+
+    /// Toy perceptual coder: sine-windowed 50% overlap frames, every bin whose
+    /// power falls under the model's threshold (plus `margin_db`) dropped
+    fn toy_encode(samples: &[f64], margin_db: f64) -> Vec<f64> {
+        let size = 1152;
+        let hop = size / 2;
+        let model = MaskingModel::new(RATE, size);
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(size);
+        let ifft = planner.plan_fft_inverse(size);
+        let window: Vec<f64> = (0..size)
+            .map(|i| (std::f64::consts::PI * (i as f64 + 0.5) / size as f64).sin())
+            .collect();
+        let mut out = vec![0.0; samples.len()];
+        for start in (0..samples.len() - size).step_by(hop) {
+            let mut buf: Vec<Complex<f64>> = (0..size)
+                .map(|i| Complex::new(samples[start + i] * window[i], 0.0))
+                .collect();
+            fft.process(&mut buf);
+            let power: Vec<f64> = buf[..size / 2].iter().map(|c| c.norm_sqr()).collect();
+            let thresholds = model.thresholds(&power);
+            for k in 0..size / 2 {
+                if power[k] < thresholds[k] * db_to_power(margin_db) {
+                    buf[k] = Complex::new(0.0, 0.0);
+                    if k > 0 {
+                        buf[size - k] = Complex::new(0.0, 0.0);
+                    }
+                }
+            }
+            ifft.process(&mut buf);
+            for i in 0..size {
+                out[start + i] += buf[i].re / size as f64 * window[i];
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_natural_signals_conform_little() {
+        let original = estimate(&test_signal(), RATE, 20000.0).unwrap();
+        assert!(original.masking_conformity < 0.3, "Got {:?}", original);
+
+        let mut rng = noise(9);
+        let white: Vec<f64> = (0..RATE as usize * 4).map(|_| 0.1 * rng()).collect();
+        let white = estimate(&white, RATE, 20000.0).unwrap();
+        assert!(white.masking_conformity < 0.3, "Got {:?}", white);
+    }
+
+    #[test]
+    fn test_toy_encoder_conforms() {
+        let encoded = estimate(&toy_encode(&test_signal(), 0.0), RATE, 20000.0).unwrap();
+        assert!(encoded.masking_conformity > 0.8, "Got {:?}", encoded);
+        assert!(encoded
+            .bands
+            .iter()
+            .any(|b| b.hole_rate > NATURAL_HOLE_RATE * CONFORMITY_SPAN));
+    }
+
+    #[test]
+    fn test_cutoff_limits_band_groups() {
+        let estimate = estimate(&test_signal(), RATE, 16000.0).unwrap();
+        assert!(estimate.bands.iter().all(|b| b.high_hz <= 16000.0));
+    }
+
+    #[test]
+    fn test_silence_and_short_input() {
+        assert!(estimate(&vec![0.0; RATE as usize * 2], RATE, 20000.0).is_none());
+        assert!(estimate(&[0.1; 1000], RATE, 20000.0).is_none());
+    }
+
+    #[test]
+    fn test_conformity_scale() {
+        assert_eq!(conformity(0.0), 0.0);
+        assert_eq!(conformity(NATURAL_HOLE_RATE), 0.0);
+        assert!((conformity(NATURAL_HOLE_RATE * CONFORMITY_SPAN.sqrt()) - 0.5).abs() < 1e-9);
+        assert_eq!(conformity(1.0), 1.0);
+    }
+
+    #[test]
+    fn test_spreading_and_bark() {
+        // Peak of the spreading function sits at the masker
+        assert!(spreading_db(0.0).abs() < 0.1);
+        // Masking spreads further upward in frequency than downward
+        assert!(spreading_db(1.0) > spreading_db(-1.0));
+        assert!((bark(1000.0) - 8.5).abs() < 0.2);
+        assert_eq!(fft_size(44100), 1024);
+        assert_eq!(fft_size(96000), 4096);
+    }
+}
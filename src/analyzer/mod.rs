@@ -3,10 +3,17 @@
 //! This module orchestrates the complete analysis pipeline:
 //!
 //! 1. **File Reading**: Load audio data and extract basic metadata
-//! 2. **Binary Analysis**: Check LAME headers, encoder signatures, frame structure
-//! 3. **Spectral Analysis**: FFT-based frequency content analysis
-//! 4. **Score Combination**: Merge evidence from both analyses
-//! 5. **Verdict**: Classify as OK, SUSPECT, or TRANSCODE
+//! 2. **Binary Analysis**: Check LAME headers, encoder signatures, frame structure,
+//!    lossyWAV zeroed bits or tags, and HDCD codes (reported only)
+//! 3. **Spectral Analysis**: FFT-based frequency content analysis, with
+//!    CD pre-emphasis taken out of the band levels, and a masking model
+//!    checking whether the noise was shaped by a lossy encoder
+//! 4. **Loudness**: Integrated loudness, true peak, dynamic range and clipping
+//!    (reported only, not scored)
+//! 5. **Integrity**: Decode errors, truncation, dropouts and clicks, with their
+//!    own OK / GLITCHES / DAMAGED verdict (not scored)
+//! 6. **Score Combination**: Merge evidence from both analyses
+//! 7. **Verdict**: Classify as OK, SUSPECT, or TRANSCODE
 //!
 //! # Scoring System
 //!
@@ -27,8 +34,24 @@
 //! evidence from independent detection methods.
 
 pub mod binary;
+pub mod content;
+pub mod cutoff;
+pub mod decode;
+pub mod emphasis;
+pub mod generation;
+pub mod hdcd;
+pub mod integrity;
+pub mod lossywav;
+pub mod loudness;
+pub mod masking;
+pub mod resample;
 pub mod spectral;
 
+#[cfg(test)]
+pub(crate) mod test_noise;
+#[cfg(test)]
+mod test_wav;
+
 use crate::mp3;
 use serde::Serialize;
 use std::path::Path;
@@ -49,10 +72,27 @@ pub struct AnalysisResult {
     pub encoder: String,
     pub lowpass: Option<u32>,
     pub spectral_details: Option<spectral::SpectralDetails>,
+    pub loudness_details: Option<loudness::LoudnessDetails>,
+    /// Stream integrity, judged apart from the transcode verdict
+    pub integrity: Option<integrity::IntegrityVerdict>,
+    pub integrity_details: Option<integrity::IntegrityDetails>,
     pub binary_details: Option<binary::BinaryDetails>,
+    /// Verdict per full-range channel of a multichannel file (empty for mono/stereo)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub channel_verdicts: Vec<ChannelVerdict>,
     pub error: Option<String>,
 }
 
+/// Verdict for one channel of a multichannel file
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelVerdict {
+    /// Speaker label ("FL", "SL"...)
+    pub channel: String,
+    pub verdict: Verdict,
+    /// Combined score using this channel's spectral score
+    pub score: u32,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum Verdict {
@@ -81,6 +121,10 @@ pub struct Analyzer {
     pub transcode_threshold: u32,
     /// Threshold for suspect verdict (default: 35)
     pub suspect_threshold: u32,
+    /// FFT size, window and hop for spectral analysis
+    pub fft_config: spectral::FftConfig,
+    /// Cue sheets already read, by directory
+    cue_sheets: emphasis::CueSheetCache,
 }
 
 impl Default for Analyzer {
@@ -89,6 +133,8 @@ impl Default for Analyzer {
             skip_spectral: false,
             transcode_threshold: 65,
             suspect_threshold: 35,
+            fft_config: spectral::FftConfig::default(),
+            cue_sheets: emphasis::CueSheetCache::default(),
         }
     }
 }
@@ -109,6 +155,11 @@ impl Analyzer {
         self
     }
 
+    pub fn with_fft_config(mut self, config: spectral::FftConfig) -> Self {
+        self.fft_config = config;
+        self
+    }
+
     /// Analyze a single MP3 file
     pub fn analyze<P: AsRef<Path>>(&self, path: P) -> AnalysisResult {
         let path = path.as_ref();
@@ -136,7 +187,11 @@ impl Analyzer {
                     encoder: String::new(),
                     lowpass: None,
                     spectral_details: None,
+                    loudness_details: None,
+                    integrity: None,
+                    integrity_details: None,
                     binary_details: None,
+                    channel_verdicts: vec![],
                     error: Some(format!("Failed to read file: {}", e)),
                 };
             }
@@ -154,7 +209,10 @@ impl Analyzer {
                 cursor.set_position(pos);
                 let mut header_buf = [0u8; 4];
                 if cursor.read_exact(&mut header_buf).is_ok() {
-                    if let Some(frame) = mp3::frame::FrameHeader::parse(header_buf) {
+                    // Free format headers still carry the sample rate
+                    let header = mp3::frame::FrameHeader::parse(header_buf)
+                        .or_else(|| mp3::frame::FrameHeader::parse_free_format(header_buf));
+                    if let Some(frame) = header {
                         frame.sample_rate
                     } else {
                         44100
@@ -169,46 +227,116 @@ impl Analyzer {
             44100
         };
 
-        // Estimate duration
-        let duration_secs = if bitrate > 0 {
-            (data.len() as f64 * 8.0) / (bitrate as f64 * 1000.0)
-        } else {
-            0.0
-        };
-
         // Binary analysis
         cursor.set_position(0);
-        let binary_result = binary::analyze(&data, &mut cursor, bitrate);
+        let mut binary_result = binary::analyze(&data, &mut cursor, bitrate);
 
-        // Spectral analysis (if not skipped)
-        let spectral_result = if self.skip_spectral {
-            spectral::SpectralResult::default()
+        // Everything that looks at samples reads this one decode
+        let decoded = if self.skip_spectral {
+            None
         } else {
-            spectral::analyze(&data, sample_rate)
+            decode::DecodedAudio::decode(&data)
         };
 
-        // Combine scores
-        let mut combined_score = binary_result.score + spectral_result.score;
+        // MP3: the frame walk, timing each frame by its own header. Anything
+        // else (or an MP3 the walk couldn't cover) goes by the decoder, then
+        // by what the container declares, then by size and bitrate
+        let duration_secs = binary_result
+            .details
+            .duration_secs
+            .or_else(|| decoded.as_ref().map(|audio| audio.duration_secs()))
+            .or_else(|| decode::DecodedAudio::declared_duration(&data))
+            .unwrap_or_else(|| match bitrate {
+                0 => 0.0,
+                _ => (data.len() as f64 * 8.0) / (bitrate as f64 * 1000.0),
+            });
+
+        // lossyWAV: tags are always checked, zeroed sample bits need the decode
+        if let Some(lossywav) = lossywav::analyze(&data, decoded.as_ref()) {
+            if lossywav.detected() {
+                binary_result.score += lossywav::LOSSYWAV_SCORE;
+                binary_result.flags.push(lossywav.flag());
+            }
+            binary_result.details.lossywav = Some(lossywav);
+        }
 
-        // Bonus if both analyses agree
-        if spectral_result.score >= 30 && binary_result.score >= 20 {
-            combined_score += 15;
+        // HDCD control codes ride in the LSB of 16-bit audio; reported, not scored
+        if let Some(hdcd) = decoded.as_ref().and_then(hdcd::analyze) {
+            if hdcd.detected() {
+                binary_result.flags.push(hdcd.flag());
+            }
+            binary_result.details.hdcd = Some(hdcd);
         }
 
-        combined_score = combined_score.min(100);
+        // Spectral analysis (if not skipped)
+        let spectral_result = match &decoded {
+            Some(audio) => {
+                // Pre-emphasis is a CD flag, so only lossless PCM can declare it:
+                // a cue sheet's PRE flag, or the FLAC's own cue sheet
+                let declared_emphasis = if !audio.is_integer_pcm() {
+                    None
+                } else if self.cue_sheets.declares_emphasis(path) {
+                    Some(emphasis::EmphasisSource::CueSheet)
+                } else {
+                    crate::flac::cuesheet_pre_emphasis(&data).then_some(emphasis::EmphasisSource::FlacCuesheet)
+                };
+                spectral::analyze_decoded(audio, &self.fft_config, declared_emphasis)
+            }
+            None if self.skip_spectral => spectral::SpectralResult::default(),
+            None => spectral::SpectralResult::with_config(self.fft_config.sanitized()),
+        };
+
+        // Fraunhofer records no lowpass: check the bandwidth its encoders use at this
+        // bitrate against the measured cutoff. The spectral score already counts the
+        // cutoff itself, so this only names the mismatch
+        if let (Some(vbri), Some(fit)) = (&binary_result.details.vbri, &spectral_result.details.cutoff_fit) {
+            if fit.is_reliable() {
+                let average = vbri.average_bitrate().unwrap_or(bitrate);
+                binary_result.flags.extend(mp3::vbri::check_bandwidth(average, fit.frequency));
+            }
+        }
+
+        // Loudness and integrity run over the whole decode
+        let loudness_details = decoded.as_ref().and_then(loudness::analyze);
+        let integrity_details = decoded.as_ref().map(|audio| {
+            integrity::analyze(audio, &data, binary_result.details.stream_structure.as_ref())
+        });
+
+        // Combine scores
+        let (combined_score, verdict) = self.combine(spectral_result.score, binary_result.score);
 
         // Merge flags
         let mut flags = binary_result.flags.clone();
         flags.extend(spectral_result.flags.clone());
+        if let Some(ref details) = integrity_details {
+            flags.extend(details.flags());
+        }
 
-        // Determine verdict
-        let verdict = if combined_score >= self.transcode_threshold {
-            Verdict::Transcode
-        } else if combined_score >= self.suspect_threshold {
-            Verdict::Suspect
-        } else {
-            Verdict::Ok
-        };
+        // Multichannel: judge each channel on its own spectral score, and name
+        // the channels that look worse than the file as a whole
+        let channel_verdicts: Vec<ChannelVerdict> = spectral_result
+            .details
+            .channels
+            .iter()
+            .flatten()
+            .filter(|c| c.scored)
+            .map(|c| {
+                let (score, verdict) = self.combine(c.score, binary_result.score);
+                ChannelVerdict {
+                    channel: c.label.clone(),
+                    verdict,
+                    score,
+                }
+            })
+            .collect();
+        let worse: Vec<&str> = channel_verdicts
+            .iter()
+            .filter(|c| c.verdict != Verdict::Ok && c.verdict != verdict && c.score > combined_score)
+            .map(|c| c.channel.as_str())
+            .collect();
+        if !worse.is_empty() {
+            flags.push(format!("lossy_channels({})", worse.join(",")));
+        }
 
         AnalysisResult {
             file_path,
@@ -224,10 +352,37 @@ impl Analyzer {
             encoder: binary_result.encoder,
             lowpass: binary_result.lowpass,
             spectral_details: Some(spectral_result.details),
+            loudness_details,
+            integrity: integrity_details.as_ref().map(|d| d.verdict()),
+            integrity_details,
             binary_details: Some(binary_result.details),
+            channel_verdicts,
             error: None,
         }
     }
+
+    /// Combine spectral and binary scores into a score and verdict
+    fn combine(&self, spectral_score: u32, binary_score: u32) -> (u32, Verdict) {
+        let mut combined_score = binary_score + spectral_score;
+
+        // Bonus if both analyses agree
+        if spectral_score >= 30 && binary_score >= 20 {
+            combined_score += 15;
+        }
+
+        combined_score = combined_score.min(100);
+
+        // Determine verdict
+        let verdict = if combined_score >= self.transcode_threshold {
+            Verdict::Transcode
+        } else if combined_score >= self.suspect_threshold {
+            Verdict::Suspect
+        } else {
+            Verdict::Ok
+        };
+
+        (combined_score, verdict)
+    }
 }
 
 use std::io::Read;
@@ -336,6 +491,23 @@ mod tests {
         assert_eq!(analyzer.transcode_threshold, 80);
     }
 
+    #[test]
+    fn test_analyzer_fft_config() {
+        // Default is the 8192-point Hann analysis the thresholds were tuned on
+        let analyzer = Analyzer::new();
+        assert_eq!(analyzer.fft_config, spectral::FftConfig::default());
+        assert_eq!(analyzer.fft_config.size, 8192);
+        assert_eq!(analyzer.fft_config.hop, 4096);
+
+        let config = spectral::FftConfig::new(16384)
+            .with_window(spectral::WindowType::BlackmanHarris)
+            .with_multi_resolution(Some(1024));
+        let analyzer = Analyzer::new().with_fft_config(config);
+        assert_eq!(analyzer.fft_config.size, 16384);
+        assert_eq!(analyzer.fft_config.window, spectral::WindowType::BlackmanHarris);
+        assert_eq!(analyzer.fft_config.multi_resolution, Some(1024));
+    }
+
     #[test]
     fn test_analyzer_builder_pattern() {
         // Builder methods should chain
@@ -348,6 +520,17 @@ mod tests {
         assert_eq!(analyzer.transcode_threshold, 70);
     }
 
+    #[test]
+    fn test_analyzer_combine() {
+        // Same scoring is applied to the file and to each surround channel
+        let analyzer = Analyzer::new();
+        assert_eq!(analyzer.combine(10, 0), (10, Verdict::Ok));
+        assert_eq!(analyzer.combine(40, 0), (40, Verdict::Suspect));
+        // Both analyses agree: +15 bonus
+        assert_eq!(analyzer.combine(30, 25), (70, Verdict::Transcode));
+        assert_eq!(analyzer.combine(80, 60), (100, Verdict::Transcode));
+    }
+
     // ==========================================================================
     // THRESHOLD DOCUMENTATION TESTS
     // ==========================================================================
@@ -443,7 +626,11 @@ mod tests {
             encoder: "LAME3.100".to_string(),
             lowpass: Some(20500),
             spectral_details: None,
+            loudness_details: None,
+            integrity: None,
+            integrity_details: None,
             binary_details: None,
+            channel_verdicts: vec![],
             error: None,
         };
 
@@ -0,0 +1,328 @@
+//! Sample-rate conversion detection
+//!
+//! A resampler has to band-limit the signal to the Nyquist frequency of the
+//! lower of its two rates. That anti-alias (or anti-image) filter leaves a
+//! stationary edge just below that Nyquist:
+//!
+//! ```text
+//! 48k → 44.1k (downsampled):          44.1k → 96k (upsampled):
+//!
+//!   ─────────────╮ │                    ─────────────╮          │
+//!                 ╲│                                  ╲         │
+//!                  │ ← 22.05k Nyquist                  ╰────────│
+//!             ~21k                                ~21k       48k Nyquist
+//! ```
+//!
+//! An edge at 88-98% of a standard rate's Nyquist is read as a resampling
+//! filter. When that Nyquist is below the file's own, the file was upsampled
+//! from that rate. When it is the file's own Nyquist, the file was downsampled
+//! from a higher rate that the filter alone can't reveal. The usual source for
+//! the target rate is reported as a guess, apart from rates that were measured.
+//!
+//! The position and width of the transition hint at the resampler:
+//!
+//! - **SoX** (`rate -v`/soxr): passband to ~95%, stopband reached by Nyquist
+//! - **libswresample** (FFmpeg default): wider transition running past Nyquist
+//! - **Core Audio** (iTunes SRC): passband ends early (~90%), steep
+//!
+//! Edges closer to Nyquist than 98% look like an ADC's own anti-alias filter,
+//! and edges in the range of MP3 lowpass filters (up to LAME's 320k ~20.5 kHz)
+//! are left to the lossy detectors. That rules out Core Audio's early passband
+//! at 44.1 kHz, where it would sit right on the 320k lowpass.
+
+use super::cutoff::{CutoffFit, EdgeShape};
+use crate::mp3::lame;
+use serde::Serialize;
+
+/// Rates a file may have been upsampled from (Hz)
+const SOURCE_RATES: [u32; 5] = [44100, 48000, 88200, 96000, 176400];
+/// Filter edge (logistic midpoint) as a fraction of Nyquist
+const MIN_FILTER_RATIO: f64 = 0.88;
+const MAX_FILTER_RATIO: f64 = 0.98;
+/// Typical filter midpoint, used to pick between overlapping source rates
+const TYPICAL_FILTER_RATIO: f64 = 0.95;
+/// Resampling filters attenuate to the noise floor (dB)
+const MIN_FILTER_DEPTH_DB: f64 = 30.0;
+/// Edges must clear the MP3 320k lowpass by this much (Hz)
+const LOSSY_CEILING_MARGIN_HZ: f64 = 250.0;
+/// Edges closer than this are the same edge (Hz)
+const SAME_EDGE_HZ: f64 = 300.0;
+/// Passband at or above this fraction of Nyquist = SoX-style
+const SOX_MIN_PASSBAND: f64 = 0.91;
+/// Transition still running at this fraction of Nyquist = aliasing allowed
+/// (the spectrum ends at Nyquist, so a fit can't see a transition past it)
+const ALIASING_STOPBAND: f64 = 0.99;
+/// 10-90% transition no wider than this fraction of Nyquist = steep filter
+const STEEP_FILTER_WIDTH: f64 = 0.03;
+
+/// Direction of the conversion
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResampleDirection {
+    /// Filter at a lower rate's Nyquist: converted up from that rate
+    #[default]
+    Upsampled,
+    /// Filter at the file's own Nyquist: converted down from a higher rate
+    Downsampled,
+}
+
+/// Likely resampler, from the shape of its filter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResamplerFamily {
+    #[default]
+    Unknown,
+    /// SoX / soxr: steep, passband to ~95%, alias-free
+    Sox,
+    /// FFmpeg libswresample: transition runs up to or past Nyquist
+    Swresample,
+    /// Apple Core Audio / iTunes: passband ends around 90%
+    CoreAudio,
+}
+
+impl ResamplerFamily {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResamplerFamily::Unknown => "unknown",
+            ResamplerFamily::Sox => "sox",
+            ResamplerFamily::Swresample => "swresample",
+            ResamplerFamily::CoreAudio => "coreaudio",
+        }
+    }
+}
+
+/// Evidence that the file went through a sample-rate conversion
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResampleEstimate {
+    pub direction: ResampleDirection,
+    /// Rate before conversion, from the filter's position (upsampled files only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_sample_rate: Option<u32>,
+    /// Usual source for the file's rate, when it was downsampled: inferred, not measured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_rate_guess: Option<u32>,
+    /// Filter midpoint as a fraction of the lower rate's Nyquist
+    pub filter_ratio: f64,
+    pub resampler: ResamplerFamily,
+    /// The filter edge
+    pub cutoff: CutoffFit,
+}
+
+impl ResampleEstimate {
+    /// Flag text, e.g. "resampled_from_44.1k(cutoff=21.3k,sox)" or
+    /// "resampled(cutoff=21.0k,sox,source_rate_guess=48k)"
+    pub fn flag(&self) -> String {
+        let source = match self.original_sample_rate {
+            Some(rate) => format!("resampled_from_{}", rate_label(rate)),
+            None => "resampled".to_string(),
+        };
+        let guess = match self.source_rate_guess {
+            Some(rate) => format!(",source_rate_guess={}", rate_label(rate)),
+            None => String::new(),
+        };
+        format!(
+            "{}(cutoff={:.1}k,{}{})",
+            source,
+            self.cutoff.frequency / 1000.0,
+            self.resampler.as_str(),
+            guess
+        )
+    }
+}
+
+/// Look for a resampling filter in a file at `sample_rate`, given its spectral
+/// edges and the whole-spectrum cutoff fit
+///
+/// Only the highest edge counts: below it there is nothing left for a later
+/// resampler to filter. The whole-spectrum fit sees the full passband, so it
+/// measures a wide edge better than the local edge fits do and is preferred
+/// when it describes the same edge.
+pub fn detect(edges: &[CutoffFit], fit: Option<&CutoffFit>, sample_rate: u32) -> Option<ResampleEstimate> {
+    let top = edges.iter().max_by(|a, b| a.frequency.total_cmp(&b.frequency));
+    let fit = fit.filter(|f| f.is_reliable() && matches!(f.shape, EdgeShape::BrickWall | EdgeShape::Steep));
+    let edge = match (top, fit) {
+        (Some(top), Some(fit)) if fit.frequency >= top.frequency - SAME_EDGE_HZ => fit,
+        (Some(top), _) => top,
+        (None, fit) => fit?,
+    };
+    if edge.depth < MIN_FILTER_DEPTH_DB || edge.frequency < lossy_ceiling_hz() {
+        return None;
+    }
+
+    let in_range = |ratio: f64| (MIN_FILTER_RATIO..=MAX_FILTER_RATIO).contains(&ratio);
+
+    // Upsampled: filter sits at a lower standard rate's Nyquist
+    let upsampled = SOURCE_RATES
+        .iter()
+        .filter(|&&rate| rate < sample_rate)
+        .map(|&rate| (rate, edge.frequency / (rate as f64 / 2.0)))
+        .filter(|&(_, ratio)| in_range(ratio))
+        .min_by(|a, b| (a.1 - TYPICAL_FILTER_RATIO).abs().total_cmp(&(b.1 - TYPICAL_FILTER_RATIO).abs()));
+
+    let (direction, original_sample_rate, source_rate_guess, filter_rate) = match upsampled {
+        Some((rate, _)) => (ResampleDirection::Upsampled, Some(rate), None, rate),
+        None => {
+            let ratio = edge.frequency / (sample_rate as f64 / 2.0);
+            if !in_range(ratio) {
+                return None;
+            }
+            (ResampleDirection::Downsampled, None, usual_source_rate(sample_rate), sample_rate)
+        }
+    };
+
+    let nyquist = filter_rate as f64 / 2.0;
+    Some(ResampleEstimate {
+        direction,
+        original_sample_rate,
+        source_rate_guess,
+        filter_ratio: edge.frequency / nyquist,
+        resampler: classify(edge, nyquist),
+        cutoff: edge.clone(),
+    })
+}
+
+/// Highest lossy lowpass a resampling filter could be mistaken for
+fn lossy_ceiling_hz() -> f64 {
+    lame::expected_lowpass_for_bitrate(320) as f64 + LOSSY_CEILING_MARGIN_HZ
+}
+
+/// Most common source when a file at `target` rate was downsampled
+fn usual_source_rate(target: u32) -> Option<u32> {
+    match target {
+        // Video and streaming audio is 48 kHz; CD-rate copies are converted from it
+        44100 => Some(48000),
+        48000 => Some(96000),
+        88200 => Some(176400),
+        96000 => Some(192000),
+        _ => None,
+    }
+}
+
+/// Guess the resampler family from where its transition band starts and ends
+fn classify(edge: &CutoffFit, nyquist: f64) -> ResamplerFamily {
+    let passband = (edge.frequency - edge.transition_width / 2.0) / nyquist;
+    let stopband = (edge.frequency + edge.transition_width / 2.0) / nyquist;
+    let width = edge.transition_width / nyquist;
+
+    if stopband >= ALIASING_STOPBAND {
+        ResamplerFamily::Swresample
+    } else if width > STEEP_FILTER_WIDTH {
+        ResamplerFamily::Unknown
+    } else if passband >= SOX_MIN_PASSBAND {
+        ResamplerFamily::Sox
+    } else {
+        ResamplerFamily::CoreAudio
+    }
+}
+
+/// "44.1k", "48k"
+fn rate_label(rate: u32) -> String {
+    if rate.is_multiple_of(1000) {
+        format!("{}k", rate / 1000)
+    } else {
+        format!("{:.1}k", rate as f64 / 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::cutoff;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: Resampling Is Not Lossy Encoding
+    // ==========================================================================
+    //
+    // Video soundtracks and streaming captures are 48 kHz. Converting them to
+    // 44.1 kHz for a CD-style library needs a steep lowpass just under
+    // 22.05 kHz, which looks a lot like an MP3 encoder's lowpass in a
+    // spectrogram. The difference is where the edge sits:
+    //
+    //   - MP3 lowpass: fixed frequencies by bitrate (16k, 18.5k, 20.5k...)
+    //   - Resampler:   a fixed fraction (~90-97%) of a standard Nyquist
+    //
+    // The same logic runs the other way: a 96 kHz "hi-res" file with nothing
+    // above ~21 kHz is a 44.1 kHz master that was upsampled.
+    // ==========================================================================
+
+    const FFT_SIZE: usize = 8192;
+
+    /// Averaged spectrum (dB) with a logistic edge at `edge_hz`
+    fn spectrum(sample_rate: u32, edge_hz: f64, width_hz: f64) -> (Vec<f64>, f64) {
+        let bin_hz = sample_rate as f64 / FFT_SIZE as f64;
+        let db = (0..FFT_SIZE / 2)
+            .map(|i| {
+                let f = i as f64 * bin_hz;
+                let ripple = ((i * 7919) % 13) as f64 / 13.0 - 0.5;
+                -80.0 / (1.0 + ((edge_hz - f) / (width_hz / 4.394)).exp()) + ripple
+            })
+            .collect();
+        (db, bin_hz)
+    }
+
+    fn detect_edge(sample_rate: u32, edge_hz: f64, width_hz: f64) -> Option<ResampleEstimate> {
+        let (db, bin_hz) = spectrum(sample_rate, edge_hz, width_hz);
+        let fit = cutoff::fit_cutoff(&db, bin_hz, 8000.0);
+        detect(&cutoff::find_edges(&db, bin_hz, 8000.0), fit.as_ref(), sample_rate)
+    }
+
+    #[test]
+    fn test_downsampled_sox() {
+        let est = detect_edge(44100, 21000.0, 200.0).expect("Resampling filter should be found");
+        assert_eq!(est.direction, ResampleDirection::Downsampled);
+        // The filter only shows the file's own rate; 48k is a guess
+        assert_eq!((est.original_sample_rate, est.source_rate_guess), (None, Some(48000)));
+        assert_eq!(est.resampler, ResamplerFamily::Sox);
+        assert_eq!(est.flag(), "resampled(cutoff=21.0k,sox,source_rate_guess=48k)");
+    }
+
+    #[test]
+    fn test_downsampled_swresample() {
+        // Transition runs up to the 22.05 kHz Nyquist
+        let est = detect_edge(44100, 21500.0, 1400.0).expect("Resampling filter should be found");
+        assert_eq!(est.resampler, ResamplerFamily::Swresample);
+    }
+
+    #[test]
+    fn test_downsampled_core_audio() {
+        // 96k → 48k with the passband ending at ~90%
+        let est = detect_edge(48000, 21800.0, 300.0).expect("Resampling filter should be found");
+        assert_eq!(est.direction, ResampleDirection::Downsampled);
+        assert_eq!(est.source_rate_guess, Some(96000));
+        assert_eq!(est.resampler, ResamplerFamily::CoreAudio);
+    }
+
+    #[test]
+    fn test_upsampled_from_cd_rate() {
+        let est = detect_edge(96000, 21300.0, 300.0).expect("Resampling filter should be found");
+        assert_eq!(est.direction, ResampleDirection::Upsampled);
+        assert_eq!((est.original_sample_rate, est.source_rate_guess), (Some(44100), None));
+        assert!(est.flag().starts_with("resampled_from_44.1k(cutoff=21.3k,"), "{}", est.flag());
+    }
+
+    #[test]
+    fn test_lossy_cutoffs_not_resampling() {
+        // 128k and 320k MP3 lowpass in a 44.1 kHz file
+        assert!(detect_edge(44100, 16000.0, 200.0).is_none());
+        assert!(detect_edge(44100, 20500.0, 200.0).is_none());
+        // 112k lowpass would otherwise sit at 97% of a 32 kHz Nyquist
+        assert!(detect_edge(44100, 15500.0, 200.0).is_none());
+    }
+
+    #[test]
+    fn test_adc_filter_at_nyquist_not_resampling() {
+        assert!(detect_edge(44100, 21900.0, 200.0).is_none());
+    }
+
+    #[test]
+    fn test_no_edges() {
+        assert!(detect(&[], None, 44100).is_none());
+    }
+
+    #[test]
+    fn test_rate_label() {
+        assert_eq!(rate_label(44100), "44.1k");
+        assert_eq!(rate_label(48000), "48k");
+        assert_eq!(rate_label(176400), "176.4k");
+    }
+}
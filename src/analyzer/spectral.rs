@@ -43,18 +43,180 @@
 //!    - White noise = 1.0, Pure silence = 0.0
 //!    - Real audio in 20-22kHz range has flatness ~0.9+
 //!    - Empty transcode band has flatness <0.3
-
-use rustfft::{num_complex::Complex, FftPlanner};
-use serde::Serialize;
-use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::DecoderOptions;
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
+//!
+//! 4. **cutoff_fit**: Logistic model fitted to the averaged rolloff (see `cutoff`)
+//!    - Sub-bin cutoff with a confidence interval and fit quality
+//!    - Edge shape: brick wall (lossy) vs gradual (analog)
+//!
+//! 5. **masking_conformity**: How closely the noise follows a masking model (see `masking`)
+//!    - Catches V0/320k/AAC-256 sources whose bandwidth is intact
+//!    - Natural audio: ~0.0-0.3; encoder-shaped noise: 0.8+
+//!
+//! 6. **content_profile**: Edge slope, HF dynamics and harmonicity (see `content`)
+//!    - Band-limited synth productions are scored like natural rolloff
+//!    - Codec brick walls (hundreds of dB/oct) never qualify
+
+use super::content::{self, ContentProfile};
+use super::cutoff::{self, CutoffFit, EdgeShape};
+use super::decode::DecodedAudio;
+use super::emphasis::{self, EmphasisEstimate, EmphasisSource};
+use super::generation::{self, GenerationEstimate};
+use super::masking::{self, MaskingEstimate};
+use super::resample::{self, ResampleEstimate};
+use crate::flac;
+use rayon::prelude::*;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use symphonia::core::audio::Channels;
 
 const FFT_SIZE: usize = 8192;
-const SAMPLE_RATE: u32 = 44100;
+/// Seconds from the start of the file that are analysed
+const ANALYSIS_SECS: usize = 15;
+
+/// Short FFT used for level/spectrogram measurements in multi-resolution mode
+pub const DEFAULT_SHORT_FFT_SIZE: usize = 1024;
+// Smallest FFT we accept - below this the 8-12kHz reference band is only a few bins wide
+const MIN_FFT_SIZE: usize = 256;
+// Kaiser window shape parameter (~-90 dB sidelobes, comparable to Blackman-Harris)
+const KAISER_BETA: f64 = 8.6;
+// Cutoff measurements are taken every this many hops, to keep the cost down
+// while still capturing how the cutoff varies
+const CUTOFF_STRIDE: usize = 4;
+
+/// Window function applied to each block before the FFT
+///
+/// - **Hann**: Good general-purpose default (-31 dB sidelobes)
+/// - **Blackman-Harris**: 4-term, -92 dB sidelobes. Use for tonal material where
+///   leakage from strong partials smears energy into an otherwise empty band
+/// - **Kaiser**: β = 8.6, similar leakage to Blackman-Harris with a slightly
+///   narrower main lobe
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WindowType {
+    #[default]
+    Hann,
+    BlackmanHarris,
+    Kaiser,
+}
+
+impl WindowType {
+    /// Generate the window coefficients for a block of `size` samples
+    pub fn coefficients(&self, size: usize) -> Vec<f64> {
+        match self {
+            WindowType::Hann => hanning_window(size),
+            WindowType::BlackmanHarris => blackman_harris_window(size),
+            WindowType::Kaiser => kaiser_window(size, KAISER_BETA),
+        }
+    }
+}
+
+impl std::fmt::Display for WindowType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WindowType::Hann => write!(f, "hann"),
+            WindowType::BlackmanHarris => write!(f, "blackman-harris"),
+            WindowType::Kaiser => write!(f, "kaiser"),
+        }
+    }
+}
+
+impl std::str::FromStr for WindowType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "hann" | "hanning" => Ok(WindowType::Hann),
+            "blackman-harris" | "blackmanharris" => Ok(WindowType::BlackmanHarris),
+            "kaiser" => Ok(WindowType::Kaiser),
+            other => Err(format!(
+                "unknown window '{}' (expected hann, blackman-harris or kaiser)",
+                other
+            )),
+        }
+    }
+}
+
+/// FFT parameters for spectral analysis
+///
+/// The default (8192-point Hann, 50% overlap) is what all the scoring
+/// thresholds were tuned on. Band levels are normalized back to that
+/// reference, so other sizes and windows stay comparable.
+///
+/// With `multi_resolution` set, cutoff/rolloff measurements use the long
+/// `size` FFT (fine frequency resolution) while band levels, flatness and
+/// the spectrogram use the short FFT (fine time resolution for transients).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FftConfig {
+    /// FFT length in samples
+    pub size: usize,
+    /// Window function
+    pub window: WindowType,
+    /// Hop between successive windows in samples
+    pub hop: usize,
+    /// Short FFT size for multi-resolution mode (None = single resolution)
+    pub multi_resolution: Option<usize>,
+}
+
+impl Default for FftConfig {
+    fn default() -> Self {
+        Self {
+            size: FFT_SIZE,
+            window: WindowType::Hann,
+            hop: FFT_SIZE / 2,
+            multi_resolution: None,
+        }
+    }
+}
+
+impl FftConfig {
+    /// Config with the given FFT size, Hann window and 50% overlap
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            hop: size / 2,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_window(mut self, window: WindowType) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn with_hop(mut self, hop: usize) -> Self {
+        self.hop = hop;
+        self
+    }
+
+    /// Enable multi-resolution mode with the given short FFT size
+    pub fn with_multi_resolution(mut self, short_size: Option<usize>) -> Self {
+        self.multi_resolution = short_size;
+        self
+    }
+
+    /// Clamp to usable values: size at least MIN_FFT_SIZE, hop within 1..=size,
+    /// and the short FFT (if any) strictly shorter than the long one
+    pub fn sanitized(&self) -> Self {
+        let size = self.size.max(MIN_FFT_SIZE);
+        let hop = if self.hop == 0 { size / 2 } else { self.hop.min(size) };
+        let multi_resolution = self
+            .multi_resolution
+            .map(|s| s.max(MIN_FFT_SIZE))
+            .filter(|&s| s < size);
+        Self {
+            size,
+            window: self.window,
+            hop,
+            multi_resolution,
+        }
+    }
+
+    /// Config for the short pass in multi-resolution mode (same window, 50% overlap)
+    fn short_pass(&self) -> Option<Self> {
+        self.multi_resolution
+            .map(|short| FftConfig::new(short).with_window(self.window))
+    }
+}
 
 // Spectrogram parameters - downsample for reasonable file size
 // Target: ~128 frequency bins, ~100 time slices max
@@ -78,6 +240,74 @@ pub struct SpectrogramData {
     pub num_time_slices: usize,
 }
 
+// Cutoff histogram bin width (Hz)
+const CUTOFF_HISTOGRAM_BIN_HZ: f64 = 250.0;
+// Lowest frequency included in the cutoff model fit (Hz)
+const CUTOFF_FIT_LOW_HZ: f64 = 8000.0;
+// Distance kept between the emphasis fit and a detected cutoff (Hz)
+const EMPHASIS_FIT_MARGIN_HZ: f64 = 1000.0;
+// Distance kept between the masking model's bands and a detected cutoff (Hz)
+const MASKING_CUTOFF_MARGIN_HZ: f64 = 500.0;
+// Highest frequency the masking model looks at (Hz)
+const MASKING_MAX_HZ: f64 = 20000.0;
+
+/// Detected cutoff frequency for each analysed window over time
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CutoffTimeline {
+    /// Window start times in seconds
+    pub times: Vec<f64>,
+    /// Detected cutoff frequency (Hz) at each time point
+    pub frequencies: Vec<f64>,
+}
+
+/// Histogram of per-window cutoff frequencies
+/// A single sharp peak = fixed encoder lowpass; a broad smear = natural content
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CutoffHistogram {
+    /// Width of each bin (Hz)
+    pub bin_width: f64,
+    /// Lower edge of each bin (Hz)
+    pub bin_starts: Vec<f64>,
+    /// Number of windows whose cutoff fell in each bin
+    pub counts: Vec<usize>,
+    /// Centre of the most populated bin (Hz)
+    pub peak_frequency: f64,
+    /// Fraction of windows in the most populated bin (0.0-1.0)
+    pub peak_fraction: f64,
+}
+
+impl CutoffHistogram {
+    /// Bin cutoff frequencies, spanning only the observed range
+    fn from_frequencies(frequencies: &[f64], bin_width: f64) -> Option<Self> {
+        if frequencies.is_empty() || bin_width <= 0.0 {
+            return None;
+        }
+
+        let bin_of = |f: f64| (f / bin_width).floor().max(0.0) as usize;
+        let first = frequencies.iter().map(|&f| bin_of(f)).min()?;
+        let last = frequencies.iter().map(|&f| bin_of(f)).max()?;
+
+        let mut counts = vec![0usize; last - first + 1];
+        for &f in frequencies {
+            counts[bin_of(f) - first] += 1;
+        }
+
+        // First of equally populated bins wins, so the result is deterministic
+        let (peak_idx, peak_count) = counts
+            .iter()
+            .enumerate()
+            .fold((0, 0), |best, (i, &c)| if c > best.1 { (i, c) } else { best });
+
+        Some(Self {
+            bin_width,
+            bin_starts: (first..=last).map(|b| b as f64 * bin_width).collect(),
+            peak_frequency: (first + peak_idx) as f64 * bin_width + bin_width / 2.0,
+            peak_fraction: peak_count as f64 / frequencies.len() as f64,
+            counts,
+        })
+    }
+}
+
 // Stereo correlation parameters
 const STEREO_WINDOW_SIZE: usize = 4096;
 const STEREO_MAX_POINTS: usize = 100;
@@ -104,6 +334,34 @@ pub struct StereoCorrelation {
     pub channel_count: usize,
 }
 
+// Stereo anomaly thresholds
+// These defects say nothing about lossy origin, so they flag without scoring
+const FAKE_STEREO_CORRELATION: f64 = 0.999;
+const POLARITY_INVERTED_CORRELATION: f64 = -0.5;
+const SILENT_CHANNEL_DB: f64 = -80.0;
+const AUDIBLE_CHANNEL_DB: f64 = -60.0;
+const CHANNEL_OFFSET_MAX_LAG: usize = STEREO_WINDOW_SIZE / 4;
+const CHANNEL_OFFSET_SEGMENTS: usize = 8;
+const CHANNEL_OFFSET_CORRELATION: f64 = 0.9;
+const CHANNEL_OFFSET_MARGIN: f64 = 0.05;
+
+/// Stereo defects common in dubious "lossless" uploads
+/// Reported as flags only - they don't contribute to the lossy-origin score
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StereoAnomalies {
+    /// RMS level of the left channel (dBFS)
+    pub left_rms: f64,
+    /// RMS level of the right channel (dBFS)
+    pub right_rms: f64,
+    /// Lag (samples) at which the right channel best matches the left
+    /// Positive = right lags left, 0 = aligned or no confident match
+    pub channel_offset: i64,
+    /// Normalised cross-correlation at `channel_offset`
+    pub offset_correlation: f64,
+    /// Fraction of measured segments that agreed on `channel_offset` (0.0-1.0)
+    pub offset_agreement: f64,
+}
+
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct SpectralDetails {
     /// RMS level of full signal (dB)
@@ -152,12 +410,48 @@ pub struct SpectralDetails {
     /// Whether this appears to be a natural/analog rolloff vs lossy brick-wall
     pub natural_rolloff: bool,
 
+    /// FFT size, window and hop the measurements were taken with
+    pub fft_config: FftConfig,
+
+    /// Per-window cutoff frequency over time (None if no windows measured)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cutoff_timeline: Option<CutoffTimeline>,
+    /// Distribution of per-window cutoff frequencies (None if no windows measured)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cutoff_histogram: Option<CutoffHistogram>,
+    /// Logistic fit of the averaged high-frequency rolloff (None if not enough data)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cutoff_fit: Option<CutoffFit>,
+    /// Estimated number of lossy encodes and the cutoff each one left (None if not measured)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation_estimate: Option<GenerationEstimate>,
+    /// Sample-rate conversion filter found in the spectrum (None if not resampled)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resample_estimate: Option<ResampleEstimate>,
+    /// Noise-to-mask measurements from a psychoacoustic model (None if too little content)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub masking_estimate: Option<MaskingEstimate>,
+    /// Edge slope, HF dynamics and harmonicity of the content, and the scoring
+    /// mode they led to (None if not measured)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_profile: Option<ContentProfile>,
+    /// CD pre-emphasis, declared or fitted (None if not emphasised);
+    /// the band levels above are de-emphasised when present
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emphasis: Option<EmphasisEstimate>,
+
     /// Spectrogram data for visualization (None if not generated)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub spectrogram: Option<SpectrogramData>,
     /// Stereo correlation data (None if mono or not analyzed)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stereo_correlation: Option<StereoCorrelation>,
+    /// Stereo defect measurements (None if mono or not analyzed)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stereo_anomalies: Option<StereoAnomalies>,
+    /// Per-channel analysis of files with more than two channels (None for mono/stereo)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<Vec<ChannelDetails>>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -167,6 +461,39 @@ pub struct SpectralResult {
     pub details: SpectralDetails,
 }
 
+impl SpectralResult {
+    /// Empty result recording the FFT configuration it was attempted with
+    pub(crate) fn with_config(config: FftConfig) -> Self {
+        let mut result = Self::default();
+        result.details.fft_config = config;
+        result
+    }
+}
+
+/// Spectral summary of one channel of a multichannel file
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChannelDetails {
+    /// Speaker position from the channel layout ("FL", "FR", "FC", "LFE", "SL"...)
+    pub label: String,
+    /// Whether the channel was scored (false for LFE and silent channels)
+    pub scored: bool,
+    /// RMS level of the channel (dB)
+    pub rms_full: f64,
+    /// Drop from mid-high to upper band (dB)
+    pub upper_drop: f64,
+    /// Drop from 19-20kHz to 20-22kHz (dB)
+    pub ultrasonic_drop: f64,
+    /// Average detected cutoff frequency across windows (Hz)
+    pub avg_cutoff_freq: f64,
+    /// Logistic fit of the channel's high-frequency rolloff
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cutoff_fit: Option<CutoffFit>,
+    /// Spectral score of this channel alone (0-100)
+    pub score: u32,
+    /// Spectral flags raised by this channel alone
+    pub flags: Vec<String>,
+}
+
 /// Hanning window function
 fn hanning_window(size: usize) -> Vec<f64> {
     (0..size)
@@ -176,6 +503,61 @@ fn hanning_window(size: usize) -> Vec<f64> {
         .collect()
 }
 
+/// 4-term Blackman-Harris window function
+fn blackman_harris_window(size: usize) -> Vec<f64> {
+    const A: [f64; 4] = [0.35875, 0.48829, 0.14128, 0.01168];
+    let denom = (size - 1) as f64;
+    (0..size)
+        .map(|i| {
+            let x = 2.0 * std::f64::consts::PI * i as f64 / denom;
+            A[0] - A[1] * x.cos() + A[2] * (2.0 * x).cos() - A[3] * (3.0 * x).cos()
+        })
+        .collect()
+}
+
+/// Kaiser window function with shape parameter `beta`
+fn kaiser_window(size: usize, beta: f64) -> Vec<f64> {
+    let denom = bessel_i0(beta);
+    let half = (size - 1) as f64 / 2.0;
+    (0..size)
+        .map(|i| {
+            let r = (i as f64 - half) / half;
+            bessel_i0(beta * (1.0 - r * r).max(0.0).sqrt()) / denom
+        })
+        .collect()
+}
+
+/// Zeroth-order modified Bessel function of the first kind (power series)
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+    for k in 1..50 {
+        term *= (half_x / k as f64).powi(2);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Linear scale factor that maps band levels measured with `window` back to the
+/// reference 8192-point Hann analysis the thresholds were tuned on
+///
+/// A band level sums power over every bin in the band. For noise and tones
+/// alike that sum grows with the FFT length times the window's power sum
+/// (Σw², 3/8 of the length for Hann), not with its coherent gain (Σw), which
+/// only holds for a single tone's peak bin.
+fn level_correction(window: &[f64]) -> f64 {
+    let power: f64 = window.iter().map(|w| w * w).sum();
+    if power <= 0.0 {
+        return 1.0;
+    }
+    let reference = FFT_SIZE as f64 * FFT_SIZE as f64 * 0.375;
+    (reference / (window.len() as f64 * power)).sqrt()
+}
+
 /// Convert linear magnitude to dB
 fn to_db(value: f64) -> f64 {
     if value <= 0.0 {
@@ -194,159 +576,92 @@ fn rms(samples: &[f64]) -> f64 {
     (sum_sq / samples.len() as f64).sqrt()
 }
 
-/// Decode audio to PCM samples using symphonia (supports MP3, FLAC, WAV, OGG, etc.)
-fn decode_audio(data: &[u8]) -> Option<(Vec<f64>, u32)> {
-    let cursor = std::io::Cursor::new(data.to_vec());
-    let mss = MediaSourceStream::new(Box::new(cursor), Default::default());
-
-    // Don't provide a hint - let symphonia auto-detect the format
-    let hint = Hint::new();
-
-    let format_opts = FormatOptions::default();
-    let metadata_opts = MetadataOptions::default();
-    let decoder_opts = DecoderOptions::default();
-
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &format_opts, &metadata_opts)
-        .ok()?;
-
-    let mut format = probed.format;
-    let track = format.default_track()?;
-    let track_id = track.id;
-    let sample_rate = track.codec_params.sample_rate.unwrap_or(SAMPLE_RATE);
-
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &decoder_opts)
-        .ok()?;
-
-    let mut samples = Vec::new();
-    let mut sample_buf: Option<SampleBuffer<f32>> = None;
-
-    // Decode up to ~15 seconds from middle of file
-    let max_samples = (sample_rate as usize) * 15;
-
-    loop {
-        let packet = match format.next_packet() {
-            Ok(p) => p,
-            Err(_) => break,
-        };
-
-        if packet.track_id() != track_id {
-            continue;
-        }
-
-        let decoded = match decoder.decode(&packet) {
-            Ok(d) => d,
-            Err(_) => continue,
-        };
-
-        if sample_buf.is_none() {
-            let spec = *decoded.spec();
-            let duration = decoded.capacity() as u64;
-            sample_buf = Some(SampleBuffer::new(duration, spec));
-        }
-
-        if let Some(ref mut buf) = sample_buf {
-            // Get channel count before moving decoded
-            let channel_count = decoded.spec().channels.count();
-            buf.copy_interleaved_ref(decoded);
+/// The start of the decoded audio with each channel kept separate
+struct ChannelAudio {
+    /// Samples per channel, in symphonia's channel order
+    channels: Vec<Vec<f64>>,
+    /// Position of each channel (empty flags if the container doesn't say)
+    layout: Vec<Channels>,
+    sample_rate: u32,
+}
 
-            // Convert to mono f64
-            for chunk in buf.samples().chunks(channel_count) {
-                let mono: f64 = chunk.iter().map(|&s| s as f64).sum::<f64>() / channel_count as f64;
-                samples.push(mono);
-            }
+impl ChannelAudio {
+    /// Mono mix of every channel except LFE
+    ///
+    /// The LFE channel only carries content below ~120 Hz; mixing it in would
+    /// dilute the high-frequency bands the cutoff analysis looks at.
+    fn mono(&self) -> Vec<f64> {
+        self.mix(false)
+    }
 
-            if samples.len() >= max_samples {
-                break;
-            }
-        }
+    /// Mono mix with the second channel's polarity flipped back, for files
+    /// where it was inverted: a plain mix would cancel the two to near silence
+    fn mono_uninverted(&self) -> Vec<f64> {
+        self.mix(true)
     }
 
-    if samples.is_empty() {
-        return None;
+    fn mix(&self, flip_second: bool) -> Vec<f64> {
+        let full_range: Vec<(usize, &Vec<f64>)> = self
+            .channels
+            .iter()
+            .enumerate()
+            .zip(self.layout_or_unknown())
+            .filter(|(_, position)| !is_lfe(*position))
+            .map(|(channel, _)| channel)
+            .collect();
+        let sign = |index: usize| if flip_second && index == 1 { -1.0 } else { 1.0 };
+        let len = full_range.iter().map(|(_, c)| c.len()).min().unwrap_or(0);
+        (0..len)
+            .map(|n| {
+                full_range.iter().map(|&(i, c)| sign(i) * c[n]).sum::<f64>() / full_range.len() as f64
+            })
+            .collect()
     }
 
-    Some((samples, sample_rate))
+    /// Position of every channel, padding with empty flags where unknown
+    fn layout_or_unknown(&self) -> impl Iterator<Item = Channels> + '_ {
+        (0..self.channels.len()).map(|i| self.layout.get(i).copied().unwrap_or(Channels::empty()))
+    }
 }
 
-/// Decode audio keeping stereo channels separate
-/// Returns (left_channel, right_channel, sample_rate, channel_count)
-fn decode_audio_stereo(data: &[u8]) -> Option<(Vec<f64>, Vec<f64>, u32, usize)> {
-    let cursor = std::io::Cursor::new(data.to_vec());
-    let mss = MediaSourceStream::new(Box::new(cursor), Default::default());
-
-    let hint = Hint::new();
-    let format_opts = FormatOptions::default();
-    let metadata_opts = MetadataOptions::default();
-    let decoder_opts = DecoderOptions::default();
-
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &format_opts, &metadata_opts)
-        .ok()?;
-
-    let mut format = probed.format;
-    let track = format.default_track()?;
-    let track_id = track.id;
-    let sample_rate = track.codec_params.sample_rate.unwrap_or(SAMPLE_RATE);
-
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &decoder_opts)
-        .ok()?;
-
-    let mut left_samples = Vec::new();
-    let mut right_samples = Vec::new();
-    let mut sample_buf: Option<SampleBuffer<f32>> = None;
-    let mut detected_channels = 1usize;
-
-    // Decode up to ~15 seconds
-    let max_samples = (sample_rate as usize) * 15;
-
-    loop {
-        let packet = match format.next_packet() {
-            Ok(p) => p,
-            Err(_) => break,
-        };
-
-        if packet.track_id() != track_id {
-            continue;
-        }
-
-        let decoded = match decoder.decode(&packet) {
-            Ok(d) => d,
-            Err(_) => continue,
-        };
-
-        if sample_buf.is_none() {
-            let spec = *decoded.spec();
-            let duration = decoded.capacity() as u64;
-            sample_buf = Some(SampleBuffer::new(duration, spec));
-        }
-
-        if let Some(ref mut buf) = sample_buf {
-            let channel_count = decoded.spec().channels.count();
-            detected_channels = channel_count;
-            buf.copy_interleaved_ref(decoded);
-
-            // Extract left and right channels
-            for chunk in buf.samples().chunks(channel_count) {
-                let left = chunk[0] as f64;
-                let right = if channel_count > 1 { chunk[1] as f64 } else { left };
-                left_samples.push(left);
-                right_samples.push(right);
-            }
+fn is_lfe(position: Channels) -> bool {
+    position.intersects(Channels::LFE1 | Channels::LFE2)
+}
 
-            if left_samples.len() >= max_samples {
-                break;
-            }
-        }
-    }
+/// Short speaker label for a channel position ("FL", "LFE", "SL"...), or "CH<n>" if unknown
+fn channel_label(position: Channels, index: usize) -> String {
+    let label = match position {
+        Channels::FRONT_LEFT => "FL",
+        Channels::FRONT_RIGHT => "FR",
+        Channels::FRONT_CENTRE => "FC",
+        Channels::LFE1 => "LFE",
+        Channels::LFE2 => "LFE2",
+        Channels::REAR_LEFT => "BL",
+        Channels::REAR_RIGHT => "BR",
+        Channels::REAR_CENTRE => "BC",
+        Channels::SIDE_LEFT => "SL",
+        Channels::SIDE_RIGHT => "SR",
+        Channels::FRONT_LEFT_CENTRE => "FLC",
+        Channels::FRONT_RIGHT_CENTRE => "FRC",
+        _ => return format!("CH{}", index + 1),
+    };
+    label.to_string()
+}
 
-    if left_samples.is_empty() {
+/// Split the first ANALYSIS_SECS of the decoded audio into channels
+fn channel_audio(audio: &DecodedAudio) -> Option<ChannelAudio> {
+    let head = audio.head(ANALYSIS_SECS);
+    if head.is_empty() {
         return None;
     }
-
-    Some((left_samples, right_samples, sample_rate, detected_channels))
+    let channels = (0..audio.channels)
+        .map(|c| head.iter().skip(c).step_by(audio.channels).map(|&s| s as f64).collect())
+        .collect();
+    Some(ChannelAudio {
+        channels,
+        layout: audio.layout.clone(),
+        sample_rate: audio.sample_rate,
+    })
 }
 
 /// Calculate Pearson correlation coefficient between two signals
@@ -373,9 +688,12 @@ fn pearson_correlation(x: &[f64], y: &[f64]) -> f64 {
 }
 
 /// Analyze stereo correlation over time
-fn analyze_stereo_correlation(data: &[u8]) -> Option<StereoCorrelation> {
-    let (left, right, sample_rate, channel_count) = decode_audio_stereo(data)?;
-
+fn analyze_stereo_correlation(
+    left: &[f64],
+    right: &[f64],
+    sample_rate: u32,
+    channel_count: usize,
+) -> Option<StereoCorrelation> {
     if left.len() < STEREO_WINDOW_SIZE {
         return None;
     }
@@ -405,6 +723,11 @@ fn analyze_stereo_correlation(data: &[u8]) -> Option<StereoCorrelation> {
         let left_window = &left[start..end];
         let right_window = &right[start..end];
 
+        // Silence has nothing to correlate (it would read as identical channels)
+        if to_db(rms(left_window)) < SILENT_CHANNEL_DB || to_db(rms(right_window)) < SILENT_CHANNEL_DB {
+            continue;
+        }
+
         let corr = pearson_correlation(left_window, right_window);
         let time = start as f64 / sample_rate as f64;
 
@@ -431,12 +754,178 @@ fn analyze_stereo_correlation(data: &[u8]) -> Option<StereoCorrelation> {
     })
 }
 
+/// Measure per-channel levels and L/R alignment for stereo defect detection
+fn analyze_stereo_anomalies(left: &[f64], right: &[f64]) -> StereoAnomalies {
+    let (channel_offset, offset_correlation, offset_agreement) = detect_channel_offset(left, right);
+
+    StereoAnomalies {
+        left_rms: to_db(rms(left)),
+        right_rms: to_db(rms(right)),
+        channel_offset,
+        offset_correlation,
+        offset_agreement,
+    }
+}
+
+/// Find a consistent sample offset between channels (e.g. from a bad join)
+///
+/// Cross-correlates evenly spaced segments via FFT. A segment votes for a lag
+/// when its normalised correlation there is high and clearly beats lag 0.
+/// Returns (lag, correlation at lag, fraction of segments agreeing).
+fn detect_channel_offset(left: &[f64], right: &[f64]) -> (i64, f64, f64) {
+    let n = STEREO_WINDOW_SIZE;
+    let len = left.len().min(right.len());
+    if len < n {
+        return (0, 0.0, 0.0);
+    }
+
+    let padded = 2 * n;
+    let mut planner = FftPlanner::new();
+    let forward = planner.plan_fft_forward(padded);
+    let inverse = planner.plan_fft_inverse(padded);
+
+    let segments = CHANNEL_OFFSET_SEGMENTS.min(len / n).max(1);
+    let stride = if segments > 1 { (len - n) / (segments - 1) } else { 0 };
+    let mut measured = 0usize;
+    let mut votes: Vec<(i64, f64)> = Vec::new();
+
+    for seg in 0..segments {
+        let start = (seg * stride).min(len - n);
+        let l = &left[start..start + n];
+        let r = &right[start..start + n];
+
+        let energy_l: f64 = l.iter().map(|x| x * x).sum();
+        let energy_r: f64 = r.iter().map(|x| x * x).sum();
+        if energy_l < 1e-9 || energy_r < 1e-9 {
+            continue;
+        }
+        measured += 1;
+
+        let to_buffer = |x: &[f64]| -> Vec<Complex<f64>> {
+            x.iter()
+                .map(|&v| Complex::new(v, 0.0))
+                .chain(std::iter::repeat_n(Complex::new(0.0, 0.0), padded - n))
+                .collect()
+        };
+        let mut spec_l = to_buffer(l);
+        let mut spec_r = to_buffer(r);
+        forward.process(&mut spec_l);
+        forward.process(&mut spec_r);
+
+        // conj(L) * R -> xcorr[k] = sum l[t] * r[t + k]
+        let mut xcorr: Vec<Complex<f64>> = spec_l
+            .iter()
+            .zip(spec_r.iter())
+            .map(|(a, b)| a.conj() * b)
+            .collect();
+        inverse.process(&mut xcorr);
+
+        // Prefix sums of squares normalise each lag by its overlap energy
+        let prefix = |x: &[f64]| -> Vec<f64> {
+            std::iter::once(0.0)
+                .chain(x.iter().scan(0.0, |acc, v| {
+                    *acc += v * v;
+                    Some(*acc)
+                }))
+                .collect()
+        };
+        let prefix_l = prefix(l);
+        let prefix_r = prefix(r);
+
+        let normalised = |lag: i64| -> f64 {
+            let (l_range, r_range) = if lag >= 0 {
+                let k = lag as usize;
+                ((0, n - k), (k, n))
+            } else {
+                let k = (-lag) as usize;
+                ((k, n), (0, n - k))
+            };
+            let e_l = prefix_l[l_range.1] - prefix_l[l_range.0];
+            let e_r = prefix_r[r_range.1] - prefix_r[r_range.0];
+            let index = if lag >= 0 { lag as usize } else { padded - (-lag) as usize };
+            let denom = (e_l * e_r).sqrt();
+            if denom <= 0.0 {
+                0.0
+            } else {
+                // Inverse FFT is unnormalised
+                (xcorr[index].re / padded as f64 / denom).clamp(-1.0, 1.0)
+            }
+        };
+
+        let max_lag = CHANNEL_OFFSET_MAX_LAG as i64;
+        let (best_lag, best_corr) = (-max_lag..=max_lag)
+            .map(|lag| (lag, normalised(lag)))
+            .fold((0, f64::NEG_INFINITY), |best, cur| if cur.1 > best.1 { cur } else { best });
+
+        if best_lag != 0
+            && best_corr >= CHANNEL_OFFSET_CORRELATION
+            && best_corr - normalised(0) >= CHANNEL_OFFSET_MARGIN
+        {
+            votes.push((best_lag, best_corr));
+        }
+    }
+
+    if measured == 0 || votes.is_empty() {
+        return (0, 0.0, 0.0);
+    }
+
+    // Most common lag wins; ties go to the smaller offset
+    let mut lags: Vec<i64> = votes.iter().map(|v| v.0).collect();
+    lags.sort_by_key(|l| (l.abs(), *l));
+    lags.dedup();
+    let (lag, count) = lags
+        .iter()
+        .map(|&lag| (lag, votes.iter().filter(|v| v.0 == lag).count()))
+        .fold((0, 0), |best, cur| if cur.1 > best.1 { cur } else { best });
+    let corr = votes.iter().filter(|v| v.0 == lag).map(|v| v.1).sum::<f64>() / count as f64;
+
+    (lag, corr, count as f64 / measured as f64)
+}
+
+/// Flags for stereo defects, with the measured evidence in parentheses.
+/// `correlation` is None when no window had both channels audible
+fn stereo_anomaly_flags(correlation: Option<&StereoCorrelation>, anomalies: &StereoAnomalies) -> Vec<String> {
+    let mut flags = Vec::new();
+
+    // One dead channel: correlation is meaningless, so report only this
+    let silent = |level: f64, other: f64| level < SILENT_CHANNEL_DB && other > AUDIBLE_CHANNEL_DB;
+    if silent(anomalies.left_rms, anomalies.right_rms) {
+        flags.push(format!("silent_left_channel({:.0}dB)", anomalies.left_rms));
+        return flags;
+    }
+    if silent(anomalies.right_rms, anomalies.left_rms) {
+        flags.push(format!("silent_right_channel({:.0}dB)", anomalies.right_rms));
+        return flags;
+    }
+
+    if let Some(correlation) = correlation {
+        // Dual mono: channels identical throughout, not just on average
+        if correlation.min_correlation >= FAKE_STEREO_CORRELATION {
+            flags.push(format!("fake_stereo(min_corr={:.4})", correlation.min_correlation));
+        }
+
+        if correlation.avg_correlation <= POLARITY_INVERTED_CORRELATION {
+            flags.push(format!("polarity_inverted(avg_corr={:.2})", correlation.avg_correlation));
+        }
+    }
+
+    // Require most segments to agree so a single echo or periodic passage can't trigger it
+    if anomalies.channel_offset != 0 && anomalies.offset_agreement >= 0.5 {
+        flags.push(format!(
+            "channel_offset({:+}samples,corr={:.2})",
+            anomalies.channel_offset, anomalies.offset_correlation
+        ));
+    }
+
+    flags
+}
+
 /// Detect the cutoff frequency in an FFT window
 /// Returns the frequency (Hz) where energy drops significantly below the reference band
 /// Uses the -20dB point relative to the 8-12kHz reference band
-fn detect_cutoff_frequency(fft_result: &[Complex<f64>], sample_rate: u32) -> f64 {
-    let bin_resolution = sample_rate as f64 / FFT_SIZE as f64;
-    let nyquist_bin = FFT_SIZE / 2;
+fn detect_cutoff_frequency(fft_result: &[Complex<f64>], sample_rate: u32, fft_size: usize) -> f64 {
+    let bin_resolution = sample_rate as f64 / fft_size as f64;
+    let nyquist_bin = fft_size / 2;
 
     // Get reference energy from 8-12kHz band (stable mid-high region)
     let ref_low_bin = (8000.0 / bin_resolution) as usize;
@@ -472,8 +961,8 @@ fn detect_cutoff_frequency(fft_result: &[Complex<f64>], sample_rate: u32) -> f64
 
 /// Measure the rolloff slope in the 12-20kHz region (dB per kHz)
 /// Steeper slopes indicate brick-wall cutoffs (lossy), gentler slopes indicate natural rolloff
-fn measure_rolloff_slope(fft_result: &[Complex<f64>], sample_rate: u32) -> f64 {
-    let bin_resolution = sample_rate as f64 / FFT_SIZE as f64;
+fn measure_rolloff_slope(fft_result: &[Complex<f64>], sample_rate: u32, fft_size: usize) -> f64 {
+    let bin_resolution = sample_rate as f64 / fft_size as f64;
 
     // Sample energy at several points in the 12-20kHz region
     let freq_points = [12000.0, 14000.0, 16000.0, 18000.0, 20000.0];
@@ -524,9 +1013,9 @@ fn measure_rolloff_slope(fft_result: &[Complex<f64>], sample_rate: u32) -> f64 {
 
 /// Measure transition width: how many Hz from -3dB to -40dB below reference
 /// Narrow = brick-wall (lossy), Wide = gradual (natural)
-fn measure_transition_width(fft_result: &[Complex<f64>], sample_rate: u32) -> f64 {
-    let bin_resolution = sample_rate as f64 / FFT_SIZE as f64;
-    let nyquist_bin = FFT_SIZE / 2;
+fn measure_transition_width(fft_result: &[Complex<f64>], sample_rate: u32, fft_size: usize) -> f64 {
+    let bin_resolution = sample_rate as f64 / fft_size as f64;
+    let nyquist_bin = fft_size / 2;
 
     // Get reference energy from 8-12kHz band
     let ref_low_bin = (8000.0 / bin_resolution) as usize;
@@ -606,10 +1095,16 @@ fn spectral_flatness(magnitudes: &[f64]) -> f64 {
 }
 
 /// Calculate energy in a frequency band using FFT results
-fn band_energy(fft_result: &[Complex<f64>], sample_rate: u32, low_hz: u32, high_hz: u32) -> f64 {
-    let bin_resolution = sample_rate as f64 / FFT_SIZE as f64;
+fn band_energy(
+    fft_result: &[Complex<f64>],
+    sample_rate: u32,
+    fft_size: usize,
+    low_hz: u32,
+    high_hz: u32,
+) -> f64 {
+    let bin_resolution = sample_rate as f64 / fft_size as f64;
     let low_bin = (low_hz as f64 / bin_resolution) as usize;
-    let high_bin = (high_hz as f64 / bin_resolution).min((FFT_SIZE / 2) as f64) as usize;
+    let high_bin = (high_hz as f64 / bin_resolution).min((fft_size / 2) as f64) as usize;
 
     let mut energy = 0.0;
     for bin in low_bin..=high_bin.min(fft_result.len() - 1) {
@@ -620,167 +1115,561 @@ fn band_energy(fft_result: &[Complex<f64>], sample_rate: u32, low_hz: u32, high_
     energy.sqrt()
 }
 
-/// Perform spectral analysis on MP3 data
-pub fn analyze(data: &[u8], _declared_sample_rate: u32) -> SpectralResult {
-    let mut result = SpectralResult::default();
-
-    // Decode audio to PCM (supports MP3, FLAC, WAV, OGG, etc.)
-    let (samples, sample_rate) = match decode_audio(data) {
-        Some(s) => s,
-        None => return result,
-    };
+/// Number of full FFT blocks that fit in `len` samples with the given config
+fn window_count(len: usize, config: &FftConfig) -> usize {
+    if len < config.size {
+        0
+    } else {
+        (len - config.size) / config.hop + 1
+    }
+}
+
+/// Windows per work unit in `map_reduce_windows`
+///
+/// Fixed rather than derived from the thread count, so chunk boundaries - and
+/// therefore floating-point summation order - are the same on every run.
+const WINDOW_CHUNK: usize = 32;
+
+/// Per-window measurements that can be combined associatively
+trait WindowAccumulator: Send {
+    /// Fold in one transformed window
+    fn add(&mut self, index: usize, start: usize, spectrum: &[Complex<f64>]);
+    /// Append `next`, which covers the windows immediately after `self`
+    fn merge(&mut self, next: Self);
+}
+
+impl<A: WindowAccumulator, B: WindowAccumulator> WindowAccumulator for (A, B) {
+    fn add(&mut self, index: usize, start: usize, spectrum: &[Complex<f64>]) {
+        self.0.add(index, start, spectrum);
+        self.1.add(index, start, spectrum);
+    }
+
+    fn merge(&mut self, next: Self) {
+        self.0.merge(next.0);
+        self.1.merge(next.1);
+    }
+}
+
+/// Window and transform each block of `samples`, folding every spectrum into
+/// an accumulator from `new`
+///
+/// Fixed-size chunks of windows are mapped in parallel, so a long file can use
+/// cores left idle by a small batch (rayon only steals work when threads are
+/// free). Partial results are merged strictly in window order, which keeps the
+/// output bit-for-bit identical however the chunks were scheduled.
+fn map_reduce_windows<A, N>(
+    samples: &[f64],
+    config: &FftConfig,
+    fft: &dyn Fft<f64>,
+    window: &[f64],
+    new: N,
+) -> A
+where
+    A: WindowAccumulator,
+    N: Fn() -> A + Sync,
+{
+    let count = window_count(samples.len(), config);
+
+    let partials: Vec<A> = (0..count.div_ceil(WINDOW_CHUNK))
+        .into_par_iter()
+        .map(|chunk| {
+            let mut acc = new();
+            let mut buffer: Vec<Complex<f64>> = Vec::with_capacity(config.size);
+            let mut scratch = vec![Complex::new(0.0, 0.0); fft.get_inplace_scratch_len()];
+
+            for i in chunk * WINDOW_CHUNK..((chunk + 1) * WINDOW_CHUNK).min(count) {
+                let start = i * config.hop;
+                let end = start + config.size;
+
+                // Apply window and convert to complex
+                buffer.clear();
+                buffer.extend(
+                    samples[start..end]
+                        .iter()
+                        .zip(window.iter())
+                        .map(|(&s, &w)| Complex::new(s * w, 0.0)),
+                );
+
+                fft.process_with_scratch(&mut buffer, &mut scratch);
+                acc.add(i, start, &buffer);
+            }
+            acc
+        })
+        .collect();
+
+    partials
+        .into_iter()
+        .reduce(|mut acc, next| {
+            acc.merge(next);
+            acc
+        })
+        .unwrap_or_else(new)
+}
+
+/// Band levels, ultrasonic flatness input and spectrogram collected per window
+struct LevelAccumulator {
+    sample_rate: u32,
+    fft_size: usize,
+    /// Linear correction back to the reference analysis (see `level_correction`)
+    scale: f64,
+    windows: usize,
+    avg_full: f64,
+    avg_mid_high: f64,
+    avg_high: f64,
+    avg_upper: f64,
+    avg_19_20k: f64,
+    avg_ultrasonic: f64,
+    ultrasonic_magnitudes: Vec<f64>,
+    freq_downsample: usize,
+    actual_freq_bins: usize,
+    time_downsample: usize,
+    actual_time_slices: usize,
+    spectrogram_times: Vec<f64>,
+    spectrogram_magnitudes: Vec<f64>,
+}
+
+impl LevelAccumulator {
+    fn new(sample_rate: u32, fft_size: usize, num_windows: usize, scale: f64) -> Self {
+        let nyquist_bin = fft_size / 2;
+
+        // Calculate frequency bin downsampling factor
+        let freq_downsample = (nyquist_bin / SPECTROGRAM_FREQ_BINS).max(1);
+        let actual_freq_bins = nyquist_bin / freq_downsample;
+
+        // Calculate time downsampling factor
+        let time_downsample = (num_windows / SPECTROGRAM_MAX_TIME_SLICES).max(1);
+        let actual_time_slices = num_windows.div_ceil(time_downsample);
+
+        Self {
+            sample_rate,
+            fft_size,
+            scale,
+            windows: 0,
+            avg_full: 0.0,
+            avg_mid_high: 0.0,
+            avg_high: 0.0,
+            avg_upper: 0.0,
+            avg_19_20k: 0.0,
+            avg_ultrasonic: 0.0,
+            ultrasonic_magnitudes: Vec::new(),
+            freq_downsample,
+            actual_freq_bins,
+            time_downsample,
+            actual_time_slices,
+            spectrogram_times: Vec::new(),
+            spectrogram_magnitudes: Vec::new(),
+        }
+    }
+
+    /// Write averaged band levels (dB) and spectrogram into `details`
+    fn finish(self, details: &mut SpectralDetails) {
+        let n = self.windows.max(1) as f64;
+        let level = |sum: f64| to_db(sum / n * self.scale);
+
+        details.rms_full = level(self.avg_full);
+        details.rms_mid_high = level(self.avg_mid_high);
+        details.rms_high = level(self.avg_high);
+        details.rms_upper = level(self.avg_upper);
+        details.rms_19_20k = level(self.avg_19_20k);
+        details.rms_ultrasonic = level(self.avg_ultrasonic);
+
+        // Flatness = geometric_mean / arithmetic_mean (1.0 = white noise, 0.0 = pure tone/silence)
+        details.ultrasonic_flatness = spectral_flatness(&self.ultrasonic_magnitudes);
+
+        if !self.spectrogram_times.is_empty() && !self.spectrogram_magnitudes.is_empty() {
+            let bin_resolution = self.sample_rate as f64 / self.fft_size as f64;
+            details.spectrogram = Some(SpectrogramData {
+                frequencies: (0..self.actual_freq_bins)
+                    .map(|i| (i * self.freq_downsample) as f64 * bin_resolution)
+                    .collect(),
+                times: self.spectrogram_times,
+                magnitudes: self.spectrogram_magnitudes,
+                num_freq_bins: self.actual_freq_bins,
+                num_time_slices: self.actual_time_slices,
+            });
+        }
+    }
+}
+
+impl WindowAccumulator for LevelAccumulator {
+    fn add(&mut self, index: usize, start: usize, buffer: &[Complex<f64>]) {
+        let (sample_rate, fft_size) = (self.sample_rate, self.fft_size);
+        self.windows += 1;
+
+        // Calculate band energies (all from FFT for fair comparison)
+        self.avg_full += band_energy(buffer, sample_rate, fft_size, 20, 20000); // Full audible range
+        self.avg_mid_high += band_energy(buffer, sample_rate, fft_size, 10000, 15000);
+        self.avg_high += band_energy(buffer, sample_rate, fft_size, 15000, 20000);
+        self.avg_upper += band_energy(buffer, sample_rate, fft_size, 17000, 20000);
+        self.avg_19_20k += band_energy(buffer, sample_rate, fft_size, 19000, 20000);
+        self.avg_ultrasonic += band_energy(buffer, sample_rate, fft_size, 20000, 22000);
+
+        // Collect magnitudes in 19-21kHz for flatness calculation
+        let bin_resolution = sample_rate as f64 / fft_size as f64;
+        let nyquist_bin = fft_size / 2;
+        let low_bin = (19000.0 / bin_resolution) as usize;
+        let high_bin = (21000.0 / bin_resolution).min(nyquist_bin as f64) as usize;
+        for bin in low_bin..=high_bin.min(buffer.len() - 1) {
+            self.ultrasonic_magnitudes.push(buffer[bin].norm());
+        }
+
+        // Collect spectrogram data (downsampled)
+        if index.is_multiple_of(self.time_downsample) {
+            let time_sec = (start as f64) / sample_rate as f64;
+            self.spectrogram_times.push(time_sec);
+
+            // Downsample frequency bins by averaging
+            for freq_idx in 0..self.actual_freq_bins {
+                let bin_start = freq_idx * self.freq_downsample;
+                let bin_end = (bin_start + self.freq_downsample).min(nyquist_bin);
+
+                let sum: f64 = buffer[bin_start..bin_end].iter().map(|c| c.norm()).sum();
+                let avg_mag = sum / (bin_end - bin_start) as f64 * self.scale;
+                // Convert to dB, floor at -96dB
+                self.spectrogram_magnitudes.push(to_db(avg_mag).max(-96.0));
+            }
+        }
+    }
+
+    fn merge(&mut self, next: Self) {
+        self.windows += next.windows;
+        self.avg_full += next.avg_full;
+        self.avg_mid_high += next.avg_mid_high;
+        self.avg_high += next.avg_high;
+        self.avg_upper += next.avg_upper;
+        self.avg_19_20k += next.avg_19_20k;
+        self.avg_ultrasonic += next.avg_ultrasonic;
+        self.ultrasonic_magnitudes.extend(next.ultrasonic_magnitudes);
+        self.spectrogram_times.extend(next.spectrogram_times);
+        self.spectrogram_magnitudes.extend(next.spectrogram_magnitudes);
+    }
+}
+
+/// Per-window cutoff frequency, rolloff slope and transition width,
+/// plus the average spectrum (dB) of the same windows for the cutoff fit
+struct CutoffMeasurements {
+    sample_rate: u32,
+    fft_size: usize,
+    /// Measure every `stride`-th window
+    stride: usize,
+    times: Vec<f64>,
+    cutoff_frequencies: Vec<f64>,
+    rolloff_slopes: Vec<f64>,
+    transition_widths: Vec<f64>,
+    spectrum_db: Vec<f64>,
+}
+
+impl CutoffMeasurements {
+    fn new(sample_rate: u32, fft_size: usize, stride: usize) -> Self {
+        Self {
+            sample_rate,
+            fft_size,
+            stride: stride.max(1),
+            times: Vec::new(),
+            cutoff_frequencies: Vec::new(),
+            rolloff_slopes: Vec::new(),
+            transition_widths: Vec::new(),
+            spectrum_db: Vec::new(),
+        }
+    }
+}
+
+impl WindowAccumulator for CutoffMeasurements {
+    fn add(&mut self, index: usize, start: usize, buffer: &[Complex<f64>]) {
+        if !index.is_multiple_of(self.stride) {
+            return;
+        }
+        let (sample_rate, fft_size) = (self.sample_rate, self.fft_size);
+
+        let nyquist_bin = fft_size / 2;
+        if self.spectrum_db.len() != nyquist_bin {
+            self.spectrum_db = vec![0.0; nyquist_bin];
+        }
+        for (sum, c) in self.spectrum_db.iter_mut().zip(buffer) {
+            *sum += to_db(c.norm());
+        }
+
+        self.times.push(start as f64 / sample_rate as f64);
+        self.cutoff_frequencies
+            .push(detect_cutoff_frequency(buffer, sample_rate, fft_size));
+        self.rolloff_slopes
+            .push(measure_rolloff_slope(buffer, sample_rate, fft_size));
+        self.transition_widths
+            .push(measure_transition_width(buffer, sample_rate, fft_size));
+    }
+
+    fn merge(&mut self, next: Self) {
+        if self.spectrum_db.is_empty() {
+            self.spectrum_db = next.spectrum_db;
+        } else {
+            for (sum, v) in self.spectrum_db.iter_mut().zip(next.spectrum_db) {
+                *sum += v;
+            }
+        }
+        self.times.extend(next.times);
+        self.cutoff_frequencies.extend(next.cutoff_frequencies);
+        self.rolloff_slopes.extend(next.rolloff_slopes);
+        self.transition_widths.extend(next.transition_widths);
+    }
+}
+
+/// Perform spectral analysis with the default FFT configuration
+pub fn analyze(data: &[u8], declared_sample_rate: u32) -> SpectralResult {
+    analyze_with_config(data, declared_sample_rate, &FftConfig::default())
+}
+
+/// Perform spectral analysis on audio data with the given FFT configuration
+pub fn analyze_with_config(
+    data: &[u8],
+    declared_sample_rate: u32,
+    config: &FftConfig,
+) -> SpectralResult {
+    analyze_with_emphasis(data, declared_sample_rate, config, None)
+}
+
+/// Spectral analysis for a file whose pre-emphasis is declared outside the
+/// audio data (a cue sheet next to it)
+///
+/// A FLAC's own CUESHEET block is checked either way.
+pub fn analyze_with_emphasis(
+    data: &[u8],
+    _declared_sample_rate: u32,
+    config: &FftConfig,
+    declared_emphasis: Option<EmphasisSource>,
+) -> SpectralResult {
+    let declared_emphasis = declared_emphasis
+        .or_else(|| flac::cuesheet_pre_emphasis(data).then_some(EmphasisSource::FlacCuesheet));
+
+    // Decode audio to PCM (supports MP3, FLAC, WAV, OGG, etc.)
+    match DecodedAudio::decode(data) {
+        Some(audio) => analyze_decoded(&audio, config, declared_emphasis),
+        None => SpectralResult::with_config(config.sanitized()),
+    }
+}
+
+/// Spectral analysis of audio already decoded, with its pre-emphasis as
+/// declared by a cue sheet or the FLAC's CUESHEET block
+pub fn analyze_decoded(
+    decoded: &DecodedAudio,
+    config: &FftConfig,
+    declared_emphasis: Option<EmphasisSource>,
+) -> SpectralResult {
+    let config = config.sanitized();
+    let audio = match channel_audio(decoded) {
+        Some(a) => a,
+        None => return SpectralResult::with_config(config),
+    };
+
+    // Stereo correlation (front left/right of multichannel files)
+    let channel_count = audio.channels.len();
+    let left = &audio.channels[0];
+    let right = audio.channels.get(1).unwrap_or(left);
+    let correlation = analyze_stereo_correlation(left, right, audio.sample_rate, channel_count);
+
+    // An inverted channel cancels the other in the mix, which would score the
+    // defect as a lossy cutoff: score the mix with the polarity put right
+    let inverted = correlation
+        .as_ref()
+        .is_some_and(|c| c.avg_correlation <= POLARITY_INVERTED_CORRELATION);
+    let samples = if inverted { audio.mono_uninverted() } else { audio.mono() };
+    if samples.len() < config.size {
+        return SpectralResult::with_config(config);
+    }
+
+    let mut result = analyze_samples(&samples, audio.sample_rate, &config, declared_emphasis);
+
+    // Surround: score every full-range channel on its own, since a lossy
+    // core often left the rear channels with lower cutoffs than the fronts
+    if audio.channels.len() > 2 {
+        result.details.channels = Some(analyze_channels(&audio, &config, declared_emphasis));
+    }
+
+    result.details.stereo_correlation = correlation;
+    if channel_count > 1 {
+        let anomalies = analyze_stereo_anomalies(left, right);
+        // Stereo defects are reported alongside, but never scored
+        result.flags.extend(stereo_anomaly_flags(result.details.stereo_correlation.as_ref(), &anomalies));
+        result.details.stereo_anomalies = Some(anomalies);
+    }
+
+    result
+}
+
+/// Spectral analysis of each channel of a multichannel file
+///
+/// LFE channels are band-limited by design and silent channels have nothing
+/// to measure, so both are listed with their level but not scored.
+fn analyze_channels(
+    audio: &ChannelAudio,
+    config: &FftConfig,
+    declared_emphasis: Option<EmphasisSource>,
+) -> Vec<ChannelDetails> {
+    audio
+        .channels
+        .iter()
+        .zip(audio.layout_or_unknown())
+        .enumerate()
+        .map(|(index, (samples, position))| {
+            let label = channel_label(position, index);
+            let rms_full = to_db(rms(samples));
+            if is_lfe(position) || rms_full < SILENT_CHANNEL_DB || samples.len() < config.size {
+                return ChannelDetails {
+                    label,
+                    rms_full,
+                    ..Default::default()
+                };
+            }
+
+            let channel = analyze_samples(samples, audio.sample_rate, config, declared_emphasis);
+            ChannelDetails {
+                label,
+                scored: true,
+                rms_full,
+                upper_drop: channel.details.upper_drop,
+                ultrasonic_drop: channel.details.ultrasonic_drop,
+                avg_cutoff_freq: channel.details.avg_cutoff_freq,
+                cutoff_fit: channel.details.cutoff_fit,
+                score: channel.score,
+                flags: channel.flags,
+            }
+        })
+        .collect()
+}
+
+/// Subtract the emphasis shelf from the upper band levels at each band's centre
+fn deemphasize_bands(details: &mut SpectralDetails) {
+    let gain = |low: f64, high: f64| emphasis::gain_db((low * high).sqrt());
+    details.rms_mid_high -= gain(10000.0, 15000.0);
+    details.rms_high -= gain(15000.0, 20000.0);
+    details.rms_upper -= gain(17000.0, 20000.0);
+    details.rms_19_20k -= gain(19000.0, 20000.0);
+    details.rms_ultrasonic -= gain(20000.0, 22000.0);
+}
 
-    if samples.len() < FFT_SIZE {
-        return result;
-    }
+/// Levels, cutoff measurements and score of one signal
+fn analyze_samples(
+    samples: &[f64],
+    sample_rate: u32,
+    config: &FftConfig,
+    declared_emphasis: Option<EmphasisSource>,
+) -> SpectralResult {
+    let config = *config;
+    let mut result = SpectralResult::with_config(config);
 
     // Calculate overall RMS
-    let rms_full = to_db(rms(&samples));
-    result.details.rms_full = rms_full;
+    result.details.rms_full = to_db(rms(samples));
 
     // Set up FFT
     let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(FFT_SIZE);
-    let window = hanning_window(FFT_SIZE);
-
-    // Process overlapping windows and average the results
-    let hop_size = FFT_SIZE / 2;
-    let num_windows = (samples.len() - FFT_SIZE) / hop_size + 1;
-
-    let mut avg_full = 0.0;
-    let mut avg_mid_high = 0.0;
-    let mut avg_high = 0.0;
-    let mut avg_upper = 0.0;
-    let mut avg_19_20k = 0.0;
-    let mut avg_ultrasonic = 0.0;
-
-    // For spectral flatness calculation
-    let mut ultrasonic_magnitudes: Vec<f64> = Vec::new();
-
-    // For lo-fi detection: collect per-window metrics
-    let mut cutoff_frequencies: Vec<f64> = Vec::with_capacity(num_windows);
-    let mut rolloff_slopes: Vec<f64> = Vec::with_capacity(num_windows);
-    let mut transition_widths: Vec<f64> = Vec::with_capacity(num_windows);
-
-    // For spectrogram: collect downsampled magnitude spectra
-    let bin_resolution = sample_rate as f64 / FFT_SIZE as f64;
-    let nyquist_bin = FFT_SIZE / 2;
-
-    // Calculate frequency bin downsampling factor
-    let freq_downsample = (nyquist_bin / SPECTROGRAM_FREQ_BINS).max(1);
-    let actual_freq_bins = nyquist_bin / freq_downsample;
-
-    // Calculate time downsampling factor
-    let time_downsample = (num_windows / SPECTROGRAM_MAX_TIME_SLICES).max(1);
-    let actual_time_slices = (num_windows + time_downsample - 1) / time_downsample;
-
-    // Pre-allocate spectrogram storage
-    let mut spectrogram_magnitudes: Vec<f64> = Vec::with_capacity(actual_time_slices * actual_freq_bins);
-    let mut spectrogram_times: Vec<f64> = Vec::with_capacity(actual_time_slices);
-
-    // Build frequency axis (Hz)
-    let spectrogram_frequencies: Vec<f64> = (0..actual_freq_bins)
-        .map(|i| (i * freq_downsample) as f64 * bin_resolution)
-        .collect();
-
-    for i in 0..num_windows {
-        let start = i * hop_size;
-        let end = start + FFT_SIZE;
-
-        if end > samples.len() {
-            break;
+    let fft = planner.plan_fft_forward(config.size);
+    let window = config.window.coefficients(config.size);
+
+    let cutoffs = match config.short_pass() {
+        // Multi-resolution: levels and spectrogram from the short FFT, cutoff
+        // measurements from the long-FFT windows the single pass would measure
+        Some(short) => {
+            let short_fft = planner.plan_fft_forward(short.size);
+            let short_window = short.window.coefficients(short.size);
+            let short_windows = window_count(samples.len(), &short);
+            let scale = level_correction(&short_window);
+            let levels = map_reduce_windows(samples, &short, short_fft.as_ref(), &short_window, || {
+                LevelAccumulator::new(sample_rate, short.size, short_windows, scale)
+            });
+            levels.finish(&mut result.details);
+
+            let measured = FftConfig {
+                hop: config.hop * CUTOFF_STRIDE,
+                ..config
+            };
+            map_reduce_windows(samples, &measured, fft.as_ref(), &window, || {
+                CutoffMeasurements::new(sample_rate, config.size, 1)
+            })
         }
-
-        // Apply window and convert to complex
-        let mut buffer: Vec<Complex<f64>> = samples[start..end]
-            .iter()
-            .zip(window.iter())
-            .map(|(&s, &w)| Complex::new(s * w, 0.0))
-            .collect();
-
-        // Perform FFT
-        fft.process(&mut buffer);
-
-        // Calculate band energies (all from FFT for fair comparison)
-        avg_full += band_energy(&buffer, sample_rate, 20, 20000); // Full audible range
-        avg_mid_high += band_energy(&buffer, sample_rate, 10000, 15000);
-        avg_high += band_energy(&buffer, sample_rate, 15000, 20000);
-        avg_upper += band_energy(&buffer, sample_rate, 17000, 20000);
-        avg_19_20k += band_energy(&buffer, sample_rate, 19000, 20000);
-        avg_ultrasonic += band_energy(&buffer, sample_rate, 20000, 22000);
-
-        // Collect magnitudes in 19-21kHz for flatness calculation
-        let low_bin = (19000.0 / bin_resolution) as usize;
-        let high_bin = (21000.0 / bin_resolution).min((FFT_SIZE / 2) as f64) as usize;
-        for bin in low_bin..=high_bin.min(buffer.len() - 1) {
-            ultrasonic_magnitudes.push(buffer[bin].norm());
+        None => {
+            let windows = window_count(samples.len(), &config);
+            let scale = level_correction(&window);
+            let (levels, cutoffs) = map_reduce_windows(samples, &config, fft.as_ref(), &window, || {
+                (
+                    LevelAccumulator::new(sample_rate, config.size, windows, scale),
+                    // Lo-fi detection: measure cutoff frequency, slope, and transition width per window
+                    CutoffMeasurements::new(sample_rate, config.size, CUTOFF_STRIDE),
+                )
+            });
+            levels.finish(&mut result.details);
+            cutoffs
         }
+    };
 
-        // Lo-fi detection: measure cutoff frequency, slope, and transition width per window
-        // Only sample every 4th window to reduce computation while still capturing variance
-        if i % 4 == 0 {
-            cutoff_frequencies.push(detect_cutoff_frequency(&buffer, sample_rate));
-            rolloff_slopes.push(measure_rolloff_slope(&buffer, sample_rate));
-            transition_widths.push(measure_transition_width(&buffer, sample_rate));
+    // Calculate lo-fi detection metrics
+    // Cutoff variance: low = fixed (lossy), high = varying (natural)
+    let CutoffMeasurements {
+        times,
+        cutoff_frequencies,
+        rolloff_slopes,
+        transition_widths,
+        mut spectrum_db,
+        ..
+    } = cutoffs;
+    let mut emphasis_strength = None;
+    if !cutoff_frequencies.is_empty() {
+        let windows = cutoff_frequencies.len() as f64;
+        spectrum_db.iter_mut().for_each(|v| *v /= windows);
+        let bin_hz = sample_rate as f64 / config.size as f64;
+        result.details.cutoff_fit = cutoff::fit_cutoff(&spectrum_db, bin_hz, CUTOFF_FIT_LOW_HZ);
+        let mut estimate = generation::estimate(&spectrum_db, bin_hz, CUTOFF_FIT_LOW_HZ, samples);
+        // A resampler's filter is not a lossy generation
+        result.details.resample_estimate =
+            resample::detect(&estimate.cutoffs, result.details.cutoff_fit.as_ref(), sample_rate);
+        if let Some(ref resampled) = result.details.resample_estimate {
+            estimate.discount_edge(resampled.cutoff.frequency);
         }
+        result.details.generation_estimate = Some(estimate);
 
-        // Collect spectrogram data (downsampled)
-        if i % time_downsample == 0 {
-            let time_sec = (start as f64) / sample_rate as f64;
-            spectrogram_times.push(time_sec);
+        // Fit the emphasis shelf below any cutoff, where the spectrum is intact
+        let fit_high = match result.details.cutoff_fit {
+            Some(ref fit) if fit.is_reliable() => fit.frequency - EMPHASIS_FIT_MARGIN_HZ,
+            _ => sample_rate as f64 / 2.0,
+        };
+        emphasis_strength = emphasis::fit_strength(&spectrum_db, bin_hz, fit_high);
 
-            // Downsample frequency bins by averaging
-            for freq_idx in 0..actual_freq_bins {
-                let bin_start = freq_idx * freq_downsample;
-                let bin_end = (bin_start + freq_downsample).min(nyquist_bin);
-
-                let mut sum = 0.0;
-                for bin in bin_start..bin_end {
-                    if bin < buffer.len() {
-                        sum += buffer[bin].norm();
-                    }
-                }
-                let avg_mag = sum / (bin_end - bin_start) as f64;
-                // Convert to dB, floor at -96dB
-                let db = if avg_mag > 0.0 { 20.0 * avg_mag.log10() } else { -96.0 };
-                spectrogram_magnitudes.push(db.max(-96.0));
-            }
-        }
-    }
+        // Keep the masking model below any cutoff: the empty band above it
+        // has no near-threshold content to flicker
+        let masking_high = match result.details.cutoff_fit {
+            Some(ref fit) if fit.is_reliable() => fit.frequency - MASKING_CUTOFF_MARGIN_HZ,
+            _ => MASKING_MAX_HZ,
+        };
+        result.details.masking_estimate =
+            masking::estimate(samples, sample_rate, masking_high.min(MASKING_MAX_HZ));
 
-    let num_windows = num_windows.max(1) as f64;
-    avg_full /= num_windows;
-    avg_mid_high /= num_windows;
-    avg_high /= num_windows;
-    avg_upper /= num_windows;
-    avg_19_20k /= num_windows;
-    avg_ultrasonic /= num_windows;
+        result.details.content_profile =
+            Some(content::profile(&spectrum_db, bin_hz, samples, sample_rate));
+    }
 
-    // Convert to dB
-    result.details.rms_full = to_db(avg_full);
-    result.details.rms_mid_high = to_db(avg_mid_high);
-    result.details.rms_high = to_db(avg_high);
-    result.details.rms_upper = to_db(avg_upper);
-    result.details.rms_19_20k = to_db(avg_19_20k);
-    result.details.rms_ultrasonic = to_db(avg_ultrasonic);
+    // Emphasis lifts the upper bands ~10 dB, hiding the drop a lossy cutoff
+    // leaves; measure the drops as a de-emphasising player would hear them
+    result.details.emphasis = EmphasisEstimate::detect(declared_emphasis, emphasis_strength);
+    if result.details.emphasis.is_some() {
+        deemphasize_bands(&mut result.details);
+    }
 
     // Calculate drops (positive = high band is quieter, which is normal)
     result.details.high_drop = result.details.rms_full - result.details.rms_high;
     result.details.upper_drop = result.details.rms_mid_high - result.details.rms_upper;
     result.details.ultrasonic_drop = result.details.rms_19_20k - result.details.rms_ultrasonic;
-
-    // Calculate spectral flatness in 19-21kHz range
-    // Flatness = geometric_mean / arithmetic_mean (1.0 = white noise, 0.0 = pure tone/silence)
-    result.details.ultrasonic_flatness = spectral_flatness(&ultrasonic_magnitudes);
-
-    // Calculate lo-fi detection metrics
-    // Cutoff variance: low = fixed (lossy), high = varying (natural)
+    result.details.cutoff_histogram =
+        CutoffHistogram::from_frequencies(&cutoff_frequencies, CUTOFF_HISTOGRAM_BIN_HZ);
     result.details.cutoff_variance = std_deviation(&cutoff_frequencies);
     result.details.avg_cutoff_freq = if !cutoff_frequencies.is_empty() {
         cutoff_frequencies.iter().sum::<f64>() / cutoff_frequencies.len() as f64
     } else {
         22050.0 // Default to Nyquist if no measurements
     };
+    if !cutoff_frequencies.is_empty() {
+        result.details.cutoff_timeline = Some(CutoffTimeline {
+            times,
+            frequencies: cutoff_frequencies,
+        });
+    }
 
     // Average rolloff slope (dB/kHz)
     result.details.rolloff_slope = if !rolloff_slopes.is_empty() {
@@ -819,20 +1708,12 @@ pub fn analyze(data: &[u8], _declared_sample_rate: u32) -> SpectralResult {
     // Require high variance as a mandatory condition, plus one other
     result.details.natural_rolloff = very_high_variance && natural_indicators >= 2;
 
-    // Store spectrogram data
-    if !spectrogram_times.is_empty() && !spectrogram_magnitudes.is_empty() {
-        result.details.spectrogram = Some(SpectrogramData {
-            times: spectrogram_times,
-            frequencies: spectrogram_frequencies,
-            magnitudes: spectrogram_magnitudes,
-            num_freq_bins: actual_freq_bins,
-            num_time_slices: actual_time_slices,
-        });
-    }
-
-    // Analyze stereo correlation (separate decode to preserve L/R channels)
-    result.details.stereo_correlation = analyze_stereo_correlation(data);
+    score(&mut result);
+    result
+}
 
+/// Score the measured details and name what was found
+fn score(result: &mut SpectralResult) {
     // Score based on analysis
     // Tuned to detect lossy origins in "lossless" files
     //
@@ -851,9 +1732,19 @@ pub fn analyze(data: &[u8], _declared_sample_rate: u32) -> SpectralResult {
         result.flags.push("natural_rolloff_detected".to_string());
     }
 
+    // Band-limited productions (synths filtered below 16-18kHz) are scored
+    // the same way: the missing top end came through a filter slope, not a codec
+    let adaptive = match result.details.content_profile {
+        Some(ref profile) if profile.is_adaptive() => {
+            result.flags.push(profile.flag());
+            true
+        }
+        _ => false,
+    };
+
     // Score multiplier: reduce scores if natural rolloff characteristics detected
     // 1.0 = full scoring, 0.3 = heavily reduced for natural sources
-    let score_multiplier = if result.details.natural_rolloff { 0.3 } else { 1.0 };
+    let score_multiplier = if result.details.natural_rolloff || adaptive { 0.3 } else { 1.0 };
 
     // Severe damage - almost certainly from low-bitrate lossy (MP3 128k or worse)
     // Unless it's natural rolloff (cassette, vintage master)
@@ -896,7 +1787,10 @@ pub fn analyze(data: &[u8], _declared_sample_rate: u32) -> SpectralResult {
 
     // Only apply 320k detection if NOT natural rolloff
     // (natural sources like tape won't have consistent 20kHz brick-wall)
-    if !result.details.natural_rolloff {
+    // and NOT resampled (the resampler's filter empties the band above ~21kHz)
+    // or a band-limited production (its synths never reached 20kHz)
+    let resampled = result.details.resample_estimate.is_some();
+    if !result.details.natural_rolloff && !resampled && !adaptive {
         // Massive cliff at 20kHz - strong indicator of 320k transcode
         if result.details.ultrasonic_drop > 40.0 {
             result.score += 35;
@@ -935,26 +1829,70 @@ pub fn analyze(data: &[u8], _declared_sample_rate: u32) -> SpectralResult {
     }
 
     // Very quiet ultrasonic band (absolute check)
-    // Skip if natural rolloff - tape/vinyl won't have ultrasonic content
-    if result.details.rms_ultrasonic < -70.0 && !result.details.natural_rolloff {
+    // Skip if natural rolloff - tape/vinyl won't have ultrasonic content - or resampled
+    // or band-limited by production
+    if result.details.rms_ultrasonic < -70.0 && !result.details.natural_rolloff && !resampled && !adaptive {
         result.score += 10;
         result.flags.push("silent_20k+".to_string());
     }
 
     // === BRICK-WALL DETECTION BONUS ===
-    // If we have low cutoff variance AND steep slope, this is almost certainly
-    // a lossy transcode, not natural rolloff. Add extra confidence.
-    if result.details.cutoff_variance < 200.0 && result.details.rolloff_slope > 8.0 {
+    // Prefer the fitted edge shape when the fit is trustworthy. Otherwise fall back
+    // to low cutoff variance AND steep slope, which is almost certainly a lossy
+    // transcode, not natural rolloff. Add extra confidence.
+    let brick_wall = match result.details.cutoff_fit {
+        Some(ref fit) if fit.is_reliable() => fit.shape == EdgeShape::BrickWall,
+        _ => result.details.cutoff_variance < 200.0 && result.details.rolloff_slope > 8.0,
+    };
+    if brick_wall && !resampled {
         result.score += 15;
         result.flags.push("brick_wall_cutoff".to_string());
     }
 
-    result
+    // Sample-rate conversion is reported, but it is not lossy damage
+    if let Some(ref resampled) = result.details.resample_estimate {
+        result.flags.push(resampled.flag());
+    }
+
+    // So is emphasis; its effect on the bands was already taken out above
+    if let Some(ref emphasis) = result.details.emphasis {
+        result.flags.push(emphasis.flag());
+    }
+
+    // Multiple lossy generations: the transcode is already scored above,
+    // this names the chain so "320 from 192 from 128" stands out
+    if let Some(ref estimate) = result.details.generation_estimate {
+        if estimate.generations >= 2 {
+            result.flags.push(format!(
+                "lossy_generations_x{}({})",
+                estimate.generations,
+                estimate.chain_description()
+            ));
+        }
+    }
+
+    // === MASKING CONFORMITY ===
+    // Noise that flickers in and out at the masking threshold is what an
+    // encoder's quantiser leaves, even when the bandwidth is full (V0, 320k).
+    // Tape hiss and filtered synths flicker too, so it's damped like the band checks
+    if let Some(ref masking) = result.details.masking_estimate {
+        if masking.masking_conformity >= 0.8 {
+            result.score += (20.0 * score_multiplier) as u32;
+            result.flags.push(masking.flag("mask_shaped_noise"));
+        } else if masking.masking_conformity >= 0.5 {
+            result.score += (10.0 * score_multiplier) as u32;
+            result.flags.push(masking.flag("possible_mask_shaped_noise"));
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::analyzer::test_noise::TestNoise;
+    use crate::analyzer::test_wav::pcm16_channels;
+
+    const SAMPLE_RATE: u32 = 44100;
 
     // ==========================================================================
     // EDUCATIONAL BACKGROUND: Understanding FFT-Based Spectral Analysis
@@ -1068,6 +2006,168 @@ mod tests {
         }
     }
 
+    // ==========================================================================
+    // ALTERNATIVE WINDOWS & FFT CONFIG TESTS
+    // ==========================================================================
+    //
+    // Hann leaks strong tonal partials ~31 dB into neighbouring bins, which can
+    // fill an otherwise empty band above a codec cutoff. Blackman-Harris and
+    // Kaiser trade a wider main lobe for ~90 dB sidelobe suppression.
+    //
+    // Band levels are scaled by the window's coherent gain so that changing
+    // window or FFT size doesn't shift the absolute dB thresholds.
+    // ==========================================================================
+
+    #[test]
+    fn test_blackman_harris_window_shape() {
+        let window = blackman_harris_window(101);
+
+        // Near-zero edges (a0 - a1 + a2 - a3 = 0.00006)
+        assert!(window[0] < 0.001, "Edge should be ~0, got {}", window[0]);
+        assert!(window[100] < 0.001, "Edge should be ~0, got {}", window[100]);
+        // Unity at the center
+        assert!((window[50] - 1.0).abs() < 0.001, "Center should be 1.0, got {}", window[50]);
+    }
+
+    #[test]
+    fn test_kaiser_window_shape() {
+        let window = kaiser_window(101, KAISER_BETA);
+
+        assert!((window[50] - 1.0).abs() < 0.001, "Center should be 1.0, got {}", window[50]);
+        // Edge value is 1 / I0(β) ≈ 0.0012
+        assert!(window[0] < 0.002, "β=8.6 edges should be near zero, got {}", window[0]);
+        for i in 0..50 {
+            assert!((window[i] - window[100 - i]).abs() < 1e-9, "Should be symmetric at {}", i);
+        }
+    }
+
+    #[test]
+    fn test_bessel_i0_known_values() {
+        assert!((bessel_i0(0.0) - 1.0).abs() < 1e-12);
+        // I0(1) = 1.2660658777...
+        assert!((bessel_i0(1.0) - 1.266_065_877_7).abs() < 1e-8);
+    }
+
+    #[test]
+    fn test_level_correction_reference_is_unity() {
+        // The default Hann/8192 analysis is the reference: no correction
+        let correction = level_correction(&hanning_window(FFT_SIZE));
+        assert!((correction - 1.0).abs() < 0.001, "Got {}", correction);
+
+        // Halving the FFT size halves the coherent sum, so levels are doubled (+6 dB)
+        let correction = level_correction(&hanning_window(FFT_SIZE / 2));
+        assert!((correction - 2.0).abs() < 0.01, "Got {}", correction);
+
+        // Blackman-Harris has a lower power sum (0.258 vs 0.375 of its length)
+        let correction = level_correction(&blackman_harris_window(FFT_SIZE));
+        assert!((correction - (0.375f64 / 0.25796).sqrt()).abs() < 0.01, "Got {}", correction);
+    }
+
+    #[test]
+    fn test_band_levels_agree_across_windows() {
+        // Broadband noise reads the same band levels whatever the window
+        let noise = test_noise(44100 * 3, 3);
+        let hann = analyze_samples(&noise, 44100, &FftConfig::default(), None);
+        for window in [WindowType::BlackmanHarris, WindowType::Kaiser] {
+            let other = analyze_samples(&noise, 44100, &FftConfig::default().with_window(window), None);
+            for (a, b) in [
+                (hann.details.rms_mid_high, other.details.rms_mid_high),
+                (hann.details.rms_upper, other.details.rms_upper),
+                (hann.details.rms_ultrasonic, other.details.rms_ultrasonic),
+            ] {
+                assert!((a - b).abs() < 0.5, "{:?}: {:.2} vs {:.2} dB", window, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_multi_resolution_measures_the_same_windows() {
+        let noise = test_noise(44100 * 3, 4);
+        let config = FftConfig::default().with_hop(2048);
+        let single = analyze_samples(&noise, 44100, &config, None);
+        let multi = analyze_samples(
+            &noise,
+            44100,
+            &config.with_multi_resolution(Some(DEFAULT_SHORT_FFT_SIZE)),
+            None,
+        );
+        let times = |result: &SpectralResult| result.details.cutoff_timeline.as_ref().unwrap().times.clone();
+        assert_eq!(times(&single), times(&multi));
+    }
+
+    #[test]
+    fn test_window_type_parsing() {
+        assert_eq!("hann".parse::<WindowType>(), Ok(WindowType::Hann));
+        assert_eq!("Blackman-Harris".parse::<WindowType>(), Ok(WindowType::BlackmanHarris));
+        assert_eq!("blackman_harris".parse::<WindowType>(), Ok(WindowType::BlackmanHarris));
+        assert_eq!("kaiser".parse::<WindowType>(), Ok(WindowType::Kaiser));
+        assert!("rectangular".parse::<WindowType>().is_err());
+
+        // Display round-trips through FromStr
+        for w in [WindowType::Hann, WindowType::BlackmanHarris, WindowType::Kaiser] {
+            assert_eq!(w.to_string().parse::<WindowType>(), Ok(w));
+        }
+    }
+
+    #[test]
+    fn test_fft_config_default() {
+        let config = FftConfig::default();
+        assert_eq!(config.size, FFT_SIZE);
+        assert_eq!(config.hop, FFT_SIZE / 2);
+        assert_eq!(config.window, WindowType::Hann);
+        assert_eq!(config.multi_resolution, None);
+        assert_eq!(FftConfig::new(FFT_SIZE), config);
+    }
+
+    #[test]
+    fn test_fft_config_sanitized() {
+        // Hop of zero falls back to 50% overlap, hop larger than size is clamped
+        assert_eq!(FftConfig::new(4096).with_hop(0).sanitized().hop, 2048);
+        assert_eq!(FftConfig::new(4096).with_hop(10000).sanitized().hop, 4096);
+
+        // Tiny FFTs are raised to the minimum
+        assert_eq!(FftConfig::new(16).sanitized().size, MIN_FFT_SIZE);
+
+        // Short FFT must be shorter than the long one to be meaningful
+        let config = FftConfig::new(4096).with_multi_resolution(Some(8192)).sanitized();
+        assert_eq!(config.multi_resolution, None);
+        let config = FftConfig::new(16384)
+            .with_multi_resolution(Some(DEFAULT_SHORT_FFT_SIZE))
+            .sanitized();
+        assert_eq!(config.multi_resolution, Some(DEFAULT_SHORT_FFT_SIZE));
+        assert_eq!(config.short_pass().map(|c| c.size), Some(DEFAULT_SHORT_FFT_SIZE));
+    }
+
+    #[test]
+    fn test_window_count() {
+        let config = FftConfig::new(1024);
+        assert_eq!(window_count(1000, &config), 0);
+        assert_eq!(window_count(1024, &config), 1);
+        assert_eq!(window_count(1024 + 512, &config), 2);
+        assert_eq!(window_count(1024 + 511, &config), 1);
+    }
+
+    #[test]
+    fn test_cutoff_detection_uses_fft_size() {
+        // A flat spectrum up to 16kHz, silence above, at two FFT sizes.
+        // The cutoff must land at ~16kHz regardless of size.
+        for fft_size in [4096, FFT_SIZE, 16384] {
+            let bin_hz = SAMPLE_RATE as f64 / fft_size as f64;
+            let cutoff_bin = (16000.0 / bin_hz) as usize;
+            let spectrum: Vec<Complex<f64>> = (0..fft_size)
+                .map(|b| Complex::new(if b < cutoff_bin { 1.0 } else { 0.0 }, 0.0))
+                .collect();
+
+            let cutoff = detect_cutoff_frequency(&spectrum, SAMPLE_RATE, fft_size);
+            assert!(
+                (cutoff - 16000.0).abs() < 2.0 * bin_hz,
+                "{}-point FFT: expected ~16000 Hz, got {}",
+                fft_size,
+                cutoff
+            );
+        }
+    }
+
     // ==========================================================================
     // DECIBEL CONVERSION TESTS
     // ==========================================================================
@@ -1241,11 +2341,11 @@ mod tests {
         fft_result[bin_1000hz] = Complex::new(1.0, 0.0);
 
         // Energy in 900-1100 Hz should capture this
-        let energy = band_energy(&fft_result, SAMPLE_RATE, 900, 1100);
+        let energy = band_energy(&fft_result, SAMPLE_RATE, FFT_SIZE, 900, 1100);
         assert!(energy > 0.0, "Should detect energy at 1000 Hz");
 
         // Energy in 2000-3000 Hz should be zero
-        let energy_high = band_energy(&fft_result, SAMPLE_RATE, 2000, 3000);
+        let energy_high = band_energy(&fft_result, SAMPLE_RATE, FFT_SIZE, 2000, 3000);
         assert!(
             energy_high < 0.001,
             "Should have no energy in 2-3kHz band"
@@ -1265,7 +2365,7 @@ mod tests {
             fft_result[bin] = Complex::new(1.0, 0.0);
         }
 
-        let energy = band_energy(&fft_result, SAMPLE_RATE, 1000, 2000);
+        let energy = band_energy(&fft_result, SAMPLE_RATE, FFT_SIZE, 1000, 2000);
         let num_bins = (bin_2000 - bin_1000 + 1) as f64;
 
         // Expected energy = sqrt(sum of magnitudes squared)
@@ -1628,4 +2728,420 @@ mod tests {
         assert!(sc.is_stereo);
         assert!(sc.avg_correlation > 0.9);
     }
+
+    // ==========================================================================
+    // STEREO ANOMALY TESTS
+    // ==========================================================================
+    //
+    // Defects common in dubious "lossless" uploads, flagged but never scored:
+    //
+    // - Fake stereo: mono copied to both channels (correlation 1.0 throughout)
+    // - Polarity inversion: one channel negated (strongly negative correlation)
+    // - Silent channel: one side is digital silence
+    // - Channel offset: one side shifted by N samples from a bad join
+    // ==========================================================================
+
+    /// Deterministic white noise at -12 dBFS peak
+    fn test_noise(len: usize, seed: u64) -> Vec<f64> {
+        TestNoise::new(seed).samples(len, 0.25)
+    }
+
+    fn test_correlation(min: f64, avg: f64) -> StereoCorrelation {
+        StereoCorrelation {
+            avg_correlation: avg,
+            min_correlation: min,
+            max_correlation: avg.max(min),
+            is_stereo: true,
+            channel_count: 2,
+            ..Default::default()
+        }
+    }
+
+    fn test_anomalies(left_rms: f64, right_rms: f64) -> StereoAnomalies {
+        StereoAnomalies {
+            left_rms,
+            right_rms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_channel_offset_detected() {
+        let left = test_noise(44100, 1);
+        let mut right = vec![0.0; 37];
+        right.extend_from_slice(&left[..left.len() - 37]);
+
+        let (lag, corr, agreement) = detect_channel_offset(&left, &right);
+        assert_eq!(lag, 37, "Right channel delayed by 37 samples");
+        assert!(corr > 0.99, "Shifted copy should match almost exactly: {}", corr);
+        assert!(agreement > 0.9);
+    }
+
+    #[test]
+    fn test_channel_offset_aligned_and_uncorrelated() {
+        let left = test_noise(44100, 1);
+        assert_eq!(detect_channel_offset(&left, &left).0, 0, "Identical channels are aligned");
+
+        let right = test_noise(44100, 2);
+        assert_eq!(detect_channel_offset(&left, &right).0, 0, "Independent noise has no offset");
+    }
+
+    #[test]
+    fn test_stereo_anomaly_flags_fake_stereo() {
+        let flags = stereo_anomaly_flags(Some(&test_correlation(0.9999, 1.0)), &test_anomalies(-20.0, -20.0));
+        assert_eq!(flags, vec!["fake_stereo(min_corr=0.9999)"]);
+
+        // Normal stereo that is briefly near-mono is not fake
+        let flags = stereo_anomaly_flags(Some(&test_correlation(0.6, 0.95)), &test_anomalies(-20.0, -20.0));
+        assert!(flags.is_empty());
+    }
+
+    #[test]
+    fn test_stereo_anomaly_flags_polarity_inverted() {
+        let flags = stereo_anomaly_flags(Some(&test_correlation(-1.0, -0.98)), &test_anomalies(-20.0, -20.0));
+        assert_eq!(flags, vec!["polarity_inverted(avg_corr=-0.98)"]);
+    }
+
+    #[test]
+    fn test_stereo_anomaly_flags_silent_channel() {
+        // A silent channel leaves nothing to correlate, so only the silence is reported
+        let flags = stereo_anomaly_flags(None, &test_anomalies(-20.0, -96.0));
+        assert_eq!(flags, vec!["silent_right_channel(-96dB)"]);
+        let flags = stereo_anomaly_flags(Some(&test_correlation(1.0, 1.0)), &test_anomalies(-20.0, -96.0));
+        assert_eq!(flags, vec!["silent_right_channel(-96dB)"]);
+
+        // Both channels quiet = quiet recording, not a dead channel
+        let flags = stereo_anomaly_flags(Some(&test_correlation(0.5, 0.7)), &test_anomalies(-85.0, -90.0));
+        assert!(flags.is_empty());
+    }
+
+    #[test]
+    fn test_inverted_noise_stays_ok() {
+        // Full-band noise with the right channel inverted: the plain mix
+        // cancels to nothing, which must not read as a missing top end
+        let left = test_noise(44100 * 3, 6);
+        let right: Vec<f64> = left.iter().map(|x| -x).collect();
+        let wav = test_wav(&left, &right, 44100);
+        let result = analyze_decoded(&DecodedAudio::decode(&wav).unwrap(), &FftConfig::default(), None);
+        assert!(result.flags.iter().any(|f| f.starts_with("polarity_inverted")), "Got {:?}", result.flags);
+        assert!(result.score < 35, "Scored {} with {:?}", result.score, result.flags);
+        assert!(!result.flags.iter().any(|f| f.starts_with("silent_") || f == "dead_ultrasonic_band"));
+    }
+
+    #[test]
+    fn test_silent_channel_through_analysis() {
+        // Silent all the way through: no window has two audible channels, so
+        // there is no correlation, but the dead channel is still reported
+        let wav = test_wav(&test_noise(44100 * 2, 5), &vec![0.0; 44100 * 2], 44100);
+        let result = analyze_decoded(&DecodedAudio::decode(&wav).unwrap(), &FftConfig::default(), None);
+        assert!(result.details.stereo_correlation.is_none());
+        assert!(
+            result.flags.iter().any(|f| f.starts_with("silent_right_channel")),
+            "Got {:?}",
+            result.flags
+        );
+    }
+
+    #[test]
+    fn test_silence_is_not_fake_stereo() {
+        let silence = vec![0.0; 44100 * 2];
+        let quiet: Vec<f64> = test_noise(44100 * 2, 4).iter().map(|x| x * 0.002).collect();
+
+        // Digital silence in both channels, and a silent channel beside a quiet one
+        for right in [&silence, &quiet] {
+            let wav = test_wav(&silence, right, 44100);
+            let result = analyze_with_config(&wav, 44100, &FftConfig::default());
+            assert!(
+                !result.flags.iter().any(|f| f.starts_with("fake_stereo")),
+                "Got {:?}",
+                result.flags
+            );
+        }
+        assert!(analyze_stereo_correlation(&silence, &silence, 44100, 2).is_none());
+    }
+
+    #[test]
+    fn test_stereo_anomaly_flags_channel_offset() {
+        let anomalies = StereoAnomalies {
+            channel_offset: -12,
+            offset_correlation: 0.97,
+            offset_agreement: 0.75,
+            ..test_anomalies(-20.0, -20.0)
+        };
+        let flags = stereo_anomaly_flags(Some(&test_correlation(0.1, 0.4)), &anomalies);
+        assert_eq!(flags, vec!["channel_offset(-12samples,corr=0.97)"]);
+
+        // A single agreeing segment isn't enough
+        let weak = StereoAnomalies { offset_agreement: 0.25, ..anomalies };
+        assert!(stereo_anomaly_flags(Some(&test_correlation(0.1, 0.4)), &weak).is_empty());
+    }
+
+    // ==========================================================================
+    // PARALLEL WINDOW PROCESSING TESTS
+    // ==========================================================================
+    //
+    // Windows are mapped in fixed-size chunks on the rayon pool and merged in
+    // window order. Floating-point addition isn't associative, so the fixed
+    // chunking is what makes results independent of the thread count.
+    // ==========================================================================
+
+    /// 16-bit stereo PCM WAV in memory
+    fn test_wav(left: &[f64], right: &[f64], sample_rate: u32) -> Vec<u8> {
+        pcm16_channels(&[left, right], sample_rate)
+    }
+
+    #[test]
+    fn test_parallel_windows_deterministic() {
+        let wav = test_wav(&test_noise(88200, 1), &test_noise(88200, 2), 44100);
+        let configs = [
+            FftConfig::default(),
+            FftConfig::new(2048).with_window(WindowType::BlackmanHarris),
+            FftConfig::default().with_multi_resolution(Some(DEFAULT_SHORT_FFT_SIZE)),
+        ];
+
+        for config in &configs {
+            let run = |threads: usize| {
+                let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+                let result = pool.install(|| analyze_with_config(&wav, 44100, config));
+                (result.score, result.flags, serde_json::to_string(&result.details).unwrap())
+            };
+
+            let serial = run(1);
+            assert!(serial.2.contains("\"spectrogram\""), "Analysis should have produced data");
+            assert_eq!(serial, run(4), "Results must not depend on thread count ({:?})", config);
+        }
+    }
+
+    #[test]
+    fn test_window_chunks_merge_in_order() {
+        let sample_rate = 44100;
+        let config = FftConfig::new(256);
+        let samples = test_noise(256 * 200, 3);
+        let window = config.window.coefficients(config.size);
+        let fft = FftPlanner::new().plan_fft_forward(config.size);
+
+        let cutoffs = map_reduce_windows(&samples, &config, fft.as_ref(), &window, || {
+            CutoffMeasurements::new(sample_rate, config.size, 1)
+        });
+
+        // Spans several chunks; times come back in window order
+        let windows = window_count(samples.len(), &config);
+        assert!(windows > 2 * WINDOW_CHUNK);
+        assert_eq!(cutoffs.times.len(), windows);
+        assert!(cutoffs.times.windows(2).all(|t| t[0] < t[1]));
+    }
+
+    // ==========================================================================
+    // CUTOFF TIMELINE / HISTOGRAM TESTS
+    // ==========================================================================
+    //
+    // avg_cutoff_freq and cutoff_variance summarise the per-window cutoffs, but
+    // the shape of the distribution matters when reviewing a borderline file:
+    //
+    // - Lossy encode: nearly every window lands in the same bin (e.g. 16 kHz)
+    // - Natural rolloff: cutoffs smear across several kHz
+    // ==========================================================================
+
+    #[test]
+    fn test_cutoff_histogram_sharp_peak() {
+        let cutoffs = vec![16010.0, 16050.0, 16100.0, 16020.0, 15900.0];
+        let hist = CutoffHistogram::from_frequencies(&cutoffs, 250.0).unwrap();
+
+        // Bins span only the observed range: 15750-16000 and 16000-16250
+        assert_eq!(hist.bin_starts, vec![15750.0, 16000.0]);
+        assert_eq!(hist.counts, vec![1, 4]);
+        assert_eq!(hist.peak_frequency, 16125.0);
+        assert!((hist.peak_fraction - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cutoff_histogram_broad_smear() {
+        let cutoffs: Vec<f64> = (0..20).map(|i| 12000.0 + i as f64 * 300.0).collect();
+        let hist = CutoffHistogram::from_frequencies(&cutoffs, 250.0).unwrap();
+
+        assert_eq!(hist.counts.iter().sum::<usize>(), 20);
+        assert!(hist.peak_fraction <= 0.1, "Smeared cutoffs should have no dominant bin");
+    }
+
+    #[test]
+    fn test_cutoff_histogram_empty() {
+        assert!(CutoffHistogram::from_frequencies(&[], 250.0).is_none());
+    }
+
+    #[test]
+    fn test_cutoff_timeline_not_serialized_when_none() {
+        let json = serde_json::to_string(&SpectralDetails::default()).unwrap();
+        assert!(!json.contains("cutoff_timeline"));
+        assert!(!json.contains("cutoff_histogram"));
+    }
+
+    // ==========================================================================
+    // MULTICHANNEL TESTS
+    // ==========================================================================
+    //
+    // Surround masters are sometimes assembled from mixed sources: clean
+    // fronts with rears taken from a lossy stem. Averaged into one mono mix
+    // the clean channels hide the damage, so each channel is measured on its
+    // own. The LFE is band-limited by design and never scored.
+    // ==========================================================================
+
+    /// White noise with everything above `cutoff` removed in the frequency domain
+    fn test_lowpassed_noise(len: usize, cutoff: f64, sample_rate: u32, seed: u64) -> Vec<f64> {
+        let mut spectrum: Vec<Complex<f64>> =
+            test_noise(len, seed).into_iter().map(|x| Complex::new(x, 0.0)).collect();
+        let mut planner = FftPlanner::new();
+        planner.plan_fft_forward(len).process(&mut spectrum);
+        let max_bin = (cutoff * len as f64 / sample_rate as f64) as usize;
+        for (bin, value) in spectrum.iter_mut().enumerate() {
+            if bin.min(len - bin) > max_bin {
+                *value = Complex::new(0.0, 0.0);
+            }
+        }
+        planner.plan_fft_inverse(len).process(&mut spectrum);
+        spectrum.iter().map(|c| c.re / len as f64).collect()
+    }
+
+    #[test]
+    fn test_channel_labels() {
+        assert_eq!(channel_label(Channels::FRONT_LEFT, 0), "FL");
+        assert_eq!(channel_label(Channels::LFE1, 3), "LFE");
+        assert_eq!(channel_label(Channels::SIDE_RIGHT, 5), "SR");
+        assert_eq!(channel_label(Channels::empty(), 6), "CH7");
+        assert!(is_lfe(Channels::LFE1) && !is_lfe(Channels::FRONT_CENTRE));
+    }
+
+    #[test]
+    fn test_mono_mix_excludes_lfe() {
+        let audio = ChannelAudio {
+            channels: vec![vec![0.2; 4], vec![0.4; 4], vec![1.0; 4]],
+            layout: vec![Channels::FRONT_LEFT, Channels::FRONT_RIGHT, Channels::LFE1],
+            sample_rate: 44100,
+        };
+        assert!(audio.mono().iter().all(|&x| (x - 0.3).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_surround_lossy_rears_scored_per_channel() {
+        let sample_rate = 44100;
+        let len = sample_rate as usize * 3;
+        let full = |seed| test_noise(len, seed);
+        let rear = |seed| test_lowpassed_noise(len, 16000.0, sample_rate, seed);
+        let lfe = test_lowpassed_noise(len, 120.0, sample_rate, 4);
+        let (fl, fr, fc, bl, br) = (full(1), full(2), full(3), rear(5), rear(6));
+        let wav = pcm16_channels(&[&fl, &fr, &fc, &lfe, &bl, &br], sample_rate);
+
+        let result = analyze(&wav, sample_rate);
+        let channels = result.details.channels.expect("6-channel file gets per-channel details");
+        let labels: Vec<&str> = channels.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, ["FL", "FR", "FC", "LFE", "BL", "BR"]);
+
+        assert!(!channels[3].scored, "LFE is listed but not scored");
+        for front in &channels[..3] {
+            assert!(front.scored);
+            assert!(front.score < 35, "{} should look clean: {:?}", front.label, front);
+        }
+        for back in &channels[4..] {
+            assert!(back.scored);
+            assert!(back.score > channels[0].score, "{} should score above the fronts", back.label);
+            assert!(back.avg_cutoff_freq < 17000.0, "{}: {:?}", back.label, back);
+        }
+    }
+
+    #[test]
+    fn test_stereo_has_no_channel_details() {
+        let wav = test_wav(&test_noise(88200, 1), &test_noise(88200, 2), 44100);
+        assert!(analyze(&wav, 44100).details.channels.is_none());
+    }
+
+    // ==========================================================================
+    // PRE-EMPHASIS TESTS
+    // ==========================================================================
+    //
+    // A pre-emphasised rip is ~10 dB brighter above 10 kHz than the master.
+    // The band levels are measured as a de-emphasising player would hear
+    // them, so the lossy-cutoff thresholds keep their meaning.
+    // ==========================================================================
+
+    /// 50/15 µs pre-emphasis shelf (bilinear transform of the analog filter)
+    fn test_pre_emphasis(samples: &[f64], sample_rate: u32) -> Vec<f64> {
+        let k = 2.0 * sample_rate as f64;
+        let (zero, pole) = (50e-6 * k, 15e-6 * k);
+        let (b0, b1) = ((1.0 + zero) / (1.0 + pole), (1.0 - zero) / (1.0 + pole));
+        let a1 = (1.0 - pole) / (1.0 + pole);
+        let (mut x1, mut y1) = (0.0, 0.0);
+        samples
+            .iter()
+            .map(|&x| {
+                let y = b0 * x + b1 * x1 - a1 * y1;
+                x1 = x;
+                y1 = y;
+                // Leave headroom for the ~10 dB boost
+                y * 0.3
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pre_emphasis_detected_and_removed() {
+        let noise = test_noise(44100 * 3, 11);
+        let emphasised = test_pre_emphasis(&noise, 44100);
+        let plain = analyze(&test_wav(&noise, &noise, 44100), 44100);
+        let wav = test_wav(&emphasised, &emphasised, 44100);
+        let result = analyze(&wav, 44100);
+
+        assert!(plain.details.emphasis.is_none(), "{:?}", plain.details.emphasis);
+        let estimate = result.details.emphasis.as_ref().expect("shelf should be fitted");
+        assert_eq!(estimate.source, EmphasisSource::Spectral);
+        let strength = estimate.strength.unwrap();
+        assert!((strength - 1.0).abs() < 0.2, "strength {}", strength);
+        assert!(result.flags.iter().any(|f| f.starts_with("pre_emphasis(spectral")));
+
+        // After de-emphasis the upper bands sit where the plain noise's do
+        // (less the 0.3 headroom gain)
+        let headroom = 20.0 * 0.3f64.log10();
+        let offset = result.details.rms_upper - plain.details.rms_upper - headroom;
+        assert!(offset.abs() < 1.5, "de-emphasised upper band off by {:.1} dB", offset);
+    }
+
+    #[test]
+    fn test_declared_emphasis_trusted() {
+        let noise = test_noise(44100 * 2, 12);
+        let wav = test_wav(&noise, &noise, 44100);
+        let result = analyze_with_emphasis(
+            &wav,
+            44100,
+            &FftConfig::default(),
+            Some(EmphasisSource::CueSheet),
+        );
+        let estimate = result.details.emphasis.expect("cue sheet declares emphasis");
+        assert_eq!(estimate.source, EmphasisSource::CueSheet);
+        assert!(result.flags.iter().any(|f| f.starts_with("pre_emphasis(cue")));
+    }
+
+    #[test]
+    fn test_masking_damped_like_band_checks() {
+        // Full ultrasonic band, so masking is the only thing scored
+        let result = |natural_rolloff: bool, mode: content::ContentMode| {
+            let mut result = SpectralResult::default();
+            result.details.ultrasonic_flatness = 1.0;
+            result.details.natural_rolloff = natural_rolloff;
+            result.details.content_profile = Some(ContentProfile { mode, ..Default::default() });
+            result.details.masking_estimate = Some(MaskingEstimate {
+                masking_conformity: 0.9,
+                bands: Vec::new(),
+            });
+            score(&mut result);
+            result
+        };
+
+        let standard = result(false, content::ContentMode::Standard);
+        assert_eq!(standard.score, 20);
+        assert_eq!(standard.flags, ["mask_shaped_noise(conformity=0.90)"]);
+
+        // A filtered synth's or a tape's noise floor flickers too
+        let adaptive = result(false, content::ContentMode::BandLimitedProduction);
+        assert_eq!(adaptive.score, 6);
+        assert!(adaptive.flags.contains(&"mask_shaped_noise(conformity=0.90)".to_string()));
+        assert_eq!(result(true, content::ContentMode::Standard).score, 6);
+    }
 }
@@ -0,0 +1,36 @@
+//! Deterministic noise for tests, so they don't need a rand dependency
+
+/// 64-bit linear congruential generator (Knuth's MMIX constants)
+pub struct TestNoise {
+    state: u64,
+}
+
+impl TestNoise {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Next raw 64-bit state
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self
+            .state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.state
+    }
+
+    /// Uniform in [0, 1)
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in [-1, 1)
+    pub fn signed(&mut self) -> f64 {
+        self.unit() * 2.0 - 1.0
+    }
+
+    /// `len` samples uniform in [-amplitude, amplitude)
+    pub fn samples(&mut self, len: usize, amplitude: f64) -> Vec<f64> {
+        (0..len).map(|_| (self.unit() - 0.5) * 2.0 * amplitude).collect()
+    }
+}
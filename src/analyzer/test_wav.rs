@@ -0,0 +1,36 @@
+//! In-memory 16-bit PCM WAV files for tests
+//!
+//! Plain PCM has no channel mask, so symphonia assigns the first N positions
+//! in WAVE order: FL, FR, FC, LFE, BL, BR for six channels.
+
+/// WAV from interleaved 16-bit samples
+pub fn pcm16(channels: u16, samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let block_align = channels * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&channels.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for s in samples {
+        wav.extend_from_slice(&s.to_le_bytes());
+    }
+    wav
+}
+
+/// WAV from one slice per channel, full scale at ±1.0 (cut to the shortest)
+pub fn pcm16_channels(channels: &[&[f64]], sample_rate: u32) -> Vec<u8> {
+    let frames = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+    let samples: Vec<i16> = (0..frames)
+        .flat_map(|n| channels.iter().map(move |channel| (channel[n] * 32767.0) as i16))
+        .collect();
+    pcm16(channels.len() as u16, &samples, sample_rate)
+}
@@ -1,5 +1,6 @@
 //! Detailed spectral comparison tool for investigating 320k detection
 
+use losselot::analyzer::spectral::{FftConfig, WindowType};
 use rustfft::{num_complex::Complex, FftPlanner};
 use symphonia::core::audio::SampleBuffer;
 use symphonia::core::codecs::DecoderOptions;
@@ -11,24 +12,54 @@ use std::env;
 use std::fs::File;
 use std::io::Read;
 
-const FFT_SIZE: usize = 8192;
+const USAGE: &str =
+    "Usage: analyze_detail [--fft-size N] [--window hann|blackman-harris|kaiser] [--hop N] <file1> [file2]";
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: analyze_detail <file1> [file2]");
+    let mut args = env::args().skip(1);
+    let mut fft_size = FftConfig::default().size;
+    let mut window = WindowType::default();
+    let mut hop = None;
+    let mut paths = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fft-size" => fft_size = parse_arg(args.next()),
+            "--window" => window = parse_arg(args.next()),
+            "--hop" => hop = Some(parse_arg(args.next())),
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
         std::process::exit(1);
     }
 
-    for path in &args[1..] {
+    let config = FftConfig::new(fft_size)
+        .with_window(window)
+        .with_hop(hop.unwrap_or(fft_size / 2))
+        .sanitized();
+
+    for path in &paths {
         println!("\n{}", "=".repeat(60));
         println!("FILE: {}", path);
         println!("{}", "=".repeat(60));
-        analyze_file(path);
+        analyze_file(path, &config);
     }
 }
 
-fn analyze_file(path: &str) {
+fn parse_arg<T: std::str::FromStr>(value: Option<String>) -> T {
+    match value.and_then(|v| v.parse().ok()) {
+        Some(v) => v,
+        None => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn analyze_file(path: &str, config: &FftConfig) {
     let mut file = File::open(path).expect("Failed to open file");
     let mut data = Vec::new();
     file.read_to_end(&mut data).expect("Failed to read file");
@@ -43,20 +74,27 @@ fn analyze_file(path: &str) {
 
     println!("Sample rate: {} Hz", sample_rate);
     println!("Samples: {} ({:.2}s)", samples.len(), samples.len() as f64 / sample_rate as f64);
+    println!("FFT: {} points, {} window, hop {}", config.size, config.window, config.hop);
+
+    let fft_size = config.size;
+    if samples.len() < fft_size {
+        eprintln!("File too short for a {}-point FFT", fft_size);
+        return;
+    }
 
     // Set up FFT
     let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(FFT_SIZE);
-    let window = hanning_window(FFT_SIZE);
+    let fft = planner.plan_fft_forward(fft_size);
+    let window = config.window.coefficients(fft_size);
 
     // Process windows and accumulate spectrum
-    let hop_size = FFT_SIZE / 2;
-    let num_windows = (samples.len() - FFT_SIZE) / hop_size + 1;
-    let mut avg_spectrum = vec![0.0f64; FFT_SIZE / 2];
+    let hop_size = config.hop;
+    let num_windows = (samples.len() - fft_size) / hop_size + 1;
+    let mut avg_spectrum = vec![0.0f64; fft_size / 2];
 
     for i in 0..num_windows.min(100) {
         let start = i * hop_size;
-        let end = start + FFT_SIZE;
+        let end = start + fft_size;
         if end > samples.len() {
             break;
         }
@@ -69,7 +107,7 @@ fn analyze_file(path: &str) {
 
         fft.process(&mut buffer);
 
-        for (j, c) in buffer.iter().take(FFT_SIZE / 2).enumerate() {
+        for (j, c) in buffer.iter().take(fft_size / 2).enumerate() {
             avg_spectrum[j] += c.norm();
         }
     }
@@ -83,7 +121,7 @@ fn analyze_file(path: &str) {
     let spectrum_db: Vec<f64> = avg_spectrum.iter().map(|&v| to_db(v)).collect();
 
     // Frequency resolution
-    let bin_hz = sample_rate as f64 / FFT_SIZE as f64;
+    let bin_hz = sample_rate as f64 / fft_size as f64;
     println!("Bin resolution: {:.2} Hz", bin_hz);
 
     // Print energy in 1kHz bands from 10kHz to 22kHz
@@ -94,7 +132,7 @@ fn analyze_file(path: &str) {
     for start_khz in (10..=21).step_by(1) {
         let start_hz = start_khz * 1000;
         let end_hz = start_hz + 1000;
-        let energy = band_energy_db(&spectrum_db, sample_rate, start_hz as u32, end_hz as u32);
+        let energy = band_energy_db(&spectrum_db, sample_rate, fft_size, start_hz as u32, end_hz as u32);
 
         let delta = match prev_energy {
             Some(p) => format!("{:+.1}", energy - p),
@@ -107,10 +145,10 @@ fn analyze_file(path: &str) {
 
     // Spectral flatness in different regions
     println!("\nSpectral flatness (higher = more noise-like, lower = more tonal):");
-    println!("  10-15kHz: {:.4}", spectral_flatness(&avg_spectrum, sample_rate, 10000, 15000));
-    println!("  15-20kHz: {:.4}", spectral_flatness(&avg_spectrum, sample_rate, 15000, 20000));
-    println!("  17-20kHz: {:.4}", spectral_flatness(&avg_spectrum, sample_rate, 17000, 20000));
-    println!("  19-21kHz: {:.4}", spectral_flatness(&avg_spectrum, sample_rate, 19000, 21000));
+    println!("  10-15kHz: {:.4}", spectral_flatness(&avg_spectrum, sample_rate, fft_size, 10000, 15000));
+    println!("  15-20kHz: {:.4}", spectral_flatness(&avg_spectrum, sample_rate, fft_size, 15000, 20000));
+    println!("  17-20kHz: {:.4}", spectral_flatness(&avg_spectrum, sample_rate, fft_size, 17000, 20000));
+    println!("  19-21kHz: {:.4}", spectral_flatness(&avg_spectrum, sample_rate, fft_size, 19000, 21000));
 
     // Rolloff analysis - find -3dB, -10dB, -20dB points from peak
     let peak_idx = spectrum_db.iter()
@@ -140,8 +178,8 @@ fn analyze_file(path: &str) {
     // Derivative analysis - how sharply does it roll off?
     println!("\nRolloff sharpness (dB/kHz) in high frequency region:");
     for start_khz in [16, 17, 18, 19, 20] {
-        let e1 = band_energy_db(&spectrum_db, sample_rate, start_khz * 1000, start_khz * 1000 + 500);
-        let e2 = band_energy_db(&spectrum_db, sample_rate, start_khz * 1000 + 500, (start_khz + 1) * 1000);
+        let e1 = band_energy_db(&spectrum_db, sample_rate, fft_size, start_khz * 1000, start_khz * 1000 + 500);
+        let e2 = band_energy_db(&spectrum_db, sample_rate, fft_size, start_khz * 1000 + 500, (start_khz + 1) * 1000);
         let slope = (e2 - e1) * 2.0; // dB per kHz
         println!("  {}-{}kHz: {:.1} dB/kHz", start_khz, start_khz + 1, slope);
     }
@@ -215,12 +253,6 @@ fn decode_audio(data: &[u8]) -> Option<(Vec<f64>, u32)> {
     Some((samples, sample_rate))
 }
 
-fn hanning_window(size: usize) -> Vec<f64> {
-    (0..size)
-        .map(|i| 0.5 * (1.0 - (2.0 * std::f64::consts::PI * i as f64 / (size - 1) as f64).cos()))
-        .collect()
-}
-
 fn to_db(value: f64) -> f64 {
     if value <= 0.0 {
         -96.0
@@ -229,10 +261,10 @@ fn to_db(value: f64) -> f64 {
     }
 }
 
-fn band_energy_db(spectrum_db: &[f64], sample_rate: u32, low_hz: u32, high_hz: u32) -> f64 {
-    let bin_resolution = sample_rate as f64 / FFT_SIZE as f64;
+fn band_energy_db(spectrum_db: &[f64], sample_rate: u32, fft_size: usize, low_hz: u32, high_hz: u32) -> f64 {
+    let bin_resolution = sample_rate as f64 / fft_size as f64;
     let low_bin = (low_hz as f64 / bin_resolution) as usize;
-    let high_bin = (high_hz as f64 / bin_resolution).min((FFT_SIZE / 2) as f64) as usize;
+    let high_bin = (high_hz as f64 / bin_resolution).min((fft_size / 2) as f64) as usize;
 
     if low_bin >= high_bin || high_bin >= spectrum_db.len() {
         return -96.0;
@@ -243,10 +275,10 @@ fn band_energy_db(spectrum_db: &[f64], sample_rate: u32, low_hz: u32, high_hz: u
     sum / (high_bin - low_bin + 1) as f64
 }
 
-fn spectral_flatness(spectrum: &[f64], sample_rate: u32, low_hz: u32, high_hz: u32) -> f64 {
-    let bin_resolution = sample_rate as f64 / FFT_SIZE as f64;
+fn spectral_flatness(spectrum: &[f64], sample_rate: u32, fft_size: usize, low_hz: u32, high_hz: u32) -> f64 {
+    let bin_resolution = sample_rate as f64 / fft_size as f64;
     let low_bin = (low_hz as f64 / bin_resolution) as usize;
-    let high_bin = (high_hz as f64 / bin_resolution).min((FFT_SIZE / 2) as f64) as usize;
+    let high_bin = (high_hz as f64 / bin_resolution).min((fft_size / 2) as f64) as usize;
 
     if low_bin >= high_bin || high_bin >= spectrum.len() {
         return 0.0;
@@ -12,12 +12,34 @@ use std::path::Path;
 
 const DEFAULT_DB_PATH: &str = "losselot.db";
 
+/// Columns added to analysis_results in v1.2.0 (see migrations/00000000000004_loudness)
+const LOUDNESS_COLUMNS: [(&str, &str); 8] = [
+    ("integrated_loudness", "REAL"),
+    ("loudness_range", "REAL"),
+    ("true_peak", "REAL"),
+    ("sample_peak", "REAL"),
+    ("dynamic_range", "REAL"),
+    ("clipped_samples", "INTEGER"),
+    ("clip_runs", "INTEGER"),
+    ("longest_clip_run", "INTEGER"),
+];
+
+/// Columns added to analysis_results in v1.3.0 (see migrations/00000000000005_integrity)
+const INTEGRITY_COLUMNS: [(&str, &str); 6] = [
+    ("integrity", "TEXT"),
+    ("decode_errors", "INTEGER"),
+    ("stream_errors", "INTEGER"),
+    ("missing_secs", "REAL"),
+    ("dropouts", "INTEGER"),
+    ("clicks", "INTEGER"),
+];
+
 /// Current analysis schema version
 pub const CURRENT_SCHEMA: AnalysisSchema = AnalysisSchema {
     major: 1,
-    minor: 1,
+    minor: 3,
     patch: 0,
-    name: "lofi-detection",
+    name: "integrity",
     features: &[
         "binary_analysis",
         "spectral_analysis",
@@ -28,6 +50,15 @@ pub const CURRENT_SCHEMA: AnalysisSchema = AnalysisSchema {
         "rolloff_slope",
         "transition_width",
         "natural_rolloff",
+        "integrated_loudness",
+        "loudness_range",
+        "true_peak",
+        "dynamic_range",
+        "clipping",
+        "decode_errors",
+        "truncation",
+        "dropouts",
+        "clicks",
     ],
 };
 
@@ -125,6 +156,20 @@ pub struct NewAnalysisResult<'a> {
     pub binary_details_json: Option<String>,
     pub flags: Option<String>,
     pub error: Option<&'a str>,
+    pub integrated_loudness: Option<f64>,
+    pub loudness_range: Option<f64>,
+    pub true_peak: Option<f64>,
+    pub sample_peak: Option<f64>,
+    pub dynamic_range: Option<f64>,
+    pub clipped_samples: Option<i64>,
+    pub clip_runs: Option<i64>,
+    pub longest_clip_run: Option<i64>,
+    pub integrity: Option<&'a str>,
+    pub decode_errors: Option<i64>,
+    pub stream_errors: Option<i64>,
+    pub missing_secs: Option<f64>,
+    pub dropouts: Option<i64>,
+    pub clicks: Option<i64>,
 }
 
 /// Queryable analysis result (database record)
@@ -164,6 +209,20 @@ pub struct DbRecord {
     pub flags: Option<String>,
     pub error: Option<String>,
     pub file_hash: Option<String>,
+    pub integrated_loudness: Option<f64>,
+    pub loudness_range: Option<f64>,
+    pub true_peak: Option<f64>,
+    pub sample_peak: Option<f64>,
+    pub dynamic_range: Option<f64>,
+    pub clipped_samples: Option<i64>,
+    pub clip_runs: Option<i64>,
+    pub longest_clip_run: Option<i64>,
+    pub integrity: Option<String>,
+    pub decode_errors: Option<i64>,
+    pub stream_errors: Option<i64>,
+    pub missing_secs: Option<f64>,
+    pub dropouts: Option<i64>,
+    pub clicks: Option<i64>,
 }
 
 // ============================================================================
@@ -356,6 +415,13 @@ struct AvgResult {
     avg: Option<f64>,
 }
 
+/// A column from PRAGMA table_info
+#[derive(QueryableByName)]
+struct ColumnInfo {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    name: String,
+}
+
 impl Database {
     /// Get the default database path
     pub fn db_path() -> std::path::PathBuf {
@@ -435,10 +501,38 @@ impl Database {
                 flags TEXT,
                 error TEXT,
                 file_hash TEXT,
+                integrated_loudness REAL,
+                loudness_range REAL,
+                true_peak REAL,
+                sample_peak REAL,
+                dynamic_range REAL,
+                clipped_samples INTEGER,
+                clip_runs INTEGER,
+                longest_clip_run INTEGER,
+                integrity TEXT,
+                decode_errors INTEGER,
+                stream_errors INTEGER,
+                missing_secs REAL,
+                dropouts INTEGER,
+                clicks INTEGER,
                 UNIQUE(file_path, analyzed_at)
             )
         "#).execute(&mut conn)?;
 
+        // Databases created before v1.2.0 lack the loudness columns, and before v1.3.0 the integrity ones.
+        // SQLite has no ADD COLUMN IF NOT EXISTS, so only add the ones table_info doesn't list.
+        let existing: Vec<String> = diesel::sql_query("PRAGMA table_info(analysis_results)")
+            .load::<ColumnInfo>(&mut conn)?
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        for (column, sql_type) in LOUDNESS_COLUMNS.iter().chain(&INTEGRITY_COLUMNS) {
+            if !existing.iter().any(|name| name == column) {
+                diesel::sql_query(format!("ALTER TABLE analysis_results ADD COLUMN {} {}", column, sql_type))
+                    .execute(&mut conn)?;
+            }
+        }
+
         diesel::sql_query(r#"
             CREATE TABLE IF NOT EXISTS decision_nodes (
                 id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
@@ -587,6 +681,10 @@ impl Database {
             (None, None, None, None, None, None, None, None, None, None, None, None, None, None, None)
         };
 
+        let loudness = result.loudness_details.as_ref();
+        let integrity_str = result.integrity.map(|v| v.to_string());
+        let integrity = result.integrity_details.as_ref();
+
         let binary_json = result.binary_details.as_ref()
             .map(|b| serde_json::to_string(b).unwrap_or_default());
 
@@ -622,6 +720,20 @@ impl Database {
             binary_details_json: binary_json,
             flags: Some(flags_str),
             error: error_str,
+            integrated_loudness: loudness.map(|l| l.integrated_loudness),
+            loudness_range: loudness.map(|l| l.loudness_range),
+            true_peak: loudness.map(|l| l.true_peak),
+            sample_peak: loudness.map(|l| l.sample_peak),
+            dynamic_range: loudness.map(|l| l.dynamic_range),
+            clipped_samples: loudness.map(|l| l.clipped_samples as i64),
+            clip_runs: loudness.map(|l| l.clip_runs as i64),
+            longest_clip_run: loudness.map(|l| l.longest_clip_run as i64),
+            integrity: integrity_str.as_deref(),
+            decode_errors: integrity.map(|i| i.decode_errors as i64),
+            stream_errors: integrity.map(|i| i.stream_errors as i64),
+            missing_secs: integrity.map(|i| i.missing_secs),
+            dropouts: integrity.map(|i| i.dropouts as i64),
+            clicks: integrity.map(|i| i.clicks as i64),
         };
 
         diesel::insert_into(analysis_results::table)
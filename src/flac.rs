@@ -0,0 +1,223 @@
+//! FLAC metadata block parsing
+//!
+//! Symphonia decodes FLAC audio but doesn't expose everything in the
+//! metadata: the Vorbis comment vendor string and the CD cue sheet's track
+//! flags are read here straight from the blocks.
+//!
+//! ```text
+//! "fLaC" │ STREAMINFO │ PADDING │ VORBIS_COMMENT │ CUESHEET* │ audio frames...
+//!          └─ each block: [last:1][type:7] [length:24 BE] [payload]
+//! ```
+
+/// Metadata block type holding the vendor string and `KEY=value` comments
+pub const VORBIS_COMMENT: u8 = 4;
+/// Metadata block type holding a CD table of contents
+pub const CUESHEET: u8 = 5;
+
+/// One metadata block
+#[derive(Debug, Clone, Copy)]
+pub struct MetadataBlock<'a> {
+    pub block_type: u8,
+    pub data: &'a [u8],
+}
+
+/// Track entry from a CUESHEET block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CueTrack {
+    pub number: u8,
+    /// Audio was mastered with 50/15 µs pre-emphasis
+    pub pre_emphasis: bool,
+}
+
+/// Every metadata block of a FLAC stream (empty if `data` isn't FLAC)
+///
+/// A leading ID3v2 tag is skipped; some taggers put one in front of the
+/// `fLaC` marker.
+pub fn metadata_blocks(data: &[u8]) -> Vec<MetadataBlock<'_>> {
+    let mut blocks = Vec::new();
+    let mut pos = id3v2_len(data);
+    if data.get(pos..pos + 4) != Some(b"fLaC") {
+        return blocks;
+    }
+    pos += 4;
+
+    while let Some(header) = data.get(pos..pos + 4) {
+        let is_last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7F;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        pos += 4;
+
+        match data.get(pos..pos + len) {
+            Some(block) => blocks.push(MetadataBlock {
+                block_type,
+                data: block,
+            }),
+            None => break,
+        }
+        if is_last {
+            break;
+        }
+        pos += len;
+    }
+    blocks
+}
+
+/// Vendor string followed by every `KEY=value` comment of a VORBIS_COMMENT block
+pub fn vorbis_comment_strings(block: &[u8]) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut pos = 0;
+    let read_string = |pos: &mut usize| -> Option<String> {
+        let len = u32::from_le_bytes(block.get(*pos..*pos + 4)?.try_into().ok()?) as usize;
+        let bytes = block.get(*pos + 4..*pos + 4 + len)?;
+        *pos += 4 + len;
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    };
+
+    if let Some(vendor) = read_string(&mut pos) {
+        strings.push(vendor);
+        let count = block
+            .get(pos..pos + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .unwrap_or(0);
+        pos += 4;
+        for _ in 0..count {
+            match read_string(&mut pos) {
+                Some(comment) => strings.push(comment),
+                None => break,
+            }
+        }
+    }
+    strings
+}
+
+/// Tracks of a CUESHEET block
+///
+/// Layout: 128-byte catalog number, u64 lead-in, 1 + 258 bytes of flags and
+/// reserved space, u8 track count, then per track: u64 offset, u8 number,
+/// 12-byte ISRC, a flags byte (bit 6 = pre-emphasis), 13 reserved bytes,
+/// u8 index count and 12 bytes per index point.
+pub fn cuesheet_tracks(block: &[u8]) -> Vec<CueTrack> {
+    const HEADER_LEN: usize = 128 + 8 + 259;
+    const TRACK_LEN: usize = 8 + 1 + 12 + 1 + 13 + 1;
+    const INDEX_LEN: usize = 12;
+
+    let mut tracks = Vec::new();
+    let count = match block.get(HEADER_LEN) {
+        Some(&count) => count,
+        None => return tracks,
+    };
+    let mut pos = HEADER_LEN + 1;
+    for _ in 0..count {
+        let track = match block.get(pos..pos + TRACK_LEN) {
+            Some(track) => track,
+            None => break,
+        };
+        tracks.push(CueTrack {
+            number: track[8],
+            pre_emphasis: track[21] & 0x40 != 0,
+        });
+        pos += TRACK_LEN + track[TRACK_LEN - 1] as usize * INDEX_LEN;
+    }
+    tracks
+}
+
+/// Whether any track in the file's embedded CUESHEET is flagged pre-emphasised
+pub fn cuesheet_pre_emphasis(data: &[u8]) -> bool {
+    metadata_blocks(data)
+        .iter()
+        .filter(|b| b.block_type == CUESHEET)
+        .flat_map(|b| cuesheet_tracks(b.data))
+        // The lead-out track (170 on CDs) carries no audio
+        .any(|t| t.pre_emphasis && t.number != 170)
+}
+
+/// Length of a leading ID3v2 tag
+fn id3v2_len(data: &[u8]) -> usize {
+    if data.len() < 10 || &data[..3] != b"ID3" {
+        return 0;
+    }
+    // Synchsafe size: 7 bits per byte
+    let size = data[6..10]
+        .iter()
+        .fold(0usize, |acc, &b| (acc << 7) | (b & 0x7F) as usize);
+    let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: The CD table of contents in a FLAC
+    // ==========================================================================
+    //
+    // A CD's TOC lists each track's start and a few flag bits per track:
+    // copy permitted, four-channel, and pre-emphasis. Rippers that store a
+    // whole disc as one FLAC can keep the TOC in a CUESHEET block, so the
+    // pre-emphasis bit survives even when no .cue file travels with the rip.
+    // Track 170 is the lead-out, which marks where the audio ends.
+    // ==========================================================================
+
+    /// CUESHEET payload with (number, pre_emphasis, index points) per track
+    fn test_cuesheet(tracks: &[(u8, bool, u8)]) -> Vec<u8> {
+        let mut block = vec![0u8; 128 + 8 + 259];
+        block.push(tracks.len() as u8);
+        for &(number, pre_emphasis, indexes) in tracks {
+            block.extend_from_slice(&0u64.to_be_bytes());
+            block.push(number);
+            block.extend_from_slice(&[0; 12]);
+            block.push(if pre_emphasis { 0x40 } else { 0 });
+            block.extend_from_slice(&[0; 13]);
+            block.push(indexes);
+            block.extend(std::iter::repeat_n(0, indexes as usize * 12));
+        }
+        block
+    }
+
+    fn test_flac(blocks: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        let mut flac = b"fLaC".to_vec();
+        for (i, (block_type, payload)) in blocks.iter().enumerate() {
+            let last = if i + 1 == blocks.len() { 0x80 } else { 0 };
+            flac.push(last | block_type);
+            flac.extend_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+            flac.extend_from_slice(payload);
+        }
+        flac
+    }
+
+    #[test]
+    fn test_metadata_blocks() {
+        let flac = test_flac(&[
+            (0, vec![0; 34]),
+            (1, vec![0; 8]),
+            (VORBIS_COMMENT, vec![0; 8]),
+        ]);
+        let types: Vec<u8> = metadata_blocks(&flac)
+            .iter()
+            .map(|b| b.block_type)
+            .collect();
+        assert_eq!(types, [0, 1, VORBIS_COMMENT]);
+        assert!(metadata_blocks(b"RIFF....WAVE").is_empty());
+    }
+
+    #[test]
+    fn test_cuesheet_pre_emphasis() {
+        let cue = test_cuesheet(&[(1, false, 1), (2, true, 2), (170, false, 0)]);
+        let tracks = cuesheet_tracks(&cue);
+        assert_eq!(tracks.len(), 3);
+        assert_eq!(
+            tracks[1],
+            CueTrack {
+                number: 2,
+                pre_emphasis: true
+            }
+        );
+
+        let flac = test_flac(&[(0, vec![0; 34]), (CUESHEET, cue)]);
+        assert!(cuesheet_pre_emphasis(&flac));
+
+        let plain = test_flac(&[(CUESHEET, test_cuesheet(&[(1, false, 1), (170, false, 0)]))]);
+        assert!(!cuesheet_pre_emphasis(&plain));
+    }
+}
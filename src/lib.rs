@@ -52,11 +52,13 @@
 //! # Modules
 //!
 //! - [`analyzer`]: Core analysis engine combining binary and spectral methods
+//! - [`flac`]: FLAC metadata blocks (Vorbis comments, cue sheet)
 //! - [`mp3`]: MP3 frame parsing and LAME header extraction
 //! - [`report`]: Output formatters (JSON, CSV)
 
 pub mod analyzer;
 pub mod db;
+pub mod flac;
 pub mod mp3;
 pub mod report;
 pub mod schema;
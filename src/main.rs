@@ -1,6 +1,7 @@
 use chrono::Local;
 use clap::{Parser, Subcommand};
 use indicatif::{ProgressBar, ProgressStyle};
+use losselot::analyzer::spectral::{FftConfig, WindowType, DEFAULT_SHORT_FFT_SIZE};
 use losselot::{AnalysisResult, Analyzer, Database, Verdict};
 use rayon::prelude::*;
 use std::io::{self, Write};
@@ -56,6 +57,22 @@ struct Args {
     /// Transcode threshold percentage (default: 65)
     #[arg(long, default_value = "65")]
     threshold: u32,
+
+    /// FFT size for spectral analysis (default: 8192)
+    #[arg(long, default_value = "8192")]
+    fft_size: usize,
+
+    /// FFT window function: hann, blackman-harris, kaiser
+    #[arg(long, default_value = "hann")]
+    window: WindowType,
+
+    /// Hop between FFT windows in samples (default: half the FFT size)
+    #[arg(long)]
+    hop: Option<usize>,
+
+    /// Measure levels with a short FFT (default 1024) and cutoffs with the long one
+    #[arg(long, value_name = "SHORT_SIZE", require_equals = true)]
+    multi_resolution: Option<Option<usize>>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -263,9 +280,17 @@ fn main() {
     };
 
     // Create analyzer
+    let fft_config = FftConfig::new(args.fft_size)
+        .with_window(args.window)
+        .with_hop(args.hop.unwrap_or(args.fft_size / 2))
+        .with_multi_resolution(
+            args.multi_resolution
+                .map(|short| short.unwrap_or(DEFAULT_SHORT_FFT_SIZE)),
+        );
     let analyzer = Analyzer::new()
         .with_skip_spectral(args.no_spectral)
-        .with_thresholds(35, args.threshold);
+        .with_thresholds(35, args.threshold)
+        .with_fft_config(fft_config);
 
     // Analyze files in parallel
     let results: Vec<AnalysisResult> = files
@@ -329,6 +354,26 @@ fn main() {
                         details.ultrasonic_flatness
                     );
                 }
+                if let Some(ref details) = r.loudness_details {
+                    eprintln!(
+                        "    Loudness: {:.1} LUFS LRA={:.1}LU true_peak={:.1}dBTP DR={:.0} clip_runs={}",
+                        details.integrated_loudness,
+                        details.loudness_range,
+                        details.true_peak,
+                        details.dynamic_range,
+                        details.clip_runs
+                    );
+                }
+                if let (Some(verdict), Some(ref details)) = (r.integrity, &r.integrity_details) {
+                    eprintln!(
+                        "    Integrity: {} decode_errors={} missing={:.1}s dropouts={} clicks={}",
+                        verdict,
+                        details.decode_errors + details.stream_errors,
+                        details.missing_secs,
+                        details.dropouts,
+                        details.clicks
+                    );
+                }
                 if let Some(ref details) = r.binary_details {
                     eprintln!(
                         "    Binary: lowpass={} encoder_count={} frame_cv={:.1}%",
@@ -337,6 +382,30 @@ fn main() {
                         details.frame_size_cv
                     );
                 }
+                if let Some(tag) = r.binary_details.as_ref().and_then(|d| d.lame_tag.as_ref()) {
+                    eprintln!(
+                        "    LAME tag: preset={} source={} delay={} padding={}",
+                        tag.preset.as_deref().unwrap_or("unknown"),
+                        tag.source_sample_rate.as_str(),
+                        tag.encoder_delay,
+                        tag.encoder_padding
+                    );
+                }
+                if let Some(vbri) = r.binary_details.as_ref().and_then(|d| d.vbri.as_ref()) {
+                    eprintln!(
+                        "    VBRI: quality={} delay={} frames={}",
+                        vbri.quality, vbri.delay, vbri.total_frames
+                    );
+                }
+                if let Some(side) = r.binary_details.as_ref().and_then(|d| d.side_info.as_ref()) {
+                    eprintln!(
+                        "    Side info: reservoir={:.0}% short={:.1}% bandwidth={:.0}Hz encoder={}",
+                        side.reservoir_use * 100.0,
+                        side.short_block_rate * 100.0,
+                        side.coded_bandwidth_hz,
+                        side.likely_encoder.as_deref().unwrap_or("?")
+                    );
+                }
             }
         }
     }
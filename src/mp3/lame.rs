@@ -6,9 +6,40 @@
 //! - Lowpass filter frequency (THE KEY for transcode detection)
 //! - VBR method used
 //! - Encoding quality settings
-
+//! - The extended tag (LAME 3.90+): encoder delay/padding, ReplayGain,
+//!   encoding flags, source sample rate, preset and CRCs
+//!
+//! # LAME Tag Layout
+//!
+//! ```text
+//! Offset  Size  Field (offsets from the "LAME" string)
+//! 0       9     Version string ("LAME3.100")
+//! 9       1     Tag revision (high nibble) | VBR method (low nibble)
+//! 10      1     Lowpass / 100 Hz
+//! 11      4     Peak signal amplitude (9.23 fixed point)
+//! 15      2     Radio ReplayGain
+//! 17      2     Audiophile ReplayGain
+//! 19      1     Encoding flags (high nibble) | ATH type (low nibble)
+//! 20      1     ABR target / CBR bitrate / VBR minimum (kbps)
+//! 21      3     Encoder delay (12 bits) | padding (12 bits)
+//! 24      1     Source rate (2) | unwise (1) | stereo mode (3) | noise shaping (2)
+//! 25      1     MP3 gain (sign + 7 bits, 1.5 dB steps)
+//! 26      2     Unused (2) | surround (3) | preset (11)
+//! 28      4     Music length (bytes)
+//! 32      2     Music CRC
+//! 34      2     Tag CRC
+//! ```
+
+use super::frame::FrameHeader;
+use super::lowpass::{self, ExpectedLowpass};
+use serde::Serialize;
 use std::io::{self, Read, Seek, SeekFrom};
 
+/// Length of the LAME tag from the "LAME" string to the end of the tag CRC
+const LAME_TAG_LEN: usize = 36;
+/// Furthest a Xing/Info header sits from its frame's sync (MPEG1 stereo + CRC)
+const MAX_XING_OFFSET: usize = 40;
+
 /// Information extracted from LAME header
 #[derive(Debug, Clone, Default)]
 pub struct LameHeader {
@@ -27,6 +58,261 @@ pub struct LameHeader {
     pub total_frames: Option<u32>,
     /// Total bytes reported by header
     pub total_bytes: Option<u32>,
+    /// Sample rate of the frame carrying the header (Hz)
+    pub sample_rate: Option<u32>,
+    /// Byte offset of the frame carrying the header
+    pub frame_offset: Option<usize>,
+    /// Byte offset of the "LAME" string (start of the LAME tag)
+    pub tag_offset: Option<usize>,
+    /// Extended LAME tag fields (None for pre-3.90 encoders and non-LAME headers)
+    pub tag: Option<LameTag>,
+}
+
+/// Extended fields of the LAME tag, written by LAME 3.90 and later
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LameTag {
+    /// Peak sample amplitude (1.0 = full scale; None if not computed)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_amplitude: Option<f64>,
+    /// Radio (track) ReplayGain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radio_gain: Option<ReplayGain>,
+    /// Audiophile (album) ReplayGain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audiophile_gain: Option<ReplayGain>,
+    /// --nspsytune was used
+    pub nspsytune: bool,
+    /// --nssafejoint was used
+    pub nssafejoint: bool,
+    /// --nogap: a track follows this one
+    pub nogap_next: bool,
+    /// --nogap: a track precedes this one
+    pub nogap_previous: bool,
+    /// ATH type (0-15)
+    pub ath_type: u8,
+    /// ABR target, CBR bitrate or VBR minimum, by VBR method (kbps; 255 = 255 or more)
+    pub bitrate: u32,
+    /// Samples of encoder delay at the start
+    pub encoder_delay: u16,
+    /// Samples of padding at the end
+    pub encoder_padding: u16,
+    /// Noise shaping (0-3)
+    pub noise_shaping: u8,
+    /// Stereo mode the encoder was set to
+    pub stereo_mode: LameStereoMode,
+    /// Unwise settings (e.g. forced lowpass above the standard) were used
+    pub unwise_settings: bool,
+    /// Sample rate of the input to the encoder
+    pub source_sample_rate: SourceSampleRate,
+    /// Gain applied by the encoder (dB, 1.5 dB steps)
+    pub mp3_gain_db: f64,
+    /// Surround info (0 = none)
+    pub surround: u8,
+    /// Raw preset ID (0 = unknown)
+    pub preset_id: u16,
+    /// Preset name ("V0", "extreme", "320kbps"; None if unknown)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// Length of the audio from this frame to the last, in bytes
+    pub music_length: u32,
+    /// CRC-16 of the audio data
+    pub music_crc: u16,
+    /// CRC-16 of the first frame up to this field
+    pub tag_crc: u16,
+}
+
+/// One ReplayGain field of the LAME tag
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReplayGain {
+    /// Gain adjustment (dB)
+    pub gain_db: f64,
+    /// Who set it
+    pub originator: GainOriginator,
+}
+
+/// Who set a ReplayGain value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GainOriginator {
+    #[default]
+    Unset,
+    Artist,
+    User,
+    /// Computed by the encoder's ReplayGain model
+    Automatic,
+    /// Simple RMS average
+    RmsAverage,
+    Other,
+}
+
+/// Stereo mode recorded in the LAME tag
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LameStereoMode {
+    Mono,
+    Stereo,
+    Dual,
+    JointStereo,
+    Force,
+    Auto,
+    Intensity,
+    #[default]
+    Undefined,
+}
+
+impl LameStereoMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LameStereoMode::Mono => "mono",
+            LameStereoMode::Stereo => "stereo",
+            LameStereoMode::Dual => "dual",
+            LameStereoMode::JointStereo => "joint_stereo",
+            LameStereoMode::Force => "force",
+            LameStereoMode::Auto => "auto",
+            LameStereoMode::Intensity => "intensity",
+            LameStereoMode::Undefined => "undefined",
+        }
+    }
+}
+
+/// Input sample rate recorded in the LAME tag (only four ranges are stored)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum SourceSampleRate {
+    #[serde(rename = "32k_or_lower")]
+    #[default]
+    Low,
+    #[serde(rename = "44.1k")]
+    Hz44100,
+    #[serde(rename = "48k")]
+    Hz48000,
+    #[serde(rename = "above_48k")]
+    High,
+}
+
+impl SourceSampleRate {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SourceSampleRate::Low => "32k_or_lower",
+            SourceSampleRate::Hz44100 => "44.1k",
+            SourceSampleRate::Hz48000 => "48k",
+            SourceSampleRate::High => "above_48k",
+        }
+    }
+
+    /// Whether a stream at `sample_rate` could have been encoded from this source
+    /// without resampling
+    pub fn matches(&self, sample_rate: u32) -> bool {
+        match self {
+            SourceSampleRate::Low => sample_rate <= 32000,
+            SourceSampleRate::Hz44100 => sample_rate == 44100,
+            SourceSampleRate::Hz48000 => sample_rate == 48000,
+            SourceSampleRate::High => sample_rate > 48000,
+        }
+    }
+}
+
+impl LameTag {
+    /// Parse the tag from the bytes starting at the "LAME" string
+    pub fn parse(tag: &[u8]) -> Option<Self> {
+        if tag.len() < LAME_TAG_LEN {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_be_bytes([tag[i], tag[i + 1]]);
+        let u32_at = |i: usize| u32::from_be_bytes([tag[i], tag[i + 1], tag[i + 2], tag[i + 3]]);
+
+        let peak = u32_at(11);
+        let (flags, misc, mp3_gain) = (tag[19], tag[24], tag[25]);
+        let preset_id = u16_at(26) & 0x07FF;
+        let mp3_gain_db = (mp3_gain & 0x7F) as f64 * 1.5;
+        Some(LameTag {
+            peak_amplitude: (peak != 0).then(|| peak as f64 / (1u32 << 23) as f64),
+            radio_gain: ReplayGain::parse(u16_at(15)),
+            audiophile_gain: ReplayGain::parse(u16_at(17)),
+            nspsytune: flags & 0x10 != 0,
+            nssafejoint: flags & 0x20 != 0,
+            nogap_next: flags & 0x40 != 0,
+            nogap_previous: flags & 0x80 != 0,
+            ath_type: flags & 0x0F,
+            bitrate: tag[20] as u32,
+            encoder_delay: ((tag[21] as u16) << 4) | (tag[22] >> 4) as u16,
+            encoder_padding: (((tag[22] & 0x0F) as u16) << 8) | tag[23] as u16,
+            noise_shaping: misc & 0x03,
+            stereo_mode: match (misc >> 2) & 0x07 {
+                0 => LameStereoMode::Mono,
+                1 => LameStereoMode::Stereo,
+                2 => LameStereoMode::Dual,
+                3 => LameStereoMode::JointStereo,
+                4 => LameStereoMode::Force,
+                5 => LameStereoMode::Auto,
+                6 => LameStereoMode::Intensity,
+                _ => LameStereoMode::Undefined,
+            },
+            unwise_settings: misc & 0x20 != 0,
+            source_sample_rate: match misc >> 6 {
+                0 => SourceSampleRate::Low,
+                1 => SourceSampleRate::Hz44100,
+                2 => SourceSampleRate::Hz48000,
+                _ => SourceSampleRate::High,
+            },
+            mp3_gain_db: if mp3_gain & 0x80 != 0 { -mp3_gain_db } else { mp3_gain_db },
+            surround: (tag[26] >> 3) & 0x07,
+            preset_id,
+            preset: preset_name(preset_id),
+            music_length: u32_at(28),
+            music_crc: u16_at(32),
+            tag_crc: u16_at(34),
+        })
+    }
+}
+
+impl ReplayGain {
+    /// Parse a 16-bit ReplayGain field: name (3 bits), originator (3),
+    /// sign (1), value in 0.1 dB (9). None if the name is unset.
+    fn parse(field: u16) -> Option<Self> {
+        if field >> 13 == 0 {
+            return None;
+        }
+        let originator = match (field >> 10) & 0x07 {
+            0 => GainOriginator::Unset,
+            1 => GainOriginator::Artist,
+            2 => GainOriginator::User,
+            3 => GainOriginator::Automatic,
+            4 => GainOriginator::RmsAverage,
+            _ => GainOriginator::Other,
+        };
+        let gain_db = (field & 0x01FF) as f64 / 10.0;
+        Some(ReplayGain {
+            gain_db: if field & 0x0200 != 0 { -gain_db } else { gain_db },
+            originator,
+        })
+    }
+}
+
+/// Name of a LAME preset ID (None if unknown)
+pub fn preset_name(id: u16) -> Option<String> {
+    let name = match id {
+        8..=320 => return Some(format!("{}kbps", id)),
+        410..=500 if id.is_multiple_of(10) => return Some(format!("V{}", (500 - id) / 10)),
+        1000 => "r3mix",
+        1001 => "standard",
+        1002 => "extreme",
+        1003 => "insane",
+        1004 => "fast standard",
+        1005 => "fast extreme",
+        1006 => "medium",
+        1007 => "fast medium",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+/// Whether a LAME version string is new enough to carry the extended tag (3.90+)
+fn has_extended_tag(version: &str) -> bool {
+    let Some(minor) = version.strip_prefix("LAME3.") else {
+        return false;
+    };
+    let digits: String = minor.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse::<u32>().is_ok_and(|minor| minor >= 90)
 }
 
 /// Other encoder signatures we might find
@@ -168,6 +454,15 @@ impl LameHeader {
 
         // Parse Xing/Info header if found
         if let Some(pos) = vbr_header_pos {
+            // The frame carrying it starts a few bytes earlier
+            let carrier = (pos.saturating_sub(MAX_XING_OFFSET)..pos)
+                .rev()
+                .find_map(|i| FrameHeader::parse_at(search_region, i).map(|frame| (i, frame)));
+            if let Some((offset, frame)) = carrier {
+                header.frame_offset = Some(offset);
+                header.sample_rate = Some(frame.sample_rate);
+            }
+
             if pos + 8 <= search_region.len() {
                 let flags = u32::from_be_bytes([
                     search_region[pos + 4],
@@ -241,6 +536,11 @@ impl LameHeader {
                         header.quality = Some((info_byte >> 4) & 0x0F);
                     }
 
+                    if has_extended_tag(&header.encoder) {
+                        header.tag = LameTag::parse(&search_region[lame_pos..]);
+                        header.tag_offset = Some(lame_pos);
+                    }
+
                     return Some(header);
                 }
 
@@ -538,7 +838,7 @@ pub fn expected_lowpass_for_bitrate(bitrate: u32) -> u32 {
 }
 
 /// Minimum acceptable lowpass for a bitrate (below this = suspicious)
-fn min_acceptable_lowpass(bitrate: u32) -> u32 {
+pub fn min_acceptable_lowpass(bitrate: u32) -> u32 {
     if bitrate >= 256 {
         18000  // 256+ kbps should have at least 18kHz
     } else if bitrate >= 192 {
@@ -555,8 +855,13 @@ fn min_acceptable_lowpass(bitrate: u32) -> u32 {
 /// Check if lowpass frequency suggests transcoding
 /// Returns (is_suspicious, expected_lowpass, reason)
 pub fn check_lowpass_mismatch(bitrate: u32, actual_lowpass: u32) -> (bool, u32, Option<String>) {
-    let expected = expected_lowpass_for_bitrate(bitrate);
-    let threshold = min_acceptable_lowpass(bitrate);
+    check_lowpass(&lowpass::for_bitrate(bitrate, None), actual_lowpass)
+}
+
+/// Check a lowpass against an expectation from the encoder's settings
+/// Returns (is_suspicious, expected_lowpass, reason)
+pub fn check_lowpass(expected: &ExpectedLowpass, actual_lowpass: u32) -> (bool, u32, Option<String>) {
+    let threshold = expected.min_hz;
 
     // If actual lowpass is significantly lower than expected, it's suspicious
     if threshold > 0 && actual_lowpass > 0 && actual_lowpass < threshold {
@@ -571,14 +876,14 @@ pub fn check_lowpass_mismatch(bitrate: u32, actual_lowpass: u32) -> (bool, u32,
 
         (
             true,
-            expected,
+            expected.hz,
             Some(format!(
                 "Lowpass {}Hz suggests transcode from {} source",
                 actual_lowpass, likely_source
             )),
         )
     } else {
-        (false, expected, None)
+        (false, expected.hz, None)
     }
 }
 
@@ -818,6 +1123,87 @@ mod tests {
         assert!(header.total_bytes.is_some());
     }
 
+    // ==========================================================================
+    // EXTENDED LAME TAG TESTS
+    // LAME 3.90+ writes 36 bytes after "LAME": delay/padding for gapless
+    // playback, ReplayGain, the options used and two CRCs
+    // ==========================================================================
+
+    /// Helper: Info frame whose LAME tag ends with `tail` (the bytes from offset 11)
+    fn create_full_tag_data(encoder_version: &str, misc: u8, tail: &[u8; 25]) -> Vec<u8> {
+        let mut data = create_lame_header_data(encoder_version, 20500, false);
+        let lame_pos = find_pattern(&data, b"LAME").unwrap();
+        data[lame_pos + 11..lame_pos + 36].copy_from_slice(tail);
+        data[lame_pos + 24] = misc;
+        data
+    }
+
+    #[test]
+    fn test_extract_extended_tag() {
+        let tail: [u8; 25] = [
+            0x00, 0x40, 0x00, 0x00, // peak 0.5 (9.23 fixed point)
+            0x2E, 0x3F, // radio gain: name 1, automatic, -6.3 dB
+            0x00, 0x00, // audiophile gain unset
+            0x34, // nspsytune + nssafejoint, ATH type 4
+            0x80, // 128 kbps
+            0x24, 0x04, 0xB0, // delay 576, padding 1200
+            0x00, // misc (set separately)
+            0x82, // MP3 gain -3.0 dB
+            0x01, 0xF4, // preset 500 = V0
+            0x00, 0x01, 0x00, 0x00, // music length 65536
+            0xAB, 0xCD, // music CRC
+            0x12, 0x34, // tag CRC
+        ];
+        // Source 48k, joint stereo, noise shaping 1
+        let data = create_full_tag_data("LAME3.100", 0x80 | (3 << 2) | 1, &tail);
+        let header = LameHeader::extract(&data).expect("Should extract header");
+        let tag = header.tag.expect("Should parse the extended tag");
+
+        assert_eq!(header.sample_rate, Some(44100));
+        assert_eq!(tag.peak_amplitude, Some(0.5));
+        let radio = tag.radio_gain.expect("Radio gain is set");
+        assert!((radio.gain_db + 6.3).abs() < 1e-9, "Got {}", radio.gain_db);
+        assert_eq!(radio.originator, GainOriginator::Automatic);
+        assert!(tag.audiophile_gain.is_none());
+        assert!(tag.nspsytune && tag.nssafejoint);
+        assert!(!tag.nogap_next && !tag.nogap_previous);
+        assert_eq!(tag.ath_type, 4);
+        assert_eq!(tag.bitrate, 128);
+        assert_eq!((tag.encoder_delay, tag.encoder_padding), (576, 1200));
+        assert_eq!(tag.noise_shaping, 1);
+        assert_eq!(tag.stereo_mode, LameStereoMode::JointStereo);
+        assert!(!tag.unwise_settings);
+        assert_eq!(tag.source_sample_rate, SourceSampleRate::Hz48000);
+        assert!(!tag.source_sample_rate.matches(44100));
+        assert_eq!(tag.mp3_gain_db, -3.0);
+        assert_eq!(tag.preset_id, 500);
+        assert_eq!(tag.preset.as_deref(), Some("V0"));
+        assert_eq!(tag.music_length, 65536);
+        assert_eq!((tag.music_crc, tag.tag_crc), (0xABCD, 0x1234));
+    }
+
+    #[test]
+    fn test_old_lame_has_no_extended_tag() {
+        // Before 3.90 the bytes after the version string were more text
+        let data = create_full_tag_data("LAME3.88 ", 0x40, &[0x20; 25]);
+        let header = LameHeader::extract(&data).expect("Should extract header");
+        assert!(header.tag.is_none());
+        assert!(has_extended_tag("LAME3.90."));
+        assert!(has_extended_tag("LAME3.100"));
+        assert!(!has_extended_tag("Lavc58.54"));
+    }
+
+    #[test]
+    fn test_preset_names() {
+        assert_eq!(preset_name(0), None);
+        assert_eq!(preset_name(320).as_deref(), Some("320kbps"));
+        assert_eq!(preset_name(410).as_deref(), Some("V9"));
+        assert_eq!(preset_name(490).as_deref(), Some("V1"));
+        assert_eq!(preset_name(495), None);
+        assert_eq!(preset_name(1002).as_deref(), Some("extreme"));
+        assert_eq!(preset_name(1007).as_deref(), Some("fast medium"));
+    }
+
     #[test]
     fn test_no_lame_header_returns_none() {
         // Not all MP3s have LAME headers. Files from other encoders
@@ -0,0 +1,288 @@
+//! LAME lowpass defaults by version, mode, preset and sample rate
+//!
+//! LAME picks its lowpass from the settings it was given, and the choice
+//! has changed between releases. The LAME tag records everything needed to
+//! look it up again:
+//!
+//! ```text
+//! Field            Where                  Used for
+//! Version          "LAME3.99r"            Which release's tables apply
+//! VBR method       Byte 9, low nibble     CBR / ABR / VBR
+//! Preset           Bytes 26-27            -V level or named preset
+//! Bitrate          Byte 20                CBR bitrate or ABR target
+//! Sample rate      Frame header           Lowpass can't pass Nyquist
+//! ```
+//!
+//! CBR and ABR use one bitrate table from 3.94 on, interpolated between
+//! entries for ABR targets. VBR has a table per -V level, which 3.98 and
+//! 3.99 both revised. Files whose settings can't be recovered are judged by
+//! the generic bitrate table in `lame`.
+//!
+//! # Not covered: 3.90-3.93
+//!
+//! The --alt-preset releases (3.90-3.93) have no table here, so their files
+//! fall back to the bitrate table too. Their tags leave the preset field
+//! empty, and the --alt-preset tunings set their own lowpass apart from the
+//! -V level, so neither the mode nor the level says which lowpass was used.
+
+use super::lame::{self, LameHeader};
+use serde::Serialize;
+
+/// How far below the expected lowpass a LAME-recorded value may sit when the
+/// settings are known. LAME writes the lowpass it actually used, so the slack
+/// only covers rounding and minor releases
+pub const SETTINGS_MARGIN_HZ: u32 = 1500;
+
+/// CBR/ABR bandwidth by bitrate (3.94 and later), interpolated in between
+const CBR_TABLE: [(u32, u32); 17] = [
+    (8, 2000),
+    (16, 3700),
+    (24, 3900),
+    (32, 5500),
+    (40, 7000),
+    (48, 7500),
+    (56, 10000),
+    (64, 11000),
+    (80, 13500),
+    (96, 15100),
+    (112, 15600),
+    (128, 17000),
+    (160, 17500),
+    (192, 18600),
+    (224, 19400),
+    (256, 19700),
+    (320, 20500),
+];
+
+/// -V0 to -V9 lowpass, 3.94-3.97
+const VBR_3_97: [u32; 10] = [
+    19500, 19000, 19000, 18500, 18000, 17000, 16000, 15000, 12500, 10000,
+];
+
+/// -V0 to -V9 lowpass, 3.98
+const VBR_3_98: [u32; 10] = [
+    19500, 19000, 18600, 18000, 17500, 16000, 15600, 14900, 12500, 10000,
+];
+
+/// -V0 to -V9 lowpass, 3.99 and 3.100
+const VBR_3_99: [u32; 10] = [
+    19700, 19300, 19000, 18600, 18000, 17000, 16000, 15100, 13000, 11000,
+];
+
+/// The lowpass a file's settings call for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExpectedLowpass {
+    /// Expected lowpass (Hz)
+    pub hz: u32,
+    /// Lowest value that isn't suspicious (0 = don't judge)
+    pub min_hz: u32,
+    /// What the expectation is based on ("LAME3.99 V2 @ 44100 Hz", "192 kbps")
+    pub basis: String,
+}
+
+/// Encoding mode from the LAME tag's VBR method
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Cbr,
+    Abr,
+    Vbr,
+}
+
+/// Expectation from the bitrate alone, for files without usable settings.
+/// The table assumes 44.1/48 kHz: at lower rates the lowpass stops at
+/// Nyquist, and the lowest acceptable value comes down by as much
+pub fn for_bitrate(bitrate: u32, sample_rate: Option<u32>) -> ExpectedLowpass {
+    let hz = lame::expected_lowpass_for_bitrate(bitrate);
+    let min_hz = lame::min_acceptable_lowpass(bitrate);
+    let (clamped, rate) = match sample_rate {
+        Some(rate) => (hz.min(rate / 2), format!(" @ {} Hz", rate)),
+        None => (hz, String::new()),
+    };
+    ExpectedLowpass {
+        hz: clamped,
+        min_hz: min_hz.saturating_sub(hz - clamped),
+        basis: format!("{} kbps{}", bitrate, rate),
+    }
+}
+
+/// Expectation from the LAME tag's settings, falling back to the bitrate
+pub fn for_header(header: &LameHeader, bitrate: u32) -> ExpectedLowpass {
+    let minor = header.encoder.strip_prefix("LAME3.").and_then(|rest| {
+        let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+        digits.parse::<u32>().ok()
+    });
+    let (Some(minor), Some(tag)) = (minor.filter(|&minor| minor >= 94), header.tag.as_ref()) else {
+        return for_bitrate(bitrate, header.sample_rate);
+    };
+
+    let mode = match header.vbr_method {
+        Some(1) | Some(8) => Some(Mode::Cbr),
+        Some(2) | Some(9) => Some(Mode::Abr),
+        Some(3..=6) => Some(Mode::Vbr),
+        _ => None,
+    };
+    // Named presets stand for a -V level or a CBR bitrate
+    let (mode, setting) = match (tag.preset_id, mode) {
+        (410..=500, _) if tag.preset_id.is_multiple_of(10) => {
+            (Mode::Vbr, (500 - tag.preset_id as u32) / 10)
+        }
+        (1002 | 1005, _) => (Mode::Vbr, 0),
+        (1001 | 1004, _) => (Mode::Vbr, 2),
+        (1006 | 1007, _) => (Mode::Vbr, 4),
+        (1003, _) => (Mode::Cbr, 320),
+        (8..=320, Some(mode @ (Mode::Cbr | Mode::Abr))) => (mode, tag.preset_id as u32),
+        (_, Some(mode @ (Mode::Cbr | Mode::Abr))) if tag.bitrate > 0 => (mode, tag.bitrate),
+        (_, Some(Mode::Cbr)) => (Mode::Cbr, bitrate),
+        _ => return for_bitrate(bitrate, header.sample_rate),
+    };
+
+    let (hz, setting_name) = match mode {
+        Mode::Vbr => {
+            let table = match minor {
+                94..=97 => &VBR_3_97,
+                98 => &VBR_3_98,
+                _ => &VBR_3_99,
+            };
+            (
+                table[setting.min(9) as usize],
+                format!("V{}", setting.min(9)),
+            )
+        }
+        Mode::Cbr | Mode::Abr => {
+            let kind = if mode == Mode::Cbr { "CBR" } else { "ABR" };
+            (cbr_bandwidth(setting), format!("{} {} kbps", kind, setting))
+        }
+    };
+
+    // The lowpass can't pass Nyquist
+    let (hz, rate) = match header.sample_rate {
+        Some(rate) => (hz.min(rate / 2), format!(" @ {} Hz", rate)),
+        None => (hz, String::new()),
+    };
+    ExpectedLowpass {
+        hz,
+        min_hz: hz.saturating_sub(SETTINGS_MARGIN_HZ),
+        basis: format!("LAME3.{} {}{}", minor, setting_name, rate),
+    }
+}
+
+/// CBR/ABR bandwidth, interpolated between table entries
+fn cbr_bandwidth(bitrate: u32) -> u32 {
+    let upper = CBR_TABLE.iter().position(|&(kbps, _)| kbps >= bitrate);
+    match upper {
+        Some(0) => CBR_TABLE[0].1,
+        Some(i) => {
+            let ((b0, f0), (b1, f1)) = (CBR_TABLE[i - 1], CBR_TABLE[i]);
+            f0 + (f1 - f0) * (bitrate - b0) / (b1 - b0)
+        }
+        None => CBR_TABLE[CBR_TABLE.len() - 1].1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp3::lame::LameTag;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: WHY ONE TABLE ISN'T ENOUGH
+    // ==========================================================================
+    //
+    // "320 kbps means a 20 kHz lowpass" is a fair rule for CBR, but most LAME
+    // files are VBR. Their average bitrate depends on the music, not the
+    // setting: a -V5 file (16-17 kHz lowpass) of loud rock can average
+    // 200 kbps, and a bitrate table would then expect 18.5 kHz and cry
+    // transcode. The tag says -V5, and -V5 means 16-17 kHz. LAME's own
+    // tables have also moved between releases, so the version decides which
+    // one applies.
+    // ==========================================================================
+
+    /// Helper: LAME header with the given version, VBR method and preset
+    fn header(encoder: &str, vbr_method: u8, preset_id: u16, bitrate: u32) -> LameHeader {
+        LameHeader {
+            encoder: encoder.to_string(),
+            vbr_method: Some(vbr_method),
+            sample_rate: Some(44100),
+            tag: Some(LameTag {
+                preset_id,
+                bitrate,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_vbr_levels_by_version() {
+        // -V2 (preset 480) from each release
+        let expected = for_header(&header("LAME3.99r", 4, 480, 0), 190);
+        assert_eq!(expected.hz, 19000);
+        assert_eq!(expected.basis, "LAME3.99 V2 @ 44100 Hz");
+        assert_eq!(for_header(&header("LAME3.98 ", 4, 480, 0), 190).hz, 18600);
+        assert_eq!(for_header(&header("LAME3.100", 4, 480, 0), 190).hz, 19000);
+
+        // A loud -V5 file averaging 200 kbps is still a 16-17 kHz encode,
+        // which the bitrate table alone would flag
+        let v5 = for_header(&header("LAME3.100", 4, 450, 0), 200);
+        assert_eq!((v5.hz, v5.min_hz), (17000, 15500));
+        assert!(for_bitrate(200, Some(44100)).min_hz > 16000);
+
+        // Named presets
+        assert_eq!(
+            for_header(&header("LAME3.97 ", 3, 1002, 0), 250).basis,
+            "LAME3.97 V0 @ 44100 Hz"
+        );
+        assert_eq!(for_header(&header("LAME3.98 ", 3, 1001, 0), 200).hz, 18600);
+    }
+
+    #[test]
+    fn test_cbr_and_abr() {
+        let cbr = for_header(&header("LAME3.100", 1, 0, 128), 128);
+        assert_eq!(
+            (cbr.hz, cbr.basis.as_str()),
+            (17000, "LAME3.100 CBR 128 kbps @ 44100 Hz")
+        );
+        // ABR 144 falls halfway between the 128 and 160 entries
+        assert_eq!(for_header(&header("LAME3.99r", 2, 0, 144), 150).hz, 17250);
+        assert_eq!(cbr_bandwidth(320), 20500);
+        assert_eq!(cbr_bandwidth(4), 2000);
+    }
+
+    #[test]
+    fn test_sample_rate_and_fallbacks() {
+        // 32 kHz: nothing passes 16 kHz
+        let mut low_rate = header("LAME3.100", 1, 0, 320);
+        low_rate.sample_rate = Some(32000);
+        assert_eq!(for_header(&low_rate, 320).hz, 16000);
+
+        // Pre-3.94, no tag or unknown mode: the bitrate decides
+        assert_eq!(
+            for_header(&header("LAME3.90.", 4, 480, 0), 192),
+            for_bitrate(192, Some(44100))
+        );
+        let mut no_tag = header("LAME3.100", 4, 480, 0);
+        no_tag.tag = None;
+        assert_eq!(for_header(&no_tag, 320), for_bitrate(320, Some(44100)));
+        assert_eq!(
+            for_header(&header("LAME3.100", 0, 0, 0), 256),
+            for_bitrate(256, Some(44100))
+        );
+    }
+
+    #[test]
+    fn test_bitrate_table_stops_at_nyquist() {
+        // 22.05 kHz at 64 kbps: the table's 14 kHz is past Nyquist
+        let low = for_bitrate(64, Some(22050));
+        assert_eq!((low.hz, low.min_hz), (11025, 0));
+        assert_eq!(low.basis, "64 kbps @ 22050 Hz");
+
+        // 32 kHz at 320 kbps: an 18 kHz floor would flag every file
+        let high = for_bitrate(320, Some(32000));
+        assert_eq!((high.hz, high.min_hz), (16000, 13500));
+
+        // Rates that pass the table's values change nothing
+        let full = for_bitrate(320, Some(48000));
+        assert_eq!((full.hz, full.min_hz), (20500, 18000));
+        assert_eq!(for_bitrate(320, None).basis, "320 kbps");
+    }
+}
@@ -1,5 +1,17 @@
 pub mod frame;
 pub mod lame;
+pub mod lowpass;
+pub mod sideinfo;
+pub mod stream;
+pub mod tags;
+pub mod vbri;
+pub mod verify;
 
 pub use frame::{FrameHeader, MpegVersion, Layer, ChannelMode};
 pub use lame::LameHeader;
+pub use lowpass::ExpectedLowpass;
+pub use sideinfo::SideInfoStats;
+pub use stream::StreamStructure;
+pub use tags::TagSet;
+pub use vbri::VbriHeader;
+pub use verify::StreamVerification;
@@ -0,0 +1,462 @@
+//! Layer III side info statistics and encoder fingerprinting
+//!
+//! Encoder text ("LAME3.100", "Fraunhofer") is easy to strip or fake. The
+//! side info of every frame is not: it records how the encoder actually
+//! coded the audio, and each encoder has habits:
+//!
+//! ```text
+//! Encoder     Bit reservoir  Short blocks  Mixed blocks  Intensity stereo
+//! LAME        yes            yes           never         never
+//! FhG         yes            yes           sometimes     at low bitrates
+//! Xing/Helix  yes            rarely/never  no            no
+//! Shine       never          never         no            no (no joint stereo)
+//! ```
+//!
+//! The big_values field gives the highest spectral line coded with more
+//! than ±1, so its distribution shows the real coded bandwidth without
+//! decoding a single sample.
+
+use super::frame::{ChannelModeStats, FrameHeader, SideInfo};
+use serde::Serialize;
+
+/// Highest big_values (576 lines / 2)
+const MAX_BIG_VALUES: usize = 288;
+
+/// Spectral lines per granule
+const GRANULE_LINES: f64 = 576.0;
+
+/// Granules needed before the absence of a feature means anything
+const MIN_GRANULES_FOR_ABSENCE: u32 = 1000;
+
+/// Percentile of big_values used as the coded bandwidth
+const BANDWIDTH_PERCENTILE: f64 = 0.95;
+
+/// Statistics over the side info of every Layer III frame
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SideInfoStats {
+    /// Frames with side info
+    pub frames: u32,
+    /// Granule/channel pairs
+    pub granules: u32,
+    /// Fraction of frames whose main data starts in an earlier frame
+    pub reservoir_use: f64,
+    /// Mean and maximum main_data_begin (bytes)
+    pub mean_main_data_begin: f64,
+    pub max_main_data_begin: u16,
+    /// Fraction of granules coded with short blocks
+    pub short_block_rate: f64,
+    /// Fraction of granules with window switching (start, short or stop blocks)
+    pub window_switching_rate: f64,
+    /// Fraction of granules with mixed blocks
+    pub mixed_block_rate: f64,
+    /// Global gain over non-silent granules
+    pub global_gain_mean: f64,
+    pub global_gain_std: f64,
+    pub global_gain_min: u8,
+    pub global_gain_max: u8,
+    /// Fraction of frames using mid/side and intensity stereo
+    pub ms_stereo_rate: f64,
+    pub intensity_stereo_rate: f64,
+    /// Fraction of second granules reusing scale factors (MPEG-1)
+    pub scfsi_rate: f64,
+    pub scalefac_scale_rate: f64,
+    pub preflag_rate: f64,
+    /// Fraction of short-block granules with a non-zero subblock gain
+    pub subblock_gain_rate: f64,
+    /// 95th percentile of the big_values region (Hz)
+    pub coded_bandwidth_hz: f64,
+    /// Highest big_values region seen (Hz)
+    pub max_coded_bandwidth_hz: f64,
+    /// Encoder habits seen in the side info ("no_bit_reservoir", "mixed_blocks", ...)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub traits: Vec<String>,
+    /// Encoder family the habits point to, when they point to one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub likely_encoder: Option<String>,
+}
+
+impl SideInfoStats {
+    /// Traits that rule out LAME, for a file whose text says LAME
+    pub fn lame_contradictions(&self) -> Vec<&str> {
+        const NEVER_LAME: [&str; 3] = ["mixed_blocks", "intensity_stereo", "no_bit_reservoir"];
+        self.traits
+            .iter()
+            .map(String::as_str)
+            .filter(|t| NEVER_LAME.contains(t))
+            .collect()
+    }
+}
+
+/// Accumulates side info frame by frame
+#[derive(Debug, Clone)]
+pub struct SideInfoCollector {
+    frames: u32,
+    granules: u32,
+    reservoir_frames: u32,
+    main_data_begin_sum: u64,
+    max_main_data_begin: u16,
+    short_blocks: u32,
+    switching: u32,
+    mixed: u32,
+    gain_count: u32,
+    gain_sum: f64,
+    gain_square_sum: f64,
+    gain_min: u8,
+    gain_max: u8,
+    modes: ChannelModeStats,
+    scfsi: u32,
+    scfsi_possible: u32,
+    scalefac_scale: u32,
+    preflag: u32,
+    subblock_gain: u32,
+    big_values: Vec<u32>,
+    sample_rate: u32,
+}
+
+impl Default for SideInfoCollector {
+    fn default() -> Self {
+        Self {
+            frames: 0,
+            granules: 0,
+            reservoir_frames: 0,
+            main_data_begin_sum: 0,
+            max_main_data_begin: 0,
+            short_blocks: 0,
+            switching: 0,
+            mixed: 0,
+            gain_count: 0,
+            gain_sum: 0.0,
+            gain_square_sum: 0.0,
+            gain_min: u8::MAX,
+            gain_max: 0,
+            modes: ChannelModeStats::default(),
+            scfsi: 0,
+            scfsi_possible: 0,
+            scalefac_scale: 0,
+            preflag: 0,
+            subblock_gain: 0,
+            big_values: vec![0; MAX_BIG_VALUES + 1],
+            sample_rate: 0,
+        }
+    }
+}
+
+impl SideInfoCollector {
+    /// Add one frame (`frame` starts at the header)
+    pub fn add(&mut self, frame: &[u8], header: &FrameHeader) {
+        let Some(info) = SideInfo::parse(frame, header) else {
+            return;
+        };
+        if self.sample_rate == 0 {
+            self.sample_rate = header.sample_rate;
+        }
+        self.frames += 1;
+        if info.main_data_begin > 0 {
+            self.reservoir_frames += 1;
+        }
+        self.main_data_begin_sum += info.main_data_begin as u64;
+        self.max_main_data_begin = self.max_main_data_begin.max(info.main_data_begin);
+        self.modes.add(header);
+
+        for (index, granule) in info.granules.iter().enumerate() {
+            for (channel, gr) in granule.iter().enumerate() {
+                self.granules += 1;
+                let short = gr.window_switching && gr.block_type == 2;
+                self.switching += gr.window_switching as u32;
+                self.short_blocks += short as u32;
+                self.mixed += gr.mixed_block as u32;
+                self.scalefac_scale += gr.scalefac_scale as u32;
+                self.preflag += gr.preflag as u32;
+                if short && gr.subblock_gain.iter().any(|&g| g != 0) {
+                    self.subblock_gain += 1;
+                }
+                // scfsi only means something for the second MPEG-1 granule of long blocks
+                if index == 1 && !gr.window_switching {
+                    self.scfsi_possible += 1;
+                    self.scfsi += info.scfsi[channel].iter().any(|&b| b) as u32;
+                }
+
+                // Silent granules carry an arbitrary gain and no spectrum
+                if gr.part2_3_length == 0 {
+                    continue;
+                }
+                self.gain_count += 1;
+                self.gain_sum += gr.global_gain as f64;
+                self.gain_square_sum += (gr.global_gain as f64).powi(2);
+                self.gain_min = self.gain_min.min(gr.global_gain);
+                self.gain_max = self.gain_max.max(gr.global_gain);
+                // Short blocks interleave three windows, so their lines aren't frequencies
+                if !short {
+                    self.big_values[(gr.big_values as usize).min(MAX_BIG_VALUES)] += 1;
+                }
+            }
+        }
+    }
+
+    /// Summarise, or None if no Layer III frames were seen
+    pub fn finish(self) -> Option<SideInfoStats> {
+        if self.frames == 0 {
+            return None;
+        }
+        let frames = self.frames as f64;
+        let granules = self.granules.max(1) as f64;
+        let rate = |count: u32, total: f64| {
+            if total > 0.0 {
+                count as f64 / total
+            } else {
+                0.0
+            }
+        };
+        let gain_mean = self.gain_sum / self.gain_count.max(1) as f64;
+        let gain_variance =
+            self.gain_square_sum / self.gain_count.max(1) as f64 - gain_mean * gain_mean;
+
+        // big_values pairs → highest coded line → Hz (576 lines span 0 to Nyquist)
+        let line_hz = self.sample_rate as f64 / 2.0 / GRANULE_LINES;
+        let coded: u32 = self.big_values.iter().sum();
+        let percentile = {
+            let target = (coded as f64 * BANDWIDTH_PERCENTILE).ceil() as u32;
+            let mut seen = 0;
+            self.big_values.iter().position(|&count| {
+                seen += count;
+                seen >= target.max(1)
+            })
+        };
+        let highest = self.big_values.iter().rposition(|&count| count > 0);
+
+        let mut stats = SideInfoStats {
+            frames: self.frames,
+            granules: self.granules,
+            reservoir_use: rate(self.reservoir_frames, frames),
+            mean_main_data_begin: self.main_data_begin_sum as f64 / frames,
+            max_main_data_begin: self.max_main_data_begin,
+            short_block_rate: rate(self.short_blocks, granules),
+            window_switching_rate: rate(self.switching, granules),
+            mixed_block_rate: rate(self.mixed, granules),
+            global_gain_mean: gain_mean,
+            global_gain_std: gain_variance.max(0.0).sqrt(),
+            global_gain_min: if self.gain_count > 0 {
+                self.gain_min
+            } else {
+                0
+            },
+            global_gain_max: self.gain_max,
+            ms_stereo_rate: self.modes.ms_rate(),
+            intensity_stereo_rate: self.modes.intensity_rate(),
+            scfsi_rate: rate(self.scfsi, self.scfsi_possible as f64),
+            scalefac_scale_rate: rate(self.scalefac_scale, granules),
+            preflag_rate: rate(self.preflag, granules),
+            subblock_gain_rate: rate(self.subblock_gain, self.short_blocks as f64),
+            coded_bandwidth_hz: percentile
+                .filter(|_| coded > 0)
+                .map_or(0.0, |bv| (bv * 2) as f64 * line_hz),
+            max_coded_bandwidth_hz: highest
+                .filter(|_| coded > 0)
+                .map_or(0.0, |bv| (bv * 2) as f64 * line_hz),
+            ..Default::default()
+        };
+        (stats.traits, stats.likely_encoder) = fingerprint(&stats);
+        Some(stats)
+    }
+}
+
+/// Name the habits and the encoder family they point to
+fn fingerprint(stats: &SideInfoStats) -> (Vec<String>, Option<String>) {
+    let mut traits = Vec::new();
+    // Absence only counts once there was plenty of music to need the feature
+    let enough = stats.granules >= MIN_GRANULES_FOR_ABSENCE;
+    let no_reservoir = enough && stats.max_main_data_begin == 0;
+    let no_switching = enough && stats.window_switching_rate == 0.0;
+
+    if no_reservoir {
+        traits.push("no_bit_reservoir");
+    }
+    if no_switching {
+        traits.push("no_short_blocks");
+    }
+    if stats.mixed_block_rate > 0.0 {
+        traits.push("mixed_blocks");
+    }
+    if stats.intensity_stereo_rate > 0.0 {
+        traits.push("intensity_stereo");
+    }
+    if stats.scfsi_rate > 0.0 {
+        traits.push("scfsi");
+    }
+    if stats.subblock_gain_rate > 0.0 {
+        traits.push("subblock_gain");
+    }
+
+    let likely = if no_reservoir && no_switching && stats.ms_stereo_rate == 0.0 {
+        Some("Shine")
+    } else if stats.mixed_block_rate > 0.0 || stats.intensity_stereo_rate > 0.0 {
+        Some("FhG")
+    } else if no_switching {
+        Some("Xing/Helix")
+    } else {
+        None
+    };
+    (
+        traits.into_iter().map(String::from).collect(),
+        likely.map(String::from),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: WHAT THE SIDE INFO GIVES AWAY
+    // ==========================================================================
+    //
+    // The MP3 standard fixes the bitstream, not the encoder. Each encoder picks
+    // its own psychoacoustic model, block switching rules and stereo coding,
+    // and those choices are written in the clear in every frame's side info:
+    //
+    // - main_data_begin > 0 means the encoder borrowed bits from earlier
+    //   frames (the bit reservoir). Shine never does.
+    // - block_type 2 (short blocks) is chosen on transients. Encoders without
+    //   a proper transient detector (Shine, old Xing) never switch.
+    // - Mixed blocks and intensity stereo are legal but LAME never uses them.
+    //   FhG does.
+    // - big_values marks where the coded spectrum effectively ends. A file
+    //   lowpassed before encoding has it far below what the bitrate allows.
+    // ==========================================================================
+
+    /// MPEG-1 Layer III 128 kbps, 44.1 kHz mono / joint stereo (mode extension 1)
+    const MONO: [u8; 4] = [0xFF, 0xFB, 0x90, 0xC0];
+    const JOINT_INTENSITY: [u8; 4] = [0xFF, 0xFB, 0x90, 0x50];
+    const FRAME_SIZE: usize = 417;
+
+    /// How one frame's granules are coded
+    #[derive(Clone, Copy)]
+    struct Coding {
+        main_data_begin: u32,
+        big_values: u32,
+        block_type: u32,
+        mixed: bool,
+    }
+
+    const LONG: Coding = Coding {
+        main_data_begin: 0,
+        big_values: 200,
+        block_type: 0,
+        mixed: false,
+    };
+
+    /// Helper: one frame with the given coding in every granule and channel
+    fn create_frame(header_bytes: [u8; 4], coding: Coding) -> (Vec<u8>, FrameHeader) {
+        let header = FrameHeader::parse(header_bytes).unwrap();
+        let channels = header.channels() as u32;
+        let mut fields = vec![
+            (coding.main_data_begin, 9),
+            (0, if channels == 1 { 5 } else { 3 }),
+        ];
+        fields.extend((0..channels).map(|_| (0, 4))); // scfsi
+        for _ in 0..2 * channels {
+            fields.extend_from_slice(&[(1000, 12), (coding.big_values, 9), (180, 8), (9, 4)]);
+            if coding.block_type != 0 {
+                fields.extend_from_slice(&[
+                    (1, 1),
+                    (coding.block_type, 2),
+                    (coding.mixed as u32, 1),
+                ]);
+                fields.extend_from_slice(&[(1, 5), (2, 5), (0, 3), (0, 3), (0, 3)]);
+            } else {
+                fields.extend_from_slice(&[(0, 1), (1, 5), (2, 5), (3, 5), (8, 4), (2, 3)]);
+            }
+            fields.extend_from_slice(&[(0, 1), (0, 1), (0, 1)]);
+        }
+
+        let mut frame = vec![0u8; FRAME_SIZE];
+        frame[..4].copy_from_slice(&header_bytes);
+        for (pos, bit) in fields
+            .iter()
+            .flat_map(|&(value, count)| (0..count).rev().map(move |i| (value >> i) & 1))
+            .enumerate()
+        {
+            frame[4 + pos / 8] |= (bit as u8) << (7 - pos % 8);
+        }
+        (frame, header)
+    }
+
+    /// Helper: collect `frames` frames, coding each with `coding(index)`
+    fn collect(header: [u8; 4], frames: usize, coding: impl Fn(usize) -> Coding) -> SideInfoStats {
+        let mut collector = SideInfoCollector::default();
+        for i in 0..frames {
+            let (frame, header) = create_frame(header, coding(i));
+            collector.add(&frame, &header);
+        }
+        collector.finish().expect("Should have side info")
+    }
+
+    #[test]
+    fn test_lame_like_stream() {
+        // Reservoir in use, a short block every tenth frame, lowpass near 17.8 kHz
+        let stats = collect(MONO, 600, |i| Coding {
+            main_data_begin: 120,
+            big_values: 232,
+            block_type: if i % 10 == 0 { 2 } else { 0 },
+            mixed: false,
+        });
+        assert_eq!((stats.frames, stats.granules), (600, 1200));
+        assert_eq!(stats.reservoir_use, 1.0);
+        assert_eq!(stats.max_main_data_begin, 120);
+        assert!((stats.short_block_rate - 0.1).abs() < 1e-9);
+        assert_eq!((stats.global_gain_min, stats.global_gain_max), (180, 180));
+        // 464 lines of 576 up to 22.05 kHz
+        assert!((stats.coded_bandwidth_hz - 17762.5).abs() < 1.0);
+        assert!(stats.traits.is_empty());
+        assert_eq!(stats.likely_encoder, None);
+        assert!(stats.lame_contradictions().is_empty());
+    }
+
+    #[test]
+    fn test_shine_fingerprint() {
+        // No reservoir, never a short block, no joint stereo
+        let stats = collect(MONO, 600, |_| LONG);
+        assert_eq!(stats.reservoir_use, 0.0);
+        assert_eq!(stats.traits, vec!["no_bit_reservoir", "no_short_blocks"]);
+        assert_eq!(stats.likely_encoder.as_deref(), Some("Shine"));
+        assert_eq!(stats.lame_contradictions(), vec!["no_bit_reservoir"]);
+
+        // Too little audio for absence to mean anything
+        let short_clip = collect(MONO, 100, |_| LONG);
+        assert!(short_clip.traits.is_empty());
+        assert_eq!(short_clip.likely_encoder, None);
+    }
+
+    #[test]
+    fn test_fhg_fingerprint() {
+        // Intensity stereo and the odd mixed block
+        let stats = collect(JOINT_INTENSITY, 50, |i| Coding {
+            main_data_begin: 60,
+            mixed: i == 7,
+            block_type: if i == 7 { 2 } else { 0 },
+            ..LONG
+        });
+        assert_eq!(stats.intensity_stereo_rate, 1.0);
+        assert_eq!(stats.ms_stereo_rate, 0.0);
+        assert!(stats.mixed_block_rate > 0.0);
+        assert_eq!(stats.likely_encoder.as_deref(), Some("FhG"));
+        assert_eq!(
+            stats.lame_contradictions(),
+            vec!["mixed_blocks", "intensity_stereo"]
+        );
+    }
+
+    #[test]
+    fn test_bandwidth_ignores_short_blocks() {
+        // Short blocks interleave three windows: their big_values is not a frequency
+        let stats = collect(MONO, 100, |i| Coding {
+            big_values: if i % 2 == 0 { 288 } else { 150 },
+            block_type: if i % 2 == 0 { 2 } else { 0 },
+            ..LONG
+        });
+        assert!((stats.max_coded_bandwidth_hz - 11484.4).abs() < 1.0);
+
+        // No Layer III frames, no stats
+        assert!(SideInfoCollector::default().finish().is_none());
+    }
+}
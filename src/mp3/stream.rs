@@ -0,0 +1,572 @@
+//! Full MP3 stream walk
+//!
+//! `scan_frames` looks at the first few hundred frames and accepts anything
+//! that parses as a header. This walk covers the whole stream and only
+//! accepts a frame when the next one follows it:
+//!
+//! ```text
+//! [frame][frame][frame][junk....][frame][frame]...
+//!                      ^ sync lost       ^ sync regained
+//! ```
+//!
+//! What it records is what editing leaves behind:
+//!
+//! - **Sync losses**: bytes between frames that aren't frames. Cut points in
+//!   a splice, damaged downloads, or a tag dropped into the middle.
+//! - **Format changes**: a different sample rate, layer or MPEG version
+//!   mid-stream. One encoder never does this; two files joined together do.
+//! - **Embedded header frames**: every LAME file starts with a Xing/Info frame
+//!   and every FhG VBR file with a VBRI one. Finding another mid-stream means
+//!   a second file was appended.
+//! - **CRC errors**: frames whose protection bit promises a CRC-16 over the
+//!   header and side info that doesn't match. This is damage rather than
+//!   editing: broadcast captures, bad sectors, incomplete P2P downloads.
+//! - **Truncation**: a last frame that runs past the end of the file, which
+//!   needs no Xing/Info header to see.
+//!
+//! It also gives the real frame count and duration, which for VBR files the
+//! bitrate-based estimate only approximates.
+
+use super::frame::{FrameHeader, Layer, MpegVersion, MAX_FREE_FORMAT_SIZE};
+use super::tags;
+use super::vbri::VBRI_OFFSET;
+use serde::Serialize;
+use std::io::Cursor;
+
+/// Frames that must follow a resync candidate to accept it
+const RESYNC_CONFIRM_FRAMES: usize = 2;
+
+/// Most bytes before the first frame for the walk to stand for the whole file
+/// (the leftovers of a stripped tag, not a stream found inside other data)
+const MAX_LEADING_BYTES: u64 = 4096;
+
+/// Bytes `chain` can read from where it starts
+pub(super) const CHAIN_REACH: usize = (RESYNC_CONFIRM_FRAMES + 2) * MAX_FREE_FORMAT_SIZE;
+
+/// Where sync was lost and where it came back
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncLoss {
+    /// Byte offset where a frame was expected
+    pub offset: u64,
+    /// Byte offset of the next confirmed frame
+    pub resync_offset: u64,
+    /// Bytes skipped
+    pub junk_bytes: u64,
+    /// Stream position (seconds)
+    pub time_secs: f64,
+}
+
+/// A mid-stream change of sample rate, layer or MPEG version
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FormatChange {
+    pub offset: u64,
+    pub time_secs: f64,
+    /// e.g. "MPEG-1 Layer III 44100 Hz"
+    pub from: String,
+    pub to: String,
+}
+
+/// A protected frame whose CRC doesn't match
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CrcError {
+    pub offset: u64,
+    pub time_secs: f64,
+}
+
+/// Everything the walk found between the leading and trailing tags
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StreamStructure {
+    /// Frames found (including any Xing/Info frames)
+    pub frames: u32,
+    /// Playing time of all frames (seconds)
+    pub duration_secs: f64,
+    /// Audio range between the tags
+    pub audio_start: u64,
+    pub audio_end: u64,
+    /// Bytes between the start of the audio range and the first frame
+    pub leading_bytes: u64,
+    /// Bytes between frames, over all sync losses
+    pub junk_bytes: u64,
+    /// Bytes after the last complete frame
+    pub trailing_bytes: u64,
+    /// The last frame runs past the end of the audio
+    pub truncated: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sync_losses: Vec<SyncLoss>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub format_changes: Vec<FormatChange>,
+    /// Offsets of Xing/Info/VBRI frames after the first frame
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub embedded_headers: Vec<u64>,
+    /// Audio frames with a CRC that could be checked
+    pub crc_frames: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub crc_errors: Vec<CrcError>,
+}
+
+impl StreamStructure {
+    /// Whether the frames are the file's audio: they start near the start of
+    /// the audio range and fill most of it. Only then do the walk's frame
+    /// count and duration describe the file
+    pub fn covers_audio(&self) -> bool {
+        let range = self.audio_end - self.audio_start;
+        let outside = self.leading_bytes + self.junk_bytes + self.trailing_bytes;
+        self.frames > 0 && self.leading_bytes <= MAX_LEADING_BYTES && outside * 2 <= range
+    }
+
+    /// Offset of the first frame, if there were any
+    pub fn first_frame(&self) -> Option<u64> {
+        (self.frames > 0).then_some(self.audio_start + self.leading_bytes)
+    }
+
+    /// Offset just past the last complete frame
+    pub fn frames_end(&self) -> u64 {
+        self.audio_end - self.trailing_bytes
+    }
+
+    /// Flags for splices, joins and damage, e.g. "sync_lost_x2(junk=1834B)"
+    pub fn flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        if !self.sync_losses.is_empty() {
+            flags.push(format!(
+                "sync_lost_x{}(junk={}B)",
+                self.sync_losses.len(),
+                self.junk_bytes
+            ));
+        }
+        for change in &self.format_changes {
+            flags.push(format!(
+                "format_change({}→{}@{:.1}s)",
+                change.from, change.to, change.time_secs
+            ));
+        }
+        if !self.embedded_headers.is_empty() {
+            flags.push(format!(
+                "concatenated_streams({})",
+                self.embedded_headers.len() + 1
+            ));
+        }
+        if !self.crc_errors.is_empty() {
+            flags.push(format!(
+                "crc_errors_x{}(checked={})",
+                self.crc_errors.len(),
+                self.crc_frames
+            ));
+        }
+        if self.truncated {
+            flags.push("stream_truncated".to_string());
+        }
+        flags
+    }
+}
+
+/// Walk every frame of the stream in `data`
+pub fn walk(data: &[u8]) -> StreamStructure {
+    walk_with(data, |_, _| {})
+}
+
+/// Walk every frame, calling `visit` with the bytes and header of each audio frame
+pub fn walk_with<F: FnMut(&[u8], &FrameHeader)>(data: &[u8], mut visit: F) -> StreamStructure {
+    let audio = tags::audio_range(&mut Cursor::new(data)).unwrap_or(0..data.len() as u64);
+    let (start, end) = (audio.start as usize, audio.end as usize);
+    // Free format frames are measured to the next header, so keep the trailing tags out
+    let data = &data[..end];
+    let mut result = StreamStructure {
+        audio_start: audio.start,
+        audio_end: audio.end,
+        ..Default::default()
+    };
+
+    let Some(first) = resync(data, start, end) else {
+        result.leading_bytes = (end - start) as u64;
+        return result;
+    };
+    result.leading_bytes = (first - start) as u64;
+
+    let mut pos = first;
+    let mut previous: Option<FrameHeader> = None;
+    while pos + 4 <= end {
+        let parsed = frame_at(data, pos);
+        let in_run = parsed.is_some_and(|frame| confirmed(data, pos, &frame, end));
+        if !in_run {
+            let next = resync(data, pos + 1, end);
+            // A header right where the previous frame ended still counts when the junk
+            // only starts after it (a tag dropped between frames, trailing garbage)
+            let frame_end = parsed.map(|frame| pos + frame.frame_size as usize);
+            let trusted = previous.is_some()
+                && frame_end.is_some_and(|frame_end| {
+                    frame_end <= end && next.is_none_or(|next| next >= frame_end)
+                });
+            if !trusted {
+                let Some(next) = next else {
+                    // Nothing follows: a header that runs past the end was cut off
+                    result.truncated = parsed.is_some();
+                    break;
+                };
+                let junk = (next - pos) as u64;
+                result.sync_losses.push(SyncLoss {
+                    offset: pos as u64,
+                    resync_offset: next as u64,
+                    junk_bytes: junk,
+                    time_secs: result.duration_secs,
+                });
+                result.junk_bytes += junk;
+                pos = next;
+                continue;
+            }
+        }
+        let Some(frame) = parsed else {
+            break;
+        };
+
+        if let Some(ref prev) = previous {
+            if !same_format(prev, &frame) {
+                result.format_changes.push(FormatChange {
+                    offset: pos as u64,
+                    time_secs: result.duration_secs,
+                    from: format_name(prev),
+                    to: format_name(&frame),
+                });
+            }
+        }
+        // Xing/Info/VBRI frames hold no audio; past the first they mark a join
+        if is_info_frame(data, pos, &frame) {
+            if previous.is_some() {
+                result.embedded_headers.push(pos as u64);
+            }
+        } else {
+            let bytes = &data[pos..(pos + frame.frame_size as usize).min(end)];
+            // Only frames the next one confirms: the "CRC" of a header taken
+            // on trust could be any two bytes
+            if let Some(matches) = frame.crc_matches(bytes).filter(|_| in_run) {
+                result.crc_frames += 1;
+                if !matches {
+                    result.crc_errors.push(CrcError {
+                        offset: pos as u64,
+                        time_secs: result.duration_secs,
+                    });
+                }
+            }
+            visit(bytes, &frame);
+        }
+        result.frames += 1;
+        result.duration_secs += frame.duration_secs();
+        pos += frame.frame_size as usize;
+        previous = Some(frame);
+    }
+    result.trailing_bytes = end.saturating_sub(pos) as u64;
+    result
+}
+
+/// Parse the frame header at `pos`
+fn frame_at(data: &[u8], pos: usize) -> Option<FrameHeader> {
+    FrameHeader::parse_at(data, pos)
+}
+
+/// A frame is real if it ends at the end of the audio, or another frame
+/// follows: one of the same format, or the start of a new run of frames
+fn confirmed(data: &[u8], pos: usize, frame: &FrameHeader, end: usize) -> bool {
+    let next = pos + frame.frame_size as usize;
+    if next > end {
+        return false;
+    }
+    // Too little left for another header: this is the last frame
+    if next + 4 > end {
+        return true;
+    }
+    match frame_at(data, next) {
+        Some(following) => same_format(frame, &following) || chain(data, next, end),
+        None => false,
+    }
+}
+
+/// Whether `RESYNC_CONFIRM_FRAMES` frames of one format follow the one at `pos`
+pub(super) fn chain(data: &[u8], pos: usize, end: usize) -> bool {
+    let Some(first) = frame_at(data, pos) else {
+        return false;
+    };
+    let mut next = pos;
+    for found in 0..=RESYNC_CONFIRM_FRAMES {
+        match frame_at(data, next) {
+            Some(frame)
+                if same_format(&first, &frame)
+                    && same_free_format_size(&first, &frame)
+                    && next + frame.frame_size as usize <= end =>
+            {
+                next += frame.frame_size as usize;
+            }
+            // A run the end of the audio cuts short still needs its frames: one
+            // header that happens to end at the last byte is chance, not a stream
+            _ => return next == end && found >= RESYNC_CONFIRM_FRAMES,
+        }
+    }
+    true
+}
+
+/// Next position at or after `from` where frames follow each other
+fn resync(data: &[u8], from: usize, end: usize) -> Option<usize> {
+    (from..end.saturating_sub(3)).find(|&pos| data[pos] == 0xFF && chain(data, pos, end))
+}
+
+fn same_format(a: &FrameHeader, b: &FrameHeader) -> bool {
+    a.version == b.version && a.layer == b.layer && a.sample_rate == b.sample_rate
+}
+
+/// Free format frames are measured to whatever looks like the next header,
+/// so a run of them only counts when the encoder's frame size holds
+fn same_free_format_size(a: &FrameHeader, b: &FrameHeader) -> bool {
+    !a.free_format
+        || (b.free_format && a.frame_size - a.padding_size() == b.frame_size - b.padding_size())
+}
+
+/// "MPEG-1 Layer III 44100 Hz"
+fn format_name(frame: &FrameHeader) -> String {
+    let version = match frame.version {
+        MpegVersion::Mpeg1 => "MPEG-1",
+        MpegVersion::Mpeg2 => "MPEG-2",
+        MpegVersion::Mpeg25 => "MPEG-2.5",
+    };
+    let layer = match frame.layer {
+        Layer::Layer1 => "Layer I",
+        Layer::Layer2 => "Layer II",
+        Layer::Layer3 => "Layer III",
+    };
+    format!("{} {} {} Hz", version, layer, frame.sample_rate)
+}
+
+/// Whether the frame at `pos` carries a Xing/Info header (the first frame of a LAME file)
+fn is_info_frame(data: &[u8], pos: usize, frame: &FrameHeader) -> bool {
+    if frame.layer != Layer::Layer3 {
+        return false;
+    }
+    // Protection bit clear: a 16-bit CRC follows the header
+    let crc = if frame.protected { 2 } else { 0 };
+    let tag = pos + 4 + crc + frame.side_info_size();
+    matches!(data.get(tag..tag + 4), Some(b"Xing") | Some(b"Info"))
+        || data.get(pos + VBRI_OFFSET..pos + VBRI_OFFSET + 4) == Some(b"VBRI")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::test_noise::TestNoise;
+    use crate::mp3::frame::mpeg_crc16;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: WHAT A SPLICE LOOKS LIKE IN THE BITSTREAM
+    // ==========================================================================
+    //
+    // An MP3 is a chain of frames: each header gives the frame's size, so
+    // the next header sits exactly that many bytes further on. Cutting two
+    // files together with a byte-level tool breaks the chain at the joint:
+    //
+    //   ...[frame A][half a frame A][frame B][frame B]...
+    //               ^ junk: the header here points into file B's data
+    //
+    // Joining two LAME files keeps both Xing/Info frames, and joining files
+    // at different sample rates changes the format mid-stream. A decoder
+    // plays through all of it, so the only place it shows is the structure.
+    // ==========================================================================
+
+    /// MPEG-1 Layer III 128 kbps: 417 bytes at 44.1 kHz, 384 at 48 kHz
+    const HEADER_44K: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+    const HEADER_48K: [u8; 4] = [0xFF, 0xFB, 0x94, 0x00];
+
+    fn frames(header: [u8; 4], count: usize) -> Vec<u8> {
+        let size = FrameHeader::parse(header).unwrap().frame_size as usize;
+        let mut data = Vec::new();
+        for _ in 0..count {
+            data.extend_from_slice(&header);
+            data.extend_from_slice(&vec![0x11; size - 4]);
+        }
+        data
+    }
+
+    /// Helper: a LAME-style first frame with an Info tag after the side info
+    fn info_frame() -> Vec<u8> {
+        let mut frame = frames(HEADER_44K, 1);
+        frame[36..40].copy_from_slice(b"Info");
+        frame
+    }
+
+    #[test]
+    fn test_clean_stream() {
+        let mut data = info_frame();
+        data.extend_from_slice(&frames(HEADER_44K, 99));
+        let structure = walk(&data);
+        assert_eq!(structure.frames, 100);
+        assert!((structure.duration_secs - 100.0 * 1152.0 / 44100.0).abs() < 1e-9);
+        assert_eq!(
+            (
+                structure.leading_bytes,
+                structure.junk_bytes,
+                structure.trailing_bytes
+            ),
+            (0, 0, 0)
+        );
+        assert!(!structure.truncated);
+        assert!(structure.flags().is_empty(), "Got {:?}", structure.flags());
+    }
+
+    #[test]
+    fn test_splice_leaves_junk() {
+        // File A cut mid-frame, then file B
+        let mut data = frames(HEADER_44K, 20);
+        let cut = data.len();
+        data.extend_from_slice(&frames(HEADER_44K, 1)[..200]);
+        data.extend_from_slice(&frames(HEADER_44K, 20));
+
+        let structure = walk(&data);
+        assert_eq!(structure.frames, 40);
+        assert_eq!(structure.sync_losses.len(), 1);
+        let loss = &structure.sync_losses[0];
+        assert_eq!(
+            (loss.offset, loss.resync_offset, loss.junk_bytes),
+            (cut as u64, cut as u64 + 200, 200)
+        );
+        assert!((loss.time_secs - 20.0 * 1152.0 / 44100.0).abs() < 1e-9);
+        assert_eq!(
+            structure.flags(),
+            vec!["sync_lost_x1(junk=200B)".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_garbage_after_a_complete_frame() {
+        // The frame before the junk ends cleanly and still counts
+        let mut data = frames(HEADER_44K, 10);
+        data.extend_from_slice(&[0x55; 64]);
+        data.extend_from_slice(&frames(HEADER_44K, 10));
+        data.extend_from_slice(&[0x55; 30]);
+
+        let structure = walk(&data);
+        assert_eq!(structure.frames, 20);
+        assert_eq!(structure.junk_bytes, 64);
+        assert_eq!(structure.trailing_bytes, 30);
+    }
+
+    #[test]
+    fn test_joined_files_change_format() {
+        // Two LAME files at different sample rates, joined end to end
+        let mut data = info_frame();
+        data.extend_from_slice(&frames(HEADER_44K, 9));
+        let joint = data.len() as u64;
+        let mut second = frames(HEADER_48K, 10);
+        second[36..40].copy_from_slice(b"Info");
+        data.extend_from_slice(&second);
+
+        let structure = walk(&data);
+        assert_eq!(structure.frames, 20);
+        assert!(structure.sync_losses.is_empty());
+        assert_eq!(structure.format_changes.len(), 1);
+        assert_eq!(structure.format_changes[0].offset, joint);
+        assert_eq!(structure.embedded_headers, vec![joint]);
+        assert_eq!(
+            structure.flags(),
+            vec![
+                "format_change(MPEG-1 Layer III 44100 Hz→MPEG-1 Layer III 48000 Hz@0.3s)"
+                    .to_string(),
+                "concatenated_streams(2)".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_truncated_last_frame() {
+        let mut data = frames(HEADER_44K, 10);
+        data.truncate(data.len() - 100);
+        let structure = walk(&data);
+        assert_eq!(structure.frames, 9);
+        assert!(structure.truncated);
+        assert!(structure.sync_losses.is_empty());
+        assert_eq!(structure.trailing_bytes, 317);
+        assert_eq!(structure.flags(), vec!["stream_truncated".to_string()]);
+    }
+
+    #[test]
+    fn test_crc_errors() {
+        // Protected frames (protection bit clear), each with a correct CRC
+        const PROTECTED_44K: [u8; 4] = [0xFF, 0xFA, 0x90, 0x00];
+        let mut data = frames(PROTECTED_44K, 20);
+        for frame in data.chunks_mut(417) {
+            let crc = mpeg_crc16(mpeg_crc16(0xFFFF, &frame[2..4], 16), &frame[6..38], 256);
+            frame[4..6].copy_from_slice(&crc.to_be_bytes());
+        }
+        assert!(walk(&data).crc_errors.is_empty());
+
+        // Flipped bits in the side info of frames 5 and 12
+        data[5 * 417 + 10] ^= 0x04;
+        data[12 * 417 + 30] ^= 0x80;
+        let structure = walk(&data);
+        assert_eq!(structure.frames, 20);
+        assert_eq!(structure.crc_frames, 20);
+        let offsets: Vec<u64> = structure.crc_errors.iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, vec![5 * 417, 12 * 417]);
+        assert_eq!(structure.flags(), vec!["crc_errors_x2(checked=20)".to_string()]);
+
+        // Unprotected frames have nothing to check
+        assert_eq!(walk(&frames(HEADER_44K, 5)).crc_frames, 0);
+    }
+
+    #[test]
+    fn test_no_crc_errors_without_a_stream() {
+        // Quiet PCM is full of headers with the protection bit clear
+        let mut data: Vec<u8> = TestNoise::new(50)
+            .samples(400_000, 300.0)
+            .iter()
+            .flat_map(|&s| (s as i16).to_le_bytes())
+            .collect();
+        // And one protected frame, with a CRC that can't match, ending at the last byte
+        data.extend_from_slice(&frames([0xFF, 0xFA, 0x90, 0x00], 1));
+        let structure = walk(&data);
+        assert_eq!((structure.frames, structure.crc_frames), (0, 0));
+        assert!(structure.crc_errors.is_empty());
+    }
+
+    #[test]
+    fn test_covers_audio() {
+        assert!(walk(&frames(HEADER_44K, 10)).covers_audio());
+
+        // A stripped tag's leftovers before the frames are fine
+        let mut data = vec![0x00; 1000];
+        data.extend_from_slice(&frames(HEADER_44K, 10));
+        assert!(walk(&data).covers_audio());
+
+        // A few frames at the end of something else are not the file's audio
+        let mut data = vec![0x00; 50_000];
+        data.extend_from_slice(&frames(HEADER_44K, 10));
+        let structure = walk(&data);
+        assert_eq!(structure.frames, 10);
+        assert!(!structure.covers_audio());
+    }
+
+    #[test]
+    fn test_lone_header_is_not_a_stream() {
+        // One header whose frame happens to end at the last byte
+        let mut data = vec![0x00; 5000];
+        data.extend_from_slice(&frames(HEADER_44K, 1));
+        let structure = walk(&data);
+        assert_eq!(structure.frames, 0);
+        assert_eq!(structure.leading_bytes, data.len() as u64);
+        assert!(structure.flags().is_empty());
+
+        // A short run at the very end still counts
+        data.extend_from_slice(&frames(HEADER_44K, 1));
+        assert_eq!(walk(&data).frames, 2);
+    }
+
+    #[test]
+    fn test_pcm_is_not_a_stream() {
+        // Quiet 16-bit PCM: every small negative sample has a 0xFF high byte,
+        // so sync words turn up everywhere, many of them free format headers
+        // that measure up to the next lookalike. Their sizes never hold
+        let pcm: Vec<u8> = TestNoise::new(43)
+            .samples(400_000, 300.0)
+            .iter()
+            .flat_map(|&s| (s as i16).to_le_bytes())
+            .collect();
+        let structure = walk(&pcm);
+        assert_eq!(structure.frames, 0);
+        assert!(structure.flags().is_empty(), "Got {:?}", structure.flags());
+    }
+}
@@ -0,0 +1,1147 @@
+//! ID3v1, ID3v2, APEv2 and Lyrics3 tag reading
+//!
+//! Tags wrap the audio frames at both ends of an MP3 file:
+//!
+//! ```text
+//! [ID3v2]... [frames ...] [APEv2] [Lyrics3] [ID3v2.4 appended] [ID3v1]
+//!  start                   trailing tags, peeled off from the end
+//! ```
+//!
+//! The locator finds where each tag sits and so where the audio starts and
+//! ends. Frame scanning stays inside that range: a cover image in an APE tag
+//! is full of bytes that look like frame sync.
+//!
+//! The text the tags carry is useful evidence. TSSE records the encoder
+//! settings, TENC who or what encoded the file, TLEN the length in
+//! milliseconds, and comments often say "Ripped by" or "Converted by". A
+//! tag that disagrees with the stream it is attached to was written for a
+//! different encode:
+//!
+//! - TSSE naming a LAME version or preset the LAME tag doesn't
+//! - TLEN far from the measured duration
+//! - Tags in the middle of the audio (files concatenated or spliced)
+
+use serde::Serialize;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+use super::lame::LameTag;
+
+/// ID3v2 header and footer size
+const ID3V2_HEADER_LEN: u64 = 10;
+
+/// ID3v1 tag size
+const ID3V1_LEN: u64 = 128;
+
+/// Extended ID3v1 ("TAG+") block that precedes ID3v1
+const ID3V1_ENHANCED_LEN: u64 = 227;
+
+/// APEv2 header and footer size
+const APE_FOOTER_LEN: u64 = 32;
+
+/// Lyrics3v2 trailer: 6-digit size + "LYRICS200"
+const LYRICS3V2_TRAILER_LEN: u64 = 15;
+
+/// Lyrics3v1 holds at most 5100 bytes of lyrics
+const LYRICS3V1_MAX_LEN: u64 = 5100 + 11 + 9;
+
+/// TLEN may be off by a frame or two of rounding, plus encoder delay
+const TLEN_TOLERANCE_SECS: f64 = 1.0;
+
+/// Relative TLEN tolerance for long files
+const TLEN_TOLERANCE_RATIO: f64 = 0.01;
+
+/// Comment phrases that say the file came from another lossy file
+const CONVERSION_PHRASES: [&str; 5] = ["convert", "transcod", "re-encod", "reencod", "upsampl"];
+
+/// Comment phrases that say how or by whom the file was made
+const PROVENANCE_PHRASES: [&str; 6] = [
+    "ripped",
+    "rip by",
+    "encoded by",
+    "created by",
+    "made by",
+    "converted",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagKind {
+    Id3v2,
+    Id3v1,
+    Ape,
+    Lyrics3,
+}
+
+impl TagKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TagKind::Id3v2 => "id3v2",
+            TagKind::Id3v1 => "id3v1",
+            TagKind::Ape => "ape",
+            TagKind::Lyrics3 => "lyrics3",
+        }
+    }
+}
+
+/// Where one tag sits in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TagLocation {
+    pub kind: TagKind,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Tag positions and the audio range between them
+#[derive(Debug, Clone, Default)]
+pub struct TagLayout {
+    pub tags: Vec<TagLocation>,
+    pub audio: Range<u64>,
+    /// Trailing tags found out of their usual order (e.g. APE after ID3v1)
+    pub out_of_order: Vec<TagLocation>,
+}
+
+/// One text field: an ID3v2 text frame, comment or TXXX, or an APE/Lyrics3 item
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TagField {
+    pub id: String,
+    /// COMM/TXXX description
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Id3v2Tag {
+    /// "2.3.0", "2.4.0", ...
+    pub version: String,
+    pub offset: u64,
+    /// Total size including header and footer
+    pub size: u64,
+    pub unsynchronised: bool,
+    pub extended_header: bool,
+    pub footer: bool,
+    pub fields: Vec<TagField>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Id3v1Tag {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub year: String,
+    pub comment: String,
+    /// ID3v1.1 track number
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track: Option<u8>,
+    pub genre: u8,
+    /// Preceded by an extended "TAG+" block
+    pub enhanced: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ApeTag {
+    /// 1000 (APEv1) or 2000 (APEv2)
+    pub version: u32,
+    pub offset: u64,
+    pub size: u64,
+    /// Text items (binary items such as cover art are skipped)
+    pub fields: Vec<TagField>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Lyrics3Tag {
+    /// 1 or 2
+    pub version: u8,
+    pub offset: u64,
+    pub size: u64,
+    /// Lyrics3v2 fields (IND, LYR, INF, AUT, ...)
+    pub fields: Vec<TagField>,
+}
+
+/// Every tag in a file and what they say about its history
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TagSet {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub id3v2: Vec<Id3v2Tag>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id3v1: Option<Id3v1Tag>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ape: Option<ApeTag>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lyrics3: Option<Lyrics3Tag>,
+    /// TSSE: encoder and settings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoder_settings: Option<String>,
+    /// TENC: who or what encoded the file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoded_by: Option<String>,
+    /// TLEN: length in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length_ms: Option<u64>,
+    /// Comments that mention ripping, encoding or conversion
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub provenance: Vec<String>,
+    /// Tags found inside the audio, or out of order at the end
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub misplaced: Vec<TagLocation>,
+    /// Where the audio frames start and end
+    pub audio_start: u64,
+    pub audio_end: u64,
+}
+
+impl TagSet {
+    pub fn is_empty(&self) -> bool {
+        self.id3v2.is_empty()
+            && self.id3v1.is_none()
+            && self.ape.is_none()
+            && self.lyrics3.is_none()
+            && self.misplaced.is_empty()
+    }
+
+    /// All text fields from ID3v2, APE and Lyrics3, in file order
+    pub fn fields(&self) -> impl Iterator<Item = &TagField> {
+        self.id3v2
+            .iter()
+            .flat_map(|tag| tag.fields.iter())
+            .chain(self.ape.iter().flat_map(|tag| tag.fields.iter()))
+            .chain(self.lyrics3.iter().flat_map(|tag| tag.fields.iter()))
+    }
+
+    /// Compare the tags with the stream they are attached to.
+    ///
+    /// `lame_version` is the LAME header's encoder string ("LAME3.100"),
+    /// `duration_secs` the measured stream duration when it is known exactly.
+    pub fn cross_check(
+        &self,
+        lame_version: Option<&str>,
+        lame_tag: Option<&LameTag>,
+        duration_secs: Option<f64>,
+    ) -> Vec<String> {
+        let mut flags = Vec::new();
+
+        if let Some(ref tsse) = self.encoder_settings {
+            let stream_version =
+                lame_version.and_then(|v| lame_version_number(v.strip_prefix("LAME")?));
+            let tag_version = tsse_lame_version(tsse);
+            if let (Some(tag), Some(stream)) = (tag_version, stream_version) {
+                if tag != stream {
+                    flags.push(format!("tsse_lame_mismatch(tag={},stream={})", tag, stream));
+                }
+            }
+            if let (Some(settings), Some(lame_tag)) = (tsse_settings(tsse), lame_tag) {
+                if let Some(stream) = settings.stream_value(lame_tag) {
+                    if settings.value != stream {
+                        flags.push(format!(
+                            "tsse_settings_mismatch(tag={},stream={})",
+                            settings.value, stream
+                        ));
+                    }
+                }
+            }
+        }
+
+        if let (Some(length_ms), Some(measured)) = (self.length_ms, duration_secs) {
+            let declared = length_ms as f64 / 1000.0;
+            let tolerance = TLEN_TOLERANCE_SECS.max(measured * TLEN_TOLERANCE_RATIO);
+            if (declared - measured).abs() > tolerance {
+                flags.push(format!(
+                    "tlen_mismatch(tag={:.1}s,measured={:.1}s)",
+                    declared, measured
+                ));
+            }
+        }
+
+        for location in &self.misplaced {
+            flags.push(format!(
+                "tag_misplaced({}@{})",
+                location.kind.as_str(),
+                location.offset
+            ));
+        }
+
+        let lowered = self.provenance.iter().map(|note| note.to_lowercase());
+        if lowered
+            .into_iter()
+            .any(|note| CONVERSION_PHRASES.iter().any(|p| note.contains(p)))
+        {
+            flags.push("tag_mentions_conversion".to_string());
+        }
+        flags
+    }
+}
+
+// =============================================================================
+// LOCATING TAGS
+// =============================================================================
+
+/// Where the audio frames start and end, skipping tags at both ends
+pub fn audio_range<R: Read + Seek>(reader: &mut R) -> io::Result<Range<u64>> {
+    Ok(locate(reader)?.audio)
+}
+
+/// Find every tag at the start and end of the file
+pub fn locate<R: Read + Seek>(reader: &mut R) -> io::Result<TagLayout> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let mut layout = TagLayout::default();
+
+    // Leading ID3v2 tags, possibly more than one
+    let mut start = 0u64;
+    while start + ID3V2_HEADER_LEN <= len {
+        let header = read_at(reader, start, ID3V2_HEADER_LEN as usize)?;
+        let Some(size) = id3v2_size(&header, b"ID3") else {
+            break;
+        };
+        if start + size > len {
+            break;
+        }
+        layout.tags.push(TagLocation {
+            kind: TagKind::Id3v2,
+            offset: start,
+            size,
+        });
+        start += size;
+    }
+
+    // Trailing tags, peeled off the end one at a time
+    let mut end = len;
+    while end > start {
+        let Some(location) = trailing_tag(reader, start, end)? else {
+            break;
+        };
+        // ID3v1 is always last: one found further in means something was appended after it
+        if location.kind == TagKind::Id3v1 && end != len {
+            layout.out_of_order.push(location);
+        }
+        layout.tags.push(location);
+        end = location.offset;
+    }
+
+    layout.audio = start..end;
+    Ok(layout)
+}
+
+/// The tag that ends at `end`, if any
+fn trailing_tag<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    end: u64,
+) -> io::Result<Option<TagLocation>> {
+    let available = end - start;
+    let location = |kind, offset: u64| TagLocation {
+        kind,
+        offset,
+        size: end - offset,
+    };
+
+    if available >= ID3V1_LEN && read_at(reader, end - ID3V1_LEN, 3)? == b"TAG" {
+        let enhanced = available >= ID3V1_LEN + ID3V1_ENHANCED_LEN
+            && read_at(reader, end - ID3V1_LEN - ID3V1_ENHANCED_LEN, 4)? == b"TAG+";
+        let offset = end - ID3V1_LEN - if enhanced { ID3V1_ENHANCED_LEN } else { 0 };
+        return Ok(Some(location(TagKind::Id3v1, offset)));
+    }
+
+    if available >= APE_FOOTER_LEN {
+        let footer = read_at(reader, end - APE_FOOTER_LEN, APE_FOOTER_LEN as usize)?;
+        if footer.starts_with(b"APETAGEX") {
+            let size = u32_le(&footer[12..16]) as u64;
+            let has_header = u32_le(&footer[20..24]) & 0x8000_0000 != 0;
+            let total = size + if has_header { APE_FOOTER_LEN } else { 0 };
+            if total >= APE_FOOTER_LEN && total <= available {
+                return Ok(Some(location(TagKind::Ape, end - total)));
+            }
+        }
+    }
+
+    if available >= LYRICS3V2_TRAILER_LEN {
+        let trailer = read_at(
+            reader,
+            end - LYRICS3V2_TRAILER_LEN,
+            LYRICS3V2_TRAILER_LEN as usize,
+        )?;
+        if &trailer[6..] == b"LYRICS200" {
+            let size = std::str::from_utf8(&trailer[..6])
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok());
+            if let Some(size) = size.filter(|&size| size + LYRICS3V2_TRAILER_LEN <= available) {
+                let offset = end - LYRICS3V2_TRAILER_LEN - size;
+                if read_at(reader, offset, 11)? == b"LYRICSBEGIN" {
+                    return Ok(Some(location(TagKind::Lyrics3, offset)));
+                }
+            }
+        } else if &trailer[6..] == b"LYRICSEND" {
+            let window = LYRICS3V1_MAX_LEN.min(available);
+            let tail = read_at(reader, end - window, window as usize)?;
+            if let Some(pos) = find(&tail, b"LYRICSBEGIN") {
+                return Ok(Some(location(TagKind::Lyrics3, end - window + pos as u64)));
+            }
+        }
+    }
+
+    // ID3v2.4 appended to the end, found by its footer
+    if available >= 2 * ID3V2_HEADER_LEN {
+        let footer = read_at(reader, end - ID3V2_HEADER_LEN, ID3V2_HEADER_LEN as usize)?;
+        if let Some(size) = id3v2_size(&footer, b"3DI").filter(|&size| size <= available) {
+            if read_at(reader, end - size, 3)? == b"ID3" {
+                return Ok(Some(location(TagKind::Id3v2, end - size)));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Total ID3v2 tag size (header, body and footer) from a 10-byte header or footer
+pub fn id3v2_size(header: &[u8], magic: &[u8; 3]) -> Option<u64> {
+    if header.len() < ID3V2_HEADER_LEN as usize || &header[..3] != magic {
+        return None;
+    }
+    let (major, revision, flags) = (header[3], header[4], header[5]);
+    if !(2..=4).contains(&major) || revision == 0xFF || header[6..10].iter().any(|&b| b >= 0x80) {
+        return None;
+    }
+    let footer = major == 4 && flags & 0x10 != 0;
+    Some(
+        ID3V2_HEADER_LEN
+            + syncsafe(&header[6..10]) as u64
+            + if footer { ID3V2_HEADER_LEN } else { 0 },
+    )
+}
+
+// =============================================================================
+// PARSING
+// =============================================================================
+
+/// Read every tag in `data` and collect the forensic fields
+pub fn read_tags(data: &[u8]) -> TagSet {
+    let Ok(layout) = locate(&mut io::Cursor::new(data)) else {
+        return TagSet::default();
+    };
+    let mut set = TagSet {
+        audio_start: layout.audio.start,
+        audio_end: layout.audio.end,
+        misplaced: layout.out_of_order.clone(),
+        ..Default::default()
+    };
+
+    for location in &layout.tags {
+        let bytes = &data[location.offset as usize..(location.offset + location.size) as usize];
+        match location.kind {
+            TagKind::Id3v2 => set.id3v2.extend(parse_id3v2(bytes, location.offset)),
+            TagKind::Id3v1 => set.id3v1 = parse_id3v1(bytes),
+            TagKind::Ape => set.ape = parse_ape(bytes, location.offset),
+            TagKind::Lyrics3 => set.lyrics3 = Some(parse_lyrics3(bytes, location.offset)),
+        }
+    }
+    // Leading tags were pushed first, appended ones last: keep file order
+    set.id3v2.sort_by_key(|tag| tag.offset);
+
+    set.misplaced
+        .extend(find_embedded_tags(data, &layout.audio));
+
+    set.encoder_settings = first_field(&set, &["TSSE", "Encoder"]);
+    set.encoded_by = first_field(&set, &["TENC", "Encoded By"]);
+    set.length_ms = first_field(&set, &["TLEN"]).and_then(|v| v.trim().parse().ok());
+
+    let comments = set
+        .fields()
+        .filter(|f| {
+            ["COMM", "TXXX", "Comment", "INF"]
+                .iter()
+                .any(|id| f.id.eq_ignore_ascii_case(id))
+        })
+        .map(|f| f.value.clone())
+        .chain(set.id3v1.iter().map(|tag| tag.comment.clone()))
+        .chain(set.encoded_by.clone());
+    let mut provenance: Vec<String> = Vec::new();
+    for comment in comments {
+        let lowered = comment.to_lowercase();
+        let notable = PROVENANCE_PHRASES
+            .iter()
+            .chain(&CONVERSION_PHRASES)
+            .any(|p| lowered.contains(p));
+        if notable && !provenance.contains(&comment) {
+            provenance.push(comment);
+        }
+    }
+    set.provenance = provenance;
+    set
+}
+
+/// First non-empty value of any of the field IDs (ID3v2 frame or APE key)
+fn first_field(set: &TagSet, ids: &[&str]) -> Option<String> {
+    set.fields()
+        .find(|f| ids.iter().any(|id| f.id.eq_ignore_ascii_case(id)) && !f.value.is_empty())
+        .map(|f| f.value.clone())
+}
+
+/// Tag headers inside the audio: files joined together, or a tag written in the wrong place
+fn find_embedded_tags(data: &[u8], audio: &Range<u64>) -> Vec<TagLocation> {
+    let audio_bytes = &data[audio.start as usize..audio.end as usize];
+    let mut found = Vec::new();
+    for (i, window) in audio_bytes.windows(ID3V2_HEADER_LEN as usize).enumerate() {
+        let offset = audio.start + i as u64;
+        if let Some(size) = id3v2_size(window, b"ID3") {
+            // Flags other than the defined ones are never set in a real header
+            if window[5] & 0x0F == 0 {
+                found.push(TagLocation {
+                    kind: TagKind::Id3v2,
+                    offset,
+                    size,
+                });
+            }
+        } else if window[..8] == *b"APETAGEX" {
+            found.push(TagLocation {
+                kind: TagKind::Ape,
+                offset,
+                size: APE_FOOTER_LEN,
+            });
+        }
+    }
+    found
+}
+
+fn parse_id3v2(bytes: &[u8], offset: u64) -> Option<Id3v2Tag> {
+    let (major, revision, flags) = (bytes[3], bytes[4], bytes[5]);
+    let unsynchronised = flags & 0x80 != 0;
+    // In ID3v2.2 bit 6 means compression, which nobody implemented
+    if major == 2 && flags & 0x40 != 0 {
+        return None;
+    }
+    let mut tag = Id3v2Tag {
+        version: format!("2.{}.{}", major, revision),
+        offset,
+        size: bytes.len() as u64,
+        unsynchronised,
+        extended_header: major >= 3 && flags & 0x40 != 0,
+        footer: major == 4 && flags & 0x10 != 0,
+        fields: Vec::new(),
+    };
+
+    let body_end = ID3V2_HEADER_LEN as usize + syncsafe(&bytes[6..10]) as usize;
+    let mut body = bytes[ID3V2_HEADER_LEN as usize..body_end.min(bytes.len())].to_vec();
+    // Before 2.4 unsynchronisation applies to the whole tag; in 2.4 to each frame
+    if unsynchronised && major < 4 {
+        body = resynchronise(&body);
+    }
+
+    let mut pos = 0;
+    if tag.extended_header && body.len() >= 4 {
+        pos = match major {
+            3 => 4 + u32::from_be_bytes([body[0], body[1], body[2], body[3]]) as usize,
+            _ => syncsafe(&body[..4]) as usize,
+        };
+    }
+
+    let (id_len, header_len) = if major == 2 { (3, 6) } else { (4, 10) };
+    while pos + header_len <= body.len() {
+        let header = &body[pos..pos + header_len];
+        // Padding
+        if header[0] == 0 {
+            break;
+        }
+        let id = String::from_utf8_lossy(&header[..id_len]).into_owned();
+        let size = match major {
+            2 => u32::from_be_bytes([0, header[3], header[4], header[5]]) as usize,
+            3 => u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize,
+            _ => syncsafe(&header[4..8]) as usize,
+        };
+        let start = pos + header_len;
+        if start + size > body.len() {
+            break;
+        }
+        pos = start + size;
+        let format = if major == 2 { 0 } else { header[9] };
+        if let Some(data) = frame_data(&body[start..start + size], major, format, unsynchronised) {
+            tag.fields
+                .extend(parse_text_frame(&normalise_frame_id(&id), &data));
+        }
+    }
+    Some(tag)
+}
+
+/// Frame payload with grouping/length prefixes and unsynchronisation removed.
+/// Compressed and encrypted frames are skipped.
+fn frame_data(data: &[u8], major: u8, format: u8, tag_unsynchronised: bool) -> Option<Vec<u8>> {
+    let (skip, unsync) = match major {
+        3 => {
+            if format & 0xC0 != 0 {
+                return None;
+            }
+            ((format & 0x20 != 0) as usize, false)
+        }
+        4 => {
+            if format & 0x0C != 0 {
+                return None;
+            }
+            let skip = (format & 0x40 != 0) as usize + if format & 0x01 != 0 { 4 } else { 0 };
+            (skip, tag_unsynchronised || format & 0x02 != 0)
+        }
+        _ => (0, false),
+    };
+    let data = data.get(skip..)?;
+    Some(if unsync {
+        resynchronise(data)
+    } else {
+        data.to_vec()
+    })
+}
+
+/// ID3v2.2 three-letter IDs for the frames we read
+fn normalise_frame_id(id: &str) -> String {
+    let long = match id {
+        "TT2" => "TIT2",
+        "TP1" => "TPE1",
+        "TAL" => "TALB",
+        "TYE" => "TYER",
+        "TCO" => "TCON",
+        "TRK" => "TRCK",
+        "TSS" => "TSSE",
+        "TEN" => "TENC",
+        "TLE" => "TLEN",
+        "TXX" => "TXXX",
+        "COM" => "COMM",
+        _ => id,
+    };
+    long.to_string()
+}
+
+fn parse_text_frame(id: &str, data: &[u8]) -> Option<TagField> {
+    let (&encoding, rest) = data.split_first()?;
+    let (description, value) = match id {
+        "COMM" => {
+            let (description, value) = split_terminated(encoding, rest.get(3..)?);
+            (Some(decode_text(encoding, description)), value)
+        }
+        "TXXX" => {
+            let (description, value) = split_terminated(encoding, rest);
+            (Some(decode_text(encoding, description)), value)
+        }
+        _ if id.starts_with('T') => (None, rest),
+        _ => return None,
+    };
+    Some(TagField {
+        id: id.to_string(),
+        description: description.filter(|d| !d.is_empty()),
+        value: decode_text(encoding, value),
+    })
+}
+
+/// Split at the first string terminator (one zero byte, or two for UTF-16)
+fn split_terminated(encoding: u8, data: &[u8]) -> (&[u8], &[u8]) {
+    if encoding == 1 || encoding == 2 {
+        let end = data.chunks_exact(2).position(|pair| pair == [0, 0]);
+        match end {
+            Some(i) => (&data[..2 * i], &data[2 * i + 2..]),
+            None => (data, &[]),
+        }
+    } else {
+        match data.iter().position(|&b| b == 0) {
+            Some(i) => (&data[..i], &data[i + 1..]),
+            None => (data, &[]),
+        }
+    }
+}
+
+/// Decode ID3v2 text; multiple values (2.4) are joined with " / "
+fn decode_text(encoding: u8, data: &[u8]) -> String {
+    let text = match encoding {
+        1 | 2 => {
+            let mut big_endian = encoding == 2;
+            let mut units = Vec::with_capacity(data.len() / 2);
+            for pair in data.chunks_exact(2) {
+                match (pair[0], pair[1]) {
+                    (0xFE, 0xFF) => big_endian = true,
+                    (0xFF, 0xFE) => big_endian = false,
+                    (a, b) => units.push(if big_endian {
+                        u16::from_be_bytes([a, b])
+                    } else {
+                        u16::from_le_bytes([a, b])
+                    }),
+                }
+            }
+            String::from_utf16_lossy(&units)
+        }
+        3 => String::from_utf8_lossy(data).into_owned(),
+        _ => latin1(data),
+    };
+    let values: Vec<&str> = text
+        .split('\0')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect();
+    values.join(" / ")
+}
+
+fn parse_id3v1(bytes: &[u8]) -> Option<Id3v1Tag> {
+    let enhanced = bytes.len() as u64 > ID3V1_LEN;
+    let tag = &bytes[bytes.len() - ID3V1_LEN as usize..];
+    let text = |range: Range<usize>| {
+        latin1(&tag[range])
+            .trim_end_matches(['\0', ' '])
+            .to_string()
+    };
+    // ID3v1.1: a zero byte before the last comment byte makes it a track number
+    let track = (tag[125] == 0 && tag[126] != 0).then_some(tag[126]);
+    Some(Id3v1Tag {
+        title: text(3..33),
+        artist: text(33..63),
+        album: text(63..93),
+        year: text(93..97),
+        comment: if track.is_some() {
+            text(97..125)
+        } else {
+            text(97..127)
+        },
+        track,
+        genre: tag[127],
+        enhanced,
+    })
+}
+
+fn parse_ape(bytes: &[u8], offset: u64) -> Option<ApeTag> {
+    let footer = &bytes[bytes.len() - APE_FOOTER_LEN as usize..];
+    let version = u32_le(&footer[8..12]);
+    let count = u32_le(&footer[16..20]);
+    let has_header = u32_le(&footer[20..24]) & 0x8000_0000 != 0;
+    let mut tag = ApeTag {
+        version,
+        offset,
+        size: bytes.len() as u64,
+        fields: Vec::new(),
+    };
+
+    let items = &bytes[if has_header {
+        APE_FOOTER_LEN as usize
+    } else {
+        0
+    }..bytes.len() - APE_FOOTER_LEN as usize];
+    let mut pos = 0;
+    for _ in 0..count {
+        let Some(header) = items.get(pos..pos + 8) else {
+            break;
+        };
+        let (size, flags) = (u32_le(&header[..4]) as usize, u32_le(&header[4..8]));
+        let Some(key_len) = items[pos + 8..].iter().position(|&b| b == 0) else {
+            break;
+        };
+        let key = String::from_utf8_lossy(&items[pos + 8..pos + 8 + key_len]).into_owned();
+        let start = pos + 8 + key_len + 1;
+        let Some(value) = items.get(start..start + size) else {
+            break;
+        };
+        // Bits 1-2: 0 = UTF-8 text, 1 = binary, 2 = external link
+        if (flags >> 1) & 0x03 == 0 {
+            tag.fields.push(TagField {
+                id: key,
+                description: None,
+                value: decode_text(3, value),
+            });
+        }
+        pos = start + size;
+    }
+    Some(tag)
+}
+
+fn parse_lyrics3(bytes: &[u8], offset: u64) -> Lyrics3Tag {
+    let mut tag = Lyrics3Tag {
+        version: 1,
+        offset,
+        size: bytes.len() as u64,
+        fields: Vec::new(),
+    };
+    if !bytes.ends_with(b"LYRICS200") {
+        return tag;
+    }
+    tag.version = 2;
+    // LYRICSBEGIN, then fields of ID (3) + size (5 digits) + data
+    let fields = &bytes[11..bytes.len() - LYRICS3V2_TRAILER_LEN as usize];
+    let mut pos = 0;
+    while pos + 8 <= fields.len() {
+        let id = String::from_utf8_lossy(&fields[pos..pos + 3]).into_owned();
+        let size = std::str::from_utf8(&fields[pos + 3..pos + 8])
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok());
+        let Some(value) = size.and_then(|size| fields.get(pos + 8..pos + 8 + size)) else {
+            break;
+        };
+        tag.fields.push(TagField {
+            id,
+            description: None,
+            value: latin1(value),
+        });
+        pos += 8 + value.len();
+    }
+    tag
+}
+
+// =============================================================================
+// TSSE
+// =============================================================================
+
+/// Encoder settings named in TSSE, and what the LAME tag says for the same setting
+struct TsseSettings {
+    value: String,
+}
+
+impl TsseSettings {
+    /// The LAME tag's value for the same kind of setting, if it records one
+    fn stream_value(&self, tag: &LameTag) -> Option<String> {
+        let preset = tag.preset.as_deref();
+        if self.value.starts_with('V') {
+            preset.filter(|p| p.starts_with('V')).map(str::to_string)
+        } else if self.value.ends_with("kbps") {
+            (tag.bitrate > 0).then(|| format!("{}kbps", tag.bitrate))
+        } else {
+            preset
+                .filter(|p| p.chars().all(|c| c.is_ascii_alphabetic() || c == ' ') && p.len() > 2)
+                .map(str::to_string)
+        }
+    }
+}
+
+/// "-V 2", "-b320", "--preset extreme" → V2 / 320kbps / extreme
+fn tsse_settings(tsse: &str) -> Option<TsseSettings> {
+    let words: Vec<&str> = tsse.split_whitespace().collect();
+    for (i, word) in words.iter().enumerate() {
+        let argument = |flag: &str| {
+            let inline = word.strip_prefix(flag).filter(|rest| !rest.is_empty());
+            inline.or_else(|| (*word == flag).then(|| words.get(i + 1).copied()).flatten())
+        };
+        if let Some(quality) = argument("-V").and_then(|v| v.parse::<u8>().ok()) {
+            return Some(TsseSettings {
+                value: format!("V{}", quality),
+            });
+        }
+        if let Some(kbps) = argument("-b").and_then(|v| v.parse::<u32>().ok()) {
+            return Some(TsseSettings {
+                value: format!("{}kbps", kbps),
+            });
+        }
+        if *word == "--preset" {
+            let name = words.get(i + 1)?;
+            return Some(TsseSettings {
+                value: match name.parse::<u32>() {
+                    Ok(kbps) => format!("{}kbps", kbps),
+                    Err(_) => name.to_lowercase(),
+                },
+            });
+        }
+    }
+    None
+}
+
+/// LAME version named in TSSE ("LAME 3.99.5", "LAME 64bits version 3.100")
+fn tsse_lame_version(tsse: &str) -> Option<String> {
+    let lowered = tsse.to_lowercase();
+    let after = &tsse[lowered.find("lame")? + 4..];
+    let start = after.find("3.")?;
+    lame_version_number(&after[start..])
+}
+
+/// "3.99r" / "3.99.5" / "3.100" → "3.99" / "3.99" / "3.100"
+fn lame_version_number(version: &str) -> Option<String> {
+    let minor = version.trim_start().strip_prefix("3.")?;
+    let digits: String = minor.chars().take_while(|c| c.is_ascii_digit()).collect();
+    (!digits.is_empty()).then(|| format!("3.{}", digits))
+}
+
+// =============================================================================
+// HELPERS
+// =============================================================================
+
+fn read_at<R: Read + Seek>(reader: &mut R, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |acc, &b| (acc << 7) | (b & 0x7F) as u32)
+}
+
+fn u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Undo unsynchronisation: every 0xFF 0x00 was written for a 0xFF
+fn resynchronise(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut previous = 0u8;
+    for &byte in data {
+        if !(previous == 0xFF && byte == 0x00) {
+            out.push(byte);
+        }
+        previous = byte;
+    }
+    out
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp3::frame;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: TAGS AS WITNESSES
+    // ==========================================================================
+    //
+    // Tags are written by whatever touched the file last: the ripper, the
+    // encoder front-end, the tagger. They are not proof of anything on their
+    // own, but they were written for a particular encode. When a TSSE says
+    // "LAME 3.99 -V 2" and the LAME tag in the first frame says 3.100 at
+    // 320 kbps, the tags belong to an earlier version of the file.
+    //
+    // Four formats are in use:
+    //   ID3v2   - at the start (2.4 may also be appended at the end)
+    //   ID3v1   - the last 128 bytes of the file
+    //   APEv2   - before ID3v1, common from foobar2000 and MP3Gain
+    //   Lyrics3 - between the audio and ID3v1, from old players
+    // ==========================================================================
+
+    /// MPEG-1 Layer III, 128 kbps, 44.1 kHz: 417-byte frames
+    fn frames(count: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        for _ in 0..count {
+            data.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+            data.extend_from_slice(&[0x11; 413]);
+        }
+        data
+    }
+
+    fn syncsafe_bytes(value: u32) -> [u8; 4] {
+        [
+            (value >> 21) as u8 & 0x7F,
+            (value >> 14) as u8 & 0x7F,
+            (value >> 7) as u8 & 0x7F,
+            value as u8 & 0x7F,
+        ]
+    }
+
+    /// Helper: ID3v2.3 tag from (frame ID, payload) pairs
+    fn id3v2_3(frames: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (id, payload) in frames {
+            body.extend_from_slice(id.as_bytes());
+            body.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            body.extend_from_slice(&[0, 0]);
+            body.extend_from_slice(payload);
+        }
+        body.extend_from_slice(&[0; 16]); // padding
+        let mut tag = b"ID3\x03\x00\x00".to_vec();
+        tag.extend_from_slice(&syncsafe_bytes(body.len() as u32));
+        tag.extend_from_slice(&body);
+        tag
+    }
+
+    /// Latin-1 text frame payload
+    fn text(value: &str) -> Vec<u8> {
+        let mut payload = vec![0];
+        payload.extend_from_slice(value.as_bytes());
+        payload
+    }
+
+    /// Helper: ID3v1.1 tag with a comment and track number
+    fn id3v1(comment: &str, track: u8) -> Vec<u8> {
+        let mut tag = vec![0u8; 128];
+        tag[..3].copy_from_slice(b"TAG");
+        tag[3..8].copy_from_slice(b"Title");
+        tag[97..97 + comment.len()].copy_from_slice(comment.as_bytes());
+        tag[126] = track;
+        tag[127] = 17;
+        tag
+    }
+
+    /// Helper: APEv2 tag with header and footer from (key, value, flags) items
+    fn ape(items: &[(&str, &[u8], u32)]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (key, value, flags) in items {
+            body.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            body.extend_from_slice(&flags.to_le_bytes());
+            body.extend_from_slice(key.as_bytes());
+            body.push(0);
+            body.extend_from_slice(value);
+        }
+        let block = |flags: u32| {
+            let mut block = b"APETAGEX".to_vec();
+            block.extend_from_slice(&2000u32.to_le_bytes());
+            block.extend_from_slice(&(body.len() as u32 + 32).to_le_bytes());
+            block.extend_from_slice(&(items.len() as u32).to_le_bytes());
+            block.extend_from_slice(&flags.to_le_bytes());
+            block.extend_from_slice(&[0; 8]);
+            block
+        };
+        let mut tag = block(0xA000_0000);
+        tag.extend_from_slice(&body);
+        tag.extend_from_slice(&block(0x8000_0000));
+        tag
+    }
+
+    #[test]
+    fn test_id3v2_3_fields() {
+        let mut comment = vec![0];
+        comment.extend_from_slice(b"eng\0Ripped by EAC 1.6");
+        let mut data = id3v2_3(&[
+            ("TIT2", text("Song")),
+            ("TSSE", text("LAME 3.99.5 -V 2")),
+            ("TLEN", text("215000")),
+            ("COMM", comment),
+        ]);
+        let tag_len = data.len() as u64;
+        data.extend_from_slice(&frames(4));
+
+        let set = read_tags(&data);
+        assert_eq!(set.id3v2.len(), 1);
+        assert_eq!(set.id3v2[0].version, "2.3.0");
+        assert_eq!(set.encoder_settings.as_deref(), Some("LAME 3.99.5 -V 2"));
+        assert_eq!(set.length_ms, Some(215000));
+        assert_eq!(set.provenance, vec!["Ripped by EAC 1.6".to_string()]);
+        assert_eq!(
+            (set.audio_start, set.audio_end),
+            (tag_len, data.len() as u64)
+        );
+    }
+
+    #[test]
+    fn test_id3v2_4_extended_header_footer_and_unsync() {
+        // Extended header (6 bytes, size includes itself), a footer, and a
+        // TENC frame with per-frame unsynchronisation: "A" 0xFF 0x00 "B" is "AÿB"
+        let payload = [0u8, b'A', 0xFF, 0x00, b'B'];
+        let mut body = vec![0, 0, 0, 6, 1, 0];
+        body.extend_from_slice(b"TENC");
+        body.extend_from_slice(&syncsafe_bytes(payload.len() as u32));
+        body.extend_from_slice(&[0, 0x02]);
+        body.extend_from_slice(&payload);
+
+        let mut data = b"ID3\x04\x00\x50".to_vec();
+        data.extend_from_slice(&syncsafe_bytes(body.len() as u32));
+        data.extend_from_slice(&body);
+        data.extend_from_slice(b"3DI\x04\x00\x50");
+        data.extend_from_slice(&syncsafe_bytes(body.len() as u32));
+        let tag_len = data.len() as u64;
+        data.extend_from_slice(&frames(2));
+
+        let set = read_tags(&data);
+        let tag = &set.id3v2[0];
+        assert!(tag.extended_header && tag.footer);
+        assert_eq!(tag.size, tag_len);
+        assert_eq!(set.encoded_by.as_deref(), Some("AÿB"));
+        assert_eq!(set.audio_start, tag_len);
+    }
+
+    #[test]
+    fn test_id3v2_2_three_letter_ids() {
+        let payload = text("LAME3.98");
+        let mut body = b"TSS".to_vec();
+        body.extend_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+        body.extend_from_slice(&payload);
+        let mut data = b"ID3\x02\x00\x00".to_vec();
+        data.extend_from_slice(&syncsafe_bytes(body.len() as u32));
+        data.extend_from_slice(&body);
+        data.extend_from_slice(&frames(2));
+
+        let set = read_tags(&data);
+        assert_eq!(set.id3v2[0].fields[0].id, "TSSE");
+        assert_eq!(set.encoder_settings.as_deref(), Some("LAME3.98"));
+    }
+
+    #[test]
+    fn test_trailing_tags_are_peeled_off() {
+        // Audio, then APEv2 (with a cover image full of fake sync), Lyrics3v2, ID3v1.1
+        let mut cover = Vec::new();
+        for _ in 0..50 {
+            cover.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+        }
+        let ape_tag = ape(&[
+            ("Comment", b"Converted by foobar2000", 0),
+            ("Cover Art (Front)", &cover, 1 << 1),
+        ]);
+        let mut lyrics = b"LYRICSBEGIN".to_vec();
+        lyrics.extend_from_slice(b"IND0000210INF00005notes");
+        let lyrics_size = format!("{:06}", lyrics.len());
+
+        let mut data = frames(10);
+        let audio_end = data.len() as u64;
+        data.extend_from_slice(&ape_tag);
+        data.extend_from_slice(&lyrics);
+        data.extend_from_slice(lyrics_size.as_bytes());
+        data.extend_from_slice(b"LYRICS200");
+        data.extend_from_slice(&id3v1("ripped by me", 7));
+
+        let set = read_tags(&data);
+        assert_eq!(set.audio_end, audio_end);
+        let id3v1 = set.id3v1.as_ref().expect("Should read ID3v1");
+        assert_eq!(
+            (id3v1.title.as_str(), id3v1.comment.as_str(), id3v1.track),
+            ("Title", "ripped by me", Some(7))
+        );
+        let ape = set.ape.as_ref().expect("Should read APE");
+        assert_eq!(ape.fields.len(), 1, "Binary items are skipped");
+        let lyrics3 = set.lyrics3.as_ref().expect("Should read Lyrics3");
+        assert_eq!(lyrics3.version, 2);
+        assert_eq!(lyrics3.fields[1].value, "notes");
+        assert!(set.misplaced.is_empty());
+        assert_eq!(
+            set.cross_check(None, None, None),
+            vec!["tag_mentions_conversion".to_string()]
+        );
+
+        // Frame scanning stops where the tags begin
+        let stats = frame::scan_frames(&mut io::Cursor::new(&data), 1000).unwrap();
+        assert_eq!(stats.frame_count, 10);
+    }
+
+    #[test]
+    fn test_cross_check_against_stream() {
+        let mut data = id3v2_3(&[
+            ("TSSE", text("LAME 3.99.5 -b 320")),
+            ("TLEN", text("240000")),
+        ]);
+        data.extend_from_slice(&frames(5));
+        // A second file's tag spliced into the middle of the audio
+        let spliced = data.len() as u64;
+        data.extend_from_slice(&id3v2_3(&[("TIT2", text("Other"))]));
+        data.extend_from_slice(&frames(5));
+
+        let set = read_tags(&data);
+        assert_eq!(
+            set.misplaced,
+            vec![TagLocation {
+                kind: TagKind::Id3v2,
+                offset: spliced,
+                size: 42
+            }]
+        );
+
+        let lame_tag = LameTag {
+            bitrate: 128,
+            preset: Some("128kbps".to_string()),
+            ..Default::default()
+        };
+        let flags = set.cross_check(Some("LAME3.100"), Some(&lame_tag), Some(180.0));
+        assert_eq!(
+            flags,
+            vec![
+                "tsse_lame_mismatch(tag=3.99,stream=3.100)".to_string(),
+                "tsse_settings_mismatch(tag=320kbps,stream=128kbps)".to_string(),
+                "tlen_mismatch(tag=240.0s,measured=180.0s)".to_string(),
+                format!("tag_misplaced(id3v2@{})", spliced),
+            ]
+        );
+
+        // Matching version and length: only the misplaced tag is left
+        let flags = set.cross_check(Some("LAME3.99r"), None, Some(239.6));
+        assert_eq!(flags, vec![format!("tag_misplaced(id3v2@{})", spliced)]);
+    }
+}
@@ -0,0 +1,275 @@
+//! Fraunhofer VBRI header parsing
+//!
+//! Fraunhofer's encoders mark VBR files with a VBRI header instead of
+//! Xing/Info. It always sits 32 bytes after the first frame header,
+//! whatever the channel mode:
+//!
+//! ```text
+//! Offset  Size  Field
+//! 0       4     "VBRI"
+//! 4       2     Version (1)
+//! 6       2     Encoder delay (samples)
+//! 8       2     Quality (0-100)
+//! 10      4     Total bytes
+//! 14      4     Total frames
+//! 18      2     TOC entries
+//! 20      2     TOC scale factor
+//! 22      2     Bytes per TOC entry (1-4)
+//! 24      2     Frames per TOC entry
+//! 26      ...   TOC: byte size of each run of frames, divided by the scale
+//! ```
+//!
+//! Unlike LAME, Fraunhofer records no lowpass. Its encoders do use a fixed
+//! bandwidth per bitrate, so the measured cutoff can be checked against
+//! that instead.
+
+use super::frame::FrameHeader;
+use super::stream::StreamStructure;
+use super::tags;
+use serde::Serialize;
+use std::io::Cursor;
+
+/// Offset of "VBRI" from the start of the frame
+pub const VBRI_OFFSET: usize = 36;
+
+/// Size of the fixed part of the header
+const VBRI_HEADER_LEN: usize = 26;
+
+/// How far past the tags to look for the first frame
+const MAX_SYNC_SEARCH: usize = 4096;
+
+/// How far the measured cutoff may fall below the expected bandwidth
+pub const BANDWIDTH_MARGIN_HZ: f64 = 1500.0;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VbriHeader {
+    pub version: u16,
+    /// Encoder delay (samples)
+    pub delay: u16,
+    /// Quality setting (0-100)
+    pub quality: u16,
+    pub total_bytes: u32,
+    pub total_frames: u32,
+    /// Frames covered by each TOC entry
+    pub frames_per_entry: u16,
+    /// Byte size of each run of frames
+    pub toc: Vec<u32>,
+    /// Byte offset of the frame carrying the header
+    pub frame_offset: usize,
+    /// Size of that frame (bytes)
+    pub frame_size: u32,
+    pub sample_rate: u32,
+    pub samples_per_frame: u32,
+}
+
+impl VbriHeader {
+    /// Find and parse the VBRI header in the first frame
+    pub fn extract(data: &[u8]) -> Option<Self> {
+        let audio = tags::audio_range(&mut Cursor::new(data)).ok()?;
+        let start = audio.start as usize;
+        let end = (start + MAX_SYNC_SEARCH).min(audio.end as usize);
+        let audio_data = &data[..audio.end as usize];
+        let (offset, frame) =
+            (start..end).find_map(|i| FrameHeader::parse_at(audio_data, i).map(|frame| (i, frame)))?;
+        let mut header = Self::parse(data.get(offset + VBRI_OFFSET..)?)?;
+        header.frame_offset = offset;
+        header.frame_size = frame.frame_size;
+        header.sample_rate = frame.sample_rate;
+        header.samples_per_frame = frame.samples_per_frame;
+        Some(header)
+    }
+
+    /// Parse the header from the bytes starting at "VBRI"
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < VBRI_HEADER_LEN || &bytes[..4] != b"VBRI" {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at =
+            |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+        let (entries, scale, entry_size) =
+            (u16_at(18) as usize, u16_at(20) as u32, u16_at(22) as usize);
+        let toc_bytes = bytes.get(VBRI_HEADER_LEN..VBRI_HEADER_LEN + entries * entry_size);
+        let toc = match toc_bytes {
+            Some(toc) if (1..=4).contains(&entry_size) => toc
+                .chunks_exact(entry_size)
+                .map(|entry| entry.iter().fold(0u32, |acc, &b| (acc << 8) | b as u32) * scale)
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Some(VbriHeader {
+            version: u16_at(4),
+            delay: u16_at(6),
+            quality: u16_at(8),
+            total_bytes: u32_at(10),
+            total_frames: u32_at(14),
+            frames_per_entry: u16_at(24),
+            toc,
+            ..Default::default()
+        })
+    }
+
+    /// Average bitrate from the byte and frame counts (kbps)
+    pub fn average_bitrate(&self) -> Option<u32> {
+        if self.total_frames == 0 || self.sample_rate == 0 {
+            return None;
+        }
+        let duration =
+            self.total_frames as f64 * self.samples_per_frame as f64 / self.sample_rate as f64;
+        Some((self.total_bytes as f64 * 8.0 / duration / 1000.0).round() as u32)
+    }
+
+    /// Compare the frame and byte counts with the stream walk, with or
+    /// without the header frame (encoders disagree on whether it counts)
+    pub fn check_length(&self, structure: &StreamStructure) -> Vec<String> {
+        let mut flags = Vec::new();
+        let frames = structure.frames;
+        if self.total_frames != frames && self.total_frames + 1 != frames {
+            flags.push(format!(
+                "vbri_frame_count_mismatch(header={},actual={})",
+                self.total_frames, frames
+            ));
+        }
+        let bytes = (structure.audio_end - structure.trailing_bytes)
+            .saturating_sub(self.frame_offset as u64);
+        let declared = self.total_bytes as u64;
+        if declared != bytes && declared != bytes.saturating_sub(self.frame_size as u64) {
+            flags.push(format!(
+                "vbri_byte_count_mismatch(header={},actual={})",
+                declared, bytes
+            ));
+        }
+        flags
+    }
+}
+
+/// Bandwidth Fraunhofer's encoders use at each bitrate (Hz). VBR averages
+/// fall between the standard bitrates, so each band runs halfway to the next
+pub fn expected_bandwidth(bitrate: u32) -> u32 {
+    match bitrate {
+        0..=80 => 11000,
+        81..=112 => 15000,
+        113..=144 => 16000,
+        145..=176 => 17500,
+        177..=208 => 19000,
+        _ => 20000,
+    }
+}
+
+/// Flag a measured cutoff well below what Fraunhofer uses at this bitrate,
+/// e.g. "fhg_bandwidth_mismatch(16.0k<19.0k)"
+pub fn check_bandwidth(bitrate: u32, cutoff_hz: f64) -> Option<String> {
+    let expected = expected_bandwidth(bitrate);
+    (cutoff_hz < expected as f64 - BANDWIDTH_MARGIN_HZ).then(|| {
+        format!(
+            "fhg_bandwidth_mismatch({:.1}k<{:.1}k)",
+            cutoff_hz / 1000.0,
+            expected as f64 / 1000.0
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp3::stream;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: FRAUNHOFER'S VBR HEADER
+    // ==========================================================================
+    //
+    // Xing/Info came from Xing's encoder and LAME adopted it; Fraunhofer, who
+    // developed MP3, wrote their own. Both do the same job: the first frame
+    // holds no audio, only the totals a player needs to show the length and
+    // seek in a VBR file. VBRI files come from FhG's own tools and the many
+    // programs that licensed them (MusicMatch, older Windows Media Player,
+    // Adobe Audition), so LAME's lowpass checks never saw them.
+    // ==========================================================================
+
+    /// MPEG-1 Layer III 128 kbps, 44.1 kHz: 417-byte frames
+    const HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+    const FRAME_SIZE: usize = 417;
+
+    /// Helper: VBRI frame followed by `audio_frames` frames, with matching totals
+    fn create_vbri_stream(audio_frames: usize) -> Vec<u8> {
+        let mut frame = vec![0u8; FRAME_SIZE];
+        frame[..4].copy_from_slice(&HEADER);
+        let total_bytes = ((audio_frames + 1) * FRAME_SIZE) as u32;
+        let mut vbri = b"VBRI".to_vec();
+        vbri.extend_from_slice(&1u16.to_be_bytes()); // version
+        vbri.extend_from_slice(&1105u16.to_be_bytes()); // delay
+        vbri.extend_from_slice(&75u16.to_be_bytes()); // quality
+        vbri.extend_from_slice(&total_bytes.to_be_bytes());
+        vbri.extend_from_slice(&(audio_frames as u32).to_be_bytes());
+        vbri.extend_from_slice(&2u16.to_be_bytes()); // TOC entries
+        vbri.extend_from_slice(&4u16.to_be_bytes()); // scale
+        vbri.extend_from_slice(&2u16.to_be_bytes()); // bytes per entry
+        vbri.extend_from_slice(&((audio_frames / 2) as u16).to_be_bytes());
+        for _ in 0..2 {
+            let entry = (audio_frames / 2 * FRAME_SIZE / 4) as u16;
+            vbri.extend_from_slice(&entry.to_be_bytes());
+        }
+        frame[VBRI_OFFSET..VBRI_OFFSET + vbri.len()].copy_from_slice(&vbri);
+
+        let mut data = frame;
+        for _ in 0..audio_frames {
+            data.extend_from_slice(&HEADER);
+            data.extend_from_slice(&[0x11; FRAME_SIZE - 4]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_vbri_header() {
+        let data = create_vbri_stream(100);
+        let header = VbriHeader::extract(&data).expect("Should find VBRI");
+        assert_eq!(
+            (header.version, header.delay, header.quality),
+            (1, 1105, 75)
+        );
+        assert_eq!(
+            (header.total_frames, header.total_bytes),
+            (100, 101 * FRAME_SIZE as u32)
+        );
+        assert_eq!(header.frames_per_entry, 50);
+        // TOC entries are stored divided by the scale factor
+        assert_eq!(header.toc, vec![20848, 20848]);
+        assert_eq!((header.frame_offset, header.sample_rate), (0, 44100));
+        assert_eq!(header.average_bitrate(), Some(129));
+        assert!(VbriHeader::parse(b"Xing").is_none());
+    }
+
+    #[test]
+    fn test_length_matches_stream() {
+        let data = create_vbri_stream(100);
+        let header = VbriHeader::extract(&data).unwrap();
+        assert!(header.check_length(&stream::walk(&data)).is_empty());
+
+        // Twenty frames cut off the end
+        let cut = &data[..81 * FRAME_SIZE];
+        assert_eq!(
+            header.check_length(&stream::walk(cut)),
+            vec![
+                "vbri_frame_count_mismatch(header=100,actual=81)".to_string(),
+                format!(
+                    "vbri_byte_count_mismatch(header={},actual={})",
+                    101 * FRAME_SIZE,
+                    81 * FRAME_SIZE
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bandwidth_check() {
+        // A "192k" FhG file with a 16 kHz cutoff was encoded from band-limited audio
+        assert_eq!(
+            check_bandwidth(192, 16000.0).as_deref(),
+            Some("fhg_bandwidth_mismatch(16.0k<19.0k)")
+        );
+        assert_eq!(check_bandwidth(128, 15800.0), None);
+        assert_eq!(check_bandwidth(320, 19600.0), None);
+    }
+}
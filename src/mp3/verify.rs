@@ -0,0 +1,303 @@
+//! MP3 stream verification against the Xing/Info header and LAME tag
+//!
+//! The first frame of a LAME-encoded file records what the encoder wrote:
+//! the frame and byte counts (Xing/Info), and the music length plus a CRC-16
+//! of the audio data (LAME tag). Anything done to the file afterwards that
+//! touches the audio frames breaks at least one of them:
+//!
+//! ```text
+//! Edit                 frames   bytes   music CRC   last frame
+//! trimmed / cut short  fewer    fewer   mismatch    often partial
+//! spliced / appended   more     more    mismatch    complete
+//! re-muxed (tag kept)  same     same    mismatch    complete
+//! tag edited           same     same    match       complete (tag CRC fails)
+//! ```
+//!
+//! The frames come from the stream walk (`stream::walk`), which starts at the
+//! header frame and resynchronises over junk; this compares what it found
+//! with the header. Counts are compared both with and without the header
+//! frame, since encoders disagree on whether it belongs in the total.
+
+use super::frame::FrameHeader;
+use super::lame::LameHeader;
+use super::stream::StreamStructure;
+use serde::Serialize;
+
+/// What the frame walk found, next to what the header declared
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StreamVerification {
+    /// Audio frames after the header frame
+    pub frames: u32,
+    /// Bytes from the header frame to the end of the last complete frame
+    pub bytes: u64,
+    /// Playing time of the walked frames (seconds)
+    pub duration_secs: f64,
+    /// Frame count declared by the Xing/Info header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header_frames: Option<u32>,
+    /// Byte count declared by the Xing/Info header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header_bytes: Option<u32>,
+    /// Music length declared by the LAME tag
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub music_length: Option<u32>,
+    /// Whether the audio CRC matches the LAME tag (None if no tag)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub music_crc_ok: Option<bool>,
+    /// Whether the header frame's CRC matches the LAME tag (None if no tag)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag_crc_ok: Option<bool>,
+    /// Size of the header frame, which encoders may or may not count
+    pub header_frame_size: u32,
+    /// Bytes skipped between frames while resynchronising
+    pub skipped_bytes: u64,
+    /// The last frame runs past the end of the file
+    pub truncated: bool,
+}
+
+impl StreamVerification {
+    /// Whether the frame count matches the header (with or without the header frame)
+    pub fn frame_count_ok(&self) -> Option<bool> {
+        self.header_frames
+            .map(|declared| declared == self.frames || declared == self.frames + 1)
+    }
+
+    /// Whether the byte count matches the header (with or without the header frame)
+    fn byte_count_ok(&self, declared: u32) -> bool {
+        let declared = declared as u64;
+        declared == self.bytes
+            || declared == self.bytes.saturating_sub(self.header_frame_size as u64)
+    }
+
+    /// Flags for every disagreement, e.g. "xing_frame_count_mismatch(header=4096,actual=3900)"
+    pub fn flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        if self.frame_count_ok() == Some(false) {
+            flags.push(format!(
+                "xing_frame_count_mismatch(header={},actual={})",
+                self.header_frames.unwrap_or(0),
+                self.frames
+            ));
+        }
+        if let Some(declared) = self.header_bytes {
+            if !self.byte_count_ok(declared) {
+                flags.push(format!(
+                    "xing_byte_count_mismatch(header={},actual={})",
+                    declared, self.bytes
+                ));
+            }
+        }
+        if let Some(declared) = self.music_length {
+            if !self.byte_count_ok(declared) {
+                flags.push(format!(
+                    "lame_music_length_mismatch(header={},actual={})",
+                    declared, self.bytes
+                ));
+            }
+        }
+        if self.music_crc_ok == Some(false) {
+            flags.push("lame_music_crc_mismatch".to_string());
+        }
+        if self.tag_crc_ok == Some(false) {
+            flags.push("lame_tag_crc_mismatch".to_string());
+        }
+        if self.truncated || self.ends_short() {
+            flags.push("stream_truncated".to_string());
+        }
+        flags
+    }
+
+    /// Fewer frames than the header declares
+    fn ends_short(&self) -> bool {
+        self.header_frames
+            .is_some_and(|declared| declared > self.frames + 1)
+    }
+}
+
+/// Compare the stream walk with the header. Returns None if the header has
+/// no frame to start from, or the walk didn't start there.
+pub fn verify(data: &[u8], header: &LameHeader, stream: &StreamStructure) -> Option<StreamVerification> {
+    let start = header.frame_offset?;
+    if stream.first_frame() != Some(start as u64) {
+        return None;
+    }
+    let first = FrameHeader::parse_at(data, start)?;
+
+    // The walk counts the header frame; the header's totals may not
+    let end = stream.frames_end() as usize;
+    let mut result = StreamVerification {
+        frames: stream.frames - 1,
+        bytes: (end - start) as u64,
+        duration_secs: stream.duration_secs - first.duration_secs(),
+        header_frames: header.total_frames,
+        header_bytes: header.total_bytes,
+        header_frame_size: first.frame_size,
+        skipped_bytes: stream.junk_bytes,
+        truncated: stream.truncated,
+        ..Default::default()
+    };
+
+    if let (Some(tag), Some(tag_offset)) = (&header.tag, header.tag_offset) {
+        // LAME 3.90-3.93 left the CRC fields empty
+        if tag.music_crc != 0 || tag.music_length != 0 {
+            result.music_length = Some(tag.music_length);
+            // Covers every byte after the header frame, junk included
+            let music_start = (start + first.frame_size as usize).min(end);
+            result.music_crc_ok = Some(lame_crc16(0, &data[music_start..end]) == tag.music_crc);
+        }
+        let crc_end = tag_offset + 34;
+        if crc_end <= data.len() {
+            result.tag_crc_ok = Some(lame_crc16(0, &data[start..crc_end]) == tag.tag_crc);
+        }
+    }
+    Some(result)
+}
+
+/// CRC-16 as LAME computes it (polynomial 0x8005, reflected, initial value 0)
+pub fn lame_crc16(mut crc: u16, bytes: &[u8]) -> u16 {
+    for &byte in bytes {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp3::stream;
+
+    // ==========================================================================
+    // EDUCATIONAL BACKGROUND: WHY THE HEADER CAN CONVICT A FILE
+    // ==========================================================================
+    //
+    // LAME writes its Info frame last: once every audio frame is out, it seeks
+    // back and fills in how many frames and bytes it wrote, and a CRC-16 of
+    // the audio. Nothing updates those numbers afterwards, so a file that was
+    // trimmed, spliced or patched still carries the description of the
+    // original stream - and a walk over the frames shows the difference.
+    // ==========================================================================
+
+    /// MPEG-1 Layer III, 128 kbps, 44.1 kHz, stereo, no padding: 417 bytes
+    const HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+    const FRAME_SIZE: usize = 417;
+    const LAME_POS: usize = 52;
+
+    /// Helper: Info frame + `audio_frames` frames, with counts and CRCs LAME would write
+    fn create_stream(audio_frames: usize) -> Vec<u8> {
+        let mut audio = Vec::new();
+        let mut state = 0x1234_5678u32;
+        for _ in 0..audio_frames {
+            audio.extend_from_slice(&HEADER);
+            for _ in 4..FRAME_SIZE {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                // Keep sync bytes out of the payload
+                audio.push((state >> 24) as u8 & 0x7F);
+            }
+        }
+
+        let mut info = vec![0u8; FRAME_SIZE];
+        info[..4].copy_from_slice(&HEADER);
+        info[36..40].copy_from_slice(b"Info");
+        info[40..44].copy_from_slice(&3u32.to_be_bytes()); // frames + bytes
+        info[44..48].copy_from_slice(&(audio_frames as u32).to_be_bytes());
+        let total_bytes = (FRAME_SIZE + audio.len()) as u32;
+        info[48..52].copy_from_slice(&total_bytes.to_be_bytes());
+        info[LAME_POS..LAME_POS + 9].copy_from_slice(b"LAME3.100");
+        info[LAME_POS + 10] = 205;
+        info[LAME_POS + 28..LAME_POS + 32].copy_from_slice(&total_bytes.to_be_bytes());
+        info[LAME_POS + 32..LAME_POS + 34].copy_from_slice(&lame_crc16(0, &audio).to_be_bytes());
+        let tag_crc = lame_crc16(0, &info[..LAME_POS + 34]);
+        info[LAME_POS + 34..LAME_POS + 36].copy_from_slice(&tag_crc.to_be_bytes());
+
+        info.extend_from_slice(&audio);
+        info
+    }
+
+    fn verify_stream(data: &[u8]) -> StreamVerification {
+        let header = LameHeader::extract(data).expect("Should extract header");
+        verify(data, &header, &stream::walk(data)).expect("Should walk the stream")
+    }
+
+    #[test]
+    fn test_lame_crc16_check_value() {
+        // CRC-16/ARC, the variant LAME uses, has check value 0xBB3D
+        assert_eq!(lame_crc16(0, b"123456789"), 0xBB3D);
+    }
+
+    #[test]
+    fn test_intact_stream_matches_header() {
+        let mut data = create_stream(20);
+        // An ID3v1 tag at the end is not part of the stream
+        let mut id3v1 = vec![0u8; 128];
+        id3v1[..3].copy_from_slice(b"TAG");
+        data.extend_from_slice(&id3v1);
+
+        let result = verify_stream(&data);
+        assert_eq!(result.frames, 20);
+        assert_eq!(result.bytes, 21 * FRAME_SIZE as u64);
+        assert_eq!(result.music_crc_ok, Some(true));
+        assert_eq!(result.tag_crc_ok, Some(true));
+        assert!(result.flags().is_empty(), "Got {:?}", result.flags());
+    }
+
+    #[test]
+    fn test_trimmed_stream_is_flagged() {
+        // Five frames cut off the end, on a frame boundary
+        let mut data = create_stream(20);
+        data.truncate(16 * FRAME_SIZE);
+        let flags = verify_stream(&data).flags();
+        assert!(
+            flags.contains(&"xing_frame_count_mismatch(header=20,actual=15)".to_string()),
+            "Got {:?}",
+            flags
+        );
+        assert!(flags.contains(&"lame_music_crc_mismatch".to_string()));
+        assert!(flags.contains(&"stream_truncated".to_string()));
+
+        // Cut mid-frame: the last frame is incomplete
+        let mut data = create_stream(20);
+        data.truncate(data.len() - 100);
+        let result = verify_stream(&data);
+        assert!(result.truncated);
+        assert!(result.flags().contains(&"stream_truncated".to_string()));
+    }
+
+    #[test]
+    fn test_patched_audio_breaks_music_crc() {
+        // Same length and frame count, different audio: only the CRC can tell
+        let mut data = create_stream(20);
+        data[10 * FRAME_SIZE + 100] ^= 0x01;
+        let result = verify_stream(&data);
+        assert_eq!(result.flags(), vec!["lame_music_crc_mismatch".to_string()]);
+        assert_eq!(result.tag_crc_ok, Some(true));
+
+        // Editing the tag itself breaks the tag CRC instead
+        let mut data = create_stream(20);
+        data[LAME_POS + 10] = 160;
+        let result = verify_stream(&data);
+        assert_eq!(result.flags(), vec!["lame_tag_crc_mismatch".to_string()]);
+    }
+
+    #[test]
+    fn test_junk_between_frames_is_skipped() {
+        // Splicing in foreign bytes: the walk resyncs, counts stay, CRC breaks
+        let mut data = create_stream(20);
+        let splice = 8 * FRAME_SIZE;
+        data.splice(splice..splice, [0x55u8; 37]);
+        let result = verify_stream(&data);
+        assert_eq!(result.skipped_bytes, 37);
+        assert_eq!(result.frames, 20);
+        assert_eq!(result.music_crc_ok, Some(false));
+        assert!(result
+            .flags()
+            .iter()
+            .any(|flag| flag.starts_with("xing_byte_count_mismatch")));
+    }
+}
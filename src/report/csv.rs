@@ -7,7 +7,9 @@ pub fn write<W: Write>(writer: &mut W, results: &[AnalysisResult]) -> io::Result
     // Header
     writeln!(
         writer,
-        "verdict,filepath,bitrate_kbps,combined_score,spectral_score,binary_score,flags,encoder,lowpass"
+        "verdict,filepath,bitrate_kbps,combined_score,spectral_score,binary_score,flags,encoder,lowpass,\
+         integrated_lufs,loudness_range_lu,true_peak_dbtp,dynamic_range_dr,clip_runs,\
+         integrity,decode_errors,dropouts,clicks"
     )?;
 
     // Rows
@@ -23,9 +25,22 @@ pub fn write<W: Write>(writer: &mut W, results: &[AnalysisResult]) -> io::Result
             .map(|l| l.to_string())
             .unwrap_or_else(|| "n/a".to_string());
 
+        let loudness = match r.loudness_details {
+            Some(ref l) => format!(
+                "{:.1},{:.1},{:.1},{:.1},{}",
+                l.integrated_loudness, l.loudness_range, l.true_peak, l.dynamic_range, l.clip_runs
+            ),
+            None => "n/a,n/a,n/a,n/a,n/a".to_string(),
+        };
+
+        let integrity = match (r.integrity, &r.integrity_details) {
+            (Some(verdict), Some(i)) => format!("{},{},{},{}", verdict, i.decode_errors, i.dropouts, i.clicks),
+            _ => "n/a,n/a,n/a,n/a".to_string(),
+        };
+
         writeln!(
             writer,
-            "{},{},{},{},{},{},{},{},{}",
+            "{},{},{},{},{},{},{},{},{},{},{}",
             r.verdict,
             escape_csv(&r.file_path),
             r.bitrate,
@@ -34,7 +49,9 @@ pub fn write<W: Write>(writer: &mut W, results: &[AnalysisResult]) -> io::Result
             r.binary_score,
             flags,
             escape_csv(&r.encoder),
-            lowpass
+            lowpass,
+            loudness,
+            integrity
         )?;
     }
 
@@ -97,7 +97,11 @@ mod tests {
             encoder: "LAME".to_string(),
             lowpass: None,
             spectral_details: None,
+            loudness_details: None,
+            integrity: None,
+            integrity_details: None,
             binary_details: None,
+            channel_verdicts: vec![],
             error: None,
         }
     }
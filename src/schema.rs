@@ -46,6 +46,20 @@ diesel::table! {
         flags -> Nullable<Text>,
         error -> Nullable<Text>,
         file_hash -> Nullable<Text>,
+        integrated_loudness -> Nullable<Double>,
+        loudness_range -> Nullable<Double>,
+        true_peak -> Nullable<Double>,
+        sample_peak -> Nullable<Double>,
+        dynamic_range -> Nullable<Double>,
+        clipped_samples -> Nullable<BigInt>,
+        clip_runs -> Nullable<BigInt>,
+        longest_clip_run -> Nullable<BigInt>,
+        integrity -> Nullable<Text>,
+        decode_errors -> Nullable<BigInt>,
+        stream_errors -> Nullable<BigInt>,
+        missing_secs -> Nullable<Double>,
+        dropouts -> Nullable<BigInt>,
+        clicks -> Nullable<BigInt>,
     }
 }
 
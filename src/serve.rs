@@ -2,6 +2,7 @@
 //!
 //! `losselot serve ./folder` → starts server, opens browser, shows results
 
+use crate::analyzer::spectral::{FftConfig, WindowType};
 use crate::db::{Database, DecisionGraph};
 use crate::report::Summary;
 use crate::{AnalysisResult, Analyzer};
@@ -38,10 +39,30 @@ pub struct AnalyzeParams {
     pub suspect_threshold: u32,
     #[serde(default)]
     pub skip_spectral: bool,
+    #[serde(default = "default_fft_size")]
+    pub fft_size: usize,
+    #[serde(default)]
+    pub window: WindowType,
+    /// Hop in samples (default: half the FFT size)
+    #[serde(default)]
+    pub hop: Option<usize>,
+    /// Short FFT size for multi-resolution mode
+    #[serde(default)]
+    pub multi_resolution: Option<usize>,
 }
 
 fn default_threshold() -> u32 { 65 }
 fn default_suspect() -> u32 { 35 }
+fn default_fft_size() -> usize { FftConfig::default().size }
+
+impl AnalyzeParams {
+    fn fft_config(&self) -> FftConfig {
+        FftConfig::new(self.fft_size)
+            .with_window(self.window)
+            .with_hop(self.hop.unwrap_or(self.fft_size / 2))
+            .with_multi_resolution(self.multi_resolution)
+    }
+}
 
 #[derive(Serialize)]
 pub struct AnalysisReport {
@@ -55,11 +76,15 @@ pub struct AnalysisReport {
 impl Serialize for AnalyzeParams {
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         use serde::ser::SerializeStruct;
-        let mut s = serializer.serialize_struct("AnalyzeParams", 4)?;
+        let mut s = serializer.serialize_struct("AnalyzeParams", 8)?;
         s.serialize_field("path", &self.path)?;
         s.serialize_field("threshold", &self.threshold)?;
         s.serialize_field("suspect_threshold", &self.suspect_threshold)?;
         s.serialize_field("skip_spectral", &self.skip_spectral)?;
+        s.serialize_field("fft_size", &self.fft_size)?;
+        s.serialize_field("window", &self.window)?;
+        s.serialize_field("hop", &self.hop)?;
+        s.serialize_field("multi_resolution", &self.multi_resolution)?;
         s.end()
     }
 }
@@ -180,6 +205,10 @@ fn parse_params(request: &mut Request, default_path: &str) -> std::io::Result<An
         threshold: default_threshold(),
         suspect_threshold: default_suspect(),
         skip_spectral: false,
+        fft_size: default_fft_size(),
+        window: WindowType::default(),
+        hop: None,
+        multi_resolution: None,
     })
 }
 
@@ -210,7 +239,8 @@ fn run_analysis(params: &AnalyzeParams) -> AnalysisReport {
 
     let analyzer = Analyzer::new()
         .with_skip_spectral(params.skip_spectral)
-        .with_thresholds(params.suspect_threshold, params.threshold);
+        .with_thresholds(params.suspect_threshold, params.threshold)
+        .with_fft_config(params.fft_config());
 
     let results: Vec<AnalysisResult> = files.par_iter().map(|p| analyzer.analyze(p)).collect();
     let summary = Summary::from_results(&results);
@@ -224,6 +254,10 @@ fn run_analysis(params: &AnalyzeParams) -> AnalysisReport {
             threshold: params.threshold,
             suspect_threshold: params.suspect_threshold,
             skip_spectral: params.skip_spectral,
+            fft_size: params.fft_size,
+            window: params.window,
+            hop: params.hop,
+            multi_resolution: params.multi_resolution,
         },
     }
 }
@@ -3,7 +3,9 @@ use rustfft::{FftPlanner, num_complex::Complex};
 use serde::{Serialize, Deserialize};
 
 const FFT_SIZE: usize = 8192;
+const MIN_FFT_SIZE: usize = 256;
 const SAMPLE_RATE: f32 = 44100.0;
+const KAISER_BETA: f32 = 8.6;
 
 // =============================================================================
 // RESULT STRUCTURES
@@ -329,6 +331,9 @@ fn count_pattern(haystack: &[u8], needle: &[u8]) -> usize {
 #[wasm_bindgen]
 pub struct Analyzer {
     fft_planner: FftPlanner<f32>,
+    fft_size: usize,
+    window: WindowType,
+    hop: usize,
 }
 
 #[wasm_bindgen]
@@ -337,9 +342,24 @@ impl Analyzer {
     pub fn new() -> Analyzer {
         Analyzer {
             fft_planner: FftPlanner::new(),
+            fft_size: FFT_SIZE,
+            window: WindowType::Hann,
+            hop: FFT_SIZE,
         }
     }
 
+    /// Configure FFT size, window ("hann", "blackman-harris", "kaiser") and hop.
+    /// A hop of 0 means non-overlapping windows (hop = fft_size).
+    #[wasm_bindgen]
+    pub fn set_fft_config(&mut self, fft_size: usize, window: &str, hop: usize) -> Result<(), JsValue> {
+        let window = WindowType::from_name(window)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown window '{}'", window)))?;
+        self.fft_size = fft_size.max(MIN_FFT_SIZE);
+        self.window = window;
+        self.hop = if hop == 0 { self.fft_size } else { hop.min(self.fft_size) };
+        Ok(())
+    }
+
     /// Analyze PCM samples only (legacy method)
     #[wasm_bindgen]
     pub fn analyze(&mut self, samples: &[f32]) -> JsValue {
@@ -458,13 +478,14 @@ impl Analyzer {
         let mut all_magnitudes: Vec<Vec<f32>> = Vec::new();
         let mut cutoff_freqs: Vec<f32> = Vec::new();
 
-        let fft = self.fft_planner.plan_fft_forward(FFT_SIZE);
-        let window = hann_window(FFT_SIZE);
+        let fft_size = self.fft_size;
+        let fft = self.fft_planner.plan_fft_forward(fft_size);
+        let window = self.window.coefficients(fft_size);
 
-        for chunk in samples.chunks(FFT_SIZE) {
-            if chunk.len() < FFT_SIZE {
-                break;
-            }
+        let mut start = 0;
+        while start + fft_size <= samples.len() {
+            let chunk = &samples[start..start + fft_size];
+            start += self.hop;
 
             let mut buffer: Vec<Complex<f32>> = chunk
                 .iter()
@@ -474,12 +495,12 @@ impl Analyzer {
 
             fft.process(&mut buffer);
 
-            let magnitudes: Vec<f32> = buffer[..FFT_SIZE / 2]
+            let magnitudes: Vec<f32> = buffer[..fft_size / 2]
                 .iter()
-                .map(|c| (c.norm() / FFT_SIZE as f32).max(1e-10).log10() * 20.0)
+                .map(|c| (c.norm() / fft_size as f32).max(1e-10).log10() * 20.0)
                 .collect();
 
-            let cutoff = find_cutoff_freq(&magnitudes, SAMPLE_RATE, FFT_SIZE);
+            let cutoff = find_cutoff_freq(&magnitudes, SAMPLE_RATE, fft_size);
             cutoff_freqs.push(cutoff);
             all_magnitudes.push(magnitudes);
         }
@@ -502,7 +523,7 @@ impl Analyzer {
         }
 
         let spectrogram_freqs: Vec<f32> = (0..num_bins)
-            .map(|i| i as f32 * SAMPLE_RATE / FFT_SIZE as f32)
+            .map(|i| i as f32 * SAMPLE_RATE / fft_size as f32)
             .collect();
 
         // Average frequency response
@@ -519,16 +540,16 @@ impl Analyzer {
         // Calculate metrics
         let avg_cutoff = cutoff_freqs.iter().sum::<f32>() / cutoff_freqs.len() as f32;
         let cutoff_variance = variance(&cutoff_freqs);
-        let rolloff_slope = calculate_rolloff_slope(&avg_response, SAMPLE_RATE, FFT_SIZE);
-        let cfcc_cliff = detect_cfcc_cliff(&avg_response, SAMPLE_RATE, FFT_SIZE);
+        let rolloff_slope = calculate_rolloff_slope(&avg_response, SAMPLE_RATE, fft_size);
+        let cfcc_cliff = detect_cfcc_cliff(&avg_response, SAMPLE_RATE, fft_size);
 
         // Band energy calculations
-        let rms_full = band_energy(&avg_response, 0.0, 20000.0, SAMPLE_RATE, FFT_SIZE);
-        let rms_mid_high = band_energy(&avg_response, 10000.0, 15000.0, SAMPLE_RATE, FFT_SIZE);
-        let rms_high = band_energy(&avg_response, 15000.0, 20000.0, SAMPLE_RATE, FFT_SIZE);
-        let rms_upper = band_energy(&avg_response, 17000.0, 20000.0, SAMPLE_RATE, FFT_SIZE);
-        let rms_19_20k = band_energy(&avg_response, 19000.0, 20000.0, SAMPLE_RATE, FFT_SIZE);
-        let rms_ultrasonic = band_energy(&avg_response, 20000.0, 22000.0, SAMPLE_RATE, FFT_SIZE);
+        let rms_full = band_energy(&avg_response, 0.0, 20000.0, SAMPLE_RATE, fft_size);
+        let rms_mid_high = band_energy(&avg_response, 10000.0, 15000.0, SAMPLE_RATE, fft_size);
+        let rms_high = band_energy(&avg_response, 15000.0, 20000.0, SAMPLE_RATE, fft_size);
+        let rms_upper = band_energy(&avg_response, 17000.0, 20000.0, SAMPLE_RATE, fft_size);
+        let rms_19_20k = band_energy(&avg_response, 19000.0, 20000.0, SAMPLE_RATE, fft_size);
+        let rms_ultrasonic = band_energy(&avg_response, 20000.0, 22000.0, SAMPLE_RATE, fft_size);
 
         let band_energy_result = BandEnergy {
             rms_full,
@@ -629,12 +650,72 @@ impl SpectralAnalysis {
 // SPECTRAL HELPER FUNCTIONS
 // =============================================================================
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WindowType {
+    Hann,
+    BlackmanHarris,
+    Kaiser,
+}
+
+impl WindowType {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "hann" | "hanning" => Some(WindowType::Hann),
+            "blackman-harris" | "blackmanharris" => Some(WindowType::BlackmanHarris),
+            "kaiser" => Some(WindowType::Kaiser),
+            _ => None,
+        }
+    }
+
+    fn coefficients(&self, size: usize) -> Vec<f32> {
+        match self {
+            WindowType::Hann => hann_window(size),
+            WindowType::BlackmanHarris => blackman_harris_window(size),
+            WindowType::Kaiser => kaiser_window(size, KAISER_BETA),
+        }
+    }
+}
+
 fn hann_window(size: usize) -> Vec<f32> {
     (0..size)
         .map(|i| 0.5 * (1.0 - (2.0 * std::f32::consts::PI * i as f32 / size as f32).cos()))
         .collect()
 }
 
+fn blackman_harris_window(size: usize) -> Vec<f32> {
+    const A: [f32; 4] = [0.35875, 0.48829, 0.14128, 0.01168];
+    (0..size)
+        .map(|i| {
+            let x = 2.0 * std::f32::consts::PI * i as f32 / size as f32;
+            A[0] - A[1] * x.cos() + A[2] * (2.0 * x).cos() - A[3] * (3.0 * x).cos()
+        })
+        .collect()
+}
+
+fn kaiser_window(size: usize, beta: f32) -> Vec<f32> {
+    let denom = bessel_i0(beta);
+    let half = size as f32 / 2.0;
+    (0..size)
+        .map(|i| {
+            let r = (i as f32 - half) / half;
+            bessel_i0(beta * (1.0 - r * r).max(0.0).sqrt()) / denom
+        })
+        .collect()
+}
+
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..50 {
+        term *= (x / 2.0 / k as f32).powi(2);
+        sum += term;
+        if term < sum * 1e-7 {
+            break;
+        }
+    }
+    sum
+}
+
 fn find_cutoff_freq(magnitudes: &[f32], sample_rate: f32, fft_size: usize) -> f32 {
     let bin_freq = sample_rate / fft_size as f32;
     let peak = magnitudes.iter().cloned().fold(f32::NEG_INFINITY, f32::max);